@@ -1,14 +1,53 @@
 use mongodb::{
     bson::{doc, Document},
-    error::Error,
-    options::ReplaceOptions,
-    results::{DeleteResult, UpdateResult},
-    Collection,
+    error::{Error, ErrorKind, WriteFailure},
+    options::{IndexOptions, UpdateOptions},
+    results::{DeleteResult, InsertOneResult, UpdateResult},
+    Collection, IndexModel,
 };
 
+use futures::TryStreamExt;
+
+use misato_security::hash_token;
 use misato_utils::get_current_timestamp;
 
+use crate::database::Unavailable;
 use crate::models::apiuser_model::*;
+use crate::user_manager::{not_hashed, DUPLICATE_KEY};
+
+#[derive(Debug)]
+pub enum ApiUserError {
+    AlreadyExists,
+    Db(Error),
+}
+
+impl From<Error> for ApiUserError {
+    fn from(error: Error) -> Self {
+        match &*error.kind {
+            ErrorKind::Write(WriteFailure::WriteError(write)) if write.code == DUPLICATE_KEY => {
+                ApiUserError::AlreadyExists
+            }
+            _ => ApiUserError::Db(error),
+        }
+    }
+}
+
+impl Unavailable for ApiUserError {
+    fn is_unavailable(&self) -> bool {
+        match self {
+            ApiUserError::AlreadyExists => false,
+            ApiUserError::Db(error) => error.is_unavailable(),
+        }
+    }
+}
+
+/// What became of a change that may take the admin role away.
+#[derive(Eq, PartialEq, Debug)]
+pub enum RoleChange {
+    Changed,
+    NotFound,
+    LastAdmin, // Refused, and undone
+}
 
 pub struct ApiUserManager {
     pub apiusers: Collection<ApiUser>,
@@ -27,16 +66,101 @@ impl ApiUserManager {
             != 0)
     }
 
-    pub async fn create_apiuser(&self, apiuser: &ApiUser) -> Result<UpdateResult, Error> {
-        let target = self
-            .apiusers
-            .replace_one(
+    /// One api account per user at the database level, so concurrent signups can't both succeed.
+    pub async fn create_indexes(&self) -> Result<(), Error> {
+        let index = IndexModel::builder()
+            .keys(doc! {"uuid": 1})
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.apiusers.create_index(index, None).await?;
+        Ok(())
+    }
+
+    /// Never replaces an existing api account, `ApiUserError::AlreadyExists` is returned instead.
+    /// Runs against the database given by `MISATO_TEST_MONGODB_URI`, skipped when unset:
+    ///
+    /// ```
+    /// use misato_database::{api_manager::{ApiUserError, ApiUserManager}, models::apiuser_model::*};
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+    /// if let Ok(uri) = std::env::var("MISATO_TEST_MONGODB_URI") {
+    ///     let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    ///     let db = client.database("misato_test_apiusers");
+    ///     let manager = ApiUserManager::init(db.collection::<ApiUser>("apiusers"));
+    ///     manager.create_indexes().await.unwrap();
+    ///
+    ///     let first = ApiUser::create("uuid".to_string(), ApiUserRoleType::User);
+    ///     let second = ApiUser::create("uuid".to_string(), ApiUserRoleType::Admin);
+    ///     manager.create_apiuser(&first).await.unwrap();
+    ///     let taken = manager.create_apiuser(&second).await;
+    ///     let found = manager.get_apiuser(None, Some("uuid")).await.unwrap().unwrap();
+    ///     db.drop(None).await.unwrap();
+    ///
+    ///     assert!(matches!(taken, Err(ApiUserError::AlreadyExists)));
+    ///     assert!(found.has_role(&ApiUserRoleType::User));
+    /// }
+    /// # });
+    /// ```
+    pub async fn create_apiuser(&self, apiuser: &ApiUser) -> Result<InsertOneResult, ApiUserError> {
+        let mut apiuser = apiuser.clone();
+        apiuser.token = apiuser.token.map(|token| token.hashed());
+        Ok(self.apiusers.insert_one(apiuser, None).await?)
+    }
+
+    /// Create the api user unless its uuid exists, an existing one is left alone
+    /// except for its token when `reset_token` is set. The given token is always
+    /// hashed, it may look like a hash when chosen by hand.
+    pub async fn ensure_apiuser(
+        &self,
+        apiuser: &ApiUser,
+        reset_token: bool,
+    ) -> Result<UpdateResult, Error> {
+        let mut apiuser = apiuser.clone();
+        let plain_token = apiuser.token.as_ref().map(|token| token.token.clone());
+        if let Some(token) = apiuser.token.as_mut() {
+            token.token = hash_token(&token.token);
+        }
+        if let Some(plain_token) = &plain_token {
+            // Stored in plain text by an older version
+            self.apiusers
+                .update_one(
+                    doc! {"uuid": &apiuser.uuid, "token.token": plain_token},
+                    doc! {"$set": {"token.token": hash_token(plain_token)}},
+                    None,
+                )
+                .await?;
+        }
+        let mut on_insert = mongodb::bson::to_document(&apiuser).unwrap();
+        let mut update = Document::new();
+        if reset_token {
+            if let Some(token) = on_insert.remove("token") {
+                update.insert("$set", doc! {"token": token});
+            }
+        }
+        update.insert("$setOnInsert", on_insert);
+        self.apiusers
+            .update_one(
                 doc! { "uuid": apiuser.uuid.clone() },
-                apiuser,
-                ReplaceOptions::builder().upsert(true).build(),
+                update,
+                UpdateOptions::builder().upsert(true).build(),
             )
+            .await
+    }
+
+    /// Hash the tokens stored in plain text by older versions, returns how many there were.
+    pub async fn hash_tokens(&self) -> Result<u64, Error> {
+        let mut apiusers = self
+            .apiusers
+            .find(doc! {"token.token": not_hashed()}, None)
             .await?;
-        Ok(target)
+        let mut hashed = 0;
+        while let Some(apiuser) = apiusers.try_next().await? {
+            if let Some(token) = &apiuser.token {
+                self.set_token(&apiuser.uuid, token).await?;
+                hashed += 1;
+            }
+        }
+        Ok(hashed)
     }
 
     pub async fn get_apiuser(
@@ -45,11 +169,11 @@ impl ApiUserManager {
         uuid: Option<&str>,
     ) -> Result<Option<ApiUser>, Error> {
         let mut doc: Document = Document::new();
-        if uuid.is_some() {
-            doc = doc! {"uuid": uuid.unwrap()};
+        if let Some(uuid) = uuid {
+            doc = doc! {"uuid": uuid};
         }
-        if username.is_some() {
-            doc = doc! {"username": username.unwrap()};
+        if let Some(username) = username {
+            doc = doc! {"username": username};
         }
         if doc.is_empty() {
             return Ok(None);
@@ -66,11 +190,11 @@ impl ApiUserManager {
         uuid: Option<&str>,
     ) -> Result<Option<DeleteResult>, Error> {
         let mut doc: Document = Document::new();
-        if uuid.is_some() {
-            doc = doc! {"uuid": uuid.unwrap()};
+        if let Some(uuid) = uuid {
+            doc = doc! {"uuid": uuid};
         }
-        if username.is_some() {
-            doc = doc! {"username": username.unwrap()};
+        if let Some(username) = username {
+            doc = doc! {"username": username};
         }
         if doc.is_empty() {
             return Ok(None);
@@ -84,41 +208,146 @@ impl ApiUserManager {
     ) -> Result<Option<DeleteResult>, Error> {
         Ok(Some(
             self.apiusers
-                .delete_one(doc! {"token.token": token, "token.expiration_timestamp": { "$gte": get_current_timestamp() as i64 } }, None)
+                .delete_one(doc! {"token.token": hash_token(token), "token.expiration_timestamp": { "$gte": get_current_timestamp() as i64 } }, None)
                 .await?,
         ))
     }
 
     pub async fn set_token(&self, uuid: &str, token: &ApiUserToken) -> Result<UpdateResult, Error> {
-        let doc = mongodb::bson::to_document(token).unwrap();
+        let doc = mongodb::bson::to_document(&token.hashed()).unwrap();
         let update = doc! {"$set": {"token": doc} };
-        Ok(self
-            .apiusers
+        self.apiusers
+            .update_one(doc! {"uuid": uuid}, update, None)
+            .await
+    }
+
+    pub async fn set_role(
+        &self,
+        uuid: &str,
+        role: &ApiUserRoleType,
+    ) -> Result<UpdateResult, Error> {
+        let role = mongodb::bson::to_bson(role).unwrap();
+        let update = doc! {"$set": {"access.role": role} };
+        self.apiusers
             .update_one(doc! {"uuid": uuid}, update, None)
-            .await?)
+            .await
+    }
+
+    pub async fn count_admins(&self) -> Result<u64, Error> {
+        let role = mongodb::bson::to_bson(&ApiUserRoleType::Admin).unwrap();
+        self.apiusers
+            .count_documents(doc! {"access.role": role}, None)
+            .await
+    }
+
+    /// Like `set_role`, undone when it took the role of the last admin. The change is made
+    /// before counting, so of two admins demoting each other at once one stays admin.
+    pub async fn set_role_keeping_an_admin(
+        &self,
+        uuid: &str,
+        role: &ApiUserRoleType,
+    ) -> Result<RoleChange, Error> {
+        let bson_role = mongodb::bson::to_bson(role).unwrap();
+        let previous = self
+            .apiusers
+            .find_one_and_update(
+                doc! {"uuid": uuid},
+                doc! {"$set": {"access.role": &bson_role}},
+                None,
+            )
+            .await?;
+        match previous {
+            None => return Ok(RoleChange::NotFound),
+            Some(previous) if !previous.has_role(&ApiUserRoleType::Admin) => {
+                return Ok(RoleChange::Changed)
+            }
+            Some(_) if role == &ApiUserRoleType::Admin => return Ok(RoleChange::Changed),
+            Some(_) => {}
+        }
+        if self.count_admins().await? > 0 {
+            return Ok(RoleChange::Changed);
+        }
+        // Unless it was changed again since
+        let admin = mongodb::bson::to_bson(&ApiUserRoleType::Admin).unwrap();
+        self.apiusers
+            .update_one(
+                doc! {"uuid": uuid, "access.role": bson_role},
+                doc! {"$set": {"access.role": admin}},
+                None,
+            )
+            .await?;
+        Ok(RoleChange::LastAdmin)
+    }
+
+    /// Like `delete_apiuser`, the last admin is put back.
+    pub async fn delete_apiuser_keeping_an_admin(&self, uuid: &str) -> Result<RoleChange, Error> {
+        let deleted = match self
+            .apiusers
+            .find_one_and_delete(doc! {"uuid": uuid}, None)
+            .await?
+        {
+            Some(deleted) => deleted,
+            None => return Ok(RoleChange::NotFound),
+        };
+        if !deleted.has_role(&ApiUserRoleType::Admin) || self.count_admins().await? > 0 {
+            return Ok(RoleChange::Changed);
+        }
+        self.apiusers.insert_one(&deleted, None).await?;
+        Ok(RoleChange::LastAdmin)
     }
 
     pub async fn clear_tokens(&self, uuid: &str) -> Result<UpdateResult, Error> {
         let update = doc! {"$unset": {"token": ""} };
-        Ok(self
-            .apiusers
+        self.apiusers
             .update_one(doc! {"uuid": uuid}, update, None)
-            .await?)
+            .await
     }
 
     pub async fn clear_tokens_from_token(&self, token: &str) -> Result<UpdateResult, Error> {
         let update = doc! {"$unset": {"token": ""} };
-        Ok(self
+        self
+            .apiusers
+            .update_one(doc! {"token.token": hash_token(token), "token.expiration_timestamp": { "$gte": get_current_timestamp() as i64 } }, update, None)
+            .await
+    }
+
+    pub async fn add_key(&self, uuid: &str, key: &ApiKey) -> Result<UpdateResult, Error> {
+        let doc = mongodb::bson::to_document(&key.hashed()).unwrap();
+        let update = doc! {"$push": {"keys": doc} };
+        self.apiusers
+            .update_one(doc! {"uuid": uuid}, update, None)
+            .await
+    }
+
+    pub async fn remove_key(&self, uuid: &str, id: &str) -> Result<UpdateResult, Error> {
+        let update = doc! {"$pull": {"keys": {"id": id}} };
+        self.apiusers
+            .update_one(doc! {"uuid": uuid, "keys.id": id}, update, None)
+            .await
+    }
+
+    /// The api user with an unexpired key matching `key`, and that key.
+    pub async fn get_apiuser_from_key(
+        &self,
+        key: &str,
+    ) -> Result<Option<(ApiUser, ApiKey)>, Error> {
+        let apiuser = match self
             .apiusers
-            .update_one(doc! {"token.token": token, "token.expiration_timestamp": { "$gte": get_current_timestamp() as i64 } }, update, None)
-            .await?)
+            .find_one(doc! {"keys.key": hash_token(key)}, None)
+            .await?
+        {
+            Some(apiuser) => apiuser,
+            None => return Ok(None),
+        };
+        let found = apiuser.key(key, get_current_timestamp()).cloned();
+        Ok(found.map(|found| (apiuser, found)))
     }
 
     pub async fn get_apiuser_from_token(&self, token: &str) -> Result<Option<ApiUser>, Error> {
         match self
             .apiusers
             .find_one(
-                doc! {"token.token": token, "token.expiration_timestamp": { "$gte": get_current_timestamp() as i64 } },
+                doc! {"token.token": hash_token(token), "token.expiration_timestamp": { "$gte": get_current_timestamp() as i64 } },
                 None,
             )
             .await?
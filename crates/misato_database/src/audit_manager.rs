@@ -0,0 +1,155 @@
+use futures::TryStreamExt;
+use mongodb::{
+    bson::{doc, Document},
+    error::Error,
+    options::FindOptions,
+    results::{InsertOneResult, UpdateResult},
+    Collection, IndexModel,
+};
+
+use crate::models::audit_model::*;
+
+/// Each criterion of the filter, the time window as a single range on `timestamp`.
+fn event_filter(filter: &AuditFilter) -> Document {
+    let mut document = Document::new();
+    if let Some(actor) = &filter.actor {
+        document.insert("actor", actor);
+    }
+    if let Some(action) = &filter.action {
+        document.insert("action", mongodb::bson::to_bson(action).unwrap());
+    }
+    let mut timestamp = Document::new();
+    if let Some(from) = filter.from {
+        timestamp.insert("$gte", from as i64);
+    }
+    if let Some(to) = filter.to {
+        timestamp.insert("$lte", to as i64);
+    }
+    if !timestamp.is_empty() {
+        document.insert("timestamp", timestamp);
+    }
+    document
+}
+
+pub struct AuditManager {
+    pub events: Collection<AuditEvent>,
+}
+
+impl AuditManager {
+    pub fn init(events: Collection<AuditEvent>) -> Self {
+        Self { events }
+    }
+
+    /// Listings sort by `timestamp`, after an equality on `action` or `actor` when filtered.
+    pub async fn create_indexes(&self) -> Result<(), Error> {
+        for keys in [
+            doc! {"timestamp": -1},
+            doc! {"action": 1, "timestamp": -1},
+            doc! {"actor": 1, "timestamp": -1},
+        ] {
+            let index = IndexModel::builder().keys(keys).build();
+            self.events.create_index(index, None).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn record_event(&self, event: &AuditEvent) -> Result<InsertOneResult, Error> {
+        self.events.insert_one(event, None).await
+    }
+
+    pub async fn count_events(&self, filter: &AuditFilter) -> Result<u64, Error> {
+        self.events
+            .count_documents(event_filter(filter), None)
+            .await
+    }
+
+    /// Newest events first, only `limit` events are loaded.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::{audit_manager::AuditManager, models::audit_model::*};
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+    /// if let Ok(uri) = std::env::var("MISATO_TEST_MONGODB_URI") {
+    ///     let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    ///     let db = client.database("misato_test_list_events");
+    ///     let manager = AuditManager::init(db.collection::<AuditEvent>("audit"));
+    ///     manager.create_indexes().await.unwrap();
+    ///
+    ///     for (timestamp, action) in [(1000, AuditAction::Login), (2000, AuditAction::Signup)] {
+    ///         let mut event = AuditEvent::create(action, Some("admin".to_string()), None);
+    ///         event.timestamp = timestamp;
+    ///         manager.record_event(&event).await.unwrap();
+    ///     }
+    ///     let logins = AuditFilter::new(None, Some("Login"), None, None).unwrap();
+    ///     let window = AuditFilter::new(Some("admin".to_string()), None, Some(1500), None).unwrap();
+    ///     let found = manager.list_events(&logins, 0, 10).await.unwrap();
+    ///     let count = manager.count_events(&window).await.unwrap();
+    ///     db.drop(None).await.unwrap();
+    ///
+    ///     assert_eq!(found.len(), 1);
+    ///     assert_eq!(found[0].timestamp, 1000);
+    ///     assert_eq!(count, 1);
+    /// }
+    /// # });
+    /// ```
+    pub async fn list_events(
+        &self,
+        filter: &AuditFilter,
+        skip: u64,
+        limit: i64,
+    ) -> Result<Vec<AuditEvent>, Error> {
+        let options = FindOptions::builder()
+            .sort(doc! {"timestamp": -1})
+            .skip(skip)
+            .limit(limit)
+            .build();
+        self.events
+            .find(event_filter(filter), options)
+            .await?
+            .try_collect()
+            .await
+    }
+
+    /// Every event done by or to an account, oldest first, for a data export.
+    /// Events about it by username only match the username it has now.
+    pub async fn list_events_about(
+        &self,
+        uuid: &str,
+        username: &str,
+    ) -> Result<Vec<AuditEvent>, Error> {
+        let filter = doc! {"$or": [
+            {"actor": uuid},
+            {"target": {"$in": [uuid, username]}},
+        ]};
+        let options = FindOptions::builder().sort(doc! {"timestamp": 1}).build();
+        self.events.find(filter, options).await?.try_collect().await
+    }
+
+    /// Point the events of an anonymized account to its new `id`, without the addresses they
+    /// were done from. Returns how many were done by it, then to it.
+    pub async fn anonymize(
+        &self,
+        uuid: &str,
+        username: &str,
+        id: &str,
+    ) -> Result<(UpdateResult, UpdateResult), Error> {
+        let by = self
+            .events
+            .update_many(
+                doc! {"actor": uuid},
+                doc! {"$set": {"actor": id}, "$unset": {"ip": ""}},
+                None,
+            )
+            .await?;
+        let to = self
+            .events
+            .update_many(
+                doc! {"target": {"$in": [uuid, username]}},
+                doc! {"$set": {"target": id}, "$unset": {"ip": ""}},
+                None,
+            )
+            .await?;
+        Ok((by, to))
+    }
+}
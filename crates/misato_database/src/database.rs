@@ -1,21 +1,188 @@
-use mongodb::{error::Error, *};
+use std::future::Future;
+use std::time::Duration;
+
+use mongodb::{
+    error::{Error, ErrorKind, WriteFailure},
+    options::{Acknowledgment, ClientOptions, ReadConcern, WriteConcern},
+    *,
+};
+use tracing::{info, warn};
 
 use crate::api_manager::*;
-use crate::models::data_model::Data;
+use crate::audit_manager::*;
+use crate::invite_manager::*;
+use crate::login_failure_manager::*;
+use crate::models::{data_model::Data, user_model::anonymous_id};
 use crate::user_manager::*;
-use misato_utils::settings::Settings;
+use misato_utils::settings::{DbSettings, DeletionMode, Settings};
+
+/// Errors that may only mean MongoDB is out of reach for now, rather than refusing the operation.
+pub trait Unavailable {
+    fn is_unavailable(&self) -> bool;
+}
+
+impl Unavailable for Error {
+    /// No server could be selected in time, or one stopped answering.
+    fn is_unavailable(&self) -> bool {
+        match &*self.kind {
+            ErrorKind::ServerSelection { .. } => true,
+            ErrorKind::Io(error) => error.kind() == std::io::ErrorKind::TimedOut,
+            _ => false,
+        }
+    }
+}
+
+/// Client options of the uri, with the concerns and the timeouts of the settings.
+/// Basic usage:
+///
+/// ```
+/// use misato_database::database::client_options;
+/// use misato_utils::{config::Config, settings::Settings};
+///
+/// let config = Config::from_toml(
+///     "MONGODB_URI = \"mongodb://localhost\"\nMONGODB_NAME = \"misato\"\n\
+///      MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"\n\
+///      MONGODB_WRITE_CONCERN = \"majority\"\nMONGODB_SERVER_SELECTION_TIMEOUT = 2000",
+/// )
+/// .unwrap();
+/// let settings = Settings::from_config(&config).unwrap();
+/// let options = tokio::runtime::Builder::new_current_thread()
+///     .enable_all()
+///     .build()
+///     .unwrap()
+///     .block_on(client_options(&settings.db))
+///     .unwrap();
+///
+/// assert_eq!(options.server_selection_timeout.unwrap().as_millis(), 2000);
+/// assert_eq!(options.write_concern.unwrap().w, Some(mongodb::options::Acknowledgment::Majority));
+/// assert_eq!(options.read_concern, None);
+/// ```
+pub async fn client_options(settings: &DbSettings) -> Result<ClientOptions, Error> {
+    let mut options = ClientOptions::parse(&settings.uri).await?;
+    options.server_selection_timeout =
+        Some(Duration::from_millis(settings.server_selection_timeout));
+    options.connect_timeout = Some(Duration::from_millis(settings.connect_timeout));
+    if let Some(w) = &settings.write_concern {
+        let w = match w.parse::<u32>() {
+            Ok(nodes) => Acknowledgment::Nodes(nodes),
+            Err(_) => Acknowledgment::from(w.to_string()),
+        };
+        let w_timeout = match settings.write_timeout {
+            0 => None,
+            timeout => Some(Duration::from_millis(timeout)),
+        };
+        options.write_concern = Some(WriteConcern::builder().w(w).w_timeout(w_timeout).build());
+    }
+    if let Some(level) = &settings.read_concern {
+        options.read_concern = Some(ReadConcern::custom(level.to_string()));
+    }
+    Ok(options)
+}
+
+/// Call `f` until it succeeds, at most `max_attempts` times, waiting `base_delay`
+/// milliseconds after the first failure and twice as long after each next one.
+/// Basic usage:
+///
+/// ```
+/// use misato_database::database::retry_with_backoff;
+///
+/// let runtime = tokio::runtime::Builder::new_current_thread()
+///     .enable_time()
+///     .build()
+///     .unwrap();
+/// let mut attempts = 0;
+/// let result: Result<(), &str> = runtime.block_on(retry_with_backoff(3, 1, |_| {
+///     attempts += 1;
+///     async { Err("bad uri") }
+/// }));
+///
+/// assert_eq!(result, Err("bad uri"));
+/// assert_eq!(attempts, 3);
+/// ```
+pub async fn retry_with_backoff<T, E, F, Fut>(
+    max_attempts: u32,
+    base_delay: u64,
+    mut f: F,
+) -> Result<T, E>
+where
+    E: std::fmt::Debug,
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 1;
+    loop {
+        match f(attempt).await {
+            Ok(value) => return Ok(value),
+            Err(error) if attempt < max_attempts => {
+                let delay = base_delay.saturating_mul(1 << (attempt - 1).min(16));
+                warn!(
+                    attempt,
+                    max_attempts,
+                    delay_ms = delay,
+                    error = ?error,
+                    "Attempt failed, retrying."
+                );
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                attempt += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+const FIRST_ADMIN_MARKER: &str = "first_admin";
 
 pub struct Database {
+    client: Client,
+    pub mongo: mongodb::Database,
     pub data: Collection<Data>,
     pub usermanager: UserManager,
     pub apiusermanager: ApiUserManager,
+    pub auditmanager: AuditManager,
+    pub invitemanager: InviteManager,
+    pub loginfailuremanager: LoginFailureManager,
 }
 
 impl Database {
+    /// Like `init`, retried as configured in the settings.
+    pub async fn connect(settings: &Settings) -> Result<Self, Error> {
+        retry_with_backoff(settings.db.max_attempts, settings.db.retry_delay, |_| {
+            Database::init(settings)
+        })
+        .await
+    }
+
+    /// Gives up once no server could be selected within `server_selection_timeout`.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use std::time::Instant;
+    ///
+    /// use misato_database::database::{Database, Unavailable};
+    /// use misato_utils::{config::Config, settings::Settings};
+    ///
+    /// // Nothing listens on port 1
+    /// let config = Config::from_toml(
+    ///     "MONGODB_URI = \"mongodb://127.0.0.1:1\"\nMONGODB_NAME = \"misato\"\n\
+    ///      MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"\n\
+    ///      MONGODB_SERVER_SELECTION_TIMEOUT = 300",
+    /// )
+    /// .unwrap();
+    /// let settings = Settings::from_config(&config).unwrap();
+    /// let start = Instant::now();
+    /// let result = tokio::runtime::Builder::new_current_thread()
+    ///     .enable_all()
+    ///     .build()
+    ///     .unwrap()
+    ///     .block_on(Database::init(&settings));
+    ///
+    /// assert_eq!(result.as_ref().err().map(|error| error.is_unavailable()), Some(true));
+    /// assert!(start.elapsed().as_millis() >= 300);
+    /// assert!(start.elapsed().as_millis() < 3000);
+    /// ```
     pub async fn init(settings: &Settings) -> Result<Self, Error> {
-        let uri = &settings.mongodb_uri;
-        let client = Client::with_uri_str(uri).await?;
-        let db = client.database(&settings.mongodb_name);
+        let database = Database::open(settings).await?;
+        let db = &database.mongo;
         let names = db.list_collection_names(None).await?;
         if !names.contains(&"data".to_string()) {
             db.create_collection("data", None).await?;
@@ -26,10 +193,139 @@ impl Database {
         if !names.contains(&"users".to_string()) {
             db.create_collection("users", None).await?;
         }
+        if !names.contains(&"audit".to_string()) {
+            db.create_collection("audit", None).await?;
+        }
+        if !names.contains(&"invites".to_string()) {
+            db.create_collection("invites", None).await?;
+        }
+        if !names.contains(&"login_failures".to_string()) {
+            db.create_collection("login_failures", None).await?;
+        }
+        if !names.contains(&"bootstrap".to_string()) {
+            db.create_collection("bootstrap", None).await?;
+        }
+        match database.usermanager.backfill_keys().await {
+            Ok(0) => {}
+            Ok(backfilled) => info!(
+                users = backfilled,
+                "Backfilled the username and email keys."
+            ),
+            Err(error) => warn!(error = ?error, "Cannot backfill the username and email keys."),
+        }
+        if let Err(error) = database.usermanager.create_indexes().await {
+            // Existing duplicates prevent the index, they must be fixed by hand
+            warn!(error = ?error, "Cannot create the users indexes.");
+        }
+        if let Err(error) = database.apiusermanager.create_indexes().await {
+            // Existing duplicates prevent the index, they must be fixed by hand
+            warn!(error = ?error, "Cannot create the api users indexes.");
+        }
+        if let Err(error) = database.auditmanager.create_indexes().await {
+            warn!(error = ?error, "Cannot create the audit indexes.");
+        }
+        if let Err(error) = database.loginfailuremanager.create_indexes().await {
+            warn!(error = ?error, "Cannot create the login failures indexes.");
+        }
+        Ok(database)
+    }
+
+    /// The handles of `init` without reaching MongoDB, which only happens on the first
+    /// operation, so requests failing before one can be tested without a server.
+    pub async fn open(settings: &Settings) -> Result<Self, Error> {
+        let client = Client::with_options(client_options(&settings.db).await?)?;
+        let db = client.database(&settings.db.name);
         Ok(Database {
             data: db.collection("data"),
             usermanager: UserManager::init(db.collection("users")),
             apiusermanager: ApiUserManager::init(db.collection("apiusers")),
+            auditmanager: AuditManager::init(db.collection("audit")),
+            invitemanager: InviteManager::init(db.collection("invites")),
+            loginfailuremanager: LoginFailureManager::init(db.collection("login_failures")),
+            mongo: db,
+            client,
         })
     }
+
+    /// Delete an account as `mode` says, returns the uuid it keeps, None when there is no such
+    /// active account. Anonymizing deletes its api account, and rewrites its audit events and
+    /// invites with the id keyed by `anonymization_key`, so no document keeps the uuid.
+    pub async fn delete_account(
+        &self,
+        uuid: &str,
+        mode: DeletionMode,
+        anonymization_key: &str,
+    ) -> Result<Option<String>, Error> {
+        if mode == DeletionMode::Delete {
+            return match self.usermanager.delete_user(None, Some(uuid)).await? {
+                Some(result) if result.modified_count == 1 => Ok(Some(uuid.to_string())),
+                _ => Ok(None),
+            };
+        }
+        let user = match self.usermanager.get_user(None, Some(uuid)).await? {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+        let id = anonymous_id(anonymization_key, uuid);
+        if self
+            .usermanager
+            .anonymize_user(uuid, &id)
+            .await?
+            .modified_count
+            == 0
+        {
+            return Ok(None);
+        }
+        self.auditmanager
+            .anonymize(uuid, &user.username, &id)
+            .await?;
+        self.apiusermanager.delete_apiuser(None, Some(uuid)).await?;
+        self.invitemanager.anonymize(uuid, &id).await?;
+        self.mongo
+            .collection::<bson::Document>("bootstrap")
+            .update_one(
+                bson::doc! {"uuid": uuid},
+                bson::doc! {"$set": {"uuid": &id}},
+                None,
+            )
+            .await?;
+        Ok(Some(id))
+    }
+
+    /// Claim the install for the first admin, false when already claimed. The marker has a
+    /// fixed `_id`, so of users signing up at once only the first insert succeeds.
+    pub async fn claim_first_admin(&self, uuid: &str) -> Result<bool, Error> {
+        let marker = bson::doc! {"_id": FIRST_ADMIN_MARKER, "uuid": uuid};
+        let bootstrap = self.mongo.collection::<bson::Document>("bootstrap");
+        match bootstrap.insert_one(marker, None).await {
+            Ok(_) => Ok(true),
+            Err(error) => match &*error.kind {
+                ErrorKind::Write(WriteFailure::WriteError(write))
+                    if write.code == DUPLICATE_KEY =>
+                {
+                    Ok(false)
+                }
+                _ => Err(error),
+            },
+        }
+    }
+
+    /// Give the claim of `uuid` back, when its signup failed after claiming.
+    pub async fn release_first_admin(&self, uuid: &str) -> Result<(), Error> {
+        let bootstrap = self.mongo.collection::<bson::Document>("bootstrap");
+        bootstrap
+            .delete_one(bson::doc! {"_id": FIRST_ADMIN_MARKER, "uuid": uuid}, None)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn ping(&self) -> Result<(), Error> {
+        self.mongo.run_command(bson::doc! {"ping": 1}, None).await?;
+        Ok(())
+    }
+
+    /// Close the connections to MongoDB, once no request uses them anymore.
+    pub async fn close(&self) {
+        self.client.clone().shutdown().await;
+    }
 }
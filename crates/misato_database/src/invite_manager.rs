@@ -0,0 +1,80 @@
+use mongodb::{
+    bson::doc,
+    error::Error,
+    results::{InsertOneResult, UpdateResult},
+    Collection,
+};
+
+use misato_utils::get_current_timestamp;
+
+use crate::models::invite_model::*;
+
+pub struct InviteManager {
+    pub invites: Collection<Invite>,
+}
+
+impl InviteManager {
+    pub fn init(invites: Collection<Invite>) -> Self {
+        Self { invites }
+    }
+
+    pub async fn create_invite(&self, invite: &Invite) -> Result<InsertOneResult, Error> {
+        self.invites.insert_one(invite, None).await
+    }
+
+    /// Spend an unused and unexpired invite on `uuid`, `modified_count` is 0 otherwise.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::{invite_manager::InviteManager, models::invite_model::Invite};
+    /// use misato_security::hash_token;
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+    /// if let Ok(uri) = std::env::var("MISATO_TEST_MONGODB_URI") {
+    ///     let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    ///     let db = client.database("misato_test_invites");
+    ///     let manager = InviteManager::init(db.collection::<Invite>("invites"));
+    ///
+    ///     let (token, invite) = Invite::create("admin".to_string(), 60);
+    ///     manager.create_invite(&invite).await.unwrap();
+    ///     let first = manager.use_invite(&hash_token(&token), "uuid").await.unwrap();
+    ///     let second = manager.use_invite(&hash_token(&token), "another uuid").await.unwrap();
+    ///     db.drop(None).await.unwrap();
+    ///
+    ///     assert_eq!(first.modified_count, 1);
+    ///     assert_eq!(second.modified_count, 0);
+    /// }
+    /// # });
+    /// ```
+    pub async fn use_invite(&self, token_hash: &str, uuid: &str) -> Result<UpdateResult, Error> {
+        let now = get_current_timestamp();
+        let filter = doc! {
+            "token.hash": token_hash,
+            "token.expiration_timestamp": { "$gte": now as i64 },
+            "used_by": { "$exists": false },
+        };
+        let update = doc! {"$set": {"used_by": uuid, "used_at": now as i64}};
+        self.invites.update_one(filter, update, None).await
+    }
+
+    /// Replace the uuid of an anonymized account by its anonymous id, see `anonymous_id`.
+    pub async fn anonymize(
+        &self,
+        uuid: &str,
+        id: &str,
+    ) -> Result<(UpdateResult, UpdateResult), Error> {
+        let created = self
+            .invites
+            .update_many(
+                doc! {"created_by": uuid},
+                doc! {"$set": {"created_by": id}},
+                None,
+            )
+            .await?;
+        let used = self
+            .invites
+            .update_many(doc! {"used_by": uuid}, doc! {"$set": {"used_by": id}}, None)
+            .await?;
+        Ok((created, used))
+    }
+}
@@ -1,4 +1,8 @@
 pub mod api_manager;
+pub mod audit_manager;
 pub mod database;
+pub mod invite_manager;
+pub mod login_failure_manager;
 pub mod models;
 pub mod user_manager;
+pub mod user_store;
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use mongodb::{
+    bson::{doc, Bson, DateTime, Document},
+    error::Error,
+    options::{FindOneAndUpdateOptions, IndexOptions, ReturnDocument},
+    Collection, IndexModel,
+};
+
+use crate::models::login_failure_model::*;
+
+/// How long the failures of an unknown identifier are kept after the last one.
+const FAILURES_TTL: u64 = 24 * 60 * 60 * 1000;
+
+fn number(document: &Document, key: &str) -> u64 {
+    match document.get(key) {
+        Some(Bson::Int32(value)) => *value as u64,
+        Some(Bson::Int64(value)) => *value as u64,
+        _ => 0,
+    }
+}
+
+/// Count a failure on the document matching `filter` in a single update, so concurrent ones all
+/// count, and lock it for `seconds` once `threshold` is reached. Only one of the attempts reaching
+/// it locks, the others find it locked. A locked document stops counting until it unlocks, `set`
+/// is applied either way.
+pub(crate) async fn count_failed_login(
+    collection: &Collection<Document>,
+    filter: Document,
+    set: Document,
+    upsert: bool,
+    lockout: (u32, u64),
+    now: u64,
+) -> Result<FailedLogin, Error> {
+    let (threshold, seconds) = lockout;
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(upsert)
+        .return_document(ReturnDocument::After)
+        .build();
+    let now_bson = now.min(i64::MAX as u64) as i64;
+    let mut fields = set;
+    fields.insert(
+        "failed_logins",
+        doc! {"$cond": [
+            {"$gt": [{"$ifNull": ["$locked_until", 0_i64]}, now_bson]},
+            {"$ifNull": ["$failed_logins", 0]},
+            {"$add": [{"$ifNull": ["$failed_logins", 0]}, 1]},
+        ]},
+    );
+    let counted = collection
+        .find_one_and_update(filter.clone(), vec![doc! {"$set": fields}], options)
+        .await?
+        .unwrap_or_default();
+    let failed_logins = number(&counted, "failed_logins") as u32;
+    let locked_until = number(&counted, "locked_until");
+    if locked_until > now {
+        return Ok(FailedLogin {
+            failed_logins,
+            locked_for: Some((locked_until - now).div_ceil(1000)),
+        });
+    }
+    if failed_logins < threshold {
+        return Ok(FailedLogin {
+            failed_logins,
+            locked_for: None,
+        });
+    }
+    let mut reached = filter;
+    reached.insert("failed_logins", doc! {"$gte": threshold as i64});
+    let lock = doc! {"$set": {
+        "failed_logins": 0,
+        "locked_until": now.saturating_add(seconds.saturating_mul(1000)).min(i64::MAX as u64) as i64,
+    }};
+    collection.update_one(reached, lock, None).await?;
+    // Locked by this attempt, or by a concurrent one that reached the threshold too
+    Ok(FailedLogin {
+        failed_logins: 0,
+        locked_for: Some(seconds),
+    })
+}
+
+pub struct LoginFailureManager {
+    pub failures: Collection<LoginFailures>,
+}
+
+impl LoginFailureManager {
+    pub fn init(failures: Collection<LoginFailures>) -> Self {
+        Self { failures }
+    }
+
+    /// One document per identifier, removed by MongoDB once expired.
+    pub async fn create_indexes(&self) -> Result<(), Error> {
+        let unique = IndexModel::builder()
+            .keys(doc! {"key": 1})
+            .options(IndexOptions::builder().unique(true).build())
+            .build();
+        self.failures.create_index(unique, None).await?;
+        let expiry = IndexOptions::builder()
+            .expire_after(Duration::from_secs(0))
+            .build();
+        let expiry = IndexModel::builder()
+            .keys(doc! {"expires_at": 1})
+            .options(expiry)
+            .build();
+        self.failures.create_index(expiry, None).await?;
+        Ok(())
+    }
+
+    /// Count a failed login of an identifier matching no account, as `UserManager::record_login_failure`.
+    /// Runs against the database given by `MISATO_TEST_MONGODB_URI`, skipped when unset:
+    ///
+    /// ```
+    /// use misato_database::{login_failure_manager::LoginFailureManager, models::login_failure_model::*};
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+    /// if let Ok(uri) = std::env::var("MISATO_TEST_MONGODB_URI") {
+    ///     let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    ///     let db = client.database("misato_test_login_failures");
+    ///     let manager = LoginFailureManager::init(db.collection::<LoginFailures>("login_failures"));
+    ///
+    ///     let first = manager.record_failure("nobody", (2, 60), 0).await.unwrap();
+    ///     let second = manager.record_failure("NOBODY", (2, 60), 0).await.unwrap();
+    ///     let locked = manager.record_failure("nobody", (2, 60), 1000).await.unwrap();
+    ///     let unlocked = manager.record_failure("nobody", (2, 60), 60000).await.unwrap();
+    ///     db.drop(None).await.unwrap();
+    ///
+    ///     assert_eq!(first, FailedLogin { failed_logins: 1, locked_for: None });
+    ///     assert_eq!(second, FailedLogin { failed_logins: 0, locked_for: Some(60) });
+    ///     assert_eq!(locked, FailedLogin { failed_logins: 0, locked_for: Some(59) });
+    ///     assert_eq!(unlocked, FailedLogin { failed_logins: 1, locked_for: None });
+    /// }
+    /// # });
+    /// ```
+    pub async fn record_failure(
+        &self,
+        identifier: &str,
+        lockout: (u32, u64),
+        now: u64,
+    ) -> Result<FailedLogin, Error> {
+        let expires_at = now
+            .saturating_add(lockout.1.saturating_mul(1000))
+            .saturating_add(FAILURES_TTL)
+            .min(i64::MAX as u64);
+        count_failed_login(
+            &self.failures.clone_with_type(),
+            doc! {"key": identifier_key(identifier)},
+            doc! {"expires_at": DateTime::from_millis(expires_at as i64)},
+            true,
+            lockout,
+            now,
+        )
+        .await
+    }
+}
@@ -1,13 +1,96 @@
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use misato_security::generate_token;
-use misato_utils::get_current_timestamp;
+use misato_security::{constant_time_eq, generate_url_token_default, hash_token, is_token_hash};
+use misato_utils::{get_current_timestamp, settings::DefaultRole};
+
+use crate::models::scope_model;
+
+/// Uuid of the admin seeded from `MISATO_ADMIN_TOKEN`.
+pub const DEFAULT_ADMIN_UUID: &str = "admin";
 
 #[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
 pub struct ApiUserToken {
     pub token: String,
-    pub timestamp: u64,
-    pub expiration_timestamp: u64,
+    pub timestamp: u64,            // Creation, in milliseconds
+    pub expiration_timestamp: u64, // Expiration, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>, // None for tokens issued before scopes, they have every one
+}
+
+impl ApiUserToken {
+    pub fn allows(&self, scope: &str) -> bool {
+        scope_model::allows(self.scopes.as_deref(), scope)
+    }
+
+    /// The token as stored, its value replaced by its hash.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::apiuser_model::*;
+    /// use misato_security::hash_token;
+    ///
+    /// let token = ApiUser::create("uuid".to_string(), ApiUserRoleType::User).new_token(60);
+    /// let stored = token.hashed();
+    ///
+    /// assert_eq!(stored.token, hash_token(&token.token));
+    /// assert_eq!(stored.hashed(), stored);
+    /// ```
+    pub fn hashed(&self) -> Self {
+        Self {
+            token: match is_token_hash(&self.token) {
+                true => self.token.clone(),
+                false => hash_token(&self.token),
+            },
+            ..self.clone()
+        }
+    }
+}
+
+/// A named key of an api account, lasting until revoked unless it was given an expiry.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub key: String, // Only its hash is stored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>, // Empty for every scope, from `scope_model`
+    pub timestamp: u64, // Creation, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expiration_timestamp: Option<u64>, // In milliseconds, None never expires
+}
+
+impl ApiKey {
+    pub fn is_expired(&self, now: u64) -> bool {
+        matches!(self.expiration_timestamp, Some(expiration) if expiration < now)
+    }
+
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::apiuser_model::*;
+    ///
+    /// let mut apiuser = ApiUser::create("uuid".to_string(), ApiUserRoleType::Dev);
+    /// let unscoped = apiuser.new_key("ci".to_string(), None, Vec::new()).unwrap();
+    /// let scoped = apiuser.new_key("bot".to_string(), None, vec!["account:read".to_string()]).unwrap();
+    ///
+    /// assert!(unscoped.allows("account:delete"));
+    /// assert!(scoped.allows("account:read"));
+    /// assert!(!scoped.allows("account:delete"));
+    /// ```
+    pub fn allows(&self, scope: &str) -> bool {
+        self.scopes.is_empty() || self.scopes.iter().any(|allowed| allowed == scope)
+    }
+
+    pub fn hashed(&self) -> Self {
+        Self {
+            key: match is_token_hash(&self.key) {
+                true => self.key.clone(),
+                false => hash_token(&self.key),
+            },
+            ..self.clone()
+        }
+    }
 }
 
 #[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
@@ -16,6 +99,8 @@ pub struct ApiUser {
     pub uuid: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub token: Option<ApiUserToken>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keys: Option<Vec<ApiKey>>,
     pub access: ApiUserAccess,
 }
 
@@ -23,31 +108,170 @@ impl ApiUser {
     pub fn create_default(token: String) -> Self {
         Self {
             timestamp: 0,
-            uuid: "admin".to_string(),
+            uuid: DEFAULT_ADMIN_UUID.to_string(),
             token: Some(ApiUserToken {
                 token,
                 timestamp: get_current_timestamp(),
                 expiration_timestamp: i64::MAX as u64,
+                scopes: Some(scope_model::full()),
             }),
+            keys: None,
             access: ApiUserAccess {
                 role: ApiUserRoleType::Admin,
                 permissions: None,
             },
         }
     }
-    pub fn create(uuid: String) -> Self {
+    pub fn create(uuid: String, role: ApiUserRoleType) -> Self {
         Self {
             timestamp: get_current_timestamp(),
             uuid,
-            ..Default::default()
+            token: None,
+            keys: None,
+            access: ApiUserAccess {
+                role,
+                permissions: None,
+            },
         }
     }
 
+    /// Whether `token`, as sent, is the stored one, compared in constant time.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::apiuser_model::*;
+    ///
+    /// let mut apiuser = ApiUser::create("uuid".to_string(), ApiUserRoleType::User);
+    /// let token = apiuser.new_token(60);
+    /// assert!(!apiuser.token_matches(&token.token));
+    ///
+    /// apiuser.token = Some(token.hashed());
+    /// assert!(apiuser.token_matches(&token.token));
+    /// assert!(!apiuser.token_matches("another token"));
+    /// ```
+    pub fn token_matches(&self, token: &str) -> bool {
+        match &self.token {
+            Some(stored) => constant_time_eq(stored.token.as_bytes(), hash_token(token).as_bytes()),
+            None => false,
+        }
+    }
+
+    /// A key with its value, only its hash is kept on the api user.
+    /// None when it would expire later than a BSON timestamp can tell.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::apiuser_model::*;
+    ///
+    /// let mut apiuser = ApiUser::create("uuid".to_string(), ApiUserRoleType::Dev);
+    ///
+    /// assert!(apiuser.new_key("ci".to_string(), Some(60), Vec::new()).is_some());
+    /// assert_eq!(apiuser.new_key("ci".to_string(), Some(u64::MAX), Vec::new()), None);
+    /// assert_eq!(apiuser.new_key("ci".to_string(), Some(i64::MAX as u64 / 1000), Vec::new()), None);
+    /// assert_eq!(apiuser.keys.map(|keys| keys.len()), Some(1));
+    /// ```
+    pub fn new_key(
+        &mut self,
+        name: String,
+        seconds: Option<u64>,
+        scopes: Vec<String>,
+    ) -> Option<ApiKey> {
+        let now = get_current_timestamp();
+        let expiration_timestamp = match seconds {
+            Some(seconds) => Some(
+                seconds
+                    .checked_mul(1000)
+                    .and_then(|milliseconds| now.checked_add(milliseconds))
+                    .filter(|expiration| *expiration <= i64::MAX as u64)?,
+            ),
+            None => None,
+        };
+        let key = ApiKey {
+            id: Uuid::new_v4().to_string(),
+            name,
+            key: generate_url_token_default(),
+            scopes,
+            timestamp: now,
+            expiration_timestamp,
+        };
+        self.keys.get_or_insert_with(Vec::new).push(key.hashed());
+        Some(key)
+    }
+
+    /// The unexpired key matching `key`, as sent, compared in constant time.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::apiuser_model::*;
+    /// use misato_utils::get_current_timestamp;
+    ///
+    /// let mut apiuser = ApiUser::create("uuid".to_string(), ApiUserRoleType::Dev);
+    /// let key = apiuser.new_key("ci".to_string(), Some(60), Vec::new()).unwrap();
+    /// let now = get_current_timestamp();
+    ///
+    /// assert_eq!(apiuser.key(&key.key, now).map(|found| &found.id), Some(&key.id));
+    /// assert_eq!(apiuser.key(&key.key, now + 61_000), None);
+    /// assert_eq!(apiuser.key("another key", now), None);
+    /// assert!(!apiuser.token_matches(&key.key));
+    /// ```
+    pub fn key(&self, key: &str, now: u64) -> Option<&ApiKey> {
+        let hash = hash_token(key);
+        self.keys.as_ref()?.iter().find(|stored| {
+            constant_time_eq(stored.key.as_bytes(), hash.as_bytes()) && !stored.is_expired(now)
+        })
+    }
+
+    pub fn has_role(&self, role: &ApiUserRoleType) -> bool {
+        &self.access.role == role
+    }
+
+    /// Roles are ordered from `User` to `Admin`.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::apiuser_model::*;
+    ///
+    /// let user = ApiUser::create("uuid".to_string(), ApiUserRoleType::Dev);
+    ///
+    /// assert!(user.has_role(&ApiUserRoleType::Dev));
+    /// assert!(user.has_at_least(&ApiUserRoleType::User));
+    /// assert!(user.has_at_least(&ApiUserRoleType::Dev));
+    /// assert!(!user.has_at_least(&ApiUserRoleType::Admin));
+    /// ```
+    pub fn has_at_least(&self, role: &ApiUserRoleType) -> bool {
+        self.access.role.rank() >= role.rank()
+    }
+
     pub fn new_token(&mut self, seconds: u64) -> ApiUserToken {
         let token = ApiUserToken {
-            token: generate_token(128),
+            token: generate_url_token_default(),
             timestamp: get_current_timestamp(),
             expiration_timestamp: get_current_timestamp() + (seconds * 1000),
+            scopes: Some(scope_model::full()),
+        };
+        self.token = Some(token.clone());
+        token
+    }
+
+    /// Replace the token by one that never expires, like the default admin one.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::apiuser_model::*;
+    ///
+    /// let mut user = ApiUser::create_default("token".to_string());
+    /// let token = user.new_permanent_token();
+    ///
+    /// assert!(token.token != "token");
+    /// assert_eq!(user.token, Some(token.clone()));
+    /// assert_eq!(token.expiration_timestamp, i64::MAX as u64);
+    /// ```
+    pub fn new_permanent_token(&mut self) -> ApiUserToken {
+        let token = ApiUserToken {
+            token: generate_url_token_default(),
+            timestamp: get_current_timestamp(),
+            expiration_timestamp: i64::MAX as u64,
+            scopes: Some(scope_model::full()),
         };
         self.token = Some(token.clone());
         token
@@ -61,27 +285,36 @@ pub struct ApiUserAccess {
     pub permissions: Option<Vec<ApiUserPermissionType>>,
 }
 
-#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone, Default)]
 pub enum ApiUserRoleType {
     Admin, // Only the main website has access
     Dev,   // Verified USER
-    User,  // New account
+    #[default]
+    User, // New account
+}
+
+impl ApiUserRoleType {
+    fn rank(&self) -> u8 {
+        match self {
+            ApiUserRoleType::User => 0,
+            ApiUserRoleType::Dev => 1,
+            ApiUserRoleType::Admin => 2,
+        }
+    }
 }
 
-impl Default for ApiUserRoleType {
-    fn default() -> Self {
-        ApiUserRoleType::User
+impl From<DefaultRole> for ApiUserRoleType {
+    fn from(role: DefaultRole) -> Self {
+        match role {
+            DefaultRole::User => ApiUserRoleType::User,
+            DefaultRole::Dev => ApiUserRoleType::Dev,
+        }
     }
 }
 
-#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone, Default)]
 pub enum ApiUserPermissionType {
     UserManager, // Create, Delete, Edit user informations
-    None,        // Default, no more access
-}
-
-impl Default for ApiUserPermissionType {
-    fn default() -> Self {
-        ApiUserPermissionType::None
-    }
+    #[default]
+    None, // Default, no more access
 }
@@ -0,0 +1,147 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use misato_utils::get_current_timestamp;
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub enum AuditAction {
+    Signup,
+    Login,
+    LoginFailed,
+    PasswordChange,
+    UsernameChange,
+    PasswordReset,
+    TokensCleared,
+    AccountDeleted,
+    AccountRestored,
+    AdminTokenRotated,
+    InviteCreated,
+    AllTokensRevoked,
+    MaintenanceToggled,
+    RefreshFamilyRevoked,
+}
+
+impl AuditAction {
+    /// Name of the webhook event, None for actions that are not sent.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::audit_model::AuditAction;
+    ///
+    /// assert_eq!(AuditAction::Signup.webhook_event(), Some("user.created"));
+    /// assert_eq!(AuditAction::Login.webhook_event(), None);
+    /// ```
+    pub fn webhook_event(&self) -> Option<&'static str> {
+        match self {
+            AuditAction::Signup => Some("user.created"),
+            AuditAction::AccountDeleted => Some("user.deleted"),
+            AuditAction::AccountRestored => Some("user.restored"),
+            AuditAction::LoginFailed => Some("login.failed"),
+            AuditAction::PasswordChange => Some("password.changed"),
+            AuditAction::UsernameChange => Some("user.renamed"),
+            AuditAction::PasswordReset => Some("password.reset"),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for AuditAction {
+    type Err = String;
+
+    /// The serialized names, whatever the case.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "signup" => Ok(AuditAction::Signup),
+            "login" => Ok(AuditAction::Login),
+            "loginfailed" => Ok(AuditAction::LoginFailed),
+            "passwordchange" => Ok(AuditAction::PasswordChange),
+            "usernamechange" => Ok(AuditAction::UsernameChange),
+            "passwordreset" => Ok(AuditAction::PasswordReset),
+            "tokenscleared" => Ok(AuditAction::TokensCleared),
+            "accountdeleted" => Ok(AuditAction::AccountDeleted),
+            "accountrestored" => Ok(AuditAction::AccountRestored),
+            "admintokenrotated" => Ok(AuditAction::AdminTokenRotated),
+            "invitecreated" => Ok(AuditAction::InviteCreated),
+            "alltokensrevoked" => Ok(AuditAction::AllTokensRevoked),
+            "maintenancetoggled" => Ok(AuditAction::MaintenanceToggled),
+            "refreshfamilyrevoked" => Ok(AuditAction::RefreshFamilyRevoked),
+            _ => Err(format!("[{}]: Unknown audit action.", value)),
+        }
+    }
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEvent {
+    pub timestamp: u64,
+    pub action: AuditAction,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor: Option<String>, // Uuid of whoever did it, user or api user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>, // Uuid or username it was done to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn create(action: AuditAction, actor: Option<String>, target: Option<String>) -> Self {
+        Self {
+            timestamp: get_current_timestamp(),
+            action,
+            actor,
+            target,
+            ip: None,
+            request_id: None,
+        }
+    }
+}
+
+/// Which events an audit listing keeps, every criterion is optional.
+#[derive(Eq, Hash, PartialEq, Debug, Default, Clone)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub from: Option<u64>, // In milliseconds, included
+    pub to: Option<u64>,   // In milliseconds, included
+}
+
+impl AuditFilter {
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::audit_model::{AuditAction, AuditFilter};
+    ///
+    /// let filter = AuditFilter::new(None, Some("loginFailed"), Some(1000), Some(2000)).unwrap();
+    /// assert_eq!(filter.action, Some(AuditAction::LoginFailed));
+    /// assert!(AuditFilter::new(None, None, Some(1000), Some(1000)).is_ok());
+    /// assert!(AuditFilter::new(None, None, Some(2000), Some(1000)).is_err());
+    /// assert!(AuditFilter::new(None, Some("Lunch"), None, None).is_err());
+    /// ```
+    pub fn new(
+        actor: Option<String>,
+        action: Option<&str>,
+        from: Option<u64>,
+        to: Option<u64>,
+    ) -> Result<Self, String> {
+        if let (Some(from), Some(to)) = (from, to) {
+            if from > to {
+                return Err(format!(
+                    "[{}-{}]: Time window ends before it starts.",
+                    from, to
+                ));
+            }
+        }
+        let action = match action {
+            Some(action) => Some(action.parse()?),
+            None => None,
+        };
+        Ok(Self {
+            actor,
+            action,
+            from,
+            to,
+        })
+    }
+}
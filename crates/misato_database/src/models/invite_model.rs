@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::user_model::UserHashedToken;
+
+/// Single-use token letting someone sign up while registration is closed.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Invite {
+    pub token: UserHashedToken,
+    pub created_by: String, // Uuid of the api user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used_by: Option<String>, // Uuid of the account it was spent on
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used_at: Option<u64>,
+}
+
+impl Invite {
+    /// The raw token along with what is stored.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::invite_model::Invite;
+    /// use misato_security::hash_token;
+    ///
+    /// let (token, invite) = Invite::create("admin".to_string(), 60);
+    ///
+    /// assert_eq!(invite.token.hash, hash_token(&token));
+    /// assert_eq!(invite.used_by, None);
+    /// ```
+    pub fn create(created_by: String, seconds: u64) -> (String, Self) {
+        let (token, hashed) = UserHashedToken::generate(seconds);
+        let invite = Self {
+            token: hashed,
+            created_by,
+            used_by: None,
+            used_at: None,
+        };
+        (token, invite)
+    }
+}
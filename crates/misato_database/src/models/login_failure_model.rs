@@ -0,0 +1,43 @@
+use mongodb::bson::DateTime;
+use serde::{Deserialize, Serialize};
+
+use misato_security::hash_token;
+
+use crate::models::user_model::{canonical_email, canonical_username};
+
+/// Failed logins of an identifier matching no account, counted like those of an account so
+/// guessing one looks the same as guessing a password.
+#[derive(PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct LoginFailures {
+    pub key: String, // See `identifier_key`
+    #[serde(default)]
+    pub failed_logins: u32,
+    #[serde(default)]
+    pub locked_until: u64, // In milliseconds
+    pub expires_at: DateTime,
+}
+
+/// Where a failed login left the count.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct FailedLogin {
+    pub failed_logins: u32,      // Back to 0 by the failure locking
+    pub locked_for: Option<u64>, // Seconds left, when locked now or already
+}
+
+/// What identifies an unknown identifier, hashed as it may be a password typed in the wrong field.
+/// Basic usage:
+///
+/// ```
+/// use misato_database::models::login_failure_model::identifier_key;
+///
+/// assert_eq!(identifier_key("Misato"), identifier_key("misato"));
+/// assert_eq!(identifier_key(" Misato@Misato.wiki"), identifier_key("misato@misato.wiki"));
+/// assert!(!identifier_key("misato").contains("misato"));
+/// ```
+pub fn identifier_key(identifier: &str) -> String {
+    let canonical = match identifier.contains('@') {
+        true => canonical_email(identifier),
+        false => canonical_username(identifier),
+    };
+    hash_token(&canonical)
+}
@@ -1,3 +1,9 @@
 pub mod apiuser_model;
+pub mod audit_model;
 pub mod data_model;
+pub mod invite_model;
+pub mod login_failure_model;
+pub mod request_model;
+pub mod response_model;
+pub mod scope_model;
 pub mod user_model;
@@ -0,0 +1,139 @@
+use serde::{Deserialize, Serialize};
+
+use misato_utils::validation::{Field, FieldKind, Schema};
+
+use crate::models::{apiuser_model::ApiUserRoleType, user_model::UserRoleType};
+
+/// `identifier` is a username or an email, `username` is still accepted for it.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Login {
+    #[serde(alias = "username")]
+    pub identifier: String,
+    pub password: String,
+    #[serde(default)]
+    pub totp: Option<String>, // Required once the user enabled a second factor
+}
+
+impl Schema for Login {
+    const FIELDS: &'static [Field] = &[
+        Field::required("identifier", FieldKind::String).alias(&["username"]),
+        Field::required("password", FieldKind::String),
+        Field::optional("totp", FieldKind::String),
+    ];
+}
+
+/// A login with a recovery code instead of a code of the authenticator app.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct RecoveryLogin {
+    #[serde(alias = "username")]
+    pub identifier: String,
+    pub password: String,
+    pub recovery_code: String,
+}
+
+impl Schema for RecoveryLogin {
+    const FIELDS: &'static [Field] = &[
+        Field::required("identifier", FieldKind::String).alias(&["username"]),
+        Field::required("password", FieldKind::String),
+        Field::required("recovery_code", FieldKind::String),
+    ];
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Signup {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub email: Option<String>, // Unverified until the user asks for a verification
+}
+
+impl Schema for Signup {
+    const FIELDS: &'static [Field] = &[
+        Field::required("username", FieldKind::String),
+        Field::required("password", FieldKind::String),
+        Field::optional("email", FieldKind::String),
+    ];
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct PasswordChange {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct UsernameChange {
+    pub username: String,
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ResetRequest {
+    pub username: String,
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ResetConfirm {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct VerifyRequest {
+    pub email: String,
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ApiSignup {
+    pub uuid: String,
+    #[serde(default)]
+    pub role: ApiUserRoleType,
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ApiRoleChange {
+    pub uuid: String,
+    pub role: ApiUserRoleType,
+}
+
+/// Every token of every user when both are None.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct RevokeAllTokens {
+    #[serde(default)]
+    pub role: Option<UserRoleType>, // Only the users of this role
+    #[serde(default)]
+    pub before: Option<u64>, // In milliseconds, only the tokens issued before
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ApiKeyCreate {
+    pub name: String,
+    #[serde(default)]
+    pub expires_in: Option<u64>, // In seconds, never expires when None
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+/// The previous `Retry-After` is kept when `retry_after` is None.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct MaintenanceToggle {
+    pub enabled: bool,
+    #[serde(default)]
+    pub retry_after: Option<u64>, // In seconds
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct BatchUser {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub role: UserRoleType,
+}
+
+/// A code of the authenticator app.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct TotpCode {
+    pub code: String,
+}
@@ -0,0 +1,603 @@
+use serde::{Deserialize, Serialize};
+
+use misato_utils::{
+    settings::Settings,
+    validation::{USERNAME_MAX_LENGTH, USERNAME_MIN_LENGTH},
+};
+
+use crate::models::{
+    apiuser_model::{ApiKey, ApiUserRoleType},
+    audit_model::AuditEvent,
+    scope_model,
+    user_model::{User, UserRefreshToken, UserRoleType, UserToken},
+};
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct LoginResponse {
+    pub token: String,
+    pub timestamp: u64,
+    pub expiration_timestamp: u64,
+    pub uuid: String,
+    pub refresh_token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub page: u64, // Starts at 1
+    pub limit: u64,
+    pub total: u64,
+    pub has_next: bool,
+}
+
+/// A page listed after a cursor, `next_cursor` is sent back to get the following one.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct CursorPaginated<T> {
+    pub items: Vec<T>,
+    pub limit: u64,
+    pub next_cursor: Option<String>, // None on the last page
+}
+
+impl<T> CursorPaginated<T> {
+    /// `items` are the next `limit + 1` items at most, each with its cursor.
+    /// The extra one only tells whether there is a next page, it isn't returned.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::response_model::CursorPaginated;
+    ///
+    /// let items = vec![("1".to_string(), "asuka"), ("2".to_string(), "misato"), ("3".to_string(), "shinji")];
+    /// let first = CursorPaginated::new(items.clone(), 2);
+    /// assert_eq!(first.items, vec!["asuka", "misato"]);
+    /// assert_eq!(first.next_cursor, Some("2".to_string()));
+    ///
+    /// let last = CursorPaginated::new(items, 3);
+    /// assert_eq!(last.items.len(), 3);
+    /// assert_eq!(last.next_cursor, None);
+    /// ```
+    pub fn new(mut items: Vec<(String, T)>, limit: u64) -> Self {
+        let mut next_cursor = None;
+        if items.len() as u64 > limit {
+            items.truncate(limit as usize);
+            next_cursor = items.last().map(|(cursor, _)| cursor.to_string());
+        }
+        Self {
+            items: items.into_iter().map(|(_, item)| item).collect(),
+            limit,
+            next_cursor,
+        }
+    }
+}
+
+/// Either kind of page, for routes that list both ways.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Page<T> {
+    Offset(Paginated<T>),
+    Cursor(CursorPaginated<T>),
+}
+
+/// Page and limit of a list request, clamped to sane values.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub struct Pagination {
+    pub page: u64,
+    pub limit: u64,
+}
+
+impl Pagination {
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::response_model::Pagination;
+    ///
+    /// assert_eq!(Pagination::new(None, None, 20, 100), Pagination { page: 1, limit: 20 });
+    /// assert_eq!(Pagination::new(Some(0), Some(0), 20, 100), Pagination { page: 1, limit: 1 });
+    /// assert_eq!(Pagination::new(Some(3), Some(1000), 20, 100), Pagination { page: 3, limit: 100 });
+    /// assert_eq!(Pagination::new(Some(3), Some(10), 20, 100).skip(), 20);
+    /// ```
+    pub fn new(page: Option<u64>, limit: Option<u64>, default_limit: u64, max_limit: u64) -> Self {
+        Self {
+            page: page.unwrap_or(1).max(1),
+            limit: limit.unwrap_or(default_limit).clamp(1, max_limit),
+        }
+    }
+
+    pub fn skip(&self) -> u64 {
+        (self.page - 1).saturating_mul(self.limit)
+    }
+
+    /// `total` counts every item, not only the ones of this page.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::response_model::Pagination;
+    ///
+    /// let empty = Pagination::new(None, Some(10), 20, 100).paginate(Vec::<u64>::new(), 0);
+    /// assert!(!empty.has_next);
+    ///
+    /// let first = Pagination::new(Some(1), Some(10), 20, 100).paginate(vec![0; 10], 20);
+    /// assert!(first.has_next);
+    ///
+    /// let last = Pagination::new(Some(2), Some(10), 20, 100).paginate(vec![0; 10], 20);
+    /// assert!(!last.has_next);
+    ///
+    /// let partial = Pagination::new(Some(3), Some(10), 20, 100).paginate(vec![0; 1], 21);
+    /// assert_eq!((partial.page, partial.has_next), (3, false));
+    /// ```
+    pub fn paginate<T>(&self, items: Vec<T>, total: u64) -> Paginated<T> {
+        Paginated {
+            has_next: self.skip() + (items.len() as u64) < total,
+            items,
+            page: self.page,
+            limit: self.limit,
+            total,
+        }
+    }
+}
+
+/// The admin behind a token, only answered when that token is valid.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct AdminIdentity {
+    pub uuid: String, // Api users have no username, `admin` for the default one
+    pub role: ApiUserRoleType,
+    pub token_valid: bool,
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct HashedTokenResponse {
+    pub token: String,
+    pub expiration_timestamp: u64,
+}
+
+/// When a token was issued and expires, so clients know when to refresh, never the token itself.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct TokenInfo {
+    pub username: String,
+    pub issued_at: u64,     // In milliseconds
+    pub expires_at: u64,    // In milliseconds
+    pub remaining_ttl: u64, // In seconds
+}
+
+impl TokenInfo {
+    /// None once the token is expired.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::{response_model::TokenInfo, user_model::UserToken};
+    ///
+    /// let token = UserToken {
+    ///     token: "token".to_string(),
+    ///     timestamp: 1000,
+    ///     expiration_timestamp: 61000,
+    ///     ..UserToken::default()
+    /// };
+    /// let info = TokenInfo::new("username", &token, 31000).unwrap();
+    /// let json = serde_json::to_string(&info).unwrap();
+    ///
+    /// assert_eq!(info.expires_at, token.expiration_timestamp);
+    /// assert_eq!(info.remaining_ttl, 30);
+    /// assert!(!json.contains("\"token\""));
+    /// assert_eq!(TokenInfo::new("username", &token, 61001), None);
+    /// ```
+    pub fn new(username: &str, token: &UserToken, now: u64) -> Option<Self> {
+        if token.is_expired(now) {
+            return None;
+        }
+        Some(Self {
+            username: username.to_string(),
+            issued_at: token.timestamp,
+            expires_at: token.expiration_timestamp,
+            remaining_ttl: (token.expiration_timestamp - now) / 1000,
+        })
+    }
+}
+
+/// A named key as listed to its owner, `key` is only there once, when it is created.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub scopes: Vec<String>,
+    pub created_at: u64, // In milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>, // In milliseconds
+}
+
+impl From<&ApiKey> for ApiKeyInfo {
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::{apiuser_model::*, response_model::ApiKeyInfo};
+    ///
+    /// let mut apiuser = ApiUser::create("uuid".to_string(), ApiUserRoleType::Dev);
+    /// let key = apiuser.new_key("ci".to_string(), None, Vec::new()).unwrap();
+    /// let listed: Vec<ApiKeyInfo> = apiuser.keys.as_ref().unwrap().iter().map(ApiKeyInfo::from).collect();
+    /// let json = serde_json::to_string(&listed).unwrap();
+    ///
+    /// assert_eq!(listed[0].id, key.id);
+    /// assert!(!json.contains("\"key\""));
+    /// assert!(!json.contains(&key.key));
+    /// ```
+    fn from(key: &ApiKey) -> Self {
+        Self {
+            id: key.id.clone(),
+            name: key.name.clone(),
+            key: None,
+            scopes: key.scopes.clone(),
+            created_at: key.timestamp,
+            expires_at: key.expiration_timestamp,
+        }
+    }
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct PasswordRequirements {
+    pub min_length: usize, // In characters
+    pub max_length: usize, // In bytes
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct UsernameRequirements {
+    pub min_length: usize, // In characters
+    pub max_length: usize,
+}
+
+/// Settings clients can check against or show, never a secret.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ServerMeta {
+    pub registration_open: bool, // Else an invite is required
+    pub totp_available: bool,
+    pub access_tokens: bool, // Whether login also answers a JWT
+    pub password: PasswordRequirements,
+    pub username: UsernameRequirements,
+    pub token_ttl: u64,         // In seconds
+    pub refresh_token_ttl: u64, // In seconds
+    pub scopes: Vec<String>,
+}
+
+impl ServerMeta {
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::response_model::ServerMeta;
+    /// use misato_utils::{config::Config, settings::Settings};
+    ///
+    /// let config = Config::from_toml(
+    ///     "MONGODB_URI = \"mongodb://secret@localhost\"\nMONGODB_NAME = \"misato\"\n\
+    ///      MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"\nMISATO_PASSWORD_MIN_LENGTH = 12",
+    /// )
+    /// .unwrap();
+    /// let meta = ServerMeta::from_settings(&Settings::from_config(&config).unwrap());
+    /// let json = serde_json::to_string(&meta).unwrap();
+    ///
+    /// assert_eq!(meta.password.min_length, 12);
+    /// assert!(!meta.totp_available);
+    /// assert!(!json.contains("Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe"));
+    /// assert!(!json.contains("secret"));
+    /// ```
+    pub fn from_settings(settings: &Settings) -> Self {
+        let security = &settings.security;
+        let policy = &security.password_policy;
+        Self {
+            registration_open: security.registration_open,
+            totp_available: security.totp_key.is_some(),
+            access_tokens: security.jwt_secret.is_some(),
+            password: PasswordRequirements {
+                min_length: policy.min_length,
+                max_length: policy.max_length,
+                require_lowercase: policy.require_lowercase,
+                require_uppercase: policy.require_uppercase,
+                require_digit: policy.require_digit,
+                require_symbol: policy.require_symbol,
+            },
+            username: UsernameRequirements {
+                min_length: USERNAME_MIN_LENGTH,
+                max_length: USERNAME_MAX_LENGTH,
+            },
+            token_ttl: security.token_ttl,
+            refresh_token_ttl: security.refresh_token_ttl,
+            scopes: scope_model::full(),
+        }
+    }
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct MaintenanceStatus {
+    pub enabled: bool,
+    pub retry_after: u64, // In seconds
+}
+
+/// A token as listed to its owner, without its value.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Session {
+    pub id: Option<String>, // None for tokens issued before sessions, they can't be revoked alone
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: u64, // In milliseconds
+    pub expires_at: u64, // In milliseconds
+}
+
+impl From<&UserToken> for Session {
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::{response_model::Session, user_model::User};
+    ///
+    /// let mut user = User::default();
+    /// let desktop = user.new_session_token(60, None, None, Some("Firefox".to_string()));
+    /// let phone = user.new_session_token(60, None, None, Some("Safari".to_string()));
+    /// let sessions: Vec<Session> = user.tokens.as_ref().unwrap().iter().map(Session::from).collect();
+    ///
+    /// assert_eq!(sessions.len(), 2);
+    /// assert_eq!(sessions[0].id, desktop.id);
+    /// assert_eq!(sessions[1].user_agent, Some("Safari".to_string()));
+    /// assert!(sessions[0].id != sessions[1].id);
+    /// assert!(!serde_json::to_string(&sessions).unwrap().contains(&phone.token));
+    /// ```
+    fn from(token: &UserToken) -> Self {
+        Self {
+            id: token.id.clone(),
+            ip: token.ip.clone(),
+            user_agent: token.user_agent.clone(),
+            created_at: token.timestamp,
+            expires_at: token.expiration_timestamp,
+        }
+    }
+}
+
+/// The refresh tokens rotated from one login, without their values.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct RefreshFamily {
+    pub id: String,
+    pub created_at: u64,              // In milliseconds
+    pub last_rotated_at: Option<u64>, // In milliseconds, None until first rotated
+    pub expires_at: u64,              // In milliseconds, of its newest token
+    pub rotations: usize,
+    pub ip: Option<String>, // Of its newest token
+    pub user_agent: Option<String>,
+}
+
+impl RefreshFamily {
+    /// One entry per family, the oldest first.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::{response_model::RefreshFamily, user_model::User};
+    ///
+    /// let mut user = User::default();
+    /// let laptop = user.new_refresh_token(60, None, None, Some("Firefox".to_string()));
+    /// let phone = user.new_refresh_token(60, None, None, None);
+    /// let rotated = user.new_refresh_token(
+    ///     60,
+    ///     Some(laptop.family.clone()),
+    ///     None,
+    ///     Some("Firefox 2".to_string()),
+    /// );
+    /// let families = RefreshFamily::list(user.refresh_tokens.as_ref().unwrap());
+    ///
+    /// assert_eq!(families.len(), 2);
+    /// assert_eq!(families[0].id, laptop.family);
+    /// assert_eq!(families[0].rotations, 1);
+    /// assert_eq!(families[0].last_rotated_at, Some(rotated.timestamp));
+    /// assert_eq!(families[0].user_agent, Some("Firefox 2".to_string()));
+    /// assert_eq!(families[1].id, phone.family);
+    /// assert_eq!(families[1].last_rotated_at, None);
+    /// assert!(!serde_json::to_string(&families).unwrap().contains(&phone.token));
+    /// ```
+    pub fn list(tokens: &[UserRefreshToken]) -> Vec<Self> {
+        let mut families: Vec<Self> = Vec::new();
+        for token in tokens {
+            match families.iter_mut().find(|family| family.id == token.family) {
+                Some(family) => {
+                    family.rotations += 1;
+                    family.last_rotated_at = Some(token.timestamp);
+                    family.expires_at = token.expiration_timestamp;
+                    family.ip = token.ip.clone();
+                    family.user_agent = token.user_agent.clone();
+                }
+                None => families.push(Self {
+                    id: token.family.clone(),
+                    created_at: token.timestamp,
+                    last_rotated_at: None,
+                    expires_at: token.expiration_timestamp,
+                    rotations: 0,
+                    ip: token.ip.clone(),
+                    user_agent: token.user_agent.clone(),
+                }),
+            }
+        }
+        families
+    }
+}
+
+/// Shown once, to be typed or scanned in the authenticator app.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct TotpEnrollment {
+    pub secret: String, // Base32
+    pub uri: String,    // `otpauth://`, for a QR code
+    pub recovery_codes: Vec<String>,
+}
+
+/// Problem of each field of a signup, if any, nothing is created.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct SignupValidation {
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+/// Outcome of one user of a batch, `error` is the code an `ApiError` would answer with.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct BatchItem {
+    pub username: String,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uuid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// What a user may see of its own account, never the password or the tokens.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct PublicUser {
+    pub uuid: String,
+    pub username: String,
+    pub role: UserRoleType,
+    pub created_at: u64, // In milliseconds
+    pub email_verified: bool,
+    pub last_login_at: Option<u64>, // In milliseconds
+    pub last_login_ip: Option<String>,
+}
+
+impl From<&User> for PublicUser {
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::{response_model::PublicUser, user_model::User};
+    /// use misato_security::password::Password;
+    ///
+    /// let password = Password::hash_password(b"password").unwrap();
+    /// let mut user = User::create("username".to_string(), password.clone(), None);
+    /// user.new_token(60);
+    /// let json = serde_json::to_string(&PublicUser::from(&user)).unwrap();
+    ///
+    /// assert!(json.contains("username"));
+    /// assert!(!json.contains("password"));
+    /// assert!(!json.contains("token"));
+    /// assert!(!json.contains(&serde_json::to_string(&password.hash).unwrap()));
+    /// assert!(!json.contains(&serde_json::to_string(&password.salt).unwrap()));
+    /// ```
+    fn from(user: &User) -> Self {
+        Self {
+            uuid: user.uuid.clone(),
+            username: user.username.clone(),
+            role: user.access.role.clone(),
+            created_at: user.timestamp,
+            email_verified: user.email_verified,
+            last_login_at: user.last_login_at,
+            last_login_ip: user.last_login_ip.clone(),
+        }
+    }
+}
+
+/// What support may see of an account, `PublicUser` and its state, never a secret.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct UserDetails {
+    #[serde(flatten)]
+    pub user: PublicUser,
+    pub email: Option<String>,
+    pub locked: bool,
+    pub locked_until: Option<u64>, // In milliseconds, only while locked
+    pub failed_logins: u32,
+    pub token_count: usize, // Unexpired tokens only
+    pub totp_enabled: bool,
+}
+
+impl UserDetails {
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::{response_model::UserDetails, user_model::User};
+    /// use misato_security::password::Password;
+    /// use misato_utils::get_current_timestamp;
+    ///
+    /// let password = Password::hash_password(b"password").unwrap();
+    /// let mut user = User::create("username".to_string(), password.clone(), None);
+    /// let token = user.new_token(60);
+    /// user.new_token(0);
+    /// let now = get_current_timestamp();
+    /// user.locked_until = now + 60000;
+    /// let details = UserDetails::from_user(&user, now);
+    ///
+    /// assert_eq!(details.user.username, "username");
+    /// assert_eq!((details.locked, details.locked_until), (true, Some(now + 60000)));
+    /// assert_eq!(details.token_count, 1);
+    /// assert!(!UserDetails::from_user(&user, now + 60000).locked);
+    ///
+    /// let json = serde_json::to_string(&details).unwrap();
+    /// assert!(json.contains("\"username\":\"username\""));
+    /// assert!(!json.contains("password"));
+    /// assert!(!json.contains(&token.token));
+    /// assert!(!json.contains(&serde_json::to_string(&password.hash).unwrap()));
+    /// ```
+    pub fn from_user(user: &User, now: u64) -> Self {
+        let locked = user.lock_remaining(now).is_some();
+        Self {
+            user: PublicUser::from(user),
+            email: user.email.clone(),
+            locked,
+            locked_until: match locked {
+                true => Some(user.locked_until),
+                false => None,
+            },
+            failed_logins: user.failed_logins,
+            token_count: user
+                .tokens
+                .iter()
+                .flatten()
+                .filter(|token| token.expiration_timestamp > now)
+                .count(),
+            totp_enabled: user.has_totp(),
+        }
+    }
+}
+
+/// Everything stored about a user, for data subject requests, still without a secret.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct UserExport {
+    pub exported_at: u64, // In milliseconds
+    pub profile: UserDetails,
+    pub sessions: Vec<Session>,
+    pub audit: Vec<AuditEvent>, // Oldest first
+}
+
+impl UserExport {
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::{
+    ///     audit_model::{AuditAction, AuditEvent},
+    ///     response_model::UserExport,
+    ///     user_model::User,
+    /// };
+    /// use misato_security::password::Password;
+    /// use misato_utils::get_current_timestamp;
+    ///
+    /// let password = Password::hash_password(b"password").unwrap();
+    /// let mut user = User::create("username".to_string(), password.clone(), None);
+    /// let token = user.new_session_token(60, None, None, Some("Firefox".to_string()));
+    /// let login = AuditEvent::create(AuditAction::Login, Some(user.uuid.clone()), None);
+    /// let export = UserExport::new(&user, vec![login], get_current_timestamp());
+    ///
+    /// assert_eq!(export.profile.user.username, "username");
+    /// assert_eq!(export.sessions[0].user_agent, Some("Firefox".to_string()));
+    /// assert_eq!(export.audit[0].action, AuditAction::Login);
+    ///
+    /// let json = serde_json::to_string(&export).unwrap();
+    /// assert!(!json.contains("password"));
+    /// assert!(!json.contains(&token.token));
+    /// assert!(!json.contains(&serde_json::to_string(&password.hash).unwrap()));
+    /// ```
+    pub fn new(user: &User, audit: Vec<AuditEvent>, now: u64) -> Self {
+        Self {
+            exported_at: now,
+            profile: UserDetails::from_user(user, now),
+            sessions: user.tokens.iter().flatten().map(Session::from).collect(),
+            audit,
+        }
+    }
+}
@@ -0,0 +1,43 @@
+pub const ACCOUNT_READ: &str = "account:read";
+pub const ACCOUNT_WRITE: &str = "account:write";
+pub const ACCOUNT_DELETE: &str = "account:delete";
+
+/// Every scope, what login and api tokens are issued with.
+pub const FULL: &[&str] = &[ACCOUNT_READ, ACCOUNT_WRITE, ACCOUNT_DELETE];
+
+pub fn full() -> Vec<String> {
+    FULL.iter().map(|scope| scope.to_string()).collect()
+}
+
+/// `None` for tokens issued before scopes, they keep every one.
+/// Basic usage:
+///
+/// ```
+/// use misato_database::models::scope_model::*;
+///
+/// assert!(allows(None, ACCOUNT_DELETE));
+/// assert!(allows(Some(&full()), ACCOUNT_DELETE));
+/// assert!(!allows(Some(&[ACCOUNT_READ.to_string()]), ACCOUNT_DELETE));
+/// assert!(!allows(Some(&[]), ACCOUNT_READ));
+/// ```
+pub fn allows(scopes: Option<&[String]>, scope: &str) -> bool {
+    match scopes {
+        Some(scopes) => scopes.iter().any(|allowed| allowed == scope),
+        None => true,
+    }
+}
+
+/// Basic usage:
+///
+/// ```
+/// use misato_database::models::scope_model::*;
+///
+/// assert_eq!(validate(&full()), Ok(()));
+/// assert!(validate(&["account:admin".to_string()]).is_err());
+/// ```
+pub fn validate(scopes: &[String]) -> Result<(), String> {
+    match scopes.iter().find(|scope| !FULL.contains(&scope.as_str())) {
+        Some(unknown) => Err(format!("[{}]: Unknown scope.", unknown)),
+        None => Ok(()),
+    }
+}
@@ -1,9 +1,70 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use misato_security::{generate_token, password::*};
+use misato_security::{
+    generate_token, generate_url_token_default, hash_token, is_token_hash, password::*,
+    sign_payload,
+};
 use misato_utils::get_current_timestamp;
 
+use crate::models::scope_model;
+
+/// Usernames are compared in this form, the given one is kept for display.
+/// Unicode lowercase rather than full case folding: "Straße" and "STRASSE" stay different.
+/// Basic usage:
+///
+/// ```
+/// use misato_database::models::user_model::*;
+///
+/// assert_eq!(canonical_username("Foo"), canonical_username("foo"));
+/// assert_eq!(canonical_username("ÉCOLE"), "école");
+/// ```
+pub fn canonical_username(username: &str) -> String {
+    username.to_lowercase()
+}
+
+/// What is left of the uuid of an anonymized account, its audit events still share it.
+/// Keyed with `MISATO_ANONYMIZATION_KEY`, so it can't be recomputed from a known uuid.
+/// Basic usage:
+///
+/// ```
+/// use misato_database::models::user_model::*;
+///
+/// let id = anonymous_id("key", "c0ffee00-0000-4000-8000-000000000000");
+/// assert_eq!(id, anonymous_id("key", "c0ffee00-0000-4000-8000-000000000000"));
+/// assert!(id != anonymous_id("another key", "c0ffee00-0000-4000-8000-000000000000"));
+/// assert!(!id.contains("c0ffee"));
+/// assert_eq!(anonymous_username(&id), format!("deleted-{}", &id[..16]));
+/// ```
+pub fn anonymous_id(key: &str, uuid: &str) -> String {
+    sign_payload(key.as_bytes(), uuid.as_bytes())
+}
+
+/// Tombstone username of an anonymized account, unique as its id is.
+pub fn anonymous_username(id: &str) -> String {
+    format!("deleted-{}", &id[..16.min(id.len())])
+}
+
+/// Emails are unique and looked up in this form, the given one is kept for display.
+/// Basic usage:
+///
+/// ```
+/// use misato_database::models::user_model::*;
+///
+/// assert_eq!(canonical_email(" Misato@Misato.Wiki"), "misato@misato.wiki");
+/// ```
+pub fn canonical_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Tokens are only stored hashed, an already hashed token is kept as is.
+pub fn stored_token(token: &str) -> String {
+    match is_token_hash(token) {
+        true => token.to_string(),
+        false => hash_token(token),
+    }
+}
+
 #[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
 pub struct UserLog {
     pub ip: String,
@@ -13,21 +74,226 @@ pub struct UserLog {
 #[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
 pub struct UserToken {
     pub token: String,
+    pub timestamp: u64,            // Creation, in milliseconds
+    pub expiration_timestamp: u64, // Expiration, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>, // Session id, tokens issued before sessions have none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scopes: Option<Vec<String>>, // None for tokens issued before scopes, they have every one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub family: Option<String>, // Of the refresh token issued along, revoked with the session
+}
+
+impl UserToken {
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::{scope_model::*, user_model::*};
+    ///
+    /// let mut user = User::default();
+    /// let mut token = user.new_token(60);
+    /// assert!(token.allows(ACCOUNT_DELETE));
+    ///
+    /// token.scopes = Some(vec![ACCOUNT_READ.to_string()]);
+    /// assert!(token.allows(ACCOUNT_READ));
+    /// assert!(!token.allows(ACCOUNT_DELETE));
+    /// ```
+    pub fn allows(&self, scope: &str) -> bool {
+        scope_model::allows(self.scopes.as_deref(), scope)
+    }
+
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::user_model::*;
+    ///
+    /// let token = UserToken {
+    ///     token: "token".to_string(),
+    ///     timestamp: 0,
+    ///     expiration_timestamp: 1000,
+    ///     ..UserToken::default()
+    /// };
+    /// assert!(!token.is_expired(999));
+    /// assert!(token.is_expired(1001));
+    /// ```
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expiration_timestamp < now
+    }
+
+    /// The token as stored, its value replaced by its hash.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::user_model::*;
+    /// use misato_security::hash_token;
+    ///
+    /// let token = User::default().new_token(60);
+    /// let stored = token.hashed();
+    ///
+    /// assert!(stored.token != token.token);
+    /// assert_eq!(stored.token, hash_token(&token.token));
+    /// assert_eq!(stored.hashed(), stored);
+    /// ```
+    pub fn hashed(&self) -> Self {
+        Self {
+            token: stored_token(&self.token),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct UserRefreshToken {
+    pub token: String,
+    pub family: String, // Shared by every token rotated from the same login
+    pub timestamp: u64,
+    pub expiration_timestamp: u64,
+    pub used: bool, // Rotated tokens are kept to detect their reuse
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+
+impl UserRefreshToken {
+    /// The token as stored, its value replaced by its hash.
+    pub fn hashed(&self) -> Self {
+        Self {
+            token: stored_token(&self.token),
+            ..self.clone()
+        }
+    }
+}
+
+pub const RECOVERY_CODES_COUNT: usize = 10;
+pub const RECOVERY_CODE_LENGTH: usize = 12;
+
+/// Second factor of a user, see `misato_security::totp`.
+#[derive(Eq, Hash, PartialEq, Serialize, Deserialize, Default, Clone)]
+pub struct UserTotp {
+    pub secret: Vec<u8>, // Encrypted with `MISATO_TOTP_KEY`
+    pub enabled: bool,   // Once a first code has been verified, login asks for codes from then on
+    pub timestamp: u64,
+    #[serde(default)]
+    pub recovery_codes: Vec<UserRecoveryCode>, // Removed once used
+    #[serde(default)]
+    pub last_step: Option<u64>, // Of the last accepted code, which can't be used again
+}
+
+/// Stored as its `hash_token` digest like the session tokens, random enough not to need a
+/// password hash. The code itself is only shown when generated.
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct UserRecoveryCode {
+    pub id: String,
+    pub hash: String,
+}
+
+impl UserRecoveryCode {
+    /// The raw codes along with what is stored.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::user_model::UserRecoveryCode;
+    /// use misato_security::hash_token;
+    ///
+    /// let (codes, stored) = UserRecoveryCode::generate(3);
+    ///
+    /// assert_eq!((codes.len(), stored.len()), (3, 3));
+    /// assert_eq!(stored[0].hash, hash_token(&codes[0]));
+    /// assert!(stored[0].hash != hash_token(&codes[1]));
+    /// assert!(stored[0].id != stored[1].id);
+    /// ```
+    pub fn generate(count: usize) -> (Vec<String>, Vec<Self>) {
+        (0..count)
+            .map(|_| {
+                let code = generate_token(RECOVERY_CODE_LENGTH);
+                let stored = Self {
+                    id: Uuid::new_v4().to_string(),
+                    hash: hash_token(&code),
+                };
+                (code, stored)
+            })
+            .unzip()
+    }
+}
+
+/// Keep the secret out of the logs, even encrypted.
+impl std::fmt::Debug for UserTotp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UserTotp")
+            .field("secret", &"[redacted]")
+            .field("enabled", &self.enabled)
+            .field("timestamp", &self.timestamp)
+            .field("recovery_codes", &self.recovery_codes.len())
+            .field("last_step", &self.last_step)
+            .finish()
+    }
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
+pub struct UserHashedToken {
+    pub hash: String, // Only the hash is stored, see `hash_token`
     pub timestamp: u64,
     pub expiration_timestamp: u64,
 }
 
+impl UserHashedToken {
+    /// The raw token along with what is stored.
+    pub fn generate(seconds: u64) -> (String, Self) {
+        let token = generate_url_token_default();
+        let hashed = Self {
+            hash: hash_token(&token),
+            timestamp: get_current_timestamp(),
+            expiration_timestamp: get_current_timestamp() + (seconds * 1000),
+        };
+        (token, hashed)
+    }
+}
+
+/// Storage model, routes respond with `response_model` types instead.
 #[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
 pub struct User {
     pub timestamp: u64,
     pub uuid: String,
     pub username: String,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub username_key: Option<String>, // See `canonical_username`, missing on older accounts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_key: Option<String>, // See `canonical_email`, missing on older accounts
+    #[serde(default)]
+    pub email_verified: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub logs: Option<Vec<UserLog>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub password: Option<Password>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tokens: Option<Vec<UserToken>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_tokens: Option<Vec<UserRefreshToken>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reset_token: Option<UserHashedToken>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification_token: Option<UserHashedToken>,
+    #[serde(default)]
+    pub failed_logins: u32, // Consecutive, reset on success and when locking
+    #[serde(default)]
+    pub locked_until: u64, // In milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_login_at: Option<u64>, // In milliseconds, of the last successful login
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_login_ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<u64>, // In milliseconds, soft deleted accounts keep their username
+    #[serde(default)]
+    pub anonymized: bool, // Deleted and scrubbed, see `anonymous_id`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub totp: Option<UserTotp>,
     pub access: UserAccess,
 }
 
@@ -41,27 +307,209 @@ impl User {
                     Some(uuid) => uuid.to_string(),
                 }
             },
+            username_key: Some(canonical_username(&username)),
             username,
             password: Some(password),
             ..Default::default()
         }
     }
 
+    /// Whether login asks for a code, an enrollment that was never verified doesn't count.
+    pub fn has_totp(&self) -> bool {
+        matches!(&self.totp, Some(totp) if totp.enabled)
+    }
+
     pub fn new_token(&mut self, seconds: u64) -> UserToken {
+        self.new_session_token(seconds, None, None, None)
+    }
+
+    /// Same as `new_token`, remembering the refresh family issued along and where the session
+    /// was opened from.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::user_model::*;
+    ///
+    /// let mut user = User::default();
+    /// let refresh_token = user.new_refresh_token(60, None, None, None);
+    /// let token = user.new_session_token(60, Some(refresh_token.family.clone()), None, None);
+    ///
+    /// assert_eq!(user.tokens.unwrap()[0], token);
+    /// assert_eq!(token.family, Some(refresh_token.family));
+    /// ```
+    pub fn new_session_token(
+        &mut self,
+        seconds: u64,
+        family: Option<String>,
+        ip: Option<String>,
+        user_agent: Option<String>,
+    ) -> UserToken {
         let token = UserToken {
-            token: generate_token(128),
+            token: generate_url_token_default(),
             timestamp: get_current_timestamp(),
             expiration_timestamp: get_current_timestamp() + (seconds * 1000),
+            id: Some(Uuid::new_v4().to_string()),
+            ip,
+            user_agent,
+            scopes: Some(scope_model::full()),
+            family,
         };
-        let mut tokens: Vec<UserToken> = if self.tokens.is_some() {
-            self.tokens.as_ref().unwrap().to_vec()
-        } else {
-            Vec::<UserToken>::new()
+        let mut tokens: Vec<UserToken> = match &self.tokens {
+            Some(tokens) => tokens.to_vec(),
+            None => Vec::<UserToken>::new(),
         };
         tokens.push(token.clone());
         self.tokens = Some(tokens);
         token
     }
+
+    /// A new family is started when none is given, `ip` and `user_agent` tell the device.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::user_model::*;
+    ///
+    /// let mut user = User::default();
+    /// let first = user.new_refresh_token(60, None, None, Some("Firefox".to_string()));
+    /// let rotated = user.new_refresh_token(60, Some(first.family.clone()), None, None);
+    /// let other = user.new_refresh_token(60, None, None, None);
+    ///
+    /// assert_eq!(first.family, rotated.family);
+    /// assert!(first.family != other.family);
+    /// assert_eq!(user.refresh_tokens.as_ref().unwrap().len(), 3);
+    /// assert_eq!(user.refresh_tokens.unwrap()[0].user_agent, Some("Firefox".to_string()));
+    /// ```
+    pub fn new_refresh_token(
+        &mut self,
+        seconds: u64,
+        family: Option<String>,
+        ip: Option<String>,
+        user_agent: Option<String>,
+    ) -> UserRefreshToken {
+        let token = UserRefreshToken {
+            token: generate_url_token_default(),
+            family: family.unwrap_or_else(|| Uuid::new_v4().to_string()),
+            timestamp: get_current_timestamp(),
+            expiration_timestamp: get_current_timestamp() + (seconds * 1000),
+            used: false,
+            ip,
+            user_agent,
+        };
+        let mut tokens = self.refresh_tokens.clone().unwrap_or_default();
+        tokens.push(token.clone());
+        self.refresh_tokens = Some(tokens);
+        token
+    }
+
+    /// Replace any previous reset token, only the returned token can be used.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::user_model::*;
+    /// use misato_security::hash_token;
+    ///
+    /// let mut user = User::default();
+    /// let token = user.new_reset_token(60);
+    ///
+    /// assert_eq!(user.reset_token.unwrap().hash, hash_token(&token));
+    /// ```
+    pub fn new_reset_token(&mut self, seconds: u64) -> String {
+        let (token, hashed) = UserHashedToken::generate(seconds);
+        self.reset_token = Some(hashed);
+        token
+    }
+
+    /// Seconds left before the account unlocks, if it is locked.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::user_model::*;
+    ///
+    /// let mut user = User::default();
+    /// assert_eq!(user.lock_remaining(0), None);
+    ///
+    /// user.locked_until = 60000;
+    /// assert_eq!(user.lock_remaining(1000), Some(59));
+    /// assert_eq!(user.lock_remaining(60000), None);
+    /// ```
+    pub fn lock_remaining(&self, now: u64) -> Option<u64> {
+        match self.locked_until > now {
+            true => Some((self.locked_until - now).div_ceil(1000)),
+            false => None,
+        }
+    }
+
+    /// Hash the tokens still in plain text, false when they all were hashed already.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::user_model::*;
+    ///
+    /// let mut user = User::default();
+    /// let token = user.new_token(60);
+    /// let refresh_token = user.new_refresh_token(60, None, None, None);
+    ///
+    /// assert!(user.hash_tokens());
+    /// assert_eq!(user.tokens.as_ref().unwrap()[0], token.hashed());
+    /// assert_eq!(user.refresh_tokens.as_ref().unwrap()[0], refresh_token.hashed());
+    /// assert!(!user.hash_tokens());
+    /// ```
+    pub fn hash_tokens(&mut self) -> bool {
+        let mut changed = false;
+        for token in self.tokens.iter_mut().flatten() {
+            changed |= !is_token_hash(&token.token);
+            *token = token.hashed();
+        }
+        for token in self.refresh_tokens.iter_mut().flatten() {
+            changed |= !is_token_hash(&token.token);
+            *token = token.hashed();
+        }
+        changed
+    }
+
+    pub fn reset_failed_logins(&mut self) {
+        self.failed_logins = 0;
+        self.locked_until = 0;
+    }
+
+    /// Set an unverified email.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::user_model::*;
+    ///
+    /// let mut user = User::default();
+    /// user.set_email("User@Misato.wiki".to_string());
+    ///
+    /// assert_eq!(user.email, Some("User@Misato.wiki".to_string()));
+    /// assert_eq!(user.email_key, Some("user@misato.wiki".to_string()));
+    /// assert!(!user.email_verified);
+    /// ```
+    pub fn set_email(&mut self, email: String) {
+        self.email_key = Some(canonical_email(&email));
+        self.email = Some(email);
+        self.email_verified = false;
+    }
+
+    /// The email is unverified until the returned token is confirmed.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::models::user_model::*;
+    /// use misato_security::hash_token;
+    ///
+    /// let mut user = User::default();
+    /// let token = user.new_verification_token("user@misato.wiki".to_string(), 60);
+    ///
+    /// assert!(!user.email_verified);
+    /// assert_eq!(user.verification_token.unwrap().hash, hash_token(&token));
+    /// ```
+    pub fn new_verification_token(&mut self, email: String, seconds: u64) -> String {
+        let (token, hashed) = UserHashedToken::generate(seconds);
+        self.set_email(email);
+        self.verification_token = Some(hashed);
+        token
+    }
 }
 
 #[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Default, Clone)]
@@ -71,26 +519,16 @@ pub struct UserAccess {
     pub permissions: Option<Vec<UserPermissionType>>,
 }
 
-#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone, Default)]
 pub enum UserRoleType {
     Admin, // Only the main website has access
-    User,  // New account
+    #[default]
+    User, // New account
 }
 
-impl Default for UserRoleType {
-    fn default() -> Self {
-        UserRoleType::User
-    }
-}
-
-#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone)]
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone, Default)]
 pub enum UserPermissionType {
     UserManager, // Create, Delete, Edit user informations
-    None,        // Default, no more access
-}
-
-impl Default for UserPermissionType {
-    fn default() -> Self {
-        UserPermissionType::None
-    }
+    #[default]
+    None, // Default, no more access
 }
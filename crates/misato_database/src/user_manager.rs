@@ -1,14 +1,86 @@
+use futures::TryStreamExt;
 use mongodb::{
-    bson::{doc, Document},
-    error::Error,
+    bson::{doc, oid::ObjectId, Document},
+    error::{Error, ErrorKind, WriteFailure},
+    options::{FindOptions, IndexOptions, InsertManyOptions},
     results::{DeleteResult, InsertOneResult, UpdateResult},
-    Collection,
+    Collection, IndexModel,
 };
 
+use misato_security::{hash_token, password::Password};
 use misato_utils::get_current_timestamp;
 
+use crate::database::Unavailable;
+use crate::login_failure_manager::count_failed_login;
+use crate::models::login_failure_model::FailedLogin;
 use crate::models::user_model::*;
 
+/// Restrict a filter to users that are not soft deleted.
+fn active(mut filter: Document) -> Document {
+    filter.insert("deleted_at", doc! {"$exists": false});
+    filter
+}
+
+/// Match the user owning this token only if that same token is not expired.
+fn valid_token_filter(token: &str) -> Document {
+    active(
+        doc! {"tokens": { "$elemMatch": { "token": hash_token(token), "expiration_timestamp": { "$gte": get_current_timestamp() as i64 } } } },
+    )
+}
+
+/// Values that are not a `hash_token` output, tokens stored before they were hashed.
+pub(crate) fn not_hashed() -> Document {
+    doc! {"$not": mongodb::bson::Regex { pattern: "^[0-9a-f]{64}$".to_string(), options: String::new() }}
+}
+
+pub(crate) const DUPLICATE_KEY: i32 = 11000;
+
+#[derive(Debug)]
+pub enum UserError {
+    AlreadyExists,
+    InvalidCursor,
+    Db(Error),
+}
+
+impl From<Error> for UserError {
+    fn from(error: Error) -> Self {
+        match &*error.kind {
+            ErrorKind::Write(WriteFailure::WriteError(write)) if write.code == DUPLICATE_KEY => {
+                UserError::AlreadyExists
+            }
+            _ => UserError::Db(error),
+        }
+    }
+}
+
+impl Unavailable for UserError {
+    fn is_unavailable(&self) -> bool {
+        match self {
+            UserError::AlreadyExists | UserError::InvalidCursor => false,
+            UserError::Db(error) => error.is_unavailable(),
+        }
+    }
+}
+
+/// Match a username whatever its case, or exactly for accounts created before `username_key`.
+fn username_filter(username: &str) -> Document {
+    doc! {"$or": [{"username_key": canonical_username(username)}, {"username": username}]}
+}
+
+/// Match an email whatever its case, or exactly for accounts created before `email_key`.
+fn email_filter(email: &str) -> Document {
+    doc! {"$or": [{"email_key": canonical_email(email)}, {"email": email}]}
+}
+
+/// Every session and pending token goes with the account.
+fn soft_delete_update() -> Document {
+    doc! {
+        "$set": {"deleted_at": get_current_timestamp() as i64},
+        "$unset": {"tokens": "", "refresh_tokens": "", "reset_token": "", "verification_token": ""},
+    }
+}
+
+#[derive(Clone)]
 pub struct UserManager {
     pub users: Collection<User>,
 }
@@ -18,10 +90,71 @@ impl UserManager {
         Self { users }
     }
 
+    /// Give the accounts created before `username_key` and `email_key` their keys, returns how many
+    /// got one. Run before `create_indexes`; a key already taken is left out, the account keeps
+    /// being found by its exact name until the duplicate is fixed by hand.
+    pub async fn backfill_keys(&self) -> Result<u64, Error> {
+        let filter = doc! {"$or": [
+            {"username_key": {"$exists": false}},
+            {"email": {"$exists": true}, "email_key": {"$exists": false}},
+        ]};
+        let mut users = self.users.find(filter, None).await?;
+        let mut backfilled = 0;
+        while let Some(user) = users.try_next().await? {
+            let mut keys = Document::new();
+            let username_key = canonical_username(&user.username);
+            if user.username_key.is_none() && !self.key_taken("username_key", &username_key).await?
+            {
+                keys.insert("username_key", username_key);
+            }
+            if let (Some(email), None) = (&user.email, &user.email_key) {
+                let email_key = canonical_email(email);
+                if !self.key_taken("email_key", &email_key).await? {
+                    keys.insert("email_key", email_key);
+                }
+            }
+            if !keys.is_empty() {
+                self.users
+                    .update_one(doc! {"uuid": &user.uuid}, doc! {"$set": keys}, None)
+                    .await?;
+                backfilled += 1;
+            }
+        }
+        Ok(backfilled)
+    }
+
+    async fn key_taken(&self, key: &str, value: &str) -> Result<bool, Error> {
+        Ok(self.users.count_documents(doc! {key: value}, None).await? > 0)
+    }
+
+    /// Usernames and emails are unique at the database level, so concurrent signups can't both succeed.
+    pub async fn create_indexes(&self) -> Result<(), Error> {
+        for key in ["username_key", "email_key"] {
+            let options = IndexOptions::builder()
+                .unique(true)
+                .partial_filter_expression(doc! {key: {"$exists": true}})
+                .build();
+            let index = IndexModel::builder()
+                .keys(doc! {key: 1})
+                .options(options)
+                .build();
+            self.users.create_index(index, None).await?;
+        }
+        Ok(())
+    }
+
     pub async fn username_exists(&self, username: &str) -> Result<bool, Error> {
         Ok(self
             .users
-            .count_documents(doc! { "username": username }, None)
+            .count_documents(username_filter(username), None)
+            .await?
+            != 0)
+    }
+
+    pub async fn email_exists(&self, email: &str) -> Result<bool, Error> {
+        Ok(self
+            .users
+            .count_documents(email_filter(email), None)
             .await?
             != 0)
     }
@@ -34,92 +167,866 @@ impl UserManager {
             != 0)
     }
 
-    pub async fn create_user(&self, user: &User) -> Result<InsertOneResult, Error> {
+    pub async fn create_user(&self, user: &User) -> Result<InsertOneResult, UserError> {
+        let mut user = user.clone();
+        user.hash_tokens();
         let target = self.users.insert_one(user, None).await?;
         Ok(target)
     }
 
+    /// Insert every user in one unordered bulk write, one result per user in the same order.
+    pub async fn create_users(&self, users: &[User]) -> Vec<Result<(), UserError>> {
+        if users.is_empty() {
+            return Vec::new();
+        }
+        let users: Vec<User> = users
+            .iter()
+            .map(|user| {
+                let mut user = user.clone();
+                user.hash_tokens();
+                user
+            })
+            .collect();
+        let options = InsertManyOptions::builder().ordered(false).build();
+        let error = match self.users.insert_many(&users, options).await {
+            Ok(_) => return users.iter().map(|_| Ok(())).collect(),
+            Err(error) => error,
+        };
+        let failure = match &*error.kind {
+            ErrorKind::BulkWrite(failure) => failure,
+            _ => {
+                return users
+                    .iter()
+                    .map(|_| Err(UserError::Db(error.clone())))
+                    .collect()
+            }
+        };
+        let mut results: Vec<Result<(), UserError>> = users.iter().map(|_| Ok(())).collect();
+        for write in failure.write_errors.iter().flatten() {
+            results[write.index] = match write.code {
+                DUPLICATE_KEY => Err(UserError::AlreadyExists),
+                _ => Err(UserError::Db(error.clone())),
+            };
+        }
+        results
+    }
+
     pub async fn get_user(
         &self,
         username: Option<&str>,
         uuid: Option<&str>,
     ) -> Result<Option<User>, Error> {
         let mut doc: Document = Document::new();
-        if uuid.is_some() {
-            doc = doc! {"uuid": uuid.unwrap()};
+        if let Some(uuid) = uuid {
+            doc = doc! {"uuid": uuid};
         }
-        if username.is_some() {
-            doc = doc! {"username": username.unwrap()};
+        if let Some(username) = username {
+            doc = username_filter(username);
         }
         if doc.is_empty() {
             return Ok(None);
         }
-        match self.users.find_one(doc, None).await? {
+        match self.users.find_one(active(doc), None).await? {
             Some(user) => Ok(Some(user)),
             None => Ok(None),
         }
     }
 
+    pub async fn get_user_by_email(&self, email: &str) -> Result<Option<User>, Error> {
+        self.users.find_one(active(email_filter(email)), None).await
+    }
+
+    /// The username wins over the email: an identifier is only tried as an email when no
+    /// username matches it, and when it contains `@`, which usernames can't.
+    pub async fn get_user_by_identifier(&self, identifier: &str) -> Result<Option<User>, Error> {
+        if let Some(user) = self.get_user(Some(identifier), None).await? {
+            return Ok(Some(user));
+        }
+        if !identifier.contains('@') {
+            return Ok(None);
+        }
+        self.get_user_by_email(identifier).await
+    }
+
+    /// Like `get_user_by_identifier`, also finding an account soft deleted at `deleted_since`
+    /// or later, so it can be restored by logging in.
+    pub async fn get_login_user(
+        &self,
+        identifier: &str,
+        deleted_since: Option<u64>,
+    ) -> Result<Option<User>, Error> {
+        if let Some(user) = self.get_user_by_identifier(identifier).await? {
+            return Ok(Some(user));
+        }
+        let since = match deleted_since {
+            Some(since) => since,
+            None => return Ok(None),
+        };
+        let mut filters = vec![username_filter(identifier)];
+        if identifier.contains('@') {
+            filters.push(email_filter(identifier));
+        }
+        for filter in filters {
+            let deleted = doc! {"$and": [
+                filter,
+                {"deleted_at": {"$gte": since as i64}, "anonymized": {"$ne": true}},
+            ]};
+            if let Some(user) = self.users.find_one(deleted, None).await? {
+                return Ok(Some(user));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Deleted users count too, only a fresh install has none.
+    pub async fn has_users(&self) -> Result<bool, Error> {
+        Ok(self.users.find_one(doc! {}, None).await?.is_some())
+    }
+
+    pub async fn count_users(&self) -> Result<u64, Error> {
+        self.users.count_documents(active(doc! {}), None).await
+    }
+
+    /// Oldest accounts first, only `limit` users are loaded.
+    pub async fn list_users(&self, skip: u64, limit: i64) -> Result<Vec<User>, Error> {
+        let options = FindOptions::builder()
+            .sort(doc! {"timestamp": 1})
+            .skip(skip)
+            .limit(limit)
+            .build();
+        self.users
+            .find(active(doc! {}), options)
+            .await?
+            .try_collect()
+            .await
+    }
+
+    /// Users in `_id` order, starting after `after`, each with its `_id`.
+    /// New users get a greater `_id`, so inserts never shift the users already listed.
+    pub async fn list_users_after(
+        &self,
+        after: Option<ObjectId>,
+        limit: i64,
+    ) -> Result<Vec<(ObjectId, User)>, Error> {
+        let mut filter = active(doc! {});
+        if let Some(after) = after {
+            filter.insert("_id", doc! {"$gt": after});
+        }
+        let options = FindOptions::builder()
+            .sort(doc! {"_id": 1})
+            .limit(limit)
+            .build();
+        let documents: Vec<Document> = self
+            .users
+            .clone_with_type::<Document>()
+            .find(filter, options)
+            .await?
+            .try_collect()
+            .await?;
+        let mut users = Vec::new();
+        for document in documents {
+            let id = match document.get_object_id("_id") {
+                Ok(id) => id,
+                Err(_) => continue, // Only documents inserted by hand could lack one
+            };
+            users.push((id, mongodb::bson::from_document(document)?));
+        }
+        Ok(users)
+    }
+
+    /// Soft delete: the account and its username are kept, but it can't be used anymore.
     pub async fn delete_user(
         &self,
         username: Option<&str>,
         uuid: Option<&str>,
-    ) -> Result<Option<DeleteResult>, Error> {
+    ) -> Result<Option<UpdateResult>, Error> {
         let mut doc: Document = Document::new();
-        if uuid.is_some() {
-            doc = doc! {"uuid": uuid.unwrap()};
+        if let Some(uuid) = uuid {
+            doc = doc! {"uuid": uuid};
         }
-        if username.is_some() {
-            doc = doc! {"username": username.unwrap()};
+        if let Some(username) = username {
+            doc = username_filter(username);
         }
         if doc.is_empty() {
             return Ok(None);
         }
-        Ok(Some(self.users.delete_one(doc, None).await?))
+        Ok(Some(
+            self.users
+                .update_one(active(doc), soft_delete_update(), None)
+                .await?,
+        ))
+    }
+
+    /// Soft delete, dropping everything the account could be recognized by: the uuid becomes
+    /// `id`, the username a tombstone, the email, credentials and login history are removed.
+    pub async fn anonymize_user(&self, uuid: &str, id: &str) -> Result<UpdateResult, Error> {
+        let username = anonymous_username(id);
+        let update = doc! {
+            "$set": {
+                "uuid": id,
+                "username_key": canonical_username(&username),
+                "username": username,
+                "email_verified": false,
+                "failed_logins": 0,
+                "locked_until": 0_i64,
+                "anonymized": true,
+                "deleted_at": get_current_timestamp() as i64,
+            },
+            "$unset": {
+                "email": "", "email_key": "", "logs": "", "password": "", "tokens": "",
+                "refresh_tokens": "", "reset_token": "", "verification_token": "", "totp": "",
+                "last_login_at": "", "last_login_ip": "",
+            },
+        };
+        self.users
+            .update_one(active(doc! {"uuid": uuid}), update, None)
+            .await
     }
 
-    pub async fn delete_user_from_token(&self, token: &str) -> Result<Option<DeleteResult>, Error> {
+    pub async fn delete_user_from_token(&self, token: &str) -> Result<Option<UpdateResult>, Error> {
         Ok(Some(
             self.users
-                .delete_one(doc! {"tokens.token": token, "tokens.expiration_timestamp": { "$gte": get_current_timestamp() as i64 } }, None)
+                .update_one(valid_token_filter(token), soft_delete_update(), None)
                 .await?,
         ))
     }
 
-    pub async fn save_token(&self, uuid: &str, token: &UserToken) -> Result<UpdateResult, Error> {
-        let doc = mongodb::bson::to_document(token).unwrap();
-        let update = doc! {"$push": {"tokens": doc} };
+    pub async fn restore_user(&self, uuid: &str) -> Result<UpdateResult, Error> {
+        let update = doc! {"$unset": {"deleted_at": ""} };
+        self.users
+            .update_one(
+                doc! {"uuid": uuid, "deleted_at": {"$exists": true}},
+                update,
+                None,
+            )
+            .await
+    }
+
+    /// Hard delete the accounts soft deleted before `before`, in milliseconds.
+    /// Anonymized accounts are kept, so their audit events still lead to them.
+    /// Runs against the database given by `MISATO_TEST_MONGODB_URI`, skipped when unset:
+    ///
+    /// ```
+    /// use misato_database::{models::user_model::User, user_manager::UserManager};
+    /// use misato_security::password::Password;
+    /// use misato_utils::get_current_timestamp;
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+    /// if let Ok(uri) = std::env::var("MISATO_TEST_MONGODB_URI") {
+    ///     let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    ///     let db = client.database("misato_test_purge_deleted");
+    ///     let manager = UserManager::init(db.collection::<User>("users"));
+    ///
+    ///     for username in ["deleted", "active"] {
+    ///         let user = User::create(username.to_string(), Password::hash_password(b"password").unwrap(), None);
+    ///         manager.create_user(&user).await.unwrap();
+    ///     }
+    ///     manager.delete_user(Some("deleted"), None).await.unwrap();
+    ///     let kept = manager.purge_deleted_users(get_current_timestamp() - 60000).await.unwrap();
+    ///     let purged = manager.purge_deleted_users(get_current_timestamp() + 1000).await.unwrap();
+    ///     let restorable = manager.get_login_user("deleted", Some(0)).await.unwrap();
+    ///     let active = manager.get_user(Some("active"), None).await.unwrap();
+    ///     db.drop(None).await.unwrap();
+    ///
+    ///     assert_eq!((kept.deleted_count, purged.deleted_count), (0, 1));
+    ///     assert_eq!(restorable, None);
+    ///     assert!(active.is_some());
+    /// }
+    /// # });
+    /// ```
+    pub async fn purge_deleted_users(&self, before: u64) -> Result<DeleteResult, Error> {
+        self.users
+            .delete_many(
+                doc! {"deleted_at": {"$lt": before as i64}, "anonymized": {"$ne": true}},
+                None,
+            )
+            .await
+    }
+
+    /// Add a token, evicting the oldest ones beyond `max_tokens` (no limit when 0).
+    /// Runs against the database given by `MISATO_TEST_MONGODB_URI`, skipped when unset:
+    ///
+    /// ```
+    /// use misato_database::{models::user_model::User, user_manager::UserManager};
+    /// use misato_security::{hash_token, password::Password};
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+    /// if let Ok(uri) = std::env::var("MISATO_TEST_MONGODB_URI") {
+    ///     let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    ///     let db = client.database("misato_test_max_tokens");
+    ///     let manager = UserManager::init(db.collection::<User>("users"));
+    ///
+    ///     let mut user = User::create("username".to_string(), Password::hash_password(b"password").unwrap(), None);
+    ///     manager.create_user(&user).await.unwrap();
+    ///     let mut logins = Vec::new();
+    ///     for _ in 0..4 {
+    ///         let token = user.new_token(60);
+    ///         manager.save_token(&user.uuid, &token, 3).await.unwrap();
+    ///         logins.push(token.token);
+    ///         std::thread::sleep(std::time::Duration::from_millis(2));
+    ///     }
+    ///     let user = manager.get_user(None, Some(&user.uuid)).await.unwrap().unwrap();
+    ///     let tokens: Vec<String> = user.tokens.unwrap().into_iter().map(|t| t.token).collect();
+    ///     db.drop(None).await.unwrap();
+    ///
+    ///     let hashes: Vec<String> = logins[1..].iter().map(|t| hash_token(t)).collect();
+    ///     assert_eq!(tokens, hashes);
+    /// }
+    /// # });
+    /// ```
+    pub async fn save_token(
+        &self,
+        uuid: &str,
+        token: &UserToken,
+        max_tokens: usize,
+    ) -> Result<UpdateResult, Error> {
+        let doc = mongodb::bson::to_document(&token.hashed()).unwrap();
+        let update = match max_tokens {
+            0 => doc! {"$push": {"tokens": doc} },
+            max => doc! {"$push": {"tokens": {
+                "$each": [doc],
+                "$sort": {"timestamp": 1},
+                "$slice": -(max as i64),
+            }}},
+        };
+        self.users
+            .update_one(doc! {"uuid": uuid}, update, None)
+            .await
+    }
+
+    /// Replace any previous enrollment, verified or not.
+    pub async fn set_totp(&self, uuid: &str, totp: &UserTotp) -> Result<UpdateResult, Error> {
+        let doc = mongodb::bson::to_document(totp).unwrap();
+        let update = doc! {"$set": {"totp": doc} };
+        self.users
+            .update_one(active(doc! {"uuid": uuid}), update, None)
+            .await
+    }
+
+    pub async fn enable_totp(&self, uuid: &str) -> Result<UpdateResult, Error> {
+        let update = doc! {"$set": {"totp.enabled": true} };
+        self.users
+            .update_one(
+                active(doc! {"uuid": uuid, "totp": {"$exists": true}}),
+                update,
+                None,
+            )
+            .await
+    }
+
+    /// Store the step of an accepted code, refused when this step or a later one was already
+    /// stored, so of two concurrent logins with the same code only one modifies.
+    pub async fn use_totp_step(&self, uuid: &str, step: u64) -> Result<UpdateResult, Error> {
+        let step = step.min(i64::MAX as u64) as i64;
+        let filter = doc! {
+            "uuid": uuid,
+            "totp": {"$exists": true},
+            "$or": [{"totp.last_step": null}, {"totp.last_step": {"$lt": step}}],
+        };
+        let update = doc! {"$set": {"totp.last_step": step} };
+        self.users.update_one(filter, update, None).await
+    }
+
+    /// Used once: a wrong code, or one already removed by a concurrent login, modifies nothing.
+    pub async fn use_recovery_code(&self, uuid: &str, code: &str) -> Result<UpdateResult, Error> {
+        let hash = hash_token(code);
+        let update = doc! {"$pull": {"totp.recovery_codes": {"hash": &hash}} };
+        self.users
+            .update_one(
+                doc! {"uuid": uuid, "totp.enabled": true, "totp.recovery_codes.hash": &hash},
+                update,
+                None,
+            )
+            .await
+    }
+
+    /// The former codes stop working.
+    pub async fn set_recovery_codes(
+        &self,
+        uuid: &str,
+        codes: &[UserRecoveryCode],
+    ) -> Result<UpdateResult, Error> {
+        let codes: Vec<Document> = codes
+            .iter()
+            .map(|code| mongodb::bson::to_document(code).unwrap())
+            .collect();
+        let update = doc! {"$set": {"totp.recovery_codes": codes} };
+        self.users
+            .update_one(
+                active(doc! {"uuid": uuid, "totp.enabled": true}),
+                update,
+                None,
+            )
+            .await
+    }
+
+    pub async fn remove_totp(&self, uuid: &str) -> Result<UpdateResult, Error> {
+        let update = doc! {"$unset": {"totp": ""} };
+        self.users
+            .update_one(doc! {"uuid": uuid}, update, None)
+            .await
+    }
+
+    pub async fn set_password(
+        &self,
+        uuid: &str,
+        password: &Password,
+    ) -> Result<UpdateResult, Error> {
+        let doc = mongodb::bson::to_document(password).unwrap();
+        let update = doc! {"$set": {"password": doc} };
+        self.users
+            .update_one(doc! {"uuid": uuid}, update, None)
+            .await
+    }
+
+    /// Relies on the unique index, a name taken since it was checked fails with `AlreadyExists`.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_database::{models::user_model::User, user_manager::{UserError, UserManager}};
+    /// use misato_security::password::Password;
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+    /// if let Ok(uri) = std::env::var("MISATO_TEST_MONGODB_URI") {
+    ///     let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    ///     let db = client.database("misato_test_set_username");
+    ///     let manager = UserManager::init(db.collection::<User>("users"));
+    ///     manager.create_indexes().await.unwrap();
+    ///
+    ///     let misato = User::create("misato".to_string(), Password::hash_password(b"password").unwrap(), None);
+    ///     let shinji = User::create("shinji".to_string(), Password::hash_password(b"password").unwrap(), None);
+    ///     manager.create_user(&misato).await.unwrap();
+    ///     manager.create_user(&shinji).await.unwrap();
+    ///     let renamed = manager.set_username(&misato.uuid, "Katsuragi").await.unwrap();
+    ///     let taken = manager.set_username(&shinji.uuid, "katsuragi").await;
+    ///     let found = manager.get_user(Some("katsuragi"), None).await.unwrap().unwrap();
+    ///     db.drop(None).await.unwrap();
+    ///
+    ///     assert_eq!(renamed.modified_count, 1);
+    ///     assert!(matches!(taken, Err(UserError::AlreadyExists)));
+    ///     assert_eq!(found.uuid, misato.uuid);
+    ///     assert_eq!(found.username, "Katsuragi");
+    /// }
+    /// # });
+    /// ```
+    pub async fn set_username(
+        &self,
+        uuid: &str,
+        username: &str,
+    ) -> Result<UpdateResult, UserError> {
+        let update = doc! {"$set": {
+            "username": username,
+            "username_key": canonical_username(username),
+        }};
         Ok(self
             .users
-            .update_one(doc! {"uuid": uuid}, update, None)
+            .update_one(active(doc! {"uuid": uuid}), update, None)
             .await?)
     }
 
-    pub async fn clear_tokens(&self, uuid: &str) -> Result<UpdateResult, Error> {
-        let update = doc! {"$unset": {"tokens": ""} };
-        Ok(self
+    /// Store every raw password as a PHC string, returns how many were converted.
+    /// Conversion doesn't need the plain text passwords, they verify as before.
+    pub async fn encode_passwords(&self) -> Result<u64, Error> {
+        let mut users = self
             .users
+            .find(
+                doc! {"password": {"$exists": true}, "password.encoded": {"$exists": false}},
+                None,
+            )
+            .await?;
+        let mut converted = 0;
+        while let Some(user) = users.try_next().await? {
+            if let Some(password) = &user.password {
+                self.set_password(&user.uuid, &password.to_encoded())
+                    .await?;
+                converted += 1;
+            }
+        }
+        Ok(converted)
+    }
+
+    /// Hash the tokens stored in plain text by older versions, returns how many users had some.
+    /// Runs against the database given by `MISATO_TEST_MONGODB_URI`, skipped when unset:
+    ///
+    /// ```
+    /// use misato_database::{models::user_model::User, user_manager::UserManager};
+    /// use misato_security::password::Password;
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+    /// if let Ok(uri) = std::env::var("MISATO_TEST_MONGODB_URI") {
+    ///     let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    ///     let db = client.database("misato_test_hash_tokens");
+    ///     let manager = UserManager::init(db.collection::<User>("users"));
+    ///
+    ///     let mut user = User::create("username".to_string(), Password::hash_password(b"password").unwrap(), None);
+    ///     let token = user.new_token(60);
+    ///     manager.users.insert_one(&user, None).await.unwrap();
+    ///     let hashed = manager.hash_tokens().await.unwrap();
+    ///     let found = manager.get_user_from_token(&token.token).await.unwrap();
+    ///     let stored = manager.get_user(None, Some(&user.uuid)).await.unwrap().unwrap();
+    ///     db.drop(None).await.unwrap();
+    ///
+    ///     assert_eq!(hashed, 1);
+    ///     assert!(found.is_some());
+    ///     assert!(stored.tokens.unwrap()[0].token != token.token);
+    /// }
+    /// # });
+    /// ```
+    pub async fn hash_tokens(&self) -> Result<u64, Error> {
+        let filter = doc! {"$or": [
+            {"tokens": {"$elemMatch": {"token": not_hashed()}}},
+            {"refresh_tokens": {"$elemMatch": {"token": not_hashed()}}},
+        ]};
+        let mut users = self.users.find(filter, None).await?;
+        let mut hashed = 0;
+        while let Some(mut user) = users.try_next().await? {
+            if user.hash_tokens() {
+                let update = doc! {"$set": {
+                    "tokens": mongodb::bson::to_bson(&user.tokens).unwrap(),
+                    "refresh_tokens": mongodb::bson::to_bson(&user.refresh_tokens).unwrap(),
+                }};
+                self.users
+                    .update_one(doc! {"uuid": &user.uuid}, update, None)
+                    .await?;
+                hashed += 1;
+            }
+        }
+        Ok(hashed)
+    }
+
+    pub async fn save_refresh_token(
+        &self,
+        uuid: &str,
+        token: &UserRefreshToken,
+    ) -> Result<UpdateResult, Error> {
+        let doc = mongodb::bson::to_document(&token.hashed()).unwrap();
+        let update = doc! {"$push": {"refresh_tokens": doc} };
+        self.users
             .update_one(doc! {"uuid": uuid}, update, None)
-            .await?)
+            .await
     }
 
-    pub async fn clear_tokens_from_token(&self, token: &str) -> Result<UpdateResult, Error> {
-        let update = doc! {"$unset": {"tokens": ""} };
+    /// Count a failed login, locking the account for `lockout.1` seconds once `lockout.0` are reached.
+    /// Concurrent failures all count, see `count_failed_login`.
+    pub async fn record_login_failure(
+        &self,
+        uuid: &str,
+        lockout: (u32, u64),
+        now: u64,
+    ) -> Result<FailedLogin, Error> {
+        count_failed_login(
+            &self.users.clone_with_type(),
+            doc! {"uuid": uuid},
+            doc! {},
+            false,
+            lockout,
+            now,
+        )
+        .await
+    }
+
+    pub async fn reset_login_failures(&self, uuid: &str) -> Result<UpdateResult, Error> {
+        let update = doc! {"$set": {"failed_logins": 0, "locked_until": 0_i64}};
+        self.users
+            .update_one(doc! {"uuid": uuid}, update, None)
+            .await
+    }
+
+    pub async fn save_last_login(
+        &self,
+        uuid: &str,
+        timestamp: u64,
+        ip: Option<&str>,
+    ) -> Result<UpdateResult, Error> {
+        let update = match ip {
+            Some(ip) => doc! {"$set": {"last_login_at": timestamp as i64, "last_login_ip": ip}},
+            None => doc! {
+                "$set": {"last_login_at": timestamp as i64},
+                "$unset": {"last_login_ip": ""},
+            },
+        };
+        self.users
+            .update_one(doc! {"uuid": uuid}, update, None)
+            .await
+    }
+
+    pub async fn save_reset_token(
+        &self,
+        uuid: &str,
+        reset_token: &UserHashedToken,
+    ) -> Result<UpdateResult, Error> {
+        let doc = mongodb::bson::to_document(reset_token).unwrap();
+        let update = doc! {"$set": {"reset_token": doc} };
+        self.users
+            .update_one(doc! {"uuid": uuid}, update, None)
+            .await
+    }
+
+    /// Set the password and drop the reset token and every session in one update,
+    /// so a reset token can only be used once. Returns the user as it was before.
+    pub async fn reset_password(
+        &self,
+        token_hash: &str,
+        password: &Password,
+    ) -> Result<Option<User>, Error> {
+        let doc = mongodb::bson::to_document(password).unwrap();
+        let update = doc! {
+            "$set": {"password": doc},
+            "$unset": {"reset_token": "", "tokens": "", "refresh_tokens": ""},
+        };
+        let filter = doc! {
+            "reset_token.hash": token_hash,
+            "reset_token.expiration_timestamp": { "$gte": get_current_timestamp() as i64 },
+        };
+        self.users
+            .find_one_and_update(active(filter), update, None)
+            .await
+    }
+
+    pub async fn save_verification_token(
+        &self,
+        uuid: &str,
+        email: &str,
+        verification_token: &UserHashedToken,
+    ) -> Result<UpdateResult, UserError> {
+        let doc = mongodb::bson::to_document(verification_token).unwrap();
+        let update = doc! {"$set": {
+            "email": email,
+            "email_key": canonical_email(email),
+            "email_verified": false,
+            "verification_token": doc,
+        }};
         Ok(self
             .users
-            .update_one(doc! {"tokens.token": token, "tokens.expiration_timestamp": { "$gte": get_current_timestamp() as i64 } }, update, None)
+            .update_one(doc! {"uuid": uuid}, update, None)
             .await?)
     }
 
-    pub async fn get_user_from_token(&self, token: &str) -> Result<Option<User>, Error> {
-        match self
-            .users
+    pub async fn verify_email(&self, token_hash: &str) -> Result<UpdateResult, Error> {
+        let update = doc! {
+            "$set": {"email_verified": true},
+            "$unset": {"verification_token": ""},
+        };
+        let filter = active(doc! {
+            "verification_token.hash": token_hash,
+            "verification_token.expiration_timestamp": { "$gte": get_current_timestamp() as i64 },
+        });
+        self.users.update_one(filter, update, None).await
+    }
+
+    pub async fn get_user_from_refresh_token(&self, token: &str) -> Result<Option<User>, Error> {
+        self.users
             .find_one(
-                doc! {"tokens.token": token, "tokens.expiration_timestamp": { "$gte": get_current_timestamp() as i64 } },
+                active(doc! {"refresh_tokens.token": hash_token(token)}),
+                None,
+            )
+            .await
+    }
+
+    /// Only one caller can mark a token as used, `modified_count` is 0 for the others.
+    pub async fn use_refresh_token(&self, token: &str) -> Result<UpdateResult, Error> {
+        let update = doc! {"$set": {"refresh_tokens.$.used": true} };
+        self
+            .users
+            .update_one(
+                doc! {"refresh_tokens": { "$elemMatch": { "token": hash_token(token), "used": false } } },
+                update,
                 None,
             )
+            .await
+    }
+
+    pub async fn revoke_refresh_token_family(
+        &self,
+        uuid: &str,
+        family: &str,
+    ) -> Result<UpdateResult, Error> {
+        let update = doc! {"$pull": {"refresh_tokens": {"family": family}} };
+        self.users
+            .update_one(doc! {"uuid": uuid}, update, None)
+            .await
+    }
+
+    /// The family wherever it is, for incidents where its owner isn't known.
+    /// Gives the uuids of the users it was pulled from, none when no one held it.
+    pub async fn revoke_refresh_token_family_everywhere(
+        &self,
+        family: &str,
+    ) -> Result<Vec<String>, Error> {
+        let options = FindOptions::builder().projection(doc! {"uuid": 1}).build();
+        let holders: Vec<Document> = self
+            .users
+            .clone_with_type::<Document>()
+            .find(doc! {"refresh_tokens.family": family}, options)
             .await?
+            .try_collect()
+            .await?;
+        let mut revoked = Vec::new();
+        for uuid in holders
+            .iter()
+            .filter_map(|holder| holder.get_str("uuid").ok())
         {
+            let update = doc! {"$pull": {"refresh_tokens": {"family": family}} };
+            let filter = doc! {"uuid": uuid, "refresh_tokens.family": family};
+            // Unless it was pulled in between, by a logout or another revocation
+            if self
+                .users
+                .update_one(filter, update, None)
+                .await?
+                .modified_count
+                == 1
+            {
+                revoked.push(uuid.to_string());
+            }
+        }
+        Ok(revoked)
+    }
+
+    /// Pull every access and refresh token expired before `now`, across all users.
+    /// Runs against the database given by `MISATO_TEST_MONGODB_URI`, skipped when unset:
+    ///
+    /// ```
+    /// use misato_database::{models::user_model::User, user_manager::UserManager};
+    /// use misato_security::{hash_token, password::Password};
+    /// use misato_utils::get_current_timestamp;
+    ///
+    /// # tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(async {
+    /// if let Ok(uri) = std::env::var("MISATO_TEST_MONGODB_URI") {
+    ///     let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+    ///     let db = client.database("misato_test_purge");
+    ///     let manager = UserManager::init(db.collection::<User>("users"));
+    ///
+    ///     let mut user = User::create("username".to_string(), Password::hash_password(b"password").unwrap(), None);
+    ///     user.new_token(0);
+    ///     let valid = user.new_token(60);
+    ///     user.new_refresh_token(0, None, None, None);
+    ///     let valid_refresh = user.new_refresh_token(60, None, None, None);
+    ///     manager.create_user(&user).await.unwrap();
+    ///
+    ///     manager.purge_expired_tokens(get_current_timestamp() + 1000).await.unwrap();
+    ///     let user = manager.get_user(None, Some(&user.uuid)).await.unwrap().unwrap();
+    ///     let tokens: Vec<String> = user.tokens.unwrap().into_iter().map(|t| t.token).collect();
+    ///     let refresh: Vec<String> = user.refresh_tokens.unwrap().into_iter().map(|t| t.token).collect();
+    ///     db.drop(None).await.unwrap();
+    ///
+    ///     assert_eq!(tokens, vec![hash_token(&valid.token)]);
+    ///     assert_eq!(refresh, vec![hash_token(&valid_refresh.token)]);
+    /// }
+    /// # });
+    /// ```
+    pub async fn purge_expired_tokens(&self, now: u64) -> Result<UpdateResult, Error> {
+        let expired = doc! {"expiration_timestamp": {"$lt": now as i64}};
+        let update = doc! {"$pull": {"tokens": &expired, "refresh_tokens": &expired} };
+        self
+            .users
+            .update_many(
+                doc! {"$or": [{"tokens": {"$elemMatch": &expired}}, {"refresh_tokens": {"$elemMatch": &expired}}]},
+                update,
+                None,
+            )
+            .await
+    }
+
+    /// Pull the `session` of the user matching `filter`, then the refresh family issued along
+    /// with it so the session can't be refreshed back. False when no user holds it.
+    async fn end_session(
+        &self,
+        filter: Document,
+        session: Document,
+        is_session: impl Fn(&UserToken) -> bool,
+    ) -> Result<bool, Error> {
+        let update = doc! {"$pull": {"tokens": session} };
+        // The user as it was before, still holding the session
+        let user = match self.users.find_one_and_update(filter, update, None).await? {
+            Some(user) => user,
+            None => return Ok(false),
+        };
+        let family = user
+            .tokens
+            .iter()
+            .flatten()
+            .find(|token| is_session(token))
+            .and_then(|token| token.family.as_ref());
+        // Sessions opened before they knew their family only end themselves
+        if let Some(family) = family {
+            self.revoke_refresh_token_family(&user.uuid, family).await?;
+        }
+        Ok(true)
+    }
+
+    pub async fn remove_token(&self, token: &str) -> Result<bool, Error> {
+        let hash = hash_token(token);
+        self.end_session(
+            doc! {"tokens.token": &hash},
+            doc! {"token": &hash},
+            |session| session.token == hash,
+        )
+        .await
+    }
+
+    pub async fn remove_session(&self, uuid: &str, id: &str) -> Result<bool, Error> {
+        self.end_session(
+            doc! {"uuid": uuid, "tokens.id": id},
+            doc! {"id": id},
+            |session| session.id.as_deref() == Some(id),
+        )
+        .await
+    }
+
+    /// Every session, the refresh tokens able to open new ones included.
+    pub async fn clear_tokens(&self, uuid: &str) -> Result<UpdateResult, Error> {
+        let update = doc! {"$unset": {"tokens": "", "refresh_tokens": ""} };
+        self.users
+            .update_one(doc! {"uuid": uuid}, update, None)
+            .await
+    }
+
+    /// Sessions and refresh tokens of every matching user in one update, the users stay.
+    /// Returns how many users lost at least one token.
+    pub async fn revoke_all_tokens(
+        &self,
+        role: Option<&UserRoleType>,
+        before: Option<u64>,
+    ) -> Result<UpdateResult, Error> {
+        let mut filter = doc! {};
+        if let Some(role) = role {
+            filter.insert("access.role", mongodb::bson::to_bson(role).unwrap());
+        }
+        let update = match before {
+            Some(before) => {
+                let issued_before = doc! {"timestamp": {"$lt": before as i64}};
+                filter.insert(
+                    "$or",
+                    vec![
+                        doc! {"tokens": {"$elemMatch": issued_before.clone()}},
+                        doc! {"refresh_tokens": {"$elemMatch": issued_before.clone()}},
+                    ],
+                );
+                doc! {"$pull": {"tokens": issued_before.clone(), "refresh_tokens": issued_before}}
+            }
+            None => {
+                filter.insert(
+                    "$or",
+                    vec![
+                        doc! {"tokens": {"$exists": true}},
+                        doc! {"refresh_tokens": {"$exists": true}},
+                    ],
+                );
+                doc! {"$unset": {"tokens": "", "refresh_tokens": ""}}
+            }
+        };
+        self.users.update_many(active(filter), update, None).await
+    }
+
+    pub async fn clear_tokens_from_token(&self, token: &str) -> Result<UpdateResult, Error> {
+        let update = doc! {"$unset": {"tokens": "", "refresh_tokens": ""} };
+        self.users
+            .update_one(valid_token_filter(token), update, None)
+            .await
+    }
+
+    pub async fn get_user_from_token(&self, token: &str) -> Result<Option<User>, Error> {
+        match self.users.find_one(valid_token_filter(token), None).await? {
             Some(user) => Ok(Some(user)),
             None => Ok(None),
         }
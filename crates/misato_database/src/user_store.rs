@@ -0,0 +1,267 @@
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use mongodb::bson::{doc, oid::ObjectId};
+
+use misato_utils::get_current_timestamp;
+
+use crate::models::user_model::*;
+use crate::user_manager::{UserError, UserManager};
+
+/// Account storage as seen by the routes, soft deleted users are never returned.
+/// `UserManager` stores them in MongoDB, `MemoryUserStore` in memory for tests.
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn create(&self, user: &User) -> Result<(), UserError>;
+    /// One result per user in the same order, a failing user doesn't stop the next ones.
+    async fn create_many(&self, users: &[User]) -> Vec<Result<(), UserError>>;
+    async fn get_by_username(&self, username: &str) -> Result<Option<User>, UserError>;
+    async fn get_by_email(&self, email: &str) -> Result<Option<User>, UserError>;
+    async fn get_by_uuid(&self, uuid: &str) -> Result<Option<User>, UserError>;
+    /// Replace the stored user of the same uuid, false when there is none.
+    async fn update(&self, user: &User) -> Result<bool, UserError>;
+    /// Soft delete, false when there is no such user.
+    async fn delete(&self, uuid: &str) -> Result<bool, UserError>;
+    /// Oldest accounts first.
+    async fn list(&self, skip: u64, limit: i64) -> Result<Vec<User>, UserError>;
+    async fn count(&self) -> Result<u64, UserError>;
+    /// Up to `limit` users after `cursor`, or from the first one, each with its own cursor.
+    /// Cursors are opaque and stable: users created meanwhile come after every listed one.
+    async fn list_after(
+        &self,
+        cursor: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<(String, User)>, UserError>;
+}
+
+#[async_trait]
+impl UserStore for UserManager {
+    async fn create(&self, user: &User) -> Result<(), UserError> {
+        self.create_user(user).await?;
+        Ok(())
+    }
+
+    async fn create_many(&self, users: &[User]) -> Vec<Result<(), UserError>> {
+        self.create_users(users).await
+    }
+
+    async fn get_by_username(&self, username: &str) -> Result<Option<User>, UserError> {
+        Ok(self.get_user(Some(username), None).await?)
+    }
+
+    async fn get_by_email(&self, email: &str) -> Result<Option<User>, UserError> {
+        Ok(self.get_user_by_email(email).await?)
+    }
+
+    async fn get_by_uuid(&self, uuid: &str) -> Result<Option<User>, UserError> {
+        Ok(self.get_user(None, Some(uuid)).await?)
+    }
+
+    async fn update(&self, user: &User) -> Result<bool, UserError> {
+        let result = self
+            .users
+            .replace_one(
+                doc! {"uuid": &user.uuid, "deleted_at": {"$exists": false}},
+                user,
+                None,
+            )
+            .await?;
+        Ok(result.matched_count == 1)
+    }
+
+    async fn delete(&self, uuid: &str) -> Result<bool, UserError> {
+        match self.delete_user(None, Some(uuid)).await? {
+            Some(result) => Ok(result.modified_count == 1),
+            None => Ok(false),
+        }
+    }
+
+    async fn list(&self, skip: u64, limit: i64) -> Result<Vec<User>, UserError> {
+        Ok(self.list_users(skip, limit).await?)
+    }
+
+    async fn count(&self) -> Result<u64, UserError> {
+        Ok(self.count_users().await?)
+    }
+
+    async fn list_after(
+        &self,
+        cursor: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<(String, User)>, UserError> {
+        let after = match cursor {
+            Some(cursor) => match ObjectId::parse_str(cursor) {
+                Ok(id) => Some(id),
+                Err(_) => return Err(UserError::InvalidCursor),
+            },
+            None => None,
+        };
+        let users = self.list_users_after(after, limit).await?;
+        Ok(users
+            .into_iter()
+            .map(|(id, user)| (id.to_hex(), user))
+            .collect())
+    }
+}
+
+/// Users kept in memory, in creation order.
+/// Basic usage:
+///
+/// ```
+/// use misato_database::{models::user_model::User, user_manager::UserError, user_store::*};
+/// use misato_security::password::Password;
+///
+/// # tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(async {
+/// let store = MemoryUserStore::default();
+/// let user = User::create("Misato".to_string(), Password::hash_password(b"password").unwrap(), None);
+/// store.create(&user).await.unwrap();
+///
+/// let duplicate = User::create("misato".to_string(), Password::hash_password(b"password").unwrap(), None);
+/// assert!(matches!(store.create(&duplicate).await, Err(UserError::AlreadyExists)));
+/// assert_eq!(store.get_by_username("MISATO").await.unwrap().unwrap().uuid, user.uuid);
+/// assert_eq!(store.count().await.unwrap(), 1);
+///
+/// let mut other = User::create("other".to_string(), Password::hash_password(b"password").unwrap(), None);
+/// other.set_email("Other@misato.wiki".to_string());
+/// store.create(&other).await.unwrap();
+/// let mut same_email = User::create("third".to_string(), Password::hash_password(b"password").unwrap(), None);
+/// same_email.set_email("other@Misato.wiki".to_string());
+/// assert!(matches!(store.create(&same_email).await, Err(UserError::AlreadyExists)));
+/// assert_eq!(store.get_by_email("OTHER@misato.wiki").await.unwrap().unwrap().uuid, other.uuid);
+/// assert!(store.delete(&other.uuid).await.unwrap());
+///
+/// assert!(store.delete(&user.uuid).await.unwrap());
+/// assert_eq!(store.get_by_uuid(&user.uuid).await.unwrap(), None);
+/// assert!(store.list(0, 10).await.unwrap().is_empty());
+/// # });
+/// ```
+#[derive(Default)]
+pub struct MemoryUserStore {
+    users: Mutex<Vec<User>>,
+}
+
+impl MemoryUserStore {
+    fn find<F: Fn(&User) -> bool>(&self, filter: F) -> Option<User> {
+        let users = self.users.lock().unwrap();
+        users
+            .iter()
+            .find(|user| user.deleted_at.is_none() && filter(user))
+            .cloned()
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryUserStore {
+    async fn create(&self, user: &User) -> Result<(), UserError> {
+        let mut users = self.users.lock().unwrap();
+        let key = canonical_username(&user.username);
+        let email_key = user.email.as_deref().map(canonical_email);
+        if users.iter().any(|other| {
+            other.deleted_at.is_none()
+                && (canonical_username(&other.username) == key
+                    || (email_key.is_some()
+                        && other.email.as_deref().map(canonical_email) == email_key))
+        }) {
+            return Err(UserError::AlreadyExists);
+        }
+        users.push(user.clone());
+        Ok(())
+    }
+
+    async fn create_many(&self, users: &[User]) -> Vec<Result<(), UserError>> {
+        let mut results = Vec::new();
+        for user in users {
+            results.push(self.create(user).await);
+        }
+        results
+    }
+
+    async fn get_by_username(&self, username: &str) -> Result<Option<User>, UserError> {
+        let key = canonical_username(username);
+        Ok(self.find(|user| canonical_username(&user.username) == key))
+    }
+
+    async fn get_by_email(&self, email: &str) -> Result<Option<User>, UserError> {
+        let key = Some(canonical_email(email));
+        Ok(self.find(|user| user.email.as_deref().map(canonical_email) == key))
+    }
+
+    async fn get_by_uuid(&self, uuid: &str) -> Result<Option<User>, UserError> {
+        Ok(self.find(|user| user.uuid == uuid))
+    }
+
+    async fn update(&self, user: &User) -> Result<bool, UserError> {
+        let mut users = self.users.lock().unwrap();
+        match users
+            .iter_mut()
+            .find(|other| other.deleted_at.is_none() && other.uuid == user.uuid)
+        {
+            Some(other) => {
+                *other = user.clone();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn delete(&self, uuid: &str) -> Result<bool, UserError> {
+        let mut users = self.users.lock().unwrap();
+        match users
+            .iter_mut()
+            .find(|user| user.deleted_at.is_none() && user.uuid == uuid)
+        {
+            Some(user) => {
+                user.deleted_at = Some(get_current_timestamp());
+                user.tokens = None;
+                user.refresh_tokens = None;
+                user.reset_token = None;
+                user.verification_token = None;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn list(&self, skip: u64, limit: i64) -> Result<Vec<User>, UserError> {
+        let users = self.users.lock().unwrap();
+        Ok(users
+            .iter()
+            .filter(|user| user.deleted_at.is_none())
+            .skip(skip as usize)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn count(&self) -> Result<u64, UserError> {
+        let users = self.users.lock().unwrap();
+        Ok(users
+            .iter()
+            .filter(|user| user.deleted_at.is_none())
+            .count() as u64)
+    }
+
+    /// The cursor is the position in the store, users are only ever appended.
+    async fn list_after(
+        &self,
+        cursor: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<(String, User)>, UserError> {
+        let start = match cursor {
+            Some(cursor) => match cursor.parse::<usize>() {
+                Ok(position) => position + 1,
+                Err(_) => return Err(UserError::InvalidCursor),
+            },
+            None => 0,
+        };
+        let users = self.users.lock().unwrap();
+        Ok(users
+            .iter()
+            .enumerate()
+            .skip(start)
+            .filter(|(_, user)| user.deleted_at.is_none())
+            .take(limit.max(0) as usize)
+            .map(|(position, user)| (position.to_string(), user.clone()))
+            .collect())
+    }
+}
@@ -0,0 +1,37 @@
+//! Time to hash and to verify a password for several argon2 costs, run with `cargo bench`.
+//! Memory is the `mem_cost` itself, argon2 allocates all of it for each hash.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+
+use misato_security::password::*;
+
+fn passwords(c: &mut Criterion) {
+    let sweep = Argon2Params::sweep(
+        &[4096, 19456, 65536],
+        &[1, 2, 3],
+        &[1, 4],
+        Argon2Variant::Argon2id,
+    );
+    let mut group = c.benchmark_group("argon2id");
+    // Each run takes up to a second at the largest costs
+    group.sample_size(10);
+    for params in sweep {
+        let costs = format!(
+            "{}KiB/t{}/p{}",
+            params.mem_cost, params.time_cost, params.lanes
+        );
+        let password = Password::hash_password_with(&params, b"anypassword").unwrap();
+        group.bench_with_input(BenchmarkId::new("hash", &costs), &params, |b, params| {
+            b.iter(|| Password::hash_password_with(params, b"anypassword").unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("verify", &costs),
+            &password,
+            |b, password| b.iter(|| assert!(password.is_correct_password(b"anypassword"))),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, passwords);
+criterion_main!(benches);
@@ -0,0 +1,84 @@
+use jsonwebtoken::{errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+#[derive(Eq, Hash, PartialEq, Debug, Serialize, Deserialize, Clone)]
+pub struct Claims {
+    pub sub: String, // User uuid
+    pub iat: u64,    // In seconds
+    pub exp: u64,    // In seconds
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Clone)]
+pub enum JwtError {
+    Expired,
+    InvalidSignature,
+    Invalid,
+}
+
+impl From<jsonwebtoken::errors::Error> for JwtError {
+    fn from(error: jsonwebtoken::errors::Error) -> Self {
+        match error.kind() {
+            ErrorKind::ExpiredSignature => JwtError::Expired,
+            ErrorKind::InvalidSignature => JwtError::InvalidSignature,
+            _ => JwtError::Invalid,
+        }
+    }
+}
+
+/// Sign the given claims with HS256.
+pub fn encode_claims(claims: &Claims, secret: &[u8]) -> Result<String, JwtError> {
+    Ok(jsonwebtoken::encode(
+        &Header::new(Algorithm::HS256),
+        claims,
+        &EncodingKey::from_secret(secret),
+    )?)
+}
+
+/// Create an access token for the user, valid for `ttl` seconds.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::jwt::*;
+///
+/// let token = encode_access_token("uuid", 60, b"secret").unwrap();
+/// let claims = decode_access_token(&token, b"secret").unwrap();
+///
+/// assert_eq!(claims.sub, "uuid");
+/// assert_eq!(claims.exp, claims.iat + 60);
+/// ```
+pub fn encode_access_token(user_id: &str, ttl: u64, secret: &[u8]) -> Result<String, JwtError> {
+    let now = jsonwebtoken::get_current_timestamp();
+    encode_claims(
+        &Claims {
+            sub: user_id.to_string(),
+            iat: now,
+            exp: now + ttl,
+        },
+        secret,
+    )
+}
+
+/// Check the signature and the expiration of an access token.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::jwt::*;
+///
+/// let token = encode_access_token("uuid", 60, b"secret").unwrap();
+/// assert_eq!(decode_access_token(&token, b"another secret"), Err(JwtError::InvalidSignature));
+///
+/// let mut tampered = token.clone();
+/// tampered.pop();
+/// tampered.push(if token.ends_with('A') { 'B' } else { 'A' });
+/// assert!(decode_access_token(&tampered, b"secret").is_err());
+///
+/// let expired = encode_claims(&Claims { sub: "uuid".to_string(), iat: 0, exp: 1 }, b"secret");
+/// assert_eq!(decode_access_token(&expired.unwrap(), b"secret"), Err(JwtError::Expired));
+/// ```
+pub fn decode_access_token(token: &str, secret: &[u8]) -> Result<Claims, JwtError> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.leeway = 0;
+    let data =
+        jsonwebtoken::decode::<Claims>(token, &DecodingKey::from_secret(secret), &validation)?;
+    Ok(data.claims)
+}
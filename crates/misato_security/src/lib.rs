@@ -1,6 +1,16 @@
-use rand::{distributions::Alphanumeric, Rng};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, rngs::OsRng, Rng, RngCore};
+use sha2::{Digest, Sha256};
+
+pub mod jwt;
 pub mod password;
+pub mod policy;
+pub mod pwned;
+pub mod rate_limit;
+pub mod totp;
 
 pub fn generate_token(size: usize) -> String {
     rand::thread_rng()
@@ -9,3 +19,139 @@ pub fn generate_token(size: usize) -> String {
         .map(char::from)
         .collect()
 }
+
+pub const DEFAULT_TOKEN_BYTES: usize = 32;
+/// Below 128 bits of entropy a token could be guessed.
+pub const MIN_TOKEN_BYTES: usize = 16;
+
+static TOKEN_BYTES: AtomicUsize = AtomicUsize::new(DEFAULT_TOKEN_BYTES);
+
+/// `bytes` from the operating system CSPRNG, base64url encoded without padding
+/// so the token can go in an url or a header as is.
+/// Basic usage:
+///
+/// ```
+/// use std::collections::HashSet;
+/// use misato_security::generate_url_token;
+///
+/// let tokens: HashSet<String> = (0..10000).map(|_| generate_url_token(32)).collect();
+/// assert_eq!(tokens.len(), 10000);
+/// assert!(tokens.iter().all(|token| token.len() == 43));
+/// assert_eq!(
+///     tokens.iter().all(|token| token
+///         .bytes()
+///         .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_')),
+///     true
+/// );
+/// assert_eq!(generate_url_token(48).len(), 64);
+/// ```
+pub fn generate_url_token(bytes: usize) -> String {
+    let mut random_bytes = vec![0u8; bytes];
+    OsRng.fill_bytes(&mut random_bytes);
+    URL_SAFE_NO_PAD.encode(random_bytes)
+}
+
+/// Set the size used by `generate_url_token_default`, done by `Settings::init`.
+pub fn set_token_bytes(bytes: usize) {
+    TOKEN_BYTES.store(bytes, Ordering::Relaxed);
+}
+
+/// Generate a token of the configured size (32 bytes unless configured).
+/// Basic usage:
+///
+/// ```
+/// use misato_security::generate_url_token_default;
+///
+/// assert_eq!(generate_url_token_default().len(), 43);
+/// ```
+pub fn generate_url_token_default() -> String {
+    generate_url_token(TOKEN_BYTES.load(Ordering::Relaxed))
+}
+
+/// Hex encoded SHA-256 of a token, to store it without being able to use it.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::hash_token;
+///
+/// let hashed = hash_token("token");
+///
+/// assert_eq!(hashed.len(), 64);
+/// assert_eq!(hashed, hash_token("token"));
+/// assert!(hashed != hash_token("another token"));
+/// ```
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Compare secrets in a time that only depends on their length, never on where they differ.
+/// Every byte is visited and folded with XOR, there is no early return on the first mismatch,
+/// so a first byte difference takes as long as a last byte one.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::{constant_time_eq, hash_token};
+///
+/// let hash = hash_token("token");
+///
+/// assert!(constant_time_eq(hash.as_bytes(), hash_token("token").as_bytes()));
+/// assert!(!constant_time_eq(hash.as_bytes(), hash_token("other").as_bytes()));
+/// assert!(!constant_time_eq(b"abc", b"abd"));
+/// assert!(!constant_time_eq(b"abc", b"xbc"));
+/// assert!(!constant_time_eq(b"abc", b"abcd"));
+/// assert!(constant_time_eq(b"", b""));
+/// ```
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let difference = a
+        .iter()
+        .zip(b.iter())
+        .fold(0u8, |difference, (x, y)| difference | (x ^ y));
+    std::hint::black_box(difference) == 0
+}
+
+/// Whether the value looks like the output of `hash_token`, generated tokens never do.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::{generate_token, generate_url_token, hash_token, is_token_hash};
+///
+/// assert!(is_token_hash(&hash_token("token")));
+/// assert!(!is_token_hash(&generate_token(128)));
+/// assert!(!is_token_hash(&generate_url_token(48)));
+/// assert!(!is_token_hash(&hash_token("token").to_uppercase()));
+/// ```
+pub fn is_token_hash(value: &str) -> bool {
+    value.len() == 64
+        && value
+            .bytes()
+            .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Hex encoded HMAC-SHA256 of a payload, for the receiver to check it came from us.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::sign_payload;
+///
+/// let signature = sign_payload(b"secret", b"{}");
+///
+/// assert_eq!(signature.len(), 64);
+/// assert_eq!(signature, sign_payload(b"secret", b"{}"));
+/// assert!(signature != sign_payload(b"another secret", b"{}"));
+/// assert!(signature != sign_payload(b"secret", b"{ }"));
+/// ```
+pub fn sign_payload(secret: &[u8], payload: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(payload);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
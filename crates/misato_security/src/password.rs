@@ -1,16 +1,346 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use base64::{engine::general_purpose, Engine as _};
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use zeroize::{Zeroize, Zeroizing};
 
-#[derive(Eq, Hash, PartialEq, Debug, Default, Clone, Serialize, Deserialize)]
+/// Serialized for storage only, responses must go through a dedicated DTO.
+#[derive(Eq, Hash, PartialEq, Default, Clone, Serialize, Deserialize)]
 pub struct Password {
-    pub salt: Vec<u8>,
-    pub hash: Vec<u8>,
+    pub salt: Vec<u8>, // Empty once encoded
+    pub hash: Vec<u8>, // Empty once encoded
+    #[serde(default = "Argon2Params::legacy")]
+    pub params: Argon2Params,
+    #[serde(default)]
+    pub peppered: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoded: Option<String>, // PHC string, `$argon2id$v=19$...`
+}
+
+/// Keep the salt and the hash out of the logs.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::password::*;
+///
+/// let password = Password::hash_password(b"password").unwrap();
+/// let debug = format!("{:?}", password);
+///
+/// assert!(!debug.contains(&format!("{:?}", password.hash)));
+/// assert!(!debug.contains(&format!("{:?}", password.salt)));
+/// ```
+impl std::fmt::Debug for Password {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Password")
+            .field("salt", &"[redacted]")
+            .field("hash", &"[redacted]")
+            .field("params", &self.params)
+            .field("peppered", &self.peppered)
+            .field("encoded", &self.encoded.as_ref().map(|_| "[redacted]"))
+            .finish()
+    }
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy, Serialize, Deserialize, Default)]
+pub enum Argon2Variant {
+    Argon2d,
+    Argon2i,
+    #[default]
+    Argon2id,
+}
+
+impl FromStr for Argon2Variant {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "argon2d" => Ok(Argon2Variant::Argon2d),
+            "argon2i" => Ok(Argon2Variant::Argon2i),
+            "argon2id" => Ok(Argon2Variant::Argon2id),
+            _ => Err(format!("[{}]: Unknown argon2 variant.", value)),
+        }
+    }
+}
+
+impl From<Argon2Variant> for argon2::Variant {
+    fn from(variant: Argon2Variant) -> Self {
+        match variant {
+            Argon2Variant::Argon2d => argon2::Variant::Argon2d,
+            Argon2Variant::Argon2i => argon2::Variant::Argon2i,
+            Argon2Variant::Argon2id => argon2::Variant::Argon2id,
+        }
+    }
+}
+
+/// Cost parameters given to argon2, Argon2id by default.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Argon2Params {
+    pub mem_cost: u32, // Memory in KiB
+    pub time_cost: u32,
+    pub lanes: u32,
+    pub variant: Argon2Variant,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Self {
+        Self {
+            mem_cost: 4096,
+            time_cost: 3,
+            lanes: 1,
+            variant: Argon2Variant::default(),
+        }
+    }
+}
+
+impl Argon2Params {
+    /// Matches `argon2::Config::default()`, so passwords stored
+    /// before the parameters were recorded still verify.
+    pub fn legacy() -> Self {
+        Self {
+            variant: Argon2Variant::Argon2i,
+            ..Self::default()
+        }
+    }
+
+    /// Every combination of the given costs, to compare them with `cargo bench`.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// let sweep = Argon2Params::sweep(&[4096, 65536], &[1, 3], &[1], Argon2Variant::Argon2id);
+    ///
+    /// assert_eq!(sweep.len(), 4);
+    /// assert_eq!(sweep[0], Argon2Params { time_cost: 1, ..Argon2Params::default() });
+    /// assert_eq!(sweep[3], Argon2Params { mem_cost: 65536, ..Argon2Params::default() });
+    /// ```
+    pub fn sweep(
+        mem_costs: &[u32],
+        time_costs: &[u32],
+        lanes: &[u32],
+        variant: Argon2Variant,
+    ) -> Vec<Self> {
+        let mut sweep = Vec::new();
+        for &mem_cost in mem_costs {
+            for &time_cost in time_costs {
+                for &lanes in lanes {
+                    sweep.push(Self {
+                        mem_cost,
+                        time_cost,
+                        lanes,
+                        variant,
+                    });
+                }
+            }
+        }
+        sweep
+    }
+
+    pub fn config(&self) -> argon2::Config<'static> {
+        argon2::Config {
+            mem_cost: self.mem_cost,
+            time_cost: self.time_cost,
+            lanes: self.lanes,
+            variant: self.variant.into(),
+            ..argon2::Config::default()
+        }
+    }
+
+    /// Hash a dummy value, so parameters argon2 refuses are found before any password is hashed.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// assert!(Argon2Params::default().validate().is_ok());
+    /// assert!(Argon2Params { lanes: 0, ..Argon2Params::default() }.validate().is_err());
+    /// assert!(Argon2Params { mem_cost: 8, lanes: 2, ..Argon2Params::default() }.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), argon2::Error> {
+        let salt = [0; DEFAULT_MIN_STORED_SALT_LENGTH];
+        argon2::hash_raw(b"password", &salt, &self.config()).map(|_| ())
+    }
+}
+
+/// How new hashes are stored: raw salt and hash bytes, or a PHC string.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub enum PasswordFormat {
+    Raw,
+    Phc,
+}
+
+impl FromStr for PasswordFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "raw" => Ok(PasswordFormat::Raw),
+            "phc" => Ok(PasswordFormat::Phc),
+            _ => Err(format!("[{}]: Unknown password format.", value)),
+        }
+    }
+}
+
+static PHC_FORMAT: AtomicBool = AtomicBool::new(false);
+
+/// Set the format of the hashes made from now on, done by `Settings::init`.
+pub fn set_password_format(format: PasswordFormat) {
+    PHC_FORMAT.store(format == PasswordFormat::Phc, Ordering::Relaxed);
+}
+
+pub fn password_format() -> PasswordFormat {
+    match PHC_FORMAT.load(Ordering::Relaxed) {
+        true => PasswordFormat::Phc,
+        false => PasswordFormat::Raw,
+    }
+}
+
+pub const DEFAULT_SALT_SIZE: usize = 16;
+
+/// Argon2 refuses shorter salts, a stored one this short can only be corrupt.
+pub const DEFAULT_MIN_STORED_SALT_LENGTH: usize = 8;
+pub const DEFAULT_MIN_STORED_HASH_LENGTH: usize = 16;
+
+/// Smallest salt and hash a stored password may have, in bytes.
+#[derive(Eq, Hash, PartialEq, Debug, Clone, Copy)]
+pub struct PasswordIntegrity {
+    pub min_salt_length: usize,
+    pub min_hash_length: usize,
+}
+
+impl Default for PasswordIntegrity {
+    fn default() -> Self {
+        Self {
+            min_salt_length: DEFAULT_MIN_STORED_SALT_LENGTH,
+            min_hash_length: DEFAULT_MIN_STORED_HASH_LENGTH,
+        }
+    }
+}
+
+/// Why a stored password can't verify anything, whatever is typed.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum CorruptPassword {
+    SaltTooShort(usize), // Length found, in bytes
+    HashTooShort(usize), // Length found, in bytes
+    MalformedEncoding,
 }
 
+impl std::fmt::Display for CorruptPassword {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CorruptPassword::SaltTooShort(length) => write!(f, "salt of {} bytes", length),
+            CorruptPassword::HashTooShort(length) => write!(f, "hash of {} bytes", length),
+            CorruptPassword::MalformedEncoding => write!(f, "malformed PHC string"),
+        }
+    }
+}
+
+/// Why a password couldn't be checked at all, unlike a wrong one this is never the user's fault.
+#[derive(PartialEq, Debug, Clone)]
+pub enum VerifyError {
+    Argon2(argon2::Error), // The stored hash or its parameters were refused
+    MissingPepper,         // Peppered hash, without a pepper configured
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyError::Argon2(error) => write!(f, "argon2: {}", error),
+            VerifyError::MissingPepper => write!(f, "peppered hash without a pepper"),
+        }
+    }
+}
+
+static SALT_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_SALT_SIZE);
+
+/// Salt is filled in one call from the operating system CSPRNG.
+/// Basic usage:
+///
+/// ```
+/// use std::collections::HashSet;
+/// use misato_security::password::*;
+///
+/// let salts: HashSet<Vec<u8>> = (0..1000).map(|_| generate_salt(16)).collect();
+/// assert_eq!(salts.len(), 1000);
+/// assert!(salts.iter().all(|salt| salt.len() == 16));
+/// ```
 pub fn generate_salt(size: usize) -> Vec<u8> {
-    let random_bytes: Vec<u8> = (0..size).map(|_| rand::random::<u8>()).collect();
+    let mut random_bytes = vec![0u8; size];
+    OsRng.fill_bytes(&mut random_bytes);
     random_bytes
 }
 
+/// Set the salt size used by `generate_salt_default`, done by `Settings::init`.
+pub fn set_salt_size(size: usize) {
+    SALT_SIZE.store(size, Ordering::Relaxed);
+}
+
+/// Generate a salt of the configured size (16 bytes unless configured).
+/// Basic usage:
+///
+/// ```
+/// use misato_security::password::*;
+///
+/// assert_eq!(generate_salt_default().len(), DEFAULT_SALT_SIZE);
+/// ```
+pub fn generate_salt_default() -> Vec<u8> {
+    generate_salt(SALT_SIZE.load(Ordering::Relaxed))
+}
+
+/// Plain text password wiped from memory once dropped.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::password::*;
+/// use zeroize::Zeroize;
+///
+/// let mut password = SecurePassword::from("anypassword".to_string());
+/// assert_eq!(password.as_bytes(), b"anypassword");
+///
+/// password.zeroize();
+/// assert!(password.as_bytes().is_empty());
+/// ```
+pub struct SecurePassword(Vec<u8>);
+
+impl SecurePassword {
+    pub fn new(password: Vec<u8>) -> Self {
+        Self(password)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<String> for SecurePassword {
+    fn from(password: String) -> Self {
+        Self(password.into_bytes())
+    }
+}
+
+impl Zeroize for SecurePassword {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecurePassword {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+/// Mix the server-side pepper into the password with HMAC-SHA256.
+fn pepper_password(pepper: &[u8], password: &[u8]) -> Zeroizing<Vec<u8>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(pepper).expect("HMAC accepts any key length");
+    mac.update(password);
+    Zeroizing::new(mac.finalize().into_bytes().to_vec())
+}
+
 impl Password {
     /// Random salt is generated everytime this function is called.
     /// Hash is always different in that case.
@@ -19,16 +349,42 @@ impl Password {
     /// ```
     /// use misato_security::password::*;
     ///
-    /// let encrypted_password = Password::hash_password(b"anypassword");
-    /// let same_password = Password::hash_password(b"anypassword");
-    /// assert_eq!(same_password.salt != encrypted_password.salt, true);
-    /// assert_eq!(same_password.hash != encrypted_password.hash, true);
+    /// let encrypted_password = Password::hash_password(b"anypassword").unwrap();
+    /// let same_password = Password::hash_password(b"anypassword").unwrap();
+    /// assert!(same_password.salt != encrypted_password.salt);
+    /// assert!(same_password.hash != encrypted_password.hash);
     /// ```
-    pub fn hash_password(password: &[u8]) -> Self {
-        let salt = generate_salt(256);
-        let hash = argon2::hash_raw(password, &salt, &argon2::Config::default()).unwrap();
+    pub fn hash_password(password: &[u8]) -> Result<Self, argon2::Error> {
+        Self::hash_password_with(&Argon2Params::default(), password)
+    }
 
-        Self { salt, hash }
+    /// Same as `hash_password` but with the given argon2 parameters.
+    /// The parameters are stored alongside the hash so verification uses them.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// let params = Argon2Params {
+    ///     mem_cost: 8192,
+    ///     time_cost: 2,
+    ///     lanes: 2,
+    ///     variant: Argon2Variant::Argon2id,
+    /// };
+    /// let default_password = Password::hash_password(b"anypassword").unwrap();
+    /// let custom_password = Password::hash_password_with(&params, b"anypassword").unwrap();
+    ///
+    /// assert_eq!(custom_password.params, params);
+    /// assert!(default_password.is_correct_password(b"anypassword"));
+    /// assert!(custom_password.is_correct_password(b"anypassword"));
+    /// assert!(!custom_password.is_correct_password(b"anotherpassword"));
+    /// ```
+    pub fn hash_password_with(
+        params: &Argon2Params,
+        password: &[u8],
+    ) -> Result<Self, argon2::Error> {
+        let salt = generate_salt_default();
+        Self::hash_password_salt_with(params, &salt, password)
     }
 
     /// You have to provide the salt.
@@ -39,23 +395,236 @@ impl Password {
     /// use misato_security::password::*;
     ///
     /// let salt = generate_salt(256); // 256 bytes salt
-    /// let encrypted_password = Password::hash_password_salt(&salt, b"anypassword");
-    /// let same_password = Password::hash_password_salt(&salt, b"anypassword");
-    /// let another_password = Password::hash_password_salt(&salt, b"anotherpassword");
+    /// let encrypted_password = Password::hash_password_salt(&salt, b"anypassword").unwrap();
+    /// let same_password = Password::hash_password_salt(&salt, b"anypassword").unwrap();
+    /// let another_password = Password::hash_password_salt(&salt, b"anotherpassword").unwrap();
+    ///
+    /// assert_eq!(encrypted_password.salt, same_password.salt);
+    /// assert_eq!(encrypted_password.salt, another_password.salt);
     ///
-    /// assert_eq!(encrypted_password.salt == same_password.salt, true);
-    /// assert_eq!(encrypted_password.salt == another_password.salt, true);
+    /// assert_eq!(encrypted_password.hash, same_password.hash);
+    /// assert!(encrypted_password.hash != another_password.hash);
+    /// ```
+    pub fn hash_password_salt(salt: &[u8], password: &[u8]) -> Result<Password, argon2::Error> {
+        Self::hash_password_salt_with(&Argon2Params::default(), salt, password)
+    }
+
+    /// Same as `hash_password_salt` but with the given argon2 parameters.
+    /// The hash is a PHC string when the format is set to `PasswordFormat::Phc`:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// set_password_format(PasswordFormat::Phc);
+    /// let password = Password::hash_password_with(&Argon2Params::default(), b"anypassword").unwrap();
+    /// set_password_format(PasswordFormat::Raw);
     ///
-    /// assert_eq!(encrypted_password.hash == same_password.hash, true);
-    /// assert_eq!(encrypted_password.hash == another_password.hash, false);
+    /// assert!(password.encoded.as_ref().unwrap().starts_with("$argon2id$v=19$"));
+    /// assert!(password.hash.is_empty());
+    /// assert!(password.is_correct_password(b"anypassword"));
+    /// assert!(!password.is_correct_password(b"anotherpassword"));
     /// ```
-    pub fn hash_password_salt(salt: &[u8], password: &[u8]) -> Password {
-        let hash = argon2::hash_raw(password, &salt, &argon2::Config::default()).unwrap();
+    pub fn hash_password_salt_with(
+        params: &Argon2Params,
+        salt: &[u8],
+        password: &[u8],
+    ) -> Result<Password, argon2::Error> {
+        let config = params.config();
+        let (salt, hash, encoded) = match password_format() {
+            PasswordFormat::Raw => (
+                salt.to_vec(),
+                argon2::hash_raw(password, salt, &config)?,
+                None,
+            ),
+            PasswordFormat::Phc => (
+                Vec::new(),
+                Vec::new(),
+                Some(argon2::hash_encoded(password, salt, &config)?),
+            ),
+        };
 
-        Password {
-            salt: salt.iter().cloned().collect(),
+        Ok(Password {
+            salt,
             hash,
+            params: *params,
+            peppered: false,
+            encoded,
+        })
+    }
+
+    /// Same password stored as a PHC string, it verifies exactly like the raw one.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// let raw = Password::hash_password_peppered(b"pepper", b"anypassword").unwrap();
+    /// let encoded = raw.to_encoded();
+    ///
+    /// assert!(encoded.encoded.as_ref().unwrap().starts_with("$argon2id$v=19$m=4096,t=3,p=1$"));
+    /// assert!(encoded.verify(Some(b"pepper"), b"anypassword"));
+    /// assert!(!encoded.verify(Some(b"pepper"), b"anotherpassword"));
+    /// assert_eq!(encoded.to_encoded(), encoded);
+    ///
+    /// let legacy = Password::hash_password_with(&Argon2Params::legacy(), b"anypassword").unwrap().to_encoded();
+    /// assert!(legacy.encoded.as_ref().unwrap().starts_with("$argon2i$"));
+    /// assert!(legacy.is_correct_password(b"anypassword"));
+    /// ```
+    pub fn to_encoded(&self) -> Password {
+        if self.encoded.is_some() {
+            return self.clone();
+        }
+        let config = self.params.config();
+        let encoded = format!(
+            "${}$v={}$m={},t={},p={}${}${}",
+            config.variant,
+            config.version,
+            config.mem_cost,
+            config.time_cost,
+            config.lanes,
+            general_purpose::STANDARD_NO_PAD.encode(&self.salt),
+            general_purpose::STANDARD_NO_PAD.encode(&self.hash),
+        );
+        Password {
+            salt: Vec::new(),
+            hash: Vec::new(),
+            params: self.params,
+            peppered: self.peppered,
+            encoded: Some(encoded),
+        }
+    }
+
+    /// The password is HMAC'd with the pepper before being hashed.
+    /// The pepper is never stored, a database leak alone is not enough to crack it.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// let encrypted_password = Password::hash_password_peppered(b"pepper A", b"anypassword").unwrap();
+    ///
+    /// assert!(encrypted_password.peppered);
+    /// assert!(encrypted_password.is_correct_password_peppered(b"pepper A", b"anypassword"));
+    /// assert!(!encrypted_password.is_correct_password_peppered(b"pepper B", b"anypassword"));
+    /// assert!(!encrypted_password.is_correct_password(b"anypassword"));
+    /// ```
+    pub fn hash_password_peppered(pepper: &[u8], password: &[u8]) -> Result<Self, argon2::Error> {
+        Self::hash_password_peppered_with(&Argon2Params::default(), pepper, password)
+    }
+
+    /// Same as `hash_password_peppered` but with the given argon2 parameters.
+    pub fn hash_password_peppered_with(
+        params: &Argon2Params,
+        pepper: &[u8],
+        password: &[u8],
+    ) -> Result<Self, argon2::Error> {
+        let mut password = Self::hash_password_with(params, &pepper_password(pepper, password))?;
+        password.peppered = true;
+        Ok(password)
+    }
+
+    /// Hash a password with the given parameters, peppered when a pepper is given.
+    /// Counterpart of `verify`.
+    pub fn hash(
+        params: &Argon2Params,
+        pepper: Option<&[u8]>,
+        password: &[u8],
+    ) -> Result<Self, argon2::Error> {
+        match pepper {
+            Some(pepper) => Self::hash_password_peppered_with(params, pepper, password),
+            None => Self::hash_password_with(params, password),
+        }
+    }
+
+    /// Check if the hash has been computed with other parameters than the given ones,
+    /// the variant included.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// let params = Argon2Params {
+    ///     time_cost: 4,
+    ///     ..Argon2Params::default()
+    /// };
+    /// let old_password = Password::hash_password(b"anypassword").unwrap();
+    /// let new_password = Password::hash_password_with(&params, b"anypassword").unwrap();
+    ///
+    /// assert!(old_password.needs_rehash(&params));
+    /// assert!(!new_password.needs_rehash(&params));
+    /// ```
+    pub fn needs_rehash(&self, params: &Argon2Params) -> bool {
+        self.needs_variant_upgrade(params)
+            || self.params.mem_cost != params.mem_cost
+            || self.params.time_cost != params.time_cost
+            || self.params.lanes != params.lanes
+    }
+
+    /// Check if the hash has been computed with another argon2 variant than the given one.
+    /// Hashes from before the variant was selectable are Argon2i, they still verify.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// let params = Argon2Params::default();
+    /// let old_password = Password::hash_password_with(&Argon2Params::legacy(), b"anypassword").unwrap();
+    /// let new_password = Password::hash_password(b"anypassword").unwrap();
+    ///
+    /// assert_eq!(params.variant, Argon2Variant::Argon2id);
+    /// assert_eq!(old_password.params.variant, Argon2Variant::Argon2i);
+    /// assert!(old_password.is_correct_password(b"anypassword"));
+    /// assert!(old_password.needs_variant_upgrade(&params));
+    /// assert!(old_password.needs_rehash(&params));
+    /// assert!(!new_password.needs_variant_upgrade(&params));
+    /// ```
+    pub fn needs_variant_upgrade(&self, params: &Argon2Params) -> bool {
+        self.params.variant != params.variant
+    }
+
+    /// A corrupt password never verifies, so it has to be told apart from a wrong one.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// let integrity = PasswordIntegrity::default();
+    /// let password = Password::hash_password(b"anypassword").unwrap();
+    /// assert_eq!(password.check_integrity(&integrity), Ok(()));
+    /// assert_eq!(password.to_encoded().check_integrity(&integrity), Ok(()));
+    ///
+    /// let corrupt = Password { salt: Vec::new(), ..password.clone() };
+    /// assert_eq!(corrupt.check_integrity(&integrity), Err(CorruptPassword::SaltTooShort(0)));
+    /// assert!(!corrupt.is_correct_password(b"anypassword"));
+    ///
+    /// let truncated = Password { hash: password.hash[..4].to_vec(), ..password.clone() };
+    /// assert_eq!(truncated.check_integrity(&integrity), Err(CorruptPassword::HashTooShort(4)));
+    ///
+    /// let mangled = Password { encoded: Some("$argon2id$v=19$".to_string()), ..password };
+    /// assert_eq!(mangled.check_integrity(&integrity), Err(CorruptPassword::MalformedEncoding));
+    /// ```
+    pub fn check_integrity(&self, integrity: &PasswordIntegrity) -> Result<(), CorruptPassword> {
+        let (salt, hash) = match &self.encoded {
+            // `$variant$v=19$m=4096,t=3,p=1$salt$hash`
+            Some(encoded) => match encoded.split('$').collect::<Vec<&str>>()[..] {
+                ["", _, _, _, salt, hash] => match (
+                    general_purpose::STANDARD_NO_PAD.decode(salt),
+                    general_purpose::STANDARD_NO_PAD.decode(hash),
+                ) {
+                    (Ok(salt), Ok(hash)) => (salt.len(), hash.len()),
+                    _ => return Err(CorruptPassword::MalformedEncoding),
+                },
+                _ => return Err(CorruptPassword::MalformedEncoding),
+            },
+            None => (self.salt.len(), self.hash.len()),
+        };
+        if salt < integrity.min_salt_length {
+            return Err(CorruptPassword::SaltTooShort(salt));
         }
+        if hash < integrity.min_hash_length {
+            return Err(CorruptPassword::HashTooShort(hash));
+        }
+        Ok(())
     }
 
     /// Check if a plain text password is equal to a hash password
@@ -64,14 +633,114 @@ impl Password {
     /// ```
     /// use misato_security::password::*;
     ///
-    /// let encrypted_password = Password::hash_password(b"anypassword");
-    /// assert_eq!(encrypted_password.is_correct_password(b"anypassword"), true);
-    /// assert_eq!(encrypted_password.is_correct_password(b"anotherpassword"), false);
+    /// let encrypted_password = Password::hash_password(b"anypassword").unwrap();
+    /// assert!(encrypted_password.is_correct_password(b"anypassword"));
+    /// assert!(!encrypted_password.is_correct_password(b"anotherpassword"));
+    /// ```
+    ///
+    /// The stored salt is used whatever its length,
+    /// passwords hashed with the former 256 bytes salt still verify:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// let old_password = Password::hash_password_salt(&generate_salt(256), b"anypassword").unwrap();
+    /// let new_password = Password::hash_password(b"anypassword").unwrap();
+    ///
+    /// assert_eq!(old_password.salt.len(), 256);
+    /// assert_eq!(new_password.salt.len(), DEFAULT_SALT_SIZE);
+    /// assert!(old_password.is_correct_password(b"anypassword"));
+    /// assert!(new_password.is_correct_password(b"anypassword"));
     /// ```
     pub fn is_correct_password(&self, password: &[u8]) -> bool {
-        match argon2::verify_raw(password, &self.salt, &self.hash, &argon2::Config::default()) {
-            Ok(result) => return result,
-            Err(_) => false,
+        self.check_password(password).unwrap_or(false)
+    }
+
+    /// Like `is_correct_password`, with an error when the stored hash can't be checked.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// let password = Password::hash_password(b"anypassword").unwrap();
+    /// assert_eq!(password.check_password(b"anypassword"), Ok(true));
+    /// assert_eq!(password.check_password(b"anotherpassword"), Ok(false));
+    /// assert_eq!(password.to_encoded().check_password(b"anotherpassword"), Ok(false));
+    ///
+    /// let corrupt = Password { salt: vec![0; 4], ..password.clone() };
+    /// assert!(corrupt.check_password(b"anypassword").is_err());
+    /// assert!(!corrupt.is_correct_password(b"anypassword"));
+    ///
+    /// let encoded = "$argon2id$v=19$m=lots,t=3,p=1$c2FsdHNhbHQ$aGFzaGhhc2g".to_string();
+    /// let mangled = Password { encoded: Some(encoded), ..password };
+    /// assert!(mangled.check_password(b"anypassword").is_err());
+    /// ```
+    pub fn check_password(&self, password: &[u8]) -> Result<bool, VerifyError> {
+        let result = match &self.encoded {
+            Some(encoded) => argon2::verify_encoded(encoded, password),
+            None => argon2::verify_raw(password, &self.salt, &self.hash, &self.params.config()),
+        };
+        result.map_err(VerifyError::Argon2)
+    }
+
+    /// Check a plain text password against a hash made with `hash_password_peppered`.
+    pub fn is_correct_password_peppered(&self, pepper: &[u8], password: &[u8]) -> bool {
+        self.is_correct_password(&pepper_password(pepper, password))
+    }
+
+    /// Check a plain text password whether or not it has been peppered.
+    /// A peppered hash never verifies without its pepper.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// let plain_password = Password::hash_password(b"anypassword").unwrap();
+    /// let peppered_password = Password::hash_password_peppered(b"pepper", b"anypassword").unwrap();
+    ///
+    /// assert!(plain_password.verify(Some(b"pepper"), b"anypassword"));
+    /// assert!(peppered_password.verify(Some(b"pepper"), b"anypassword"));
+    /// assert!(!peppered_password.verify(None, b"anypassword"));
+    /// ```
+    pub fn verify(&self, pepper: Option<&[u8]>, password: &[u8]) -> bool {
+        self.try_verify(pepper, password).unwrap_or(false)
+    }
+
+    /// Like `verify`, telling a wrong password apart from a record that can't be checked.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// let peppered_password = Password::hash_password_peppered(b"pepper", b"anypassword").unwrap();
+    ///
+    /// assert_eq!(peppered_password.try_verify(Some(b"pepper"), b"anypassword"), Ok(true));
+    /// assert_eq!(peppered_password.try_verify(Some(b"pepper"), b"anotherpassword"), Ok(false));
+    /// assert_eq!(peppered_password.try_verify(Some(b"another pepper"), b"anypassword"), Ok(false));
+    /// assert_eq!(
+    ///     peppered_password.try_verify(None, b"anypassword"),
+    ///     Err(VerifyError::MissingPepper)
+    /// );
+    /// ```
+    pub fn try_verify(&self, pepper: Option<&[u8]>, password: &[u8]) -> Result<bool, VerifyError> {
+        match (self.peppered, pepper) {
+            (false, _) => self.check_password(password),
+            (true, Some(pepper)) => self.check_password(&pepper_password(pepper, password)),
+            (true, None) => Err(VerifyError::MissingPepper),
         }
     }
+
+    /// Cost as much as `verify` with the same parameters, for when there is no account
+    /// to check, so response times don't reveal which usernames exist. Always false.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::password::*;
+    ///
+    /// assert!(!Password::verify_dummy(&Argon2Params::default(), None, b"anypassword"));
+    /// ```
+    pub fn verify_dummy(params: &Argon2Params, pepper: Option<&[u8]>, password: &[u8]) -> bool {
+        let _ = Self::hash(params, pepper, password);
+        false
+    }
 }
@@ -0,0 +1,165 @@
+use std::fmt;
+
+#[derive(Eq, Hash, PartialEq, Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize, // In characters
+    pub max_length: usize, // In bytes
+    pub require_lowercase: bool,
+    pub require_uppercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    pub banned_passwords: Option<Vec<String>>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 8,
+            max_length: 1024,
+            require_lowercase: false,
+            require_uppercase: false,
+            require_digit: false,
+            require_symbol: false,
+            banned_passwords: None,
+        }
+    }
+}
+
+#[derive(Eq, Hash, PartialEq, Debug, Clone)]
+pub enum PolicyViolation {
+    TooShort(usize),
+    TooLong(usize),
+    MissingLowercase,
+    MissingUppercase,
+    MissingDigit,
+    MissingSymbol,
+    Banned,
+    Pwned(u64),     // Breaches the password was seen in
+    PwnedUnchecked, // The breach check failed and fails closed
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyViolation::TooShort(min) => {
+                write!(f, "Password must be at least {} characters long.", min)
+            }
+            PolicyViolation::TooLong(max) => {
+                write!(f, "Password must be at most {} bytes long.", max)
+            }
+            PolicyViolation::MissingLowercase => {
+                write!(f, "Password must contain a lowercase letter.")
+            }
+            PolicyViolation::MissingUppercase => {
+                write!(f, "Password must contain an uppercase letter.")
+            }
+            PolicyViolation::MissingDigit => write!(f, "Password must contain a digit."),
+            PolicyViolation::MissingSymbol => write!(f, "Password must contain a symbol."),
+            PolicyViolation::Banned => write!(f, "Password is too common."),
+            PolicyViolation::Pwned(_) => {
+                write!(f, "Password appeared in a data breach, choose another one.")
+            }
+            PolicyViolation::PwnedUnchecked => write!(
+                f,
+                "Password could not be checked against known breaches, try again later."
+            ),
+        }
+    }
+}
+
+impl PasswordPolicy {
+    /// Only the upper bound, to run before hashing a password the policy wasn't checked on,
+    /// like the one of a login, a huge input costs as much to hash as to store.
+    ///
+    /// ```
+    /// use misato_security::policy::*;
+    ///
+    /// let policy = PasswordPolicy::default();
+    /// assert_eq!(policy.check_length(b"123"), Ok(()));
+    /// assert_eq!(policy.check_length(&[b'a'; 1024]), Ok(()));
+    /// assert_eq!(policy.check_length(&[b'a'; 1025]), Err(PolicyViolation::TooLong(1024)));
+    /// ```
+    pub fn check_length(&self, password: &[u8]) -> Result<(), PolicyViolation> {
+        if password.len() > self.max_length {
+            return Err(PolicyViolation::TooLong(self.max_length));
+        }
+        Ok(())
+    }
+
+    /// Check a plain text password against the policy.
+    /// The first violation found is returned.
+    ///
+    /// ```
+    /// use misato_security::policy::*;
+    ///
+    /// let policy = PasswordPolicy::default();
+    /// assert_eq!(policy.validate(b"anypassword"), Ok(()));
+    /// assert_eq!(policy.validate(b"123"), Err(PolicyViolation::TooShort(8)));
+    /// ```
+    ///
+    /// ```
+    /// use misato_security::policy::*;
+    ///
+    /// let policy = PasswordPolicy {
+    ///     max_length: 16,
+    ///     ..PasswordPolicy::default()
+    /// };
+    /// assert_eq!(policy.validate(&[b'a'; 17]), Err(PolicyViolation::TooLong(16)));
+    /// ```
+    ///
+    /// ```
+    /// use misato_security::policy::*;
+    ///
+    /// let policy = PasswordPolicy {
+    ///     require_lowercase: true,
+    ///     require_uppercase: true,
+    ///     require_digit: true,
+    ///     require_symbol: true,
+    ///     ..PasswordPolicy::default()
+    /// };
+    /// assert_eq!(policy.validate(b"ANYPASSWORD1!"), Err(PolicyViolation::MissingLowercase));
+    /// assert_eq!(policy.validate(b"anypassword1!"), Err(PolicyViolation::MissingUppercase));
+    /// assert_eq!(policy.validate(b"AnyPassword!"), Err(PolicyViolation::MissingDigit));
+    /// assert_eq!(policy.validate(b"AnyPassword1"), Err(PolicyViolation::MissingSymbol));
+    /// assert_eq!(policy.validate(b"AnyPassword1!"), Ok(()));
+    /// ```
+    ///
+    /// ```
+    /// use misato_security::policy::*;
+    ///
+    /// let policy = PasswordPolicy {
+    ///     banned_passwords: Some(vec!["password123".to_string()]),
+    ///     ..PasswordPolicy::default()
+    /// };
+    /// assert_eq!(policy.validate(b"PassWord123"), Err(PolicyViolation::Banned));
+    /// ```
+    pub fn validate(&self, password: &[u8]) -> Result<(), PolicyViolation> {
+        self.check_length(password)?;
+        let password = String::from_utf8_lossy(password);
+        if password.chars().count() < self.min_length {
+            return Err(PolicyViolation::TooShort(self.min_length));
+        }
+        if self.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+            return Err(PolicyViolation::MissingLowercase);
+        }
+        if self.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+            return Err(PolicyViolation::MissingUppercase);
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            return Err(PolicyViolation::MissingDigit);
+        }
+        if self.require_symbol && !password.chars().any(|c| !c.is_alphanumeric()) {
+            return Err(PolicyViolation::MissingSymbol);
+        }
+        if let Some(banned_passwords) = &self.banned_passwords {
+            let password = password.to_lowercase();
+            if banned_passwords
+                .iter()
+                .any(|banned| banned.to_lowercase() == password)
+            {
+                return Err(PolicyViolation::Banned);
+            }
+        }
+        Ok(())
+    }
+}
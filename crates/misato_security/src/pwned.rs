@@ -0,0 +1,48 @@
+use ring::digest::{digest, SHA1_FOR_LEGACY_USE_ONLY};
+
+/// Uppercase hex SHA-1 of a password, split for a k-anonymity range query:
+/// the 5 digits sent to the range API, and the 35 left that never leave the server.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::pwned::range_query;
+///
+/// let (prefix, suffix) = range_query(b"password");
+/// assert_eq!(prefix, "5BAA6");
+/// assert_eq!(suffix, "1E4C9B93F3F0682250B6CF8331B7EE68FD8");
+/// ```
+pub fn range_query(password: &[u8]) -> (String, String) {
+    let hash: String = digest(&SHA1_FOR_LEGACY_USE_ONLY, password)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect();
+    let suffix = hash[5..].to_string();
+    let mut prefix = hash;
+    prefix.truncate(5);
+    (prefix, suffix)
+}
+
+/// How many breaches the `suffix` was seen in, from a range response of `SUFFIX:COUNT` lines.
+/// Padding lines have a count of 0, so they never count as breached.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::pwned::breach_count;
+///
+/// let range = "003D68EB55068C33ACE09247EE4C639306B:3\r\n\
+///              1E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\r\n\
+///              7A3FC3F58D1C2D1B1C9E7B0A7B4F6E2D4C1:0";
+/// assert_eq!(breach_count(range, "1E4C9B93F3F0682250B6CF8331B7EE68FD8"), 9545824);
+/// assert_eq!(breach_count(range, "1e4c9b93f3f0682250b6cf8331b7ee68fd8"), 9545824);
+/// assert_eq!(breach_count(range, "7A3FC3F58D1C2D1B1C9E7B0A7B4F6E2D4C1"), 0);
+/// assert_eq!(breach_count(range, "0000000000000000000000000000000000A"), 0);
+/// ```
+pub fn breach_count(range: &str, suffix: &str) -> u64 {
+    range
+        .lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .find(|(hash, _)| hash.eq_ignore_ascii_case(suffix))
+        .and_then(|(_, count)| count.trim().parse().ok())
+        .unwrap_or(0)
+}
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+/// In-memory counter of failed attempts per key over a fixed time window.
+/// Timestamps are given in milliseconds so callers decide the clock.
+pub struct RateLimiter<K> {
+    window: u64, // In milliseconds
+    max_attempts: u32,
+    attempts: Mutex<HashMap<K, (u64, u32)>>,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    pub fn new(window_seconds: u64, max_attempts: u32) -> Self {
+        Self {
+            window: window_seconds * 1000,
+            max_attempts,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Seconds to wait before the key is allowed again, `None` if it is allowed.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::rate_limit::*;
+    ///
+    /// let limiter = RateLimiter::new(60, 2);
+    /// limiter.record_failure("127.0.0.1", 0);
+    /// assert_eq!(limiter.retry_after(&"127.0.0.1", 1000), None);
+    ///
+    /// limiter.record_failure("127.0.0.1", 1000);
+    /// assert_eq!(limiter.retry_after(&"127.0.0.1", 1000), Some(59));
+    /// assert_eq!(limiter.retry_after(&"127.0.0.2", 1000), None);
+    ///
+    /// // The counter resets once the window is over
+    /// assert_eq!(limiter.retry_after(&"127.0.0.1", 60_000), None);
+    /// ```
+    pub fn retry_after(&self, key: &K, now: u64) -> Option<u64> {
+        let attempts = self.attempts.lock().unwrap();
+        match attempts.get(key) {
+            Some((start, count)) if now < start + self.window && *count >= self.max_attempts => {
+                Some((start + self.window - now).div_ceil(1000))
+            }
+            _ => None,
+        }
+    }
+
+    /// Failed attempts of the key in its current window.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_security::rate_limit::*;
+    ///
+    /// let limiter = RateLimiter::new(60, 10);
+    /// limiter.record_failure("127.0.0.1", 0);
+    /// limiter.record_failure("127.0.0.1", 1000);
+    ///
+    /// assert_eq!(limiter.failures(&"127.0.0.1", 1000), 2);
+    /// assert_eq!(limiter.failures(&"127.0.0.2", 1000), 0);
+    /// assert_eq!(limiter.failures(&"127.0.0.1", 60_000), 0);
+    /// ```
+    pub fn failures(&self, key: &K, now: u64) -> u32 {
+        let attempts = self.attempts.lock().unwrap();
+        match attempts.get(key) {
+            Some((start, count)) if now < start + self.window => *count,
+            _ => 0,
+        }
+    }
+
+    /// Count a failed attempt, a new window starts if the previous one is over.
+    pub fn record_failure(&self, key: K, now: u64) {
+        let mut attempts = self.attempts.lock().unwrap();
+        // Forget expired windows so the map doesn't grow forever
+        attempts.retain(|_, (start, _)| now < *start + self.window);
+        let entry = attempts.entry(key).or_insert((now, 0));
+        entry.1 += 1;
+    }
+}
+
+/// Milliseconds to wait before answering the `failures`th consecutive failure,
+/// `base` doubled after each failure up to `cap`.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::rate_limit::progressive_delay;
+///
+/// assert_eq!(progressive_delay(0, 250, 5000), 0);
+/// assert_eq!(progressive_delay(1, 250, 5000), 250);
+/// assert_eq!(progressive_delay(3, 250, 5000), 1000);
+/// assert_eq!(progressive_delay(10, 250, 5000), 5000);
+/// assert_eq!(progressive_delay(100, 250, 5000), 5000);
+/// assert_eq!(progressive_delay(3, 0, 5000), 0);
+/// ```
+pub fn progressive_delay(failures: u32, base: u64, cap: u64) -> u64 {
+    if failures == 0 {
+        return 0;
+    }
+    base.saturating_mul(1 << (failures - 1).min(32)).min(cap)
+}
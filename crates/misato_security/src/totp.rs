@@ -0,0 +1,201 @@
+use rand::{rngs::OsRng, RngCore};
+use ring::{aead, hmac};
+use sha2::{Digest, Sha256};
+
+use crate::constant_time_eq;
+
+pub const TOTP_DIGITS: usize = 6;
+pub const TOTP_STEP: u64 = 30; // In seconds
+pub const TOTP_SECRET_SIZE: usize = 20; // As long as the SHA-1 output, RFC 4226 section 4
+
+const NONCE_SIZE: usize = 12;
+
+/// Random secret shared with the authenticator app.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::totp::*;
+///
+/// assert_eq!(generate_secret().len(), TOTP_SECRET_SIZE);
+/// assert!(generate_secret() != generate_secret());
+/// ```
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0u8; TOTP_SECRET_SIZE];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+/// Base32 without padding, the form authenticator apps ask for.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::totp::encode_secret;
+///
+/// assert_eq!(encode_secret(b"12345678901234567890"), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+/// ```
+pub fn encode_secret(secret: &[u8]) -> String {
+    data_encoding::BASE32_NOPAD.encode(secret)
+}
+
+fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// `otpauth://` URI to show as a QR code, `account` is what the app lists under `issuer`.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::totp::provisioning_uri;
+///
+/// assert_eq!(
+///     provisioning_uri(b"12345678901234567890", "Misato Wiki", "misato"),
+///     "otpauth://totp/Misato%20Wiki:misato?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+///      &issuer=Misato%20Wiki&algorithm=SHA1&digits=6&period=30"
+/// );
+/// ```
+pub fn provisioning_uri(secret: &[u8], issuer: &str, account: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        uri_encode(issuer),
+        uri_encode(account),
+        encode_secret(secret),
+        uri_encode(issuer),
+        TOTP_DIGITS,
+        TOTP_STEP
+    )
+}
+
+/// HOTP value of `counter`, RFC 4226 section 5.3.
+fn hotp(secret: &[u8], counter: u64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, secret);
+    let digest = hmac::sign(&key, &counter.to_be_bytes());
+    let digest = digest.as_ref();
+    let offset = (digest[digest.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        digest[offset] & 0x7f,
+        digest[offset + 1],
+        digest[offset + 2],
+        digest[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10u32.pow(TOTP_DIGITS as u32),
+        width = TOTP_DIGITS
+    )
+}
+
+/// Code of the step holding `timestamp`, in seconds.
+/// Basic usage, with the SHA-1 test vectors of RFC 6238 cut to 6 digits:
+///
+/// ```
+/// use misato_security::totp::code_at;
+///
+/// let secret = b"12345678901234567890";
+///
+/// assert_eq!(code_at(secret, 59), "287082");
+/// assert_eq!(code_at(secret, 1111111109), "081804");
+/// assert_eq!(code_at(secret, 2000000000), "279037");
+/// ```
+pub fn code_at(secret: &[u8], timestamp: u64) -> String {
+    hotp(secret, timestamp / TOTP_STEP)
+}
+
+/// Accept the codes of `skew` steps before and after the one holding `timestamp`, in seconds,
+/// for clocks that drift and codes typed at the end of their step. None of the `used` step or of
+/// an earlier one is accepted, so a code works once. Gives the step of the accepted code.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::totp::*;
+///
+/// let secret = generate_secret();
+/// let now = 1_700_000_000;
+/// let step = now / TOTP_STEP;
+/// let code = code_at(&secret, now);
+///
+/// assert_eq!(verify(&secret, &code, now, 1, None), Some(step));
+/// assert_eq!(verify(&secret, &code, now + TOTP_STEP, 1, None), Some(step));
+/// assert_eq!(verify(&secret, &code, now - TOTP_STEP, 1, None), Some(step));
+/// assert_eq!(verify(&secret, &code, now + 2 * TOTP_STEP, 1, None), None);
+/// assert_eq!(verify(&secret, &code, now + TOTP_STEP, 0, None), None);
+/// assert_eq!(verify(&secret, "12345", now, 1, None), None);
+/// // Replayed, or older than the last one used
+/// assert_eq!(verify(&secret, &code, now, 1, Some(step)), None);
+/// assert_eq!(verify(&secret, &code, now, 1, Some(step + 1)), None);
+/// assert_eq!(verify(&secret, &code, now, 1, Some(step - 1)), Some(step));
+/// ```
+pub fn verify(
+    secret: &[u8],
+    code: &str,
+    timestamp: u64,
+    skew: u64,
+    used: Option<u64>,
+) -> Option<u64> {
+    let step = timestamp / TOTP_STEP;
+    let mut accepted = None;
+    // Every step is checked, a match doesn't return earlier than a miss
+    for counter in step.saturating_sub(skew)..=step.saturating_add(skew) {
+        let valid = constant_time_eq(hotp(secret, counter).as_bytes(), code.as_bytes());
+        if valid && used.is_none_or(|used| counter > used) {
+            accepted = Some(counter);
+        }
+    }
+    accepted
+}
+
+fn aead_key(key: &str) -> aead::LessSafeKey {
+    let key = Sha256::digest(key.as_bytes());
+    aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_256_GCM, &key).unwrap())
+}
+
+/// AES-256-GCM with a key derived from `key`, the random nonce is stored in front.
+/// Basic usage:
+///
+/// ```
+/// use misato_security::totp::*;
+///
+/// let secret = generate_secret();
+/// let encrypted = encrypt_secret("totp key", &secret);
+///
+/// assert!(!encrypted.windows(secret.len()).any(|window| window == secret));
+/// assert_eq!(decrypt_secret("totp key", &encrypted), Some(secret));
+/// assert_eq!(decrypt_secret("another key", &encrypted), None);
+/// assert_eq!(decrypt_secret("totp key", &encrypted[..8]), None);
+/// ```
+pub fn encrypt_secret(key: &str, secret: &[u8]) -> Vec<u8> {
+    let mut nonce = [0u8; NONCE_SIZE];
+    OsRng.fill_bytes(&mut nonce);
+    let mut sealed = secret.to_vec();
+    aead_key(key)
+        .seal_in_place_append_tag(
+            aead::Nonce::assume_unique_for_key(nonce),
+            aead::Aad::empty(),
+            &mut sealed,
+        )
+        .unwrap();
+    let mut encrypted = nonce.to_vec();
+    encrypted.append(&mut sealed);
+    encrypted
+}
+
+/// None when `key` is not the one it was encrypted with, or `encrypted` was altered.
+pub fn decrypt_secret(key: &str, encrypted: &[u8]) -> Option<Vec<u8>> {
+    if encrypted.len() < NONCE_SIZE {
+        return None;
+    }
+    let (nonce, sealed) = encrypted.split_at(NONCE_SIZE);
+    let nonce = aead::Nonce::try_assume_unique_for_key(nonce).ok()?;
+    let mut sealed = sealed.to_vec();
+    let secret = aead_key(key)
+        .open_in_place(nonce, aead::Aad::empty(), &mut sealed)
+        .ok()?;
+    Some(secret.to_vec())
+}
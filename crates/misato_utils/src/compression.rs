@@ -0,0 +1,117 @@
+use std::io::Write;
+
+use flate2::{write::GzEncoder, write::ZlibEncoder, Compression};
+
+/// Content codings the api can answer with, in order of preference.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Encoding {
+    Br,
+    Gzip,
+    Deflate, // A zlib stream, as RFC 9110 defines `deflate`
+}
+
+const SUPPORTED: [Encoding; 3] = [Encoding::Br, Encoding::Gzip, Encoding::Deflate];
+const BROTLI_QUALITY: u32 = 5; // Of 11, higher ones cost far more time than they save bytes
+const BROTLI_WINDOW: u32 = 22; // In bits, the default of the reference encoder
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Br => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    /// The preferred supported coding of an `Accept-Encoding` value, `None` to send the body as is.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_utils::compression::Encoding;
+    ///
+    /// assert_eq!(Encoding::negotiate("gzip, deflate, br"), Some(Encoding::Br));
+    /// assert_eq!(Encoding::negotiate("gzip, deflate"), Some(Encoding::Gzip));
+    /// assert_eq!(Encoding::negotiate("gzip;q=0.5, deflate"), Some(Encoding::Deflate));
+    /// assert_eq!(Encoding::negotiate("*"), Some(Encoding::Br));
+    /// assert_eq!(Encoding::negotiate("*, br;q=0, gzip;q=0"), Some(Encoding::Deflate));
+    /// assert_eq!(Encoding::negotiate("zstd"), None);
+    /// assert_eq!(Encoding::negotiate("identity"), None);
+    /// ```
+    pub fn negotiate(accept_encoding: &str) -> Option<Self> {
+        let mut weights: Vec<(String, f32)> = Vec::new();
+        for item in accept_encoding.split(',') {
+            let mut parts = item.split(';').map(str::trim);
+            let name = parts.next().unwrap_or("").to_lowercase();
+            let weight = parts
+                .filter_map(|param| param.strip_prefix("q="))
+                .find_map(|value| value.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            weights.push((name, weight));
+        }
+        let weight = |name: &str| {
+            weights
+                .iter()
+                .find(|(item, _)| item == name)
+                .map(|(_, q)| *q)
+        };
+        let mut best: Option<(Self, f32)> = None;
+        for encoding in SUPPORTED {
+            let q = weight(encoding.name())
+                .or_else(|| weight("*"))
+                .unwrap_or(0.0);
+            if q > best.map(|(_, best_q)| best_q).unwrap_or(0.0) {
+                best = Some((encoding, q));
+            }
+        }
+        best.map(|(encoding, _)| encoding)
+    }
+
+    /// The body encoded with this coding.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use std::io::Read;
+    ///
+    /// use misato_utils::compression::Encoding;
+    ///
+    /// let body = "{\"username\":\"username\"}".repeat(100);
+    /// let gzip = Encoding::Gzip.encode(body.as_bytes());
+    /// let deflate = Encoding::Deflate.encode(body.as_bytes());
+    /// let br = Encoding::Br.encode(body.as_bytes());
+    ///
+    /// let mut decoded = String::new();
+    /// flate2::read::GzDecoder::new(&gzip[..]).read_to_string(&mut decoded).unwrap();
+    /// assert_eq!(decoded, body);
+    /// assert!(gzip.len() < body.len() / 10);
+    /// let mut decoded = String::new();
+    /// flate2::read::ZlibDecoder::new(&deflate[..]).read_to_string(&mut decoded).unwrap();
+    /// assert_eq!(decoded, body);
+    /// let mut decoded = String::new();
+    /// brotli::Decompressor::new(&br[..], 4096).read_to_string(&mut decoded).unwrap();
+    /// assert_eq!(decoded, body);
+    /// assert!(br.len() < body.len() / 10);
+    /// ```
+    pub fn encode(&self, data: &[u8]) -> Vec<u8> {
+        // Writing to a `Vec` can't fail
+        match self {
+            Encoding::Br => {
+                let mut out = Vec::new();
+                let mut encoder =
+                    brotli::CompressorWriter::new(&mut out, 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+                encoder.write_all(data).unwrap();
+                drop(encoder);
+                out
+            }
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+            Encoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use misato_security::password::DEFAULT_MIN_STORED_SALT_LENGTH;
+use tracing::warn;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Missing(String),        // Required key
+    Unreadable(String),     // Config file path
+    Invalid(String),        // Config file content
+    WeakAdminToken(String), // Reason
+    IncompleteTls(String),  // Missing key
+    CredentialedWildcard,   // Credentials with the `*` origin
+    SeededFirstUserAdmin,   // Both ways to get the first admin
+    ShortSalt(usize),       // Configured size, in bytes
+    Argon2(String),         // Why argon2 refused the parameters
+    InvalidValue(String),   // Key
+    Many(Vec<ConfigError>), // Every problem found
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Missing(key) => {
+                write!(
+                    f,
+                    "[{}] is not present in the environment nor in the config file!",
+                    key
+                )
+            }
+            ConfigError::Unreadable(path) => write!(f, "[{}]: Cannot read the config file.", path),
+            ConfigError::Invalid(reason) => write!(f, "Invalid config file: {}", reason),
+            ConfigError::WeakAdminToken(reason) => write!(
+                f,
+                "[MISATO_ADMIN_TOKEN] {}, set MISATO_ALLOW_WEAK_ADMIN_TOKEN to boot anyway.",
+                reason
+            ),
+            ConfigError::IncompleteTls(key) => write!(
+                f,
+                "[{}] is missing, set both MISATO_TLS_CERTS and MISATO_TLS_KEY to serve HTTPS, or neither.",
+                key
+            ),
+            ConfigError::CredentialedWildcard => write!(
+                f,
+                "[MISATO_CORS_ALLOW_CREDENTIALS] cannot be used with the `*` origin, list the origins in MISATO_CORS_ORIGINS."
+            ),
+            ConfigError::SeededFirstUserAdmin => write!(
+                f,
+                "[MISATO_ADMIN_TOKEN] seeds the admin, remove it or MISATO_FIRST_USER_ADMIN."
+            ),
+            ConfigError::ShortSalt(size) => write!(
+                f,
+                "[MISATO_SALT_SIZE] {} bytes is too short, Argon2 needs at least {}.",
+                size, DEFAULT_MIN_STORED_SALT_LENGTH
+            ),
+            ConfigError::Argon2(reason) => write!(
+                f,
+                "[MISATO_ARGON2_MEMORY_COST, MISATO_ARGON2_TIME_COST, MISATO_ARGON2_LANES] are refused by Argon2: {}.",
+                reason
+            ),
+            ConfigError::InvalidValue(key) => write!(f, "[{}] cannot be parsed.", key),
+            ConfigError::Many(errors) => {
+                write!(f, "{} problems in the configuration:", errors.len())?;
+                for error in errors {
+                    write!(f, "\n- {}", error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Values of an optional config file, overridden by the environment.
+/// The file uses the same keys as the environment, flat, in TOML or JSON.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    values: HashMap<String, String>,
+}
+
+fn toml_to_string(value: toml::Value) -> String {
+    match value {
+        toml::Value::String(v) => v,
+        toml::Value::Array(values) => values
+            .into_iter()
+            .map(toml_to_string)
+            .collect::<Vec<String>>()
+            .join(","),
+        v => v.to_string(),
+    }
+}
+
+fn json_to_string(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(v) => v,
+        serde_json::Value::Array(values) => values
+            .into_iter()
+            .map(json_to_string)
+            .collect::<Vec<String>>()
+            .join(","),
+        v => v.to_string(),
+    }
+}
+
+impl Config {
+    /// The file given by `MISATO_CONFIG`, if any.
+    pub fn load() -> Result<Self, ConfigError> {
+        match env::var("MISATO_CONFIG") {
+            Ok(path) if !path.is_empty() => Self::from_file(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// JSON for a `.json` file, TOML otherwise.
+    pub fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let content =
+            fs::read_to_string(path).map_err(|_| ConfigError::Unreadable(path.to_string()))?;
+        match Path::new(path).extension().and_then(|v| v.to_str()) {
+            Some("json") => Self::from_json(&content),
+            _ => Self::from_toml(&content),
+        }
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self, ConfigError> {
+        let table = content
+            .parse::<toml::Table>()
+            .map_err(|error| ConfigError::Invalid(error.to_string()))?;
+        Ok(Self {
+            values: table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_string(value)))
+                .collect(),
+        })
+    }
+
+    pub fn from_json(content: &str) -> Result<Self, ConfigError> {
+        let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(content)
+            .map_err(|error| ConfigError::Invalid(error.to_string()))?;
+        Ok(Self {
+            values: object
+                .into_iter()
+                .map(|(key, value)| (key, json_to_string(value)))
+                .collect(),
+        })
+    }
+
+    /// The environment first, then the file. Empty values count as missing.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_utils::config::Config;
+    ///
+    /// let config = Config::from_toml("MISATO_DOC_A = 60\nMISATO_DOC_B = \"file\"").unwrap();
+    /// std::env::set_var("MISATO_DOC_B", "env");
+    ///
+    /// assert_eq!(config.get("MISATO_DOC_A"), Some("60".to_string()));
+    /// assert_eq!(config.get("MISATO_DOC_B"), Some("env".to_string()));
+    /// assert_eq!(config.get("MISATO_DOC_C"), None);
+    /// assert_eq!(config.parse("MISATO_DOC_A", 0), 60);
+    /// assert!(config.require("MISATO_DOC_C").is_err());
+    /// ```
+    pub fn get(&self, key: &str) -> Option<String> {
+        match env::var(key) {
+            Ok(v) if !v.is_empty() => Some(v),
+            _ => self.values.get(key).filter(|v| !v.is_empty()).cloned(),
+        }
+    }
+
+    pub fn require(&self, key: &str) -> Result<String, ConfigError> {
+        self.get(key)
+            .ok_or_else(|| ConfigError::Missing(key.to_string()))
+    }
+
+    /// Falls back to `default` when it is missing, the error comes along with it when it
+    /// cannot be parsed.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_utils::config::Config;
+    ///
+    /// let config = Config::from_toml("MISATO_DOC_TTL = \"soon\"").unwrap();
+    ///
+    /// assert_eq!(config.parse_strict("MISATO_DOC_MISSING", 60).unwrap(), 60);
+    /// assert_eq!(config.parse_strict("MISATO_DOC_TTL", 60).unwrap_err().1, 60);
+    /// ```
+    pub fn parse_strict<T: FromStr>(&self, key: &str, default: T) -> Result<T, (ConfigError, T)> {
+        match self.get(key) {
+            Some(v) => match v.parse::<T>() {
+                Ok(v) => Ok(v),
+                Err(_) => Err((ConfigError::InvalidValue(key.to_string()), default)),
+            },
+            None => Ok(default),
+        }
+    }
+
+    /// Falls back to `default` when it is missing or cannot be parsed.
+    pub fn parse<T: FromStr>(&self, key: &str, default: T) -> T {
+        match self.get(key) {
+            Some(v) => match v.parse::<T>() {
+                Ok(v) => v,
+                Err(_) => {
+                    warn!(key, "Invalid value, using the default one.");
+                    default
+                }
+            },
+            None => default,
+        }
+    }
+}
@@ -1,6 +1,11 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
+pub mod compression;
+pub mod config;
+pub mod proxy;
 pub mod settings;
+pub mod ttl_cache;
+pub mod validation;
 
 pub fn get_current_timestamp() -> u64 {
     SystemTime::now()
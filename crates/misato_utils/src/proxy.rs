@@ -0,0 +1,162 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+/// An address or a CIDR block, like `10.0.0.0/8` or `2001:db8::/32`.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::proxy::Network;
+///
+/// let network: Network = "10.0.0.0/8".parse().unwrap();
+/// assert!(network.contains(&"10.1.2.3".parse().unwrap()));
+/// assert!(!network.contains(&"11.0.0.1".parse().unwrap()));
+/// assert!(network.contains(&"::ffff:10.1.2.3".parse().unwrap()));
+///
+/// let single: Network = "2001:db8::1".parse().unwrap();
+/// assert!(single.contains(&"2001:db8::1".parse().unwrap()));
+/// assert!(!single.contains(&"2001:db8::2".parse().unwrap()));
+///
+/// assert!("10.0.0.0/33".parse::<Network>().is_err());
+/// assert!("proxy".parse::<Network>().is_err());
+/// ```
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Network {
+    address: IpAddr,
+    prefix: u8,
+}
+
+impl FromStr for Network {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (address, prefix) = match value.trim().split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value.trim(), None),
+        };
+        let address = address.parse::<IpAddr>().map_err(|_| ())?.to_canonical();
+        let bits = match address {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| ())?,
+            None => bits,
+        };
+        match prefix <= bits {
+            true => Ok(Self { address, prefix }),
+            false => Err(()),
+        }
+    }
+}
+
+impl Network {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.address, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Addresses of an `X-Forwarded-For` (or `X-Real-IP`) value, the client first.
+/// None when one of them isn't an address, the chain can't be followed then.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::proxy::x_forwarded_for;
+///
+/// assert_eq!(
+///     x_forwarded_for("203.0.113.7, 10.0.0.1"),
+///     Some(vec!["203.0.113.7".parse().unwrap(), "10.0.0.1".parse().unwrap()])
+/// );
+/// assert_eq!(x_forwarded_for(" 2001:db8::1 "), Some(vec!["2001:db8::1".parse().unwrap()]));
+/// assert_eq!(x_forwarded_for("203.0.113.7, unknown"), None);
+/// assert_eq!(x_forwarded_for(""), None);
+/// ```
+pub fn x_forwarded_for(value: &str) -> Option<Vec<IpAddr>> {
+    value
+        .split(',')
+        .map(|item| item.trim().parse().ok())
+        .collect()
+}
+
+/// Addresses of the `for` parameters of a `Forwarded` value (RFC 7239), the client first.
+/// Ports and quotes are dropped, obfuscated or `unknown` nodes make the chain unusable.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::proxy::forwarded;
+///
+/// assert_eq!(
+///     forwarded("for=192.0.2.60;proto=https;by=203.0.113.43, For=\"[2001:db8:cafe::17]:4711\""),
+///     Some(vec!["192.0.2.60".parse().unwrap(), "2001:db8:cafe::17".parse().unwrap()])
+/// );
+/// assert_eq!(forwarded("for=\"192.0.2.60:8080\""), Some(vec!["192.0.2.60".parse().unwrap()]));
+/// assert_eq!(forwarded("for=_hidden, for=10.0.0.1"), None);
+/// assert_eq!(forwarded("proto=https"), None);
+/// ```
+pub fn forwarded(value: &str) -> Option<Vec<IpAddr>> {
+    value
+        .split(',')
+        .map(|element| {
+            let node = element.split(';').find_map(|pair| {
+                let (name, value) = pair.trim().split_once('=')?;
+                match name.trim().eq_ignore_ascii_case("for") {
+                    true => Some(value.trim().trim_matches('"')),
+                    false => None,
+                }
+            })?;
+            if let Some(ip) = node.strip_prefix('[') {
+                return ip.split(']').next()?.parse().ok();
+            }
+            match node.parse() {
+                Ok(ip) => Some(ip),
+                Err(_) => node.rsplit_once(':')?.0.parse().ok(),
+            }
+        })
+        .collect()
+}
+
+/// The client of a request coming from `peer`, through the proxies of `chain`.
+/// The chain is only believed when the peer is a trusted proxy, and then read from the
+/// right: the first address that isn't a trusted proxy is the client, what is left of it
+/// was sent by the client and could be anything.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::proxy::{client_address, Network};
+///
+/// let trusted: Vec<Network> = vec!["10.0.0.0/8".parse().unwrap()];
+/// let ip = |ip: &str| ip.parse().unwrap();
+///
+/// // Behind the proxies
+/// let chain = [ip("198.51.100.9"), ip("203.0.113.7"), ip("10.0.0.2")];
+/// assert_eq!(client_address(ip("10.0.0.1"), Some(&chain), &trusted), ip("203.0.113.7"));
+/// // A direct connection, the header is the client's own
+/// assert_eq!(client_address(ip("203.0.113.7"), Some(&chain), &trusted), ip("203.0.113.7"));
+/// // Nothing trusted, the header is ignored
+/// assert_eq!(client_address(ip("10.0.0.1"), Some(&chain), &[]), ip("10.0.0.1"));
+/// // No usable header from the proxy
+/// assert_eq!(client_address(ip("10.0.0.1"), None, &trusted), ip("10.0.0.1"));
+/// ```
+pub fn client_address(peer: IpAddr, chain: Option<&[IpAddr]>, trusted: &[Network]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|network| network.contains(ip));
+    if !is_trusted(&peer) {
+        return peer.to_canonical();
+    }
+    let mut client = peer;
+    for ip in chain.unwrap_or(&[]).iter().rev() {
+        client = *ip;
+        if !is_trusted(ip) {
+            break;
+        }
+    }
+    client.to_canonical()
+}
@@ -1,35 +1,746 @@
+use std::str::FromStr;
+
 use dotenv::dotenv;
-use std::env;
+use tracing::warn;
+
+use crate::config::{Config, ConfigError};
+use crate::proxy::Network;
+use misato_security::{
+    password::{
+        set_password_format, set_salt_size, Argon2Params, PasswordFormat, PasswordIntegrity,
+        DEFAULT_MIN_STORED_SALT_LENGTH, DEFAULT_SALT_SIZE,
+    },
+    policy::PasswordPolicy,
+    set_token_bytes, DEFAULT_TOKEN_BYTES, MIN_TOKEN_BYTES,
+};
+
+/// Certificate chain and private key, both PEM encoded.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct TlsPaths {
+    pub certs: String,
+    pub key: String,
+}
+
+#[derive(Clone)]
+pub struct DbSettings {
+    pub uri: String,
+    pub name: String,
+    pub max_attempts: u32,
+    pub retry_delay: u64, // In milliseconds, doubled after each attempt
+    pub write_concern: Option<String>, // `majority`, a number of nodes or a tag, the server default when None
+    pub write_timeout: u64,            // In milliseconds, 0 waits for the write concern forever
+    pub read_concern: Option<String>,  // `local`, `majority`, `linearizable`...
+    pub server_selection_timeout: u64, // In milliseconds
+    pub connect_timeout: u64,          // In milliseconds
+}
+
+#[derive(Clone)]
+pub struct SecuritySettings {
+    pub admin_token: String,       // Empty when the first user becomes admin
+    pub first_user_admin: bool,    // Instead of the default admin seeded from the token
+    pub default_role: DefaultRole, // Of api accounts
+    pub reset_admin: bool,         // Replace the token of an existing default admin
+    pub argon2_params: Argon2Params,
+    pub salt_size: usize,
+    pub token_bytes: usize, // Of random per token, base64url encoded, never under 16
+    pub password_format: PasswordFormat,
+    pub password_pepper: Option<String>,
+    pub password_policy: PasswordPolicy,
+    pub password_integrity: PasswordIntegrity, // Stored passwords under it are reported corrupt
+    pub pwned_check: bool, // New passwords are looked up in a Have I Been Pwned range API
+    pub pwned_api_url: String, // The 5 hex digits prefix of a range is appended to it
+    pub pwned_fail_closed: bool, // Refuse new passwords when the range API can't be reached
+    pub pwned_cache_ttl: u64, // In seconds, how long a fetched range is reused
+    pub captcha: bool,     // Api account signups need a CAPTCHA token, verified by the provider
+    pub captcha_secret: String, // Of the provider, required when `captcha` is on
+    pub captcha_verify_url: String, // hCaptcha, Turnstile and reCAPTCHA share the protocol
+    pub login_rate_window: u64, // In seconds
+    pub login_rate_max_attempts: u32,
+    pub lockout_threshold: u32,
+    pub lockout_duration: u64, // In seconds
+    pub login_delay_base: u64, // In milliseconds, doubled after each failure, 0 disables the delay
+    pub login_delay_cap: u64,  // In milliseconds
+    pub jwt_secret: Option<String>,
+    pub totp_key: Option<String>, // Encrypts second factor secrets, enrollment is refused without it
+    pub totp_issuer: String,      // Name shown by authenticator apps
+    pub totp_skew: u64,           // Steps of 30 seconds accepted before and after the current one
+    pub jwt_ttl: u64,             // In seconds
+    pub token_ttl: u64,           // In seconds
+    pub refresh_token_ttl: u64,   // In seconds
+    pub reset_token_ttl: u64,     // In seconds
+    pub verification_token_ttl: u64, // In seconds
+    pub token_purge_interval: u64, // In seconds, 0 disables the purge
+    pub deletion_grace_period: u64, // In seconds, 0 keeps deleted accounts with no self-restore
+    pub deletion_mode: DeletionMode,
+    pub anonymization_key: String, // Keys the ids of anonymized accounts, required to anonymize
+    pub max_active_tokens: usize,  // Per user, oldest evicted first, 0 for no limit
+    pub password_change_clears_tokens: bool,
+    pub registration_open: bool,               // Without an invite
+    pub invite_ttl: u64,                       // In seconds
+    pub signup_validation_reports_taken: bool, // Lets anyone holding a token enumerate accounts
+}
+
+#[derive(Clone)]
+pub struct HttpSettings {
+    pub cors_allowed_origins: Vec<String>,
+    pub cors_allow_credentials: bool, // Never with the `*` origin
+    pub cors_expose_headers: Vec<String>,
+    pub tls: Option<TlsPaths>, // Plain HTTP when None
+    pub hsts_max_age: u64,     // In seconds, 0 disables HSTS
+    pub hsts_include_subdomains: bool,
+    pub shutdown_grace: u32, // In seconds, to finish in-flight requests
+    pub json_limit: u64,     // In bytes, for every JSON body
+    pub body_limits: Vec<(String, u64)>, // Named limits, for routes reading `request.limits()`
+    pub base_path: String,   // Every route is mounted under it
+    pub idempotency_ttl: u64, // In seconds, how long an `Idempotency-Key` result is replayed
+    pub compression: bool,
+    pub compression_min_size: usize, // In bytes, smaller bodies are sent as is
+    pub request_timeout: u64,        // In seconds, handlers running longer answer 504, 0 for none
+    pub trusted_proxies: Vec<Network>, // Peers whose forwarding headers are believed
+    pub trusted_proxy_header: Option<String>, // Set by the proxies, else `Forwarded` then `X-Forwarded-For`
+}
+
+#[derive(Clone)]
+pub struct WebhookSettings {
+    pub urls: Vec<String>,      // Every account event is posted to each of them
+    pub secret: Option<String>, // Required as soon as there is an url
+    pub max_attempts: u32,
+}
+
+/// Role of a new api account, admins are only ever granted.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum DefaultRole {
+    User,
+    Dev,
+}
+
+impl FromStr for DefaultRole {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "user" => Ok(DefaultRole::User),
+            "dev" => Ok(DefaultRole::Dev),
+            _ => Err(format!("[{}]: Unknown default role.", value)),
+        }
+    }
+}
+
+/// What deleting an account does. `Delete` keeps it restorable until the grace period is over,
+/// `Anonymize` scrubs it at once, keeping only a keyed hash of its uuid for the audit log.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum DeletionMode {
+    Delete,
+    Anonymize,
+}
+
+impl FromStr for DeletionMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "delete" => Ok(DeletionMode::Delete),
+            "anonymize" => Ok(DeletionMode::Anonymize),
+            _ => Err(format!("[{}]: Unknown deletion mode.", value)),
+        }
+    }
+}
+
+/// Most verbose level that is logged.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            "debug" => Ok(LogLevel::Debug),
+            "trace" => Ok(LogLevel::Trace),
+            _ => Err(format!("[{}]: Unknown log level.", value)),
+        }
+    }
+}
+
+/// Human readable lines, or one JSON object per line for log collectors.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum LogFormat {
+    Pretty,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("[{}]: Unknown log format.", value)),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct LogSettings {
+    pub level: LogLevel,
+    pub format: LogFormat,
+}
 
 #[derive(Clone)]
 pub struct Settings {
-    pub mongodb_uri: String,
-    pub mongodb_name: String,
-    pub admin_token: String,
+    pub db: DbSettings,
+    pub security: SecuritySettings,
+    pub http: HttpSettings,
+    pub webhooks: WebhookSettings,
+    pub log: LogSettings,
+}
+
+/// Reads the config and keeps every problem found, rather than stopping at the first.
+struct Checks<'a> {
+    config: &'a Config,
+    errors: Vec<ConfigError>,
+}
+
+impl<'a> Checks<'a> {
+    fn require(&mut self, key: &str) -> String {
+        match self.config.require(key) {
+            Ok(value) => value,
+            Err(error) => {
+                self.errors.push(error);
+                String::new()
+            }
+        }
+    }
+
+    fn parse<T: FromStr>(&mut self, key: &str, default: T) -> T {
+        match self.config.parse_strict(key, default) {
+            Ok(value) => value,
+            Err((error, default)) => {
+                self.errors.push(error);
+                default
+            }
+        }
+    }
+
+    fn check<T>(&mut self, result: Result<T, ConfigError>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.errors.push(error);
+                None
+            }
+        }
+    }
+
+    /// Comma separated, empty items are skipped.
+    fn list(&self, key: &str) -> Vec<String> {
+        match self.config.get(key) {
+            Some(v) => v
+                .split(',')
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+pub const DEFAULT_ADMIN_TOKEN_MIN_LENGTH: usize = 32;
+
+/// Values found in examples and tutorials, in lowercase.
+const KNOWN_ADMIN_TOKENS: [&str; 8] = [
+    "admin", "changeme", "default", "misato", "password", "secret", "test", "token",
+];
+
+/// An empty token is always refused, a short or well-known one only when `allow_weak` is false.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::settings::validate_admin_token;
+///
+/// assert!(validate_admin_token("", 8, true).is_err());
+/// assert!(validate_admin_token("short", 8, false).is_err());
+/// assert!(validate_admin_token("changeme", 8, false).is_err());
+/// assert!(validate_admin_token("aaaaaaaaaa", 8, false).is_err());
+/// assert!(validate_admin_token("short", 8, true).is_ok());
+/// assert!(validate_admin_token("Zq9vR2xLk7Pw", 8, false).is_ok());
+/// ```
+pub fn validate_admin_token(
+    token: &str,
+    min_length: usize,
+    allow_weak: bool,
+) -> Result<(), ConfigError> {
+    if token.trim().is_empty() {
+        return Err(ConfigError::WeakAdminToken("is empty".to_string()));
+    }
+    let reason = if token.chars().count() < min_length {
+        Some(format!("is shorter than {} characters", min_length))
+    } else if KNOWN_ADMIN_TOKENS.contains(&token.to_lowercase().as_str()) {
+        Some("is a well-known default".to_string())
+    } else if token.chars().all(|c| token.starts_with(c)) {
+        Some("repeats a single character".to_string())
+    } else {
+        None
+    };
+    match reason {
+        Some(reason) if allow_weak => {
+            warn!(reason = %reason, "[MISATO_ADMIN_TOKEN] Weak token, the admin account is at risk!");
+            Ok(())
+        }
+        Some(reason) => Err(ConfigError::WeakAdminToken(reason)),
+        None => Ok(()),
+    }
+}
+
+/// HTTPS needs both paths, plain HTTP neither.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::settings::{tls_paths, TlsPaths};
+///
+/// assert_eq!(tls_paths(None, None).unwrap(), None);
+/// assert!(tls_paths(Some("cert.pem".to_string()), None).is_err());
+/// assert!(tls_paths(None, Some("key.pem".to_string())).is_err());
+/// assert_eq!(
+///     tls_paths(Some("cert.pem".to_string()), Some("key.pem".to_string())).unwrap(),
+///     Some(TlsPaths { certs: "cert.pem".to_string(), key: "key.pem".to_string() })
+/// );
+/// ```
+pub fn tls_paths(
+    certs: Option<String>,
+    key: Option<String>,
+) -> Result<Option<TlsPaths>, ConfigError> {
+    match (certs, key) {
+        (Some(certs), Some(key)) => Ok(Some(TlsPaths { certs, key })),
+        (Some(_), None) => Err(ConfigError::IncompleteTls("MISATO_TLS_KEY".to_string())),
+        (None, Some(_)) => Err(ConfigError::IncompleteTls("MISATO_TLS_CERTS".to_string())),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Browsers refuse credentials unless the origin is echoed, which the `*` origin never is.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::settings::cors_credentials;
+///
+/// let origins = vec!["https://misato.wiki".to_string()];
+/// assert!(cors_credentials(&origins, true).unwrap());
+/// assert!(!cors_credentials(&["*".to_string()], false).unwrap());
+/// assert!(cors_credentials(&["*".to_string()], true).is_err());
+/// ```
+pub fn cors_credentials(origins: &[String], allow_credentials: bool) -> Result<bool, ConfigError> {
+    match allow_credentials && origins.iter().any(|v| v == "*") {
+        true => Err(ConfigError::CredentialedWildcard),
+        false => Ok(allow_credentials),
+    }
+}
+
+/// Argon2 refuses salts under `DEFAULT_MIN_STORED_SALT_LENGTH` bytes, every hash would fail.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::settings::validate_salt_size;
+///
+/// assert_eq!(validate_salt_size(16).unwrap(), 16);
+/// assert_eq!(validate_salt_size(8).unwrap(), 8);
+/// assert!(validate_salt_size(4).is_err());
+/// ```
+pub fn validate_salt_size(size: usize) -> Result<usize, ConfigError> {
+    match size < DEFAULT_MIN_STORED_SALT_LENGTH {
+        true => Err(ConfigError::ShortSalt(size)),
+        false => Ok(size),
+    }
+}
+
+/// Parse `name=bytes` pairs separated by commas, malformed pairs are skipped.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::settings::parse_body_limits;
+///
+/// assert_eq!(
+///     parse_body_limits("avatar=1048576, json=, bytes=big"),
+///     vec![("avatar".to_string(), 1048576)]
+/// );
+/// assert_eq!(parse_body_limits(""), vec![]);
+/// ```
+pub fn parse_body_limits(value: &str) -> Vec<(String, u64)> {
+    value
+        .split(',')
+        .map(|pair| pair.trim())
+        .filter(|pair| !pair.is_empty())
+        .filter_map(|pair| {
+            let limit = pair
+                .split_once('=')
+                .and_then(|(name, bytes)| Some((name.trim(), bytes.trim().parse::<u64>().ok()?)))
+                .filter(|(name, _)| !name.is_empty());
+            if limit.is_none() {
+                warn!(
+                    limit = pair,
+                    "[MISATO_BODY_LIMITS] Invalid limit, it is ignored."
+                );
+            }
+            limit.map(|(name, bytes)| (name.to_string(), bytes))
+        })
+        .collect()
+}
+
+/// Mount `path` under `base`, both starting with a slash.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::settings::join_path;
+///
+/// assert_eq!(join_path("/", "/"), "/");
+/// assert_eq!(join_path("/", "/api/v1"), "/api/v1");
+/// assert_eq!(join_path("/misato-api", "/"), "/misato-api");
+/// assert_eq!(join_path("/misato-api/", "/health"), "/misato-api/health");
+/// ```
+pub fn join_path(base: &str, path: &str) -> String {
+    let joined = format!("{}{}", base.trim_end_matches('/'), path);
+    match joined.len() {
+        1 => joined,
+        _ => joined.trim_end_matches('/').to_string(),
+    }
 }
 
 impl Settings {
-    pub fn init() -> Self {
+    /// From the environment, then the `.env` file, then the `MISATO_CONFIG` file.
+    pub fn try_init() -> Result<Self, ConfigError> {
         dotenv().ok();
-        let mongodb_uri = match env::var("MONGODB_URI") {
-            Ok(v) => v.to_string(),
-            Err(_) => format!("[{}] is not present in the environment!", "MONGODB_URI"),
-        };
-        let mongodb_name = match env::var("MONGODB_NAME") {
-            Ok(v) => v.to_string(),
-            Err(_) => format!("[{}] is not present in the environment!", "MONGODB_NAME"),
-        };
-        let admin_token = match env::var("MISATO_ADMIN_TOKEN") {
-            Ok(v) => v.to_string(),
-            Err(_) => format!(
-                "[{}] is not present in the environment!",
-                "MISATO_ADMIN_TOKEN"
+        Self::from_config(&Config::load()?)
+    }
+
+    /// Like `try_init`, panics with every problem of the configuration.
+    pub fn init() -> Self {
+        match Self::try_init() {
+            Ok(settings) => settings,
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    /// The environment still takes precedence over the given values.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_utils::{config::{Config, ConfigError}, settings::{LogLevel, Settings}};
+    ///
+    /// let config = Config::from_toml(
+    ///     "MONGODB_URI = \"mongodb://localhost\"\nMONGODB_NAME = \"misato\"\n\
+    ///      MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"\nMISATO_TOKEN_TTL = 60",
+    /// )
+    /// .unwrap();
+    /// let settings = Settings::from_config(&config).unwrap();
+    /// assert_eq!(settings.db.name, "misato");
+    /// assert_eq!(settings.security.token_ttl, 60);
+    /// assert_eq!(settings.http.base_path, "/");
+    /// assert!(settings.http.trusted_proxies.is_empty());
+    /// assert_eq!(settings.log.level, LogLevel::Info);
+    ///
+    /// let config = Config::from_toml(
+    ///     "MONGODB_URI = \"mongodb://localhost\"\nMISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"\n\
+    ///      MISATO_TOKEN_TTL = \"soon\"\nMISATO_TLS_CERTS = \"cert.pem\"\n\
+    ///      MISATO_TRUSTED_PROXIES = \"10.0.0.0/8, the-proxy\"\nMISATO_CAPTCHA = true\n\
+    ///      MISATO_DELETION_MODE = \"anonymize\"\nMISATO_SALT_SIZE = 4\nMISATO_ARGON2_LANES = 0",
+    /// )
+    /// .unwrap();
+    /// let errors = match Settings::from_config(&config) {
+    ///     Err(ConfigError::Many(errors)) => errors,
+    ///     _ => panic!("expected several errors"),
+    /// };
+    /// let message = ConfigError::Many(errors).to_string();
+    /// assert!(message.contains("MONGODB_NAME"));
+    /// assert!(message.contains("MISATO_TOKEN_TTL"));
+    /// assert!(message.contains("MISATO_TLS_KEY"));
+    /// assert!(message.contains("MISATO_TRUSTED_PROXIES"));
+    /// assert!(message.contains("MISATO_CAPTCHA_SECRET"));
+    /// assert!(message.contains("MISATO_ANONYMIZATION_KEY"));
+    /// assert!(message.contains("MISATO_SALT_SIZE"));
+    /// assert!(message.contains("MISATO_ARGON2_LANES"));
+    ///
+    /// let config = Config::from_toml(
+    ///     "MONGODB_URI = \"mongodb://localhost\"\nMONGODB_NAME = \"misato\"\nMISATO_FIRST_USER_ADMIN = true\n\
+    ///      MISATO_TOKEN_BYTES = 8",
+    /// )
+    /// .unwrap();
+    /// let settings = Settings::from_config(&config).unwrap();
+    /// assert_eq!(settings.security.admin_token, "");
+    /// assert_eq!(settings.security.token_bytes, 16);
+    ///
+    /// let config = Config::from_toml(
+    ///     "MONGODB_URI = \"mongodb://localhost\"\nMONGODB_NAME = \"misato\"\nMISATO_FIRST_USER_ADMIN = true\n\
+    ///      MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"",
+    /// )
+    /// .unwrap();
+    /// let seeded = Settings::from_config(&config);
+    /// assert!(matches!(seeded, Err(ConfigError::SeededFirstUserAdmin)));
+    /// ```
+    pub fn from_config(config: &Config) -> Result<Self, ConfigError> {
+        let mut checks = Checks {
+            config,
+            errors: Vec::new(),
+        };
+        let db = DbSettings {
+            uri: checks.require("MONGODB_URI"),
+            name: checks.require("MONGODB_NAME"),
+            max_attempts: checks.parse("MONGODB_MAX_ATTEMPTS", 5),
+            retry_delay: checks.parse("MONGODB_RETRY_DELAY", 500),
+            write_concern: checks.config.get("MONGODB_WRITE_CONCERN"),
+            write_timeout: checks.parse("MONGODB_WRITE_TIMEOUT", 0),
+            read_concern: checks.config.get("MONGODB_READ_CONCERN"),
+            server_selection_timeout: checks.parse("MONGODB_SERVER_SELECTION_TIMEOUT", 30_000),
+            connect_timeout: checks.parse("MONGODB_CONNECT_TIMEOUT", 10_000),
+        };
+        let security = SecuritySettings::from_checks(&mut checks);
+        let http = HttpSettings::from_checks(&mut checks);
+        let webhooks = WebhookSettings::from_checks(&mut checks);
+        let log = LogSettings {
+            level: checks.parse("MISATO_LOG_LEVEL", LogLevel::Info),
+            format: checks.parse("MISATO_LOG_FORMAT", LogFormat::Pretty),
+        };
+        let mut errors = checks.errors;
+        match errors.len() {
+            0 => Ok(Self {
+                db,
+                security,
+                http,
+                webhooks,
+                log,
+            }),
+            1 => Err(errors.remove(0)),
+            _ => Err(ConfigError::Many(errors)),
+        }
+    }
+}
+
+impl SecuritySettings {
+    fn from_checks(checks: &mut Checks) -> Self {
+        let first_user_admin = checks.parse("MISATO_FIRST_USER_ADMIN", false);
+        let admin_token = match first_user_admin {
+            true => {
+                if checks.config.get("MISATO_ADMIN_TOKEN").is_some() {
+                    checks.errors.push(ConfigError::SeededFirstUserAdmin);
+                }
+                String::new()
+            }
+            false => checks.require("MISATO_ADMIN_TOKEN"),
+        };
+        if !admin_token.is_empty() {
+            let min_length = checks.parse(
+                "MISATO_ADMIN_TOKEN_MIN_LENGTH",
+                DEFAULT_ADMIN_TOKEN_MIN_LENGTH,
+            );
+            let allow_weak = checks.parse("MISATO_ALLOW_WEAK_ADMIN_TOKEN", false);
+            checks.check(validate_admin_token(&admin_token, min_length, allow_weak));
+        }
+        let default_params = Argon2Params::default();
+        let argon2_params = Argon2Params {
+            mem_cost: checks.parse("MISATO_ARGON2_MEMORY_COST", default_params.mem_cost),
+            time_cost: checks.parse("MISATO_ARGON2_TIME_COST", default_params.time_cost),
+            lanes: checks.parse("MISATO_ARGON2_LANES", default_params.lanes),
+            variant: checks.parse("MISATO_ARGON2_VARIANT", default_params.variant),
+        };
+        let validated = argon2_params
+            .validate()
+            .map_err(|error| ConfigError::Argon2(error.to_string()));
+        checks.check(validated);
+        let salt_size = checks.parse("MISATO_SALT_SIZE", DEFAULT_SALT_SIZE);
+        let salt_size = checks
+            .check(validate_salt_size(salt_size))
+            .unwrap_or(DEFAULT_SALT_SIZE);
+        set_salt_size(salt_size);
+        let token_bytes = checks
+            .parse("MISATO_TOKEN_BYTES", DEFAULT_TOKEN_BYTES)
+            .max(MIN_TOKEN_BYTES);
+        set_token_bytes(token_bytes);
+        let password_format = checks.parse("MISATO_PASSWORD_FORMAT", PasswordFormat::Raw);
+        set_password_format(password_format);
+        let default_policy = PasswordPolicy::default();
+        let password_policy = PasswordPolicy {
+            min_length: checks.parse("MISATO_PASSWORD_MIN_LENGTH", default_policy.min_length),
+            max_length: checks.parse("MISATO_PASSWORD_MAX_LENGTH", default_policy.max_length),
+            require_lowercase: checks.parse(
+                "MISATO_PASSWORD_REQUIRE_LOWERCASE",
+                default_policy.require_lowercase,
+            ),
+            require_uppercase: checks.parse(
+                "MISATO_PASSWORD_REQUIRE_UPPERCASE",
+                default_policy.require_uppercase,
+            ),
+            require_digit: checks.parse(
+                "MISATO_PASSWORD_REQUIRE_DIGIT",
+                default_policy.require_digit,
+            ),
+            require_symbol: checks.parse(
+                "MISATO_PASSWORD_REQUIRE_SYMBOL",
+                default_policy.require_symbol,
+            ),
+            banned_passwords: checks
+                .config
+                .get("MISATO_PASSWORD_BANNED")
+                .map(|v| v.split(',').map(|v| v.trim().to_string()).collect()),
+        };
+        let default_integrity = PasswordIntegrity::default();
+        let password_integrity = PasswordIntegrity {
+            min_salt_length: checks.parse(
+                "MISATO_STORED_SALT_MIN_LENGTH",
+                default_integrity.min_salt_length,
+            ),
+            min_hash_length: checks.parse(
+                "MISATO_STORED_HASH_MIN_LENGTH",
+                default_integrity.min_hash_length,
             ),
         };
+        let captcha = checks.parse("MISATO_CAPTCHA", false);
+        let captcha_secret = match captcha {
+            true => checks.require("MISATO_CAPTCHA_SECRET"),
+            false => checks
+                .config
+                .get("MISATO_CAPTCHA_SECRET")
+                .unwrap_or_default(),
+        };
+        let deletion_mode = checks.parse("MISATO_DELETION_MODE", DeletionMode::Delete);
+        let anonymization_key = match deletion_mode {
+            DeletionMode::Anonymize => checks.require("MISATO_ANONYMIZATION_KEY"),
+            DeletionMode::Delete => checks
+                .config
+                .get("MISATO_ANONYMIZATION_KEY")
+                .unwrap_or_default(),
+        };
+        Self {
+            admin_token,
+            first_user_admin,
+            default_role: checks.parse("MISATO_DEFAULT_ROLE", DefaultRole::User),
+            reset_admin: checks.parse("MISATO_RESET_ADMIN", false),
+            argon2_params,
+            salt_size,
+            token_bytes,
+            password_format,
+            password_pepper: checks.config.get("MISATO_PASSWORD_PEPPER"),
+            password_policy,
+            password_integrity,
+            pwned_check: checks.parse("MISATO_PWNED_CHECK", false),
+            pwned_api_url: checks
+                .config
+                .get("MISATO_PWNED_API_URL")
+                .unwrap_or_else(|| "https://api.pwnedpasswords.com/range/".to_string()),
+            pwned_fail_closed: checks.parse("MISATO_PWNED_FAIL_CLOSED", false),
+            pwned_cache_ttl: checks.parse("MISATO_PWNED_CACHE_TTL", 5 * 60),
+            captcha,
+            captcha_secret,
+            captcha_verify_url: checks
+                .config
+                .get("MISATO_CAPTCHA_VERIFY_URL")
+                .unwrap_or_else(|| {
+                    "https://challenges.cloudflare.com/turnstile/v0/siteverify".to_string()
+                }),
+            login_rate_window: checks.parse("MISATO_LOGIN_RATE_WINDOW", 5 * 60),
+            login_rate_max_attempts: checks.parse("MISATO_LOGIN_RATE_MAX_ATTEMPTS", 10),
+            lockout_threshold: checks.parse("MISATO_LOCKOUT_THRESHOLD", 5),
+            lockout_duration: checks.parse("MISATO_LOCKOUT_DURATION", 15 * 60),
+            login_delay_base: checks.parse("MISATO_LOGIN_DELAY_BASE", 250),
+            login_delay_cap: checks.parse("MISATO_LOGIN_DELAY_CAP", 5000),
+            jwt_secret: checks.config.get("MISATO_JWT_SECRET"),
+            totp_key: checks.config.get("MISATO_TOTP_KEY"),
+            totp_issuer: checks
+                .config
+                .get("MISATO_TOTP_ISSUER")
+                .unwrap_or_else(|| "Misato".to_string()),
+            totp_skew: checks.parse("MISATO_TOTP_SKEW", 1),
+            jwt_ttl: checks.parse("MISATO_JWT_TTL", 15 * 60),
+            token_ttl: checks.parse("MISATO_TOKEN_TTL", 7 * 24 * 60 * 60),
+            refresh_token_ttl: checks.parse("MISATO_REFRESH_TOKEN_TTL", 30 * 24 * 60 * 60),
+            reset_token_ttl: checks.parse("MISATO_RESET_TOKEN_TTL", 60 * 60),
+            verification_token_ttl: checks.parse("MISATO_VERIFICATION_TOKEN_TTL", 24 * 60 * 60),
+            token_purge_interval: checks.parse("MISATO_TOKEN_PURGE_INTERVAL", 60 * 60),
+            deletion_grace_period: checks.parse("MISATO_DELETION_GRACE_PERIOD", 30 * 24 * 60 * 60),
+            deletion_mode,
+            anonymization_key,
+            max_active_tokens: checks.parse("MISATO_MAX_ACTIVE_TOKENS", 10),
+            password_change_clears_tokens: checks
+                .parse("MISATO_PASSWORD_CHANGE_CLEARS_TOKENS", true),
+            registration_open: checks.parse("MISATO_REGISTRATION_OPEN", true),
+            invite_ttl: checks.parse("MISATO_INVITE_TTL", 7 * 24 * 60 * 60),
+            signup_validation_reports_taken: checks
+                .parse("MISATO_SIGNUP_VALIDATION_REPORTS_TAKEN", false),
+        }
+    }
+}
+
+impl HttpSettings {
+    fn from_checks(checks: &mut Checks) -> Self {
+        let base_path = match checks.config.get("MISATO_BASE_PATH") {
+            Some(path) if path.starts_with('/') => path,
+            Some(path) => format!("/{}", path),
+            None => "/".to_string(),
+        };
+        let body_limits = match checks.config.get("MISATO_BODY_LIMITS") {
+            Some(value) => parse_body_limits(&value),
+            None => Vec::new(),
+        };
+        let tls = tls_paths(
+            checks.config.get("MISATO_TLS_CERTS"),
+            checks.config.get("MISATO_TLS_KEY"),
+        );
+        let cors_allowed_origins = checks.list("MISATO_CORS_ORIGINS");
+        let allow_credentials = checks.parse("MISATO_CORS_ALLOW_CREDENTIALS", false);
+        let cors_allow_credentials = checks
+            .check(cors_credentials(&cors_allowed_origins, allow_credentials))
+            .unwrap_or(false);
+        let trusted_proxies = checks
+            .list("MISATO_TRUSTED_PROXIES")
+            .iter()
+            .map(|network| network.parse::<Network>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| ConfigError::InvalidValue("MISATO_TRUSTED_PROXIES".to_string()));
+        let trusted_proxies = checks.check(trusted_proxies).unwrap_or_default();
+        Self {
+            cors_allowed_origins,
+            cors_allow_credentials,
+            cors_expose_headers: checks.list("MISATO_CORS_EXPOSE_HEADERS"),
+            tls: checks.check(tls).flatten(),
+            hsts_max_age: checks.parse("MISATO_HSTS_MAX_AGE", 365 * 24 * 60 * 60),
+            hsts_include_subdomains: checks.parse("MISATO_HSTS_INCLUDE_SUBDOMAINS", false),
+            shutdown_grace: checks.parse("MISATO_SHUTDOWN_GRACE", 5),
+            json_limit: checks.parse("MISATO_JSON_LIMIT", 16 * 1024),
+            body_limits,
+            base_path,
+            idempotency_ttl: checks.parse("MISATO_IDEMPOTENCY_TTL", 15 * 60),
+            compression: checks.parse("MISATO_COMPRESSION", true),
+            compression_min_size: checks.parse("MISATO_COMPRESSION_MIN_SIZE", 1024),
+            request_timeout: checks.parse("MISATO_REQUEST_TIMEOUT", 30),
+            trusted_proxies,
+            trusted_proxy_header: checks
+                .config
+                .get("MISATO_TRUSTED_PROXY_HEADER")
+                .map(|header| header.trim().to_string())
+                .filter(|header| !header.is_empty()),
+        }
+    }
+}
+
+impl WebhookSettings {
+    fn from_checks(checks: &mut Checks) -> Self {
+        let urls = checks.list("MISATO_WEBHOOK_URLS");
+        let secret = match urls.is_empty() {
+            true => checks.config.get("MISATO_WEBHOOK_SECRET"),
+            false => Some(checks.require("MISATO_WEBHOOK_SECRET")),
+        };
         Self {
-            mongodb_uri: mongodb_uri,
-            mongodb_name: mongodb_name,
-            admin_token: admin_token,
+            urls,
+            secret,
+            max_attempts: checks.parse("MISATO_WEBHOOK_MAX_ATTEMPTS", 5),
         }
     }
 }
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+
+/// In-memory values kept for a fixed time per key, at most `max_entries` of them: the oldest
+/// one is dropped to make room. Timestamps are given in milliseconds so callers decide the clock.
+pub struct TtlCache<K, V> {
+    ttl: u64, // In milliseconds
+    max_entries: usize,
+    entries: Mutex<HashMap<K, (u64, V)>>,
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> TtlCache<K, V> {
+    pub fn new(ttl_seconds: u64, max_entries: usize) -> Self {
+        Self {
+            ttl: ttl_seconds.saturating_mul(1000),
+            max_entries,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The value stored for the key, `None` if there is none or it expired.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_utils::ttl_cache::TtlCache;
+    ///
+    /// let cache = TtlCache::new(60, 100);
+    /// cache.insert("key", "value", 0);
+    ///
+    /// assert_eq!(cache.get(&"key", 1000), Some("value"));
+    /// assert_eq!(cache.get(&"other key", 1000), None);
+    /// assert_eq!(cache.get(&"key", 60_000), None);
+    /// ```
+    pub fn get(&self, key: &K, now: u64) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((stored_at, value)) if now < stored_at + self.ttl => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    /// Forget expired values, then the oldest ones until there is room for one more.
+    fn make_room(&self, entries: &mut HashMap<K, (u64, V)>, now: u64) {
+        entries.retain(|_, (stored_at, _)| now < *stored_at + self.ttl);
+        while !entries.is_empty() && entries.len() >= self.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (stored_at, _))| *stored_at)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            entries.remove(&oldest);
+        }
+    }
+
+    /// Store the value of the key, replacing a previous one.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_utils::ttl_cache::TtlCache;
+    ///
+    /// let cache = TtlCache::new(60, 2);
+    /// cache.insert("first", 1, 0);
+    /// cache.insert("second", 2, 1);
+    /// cache.insert("third", 3, 2);
+    ///
+    /// assert_eq!(cache.get(&"first", 3), None);
+    /// assert_eq!(cache.get(&"second", 3), Some(2));
+    /// assert_eq!(cache.get(&"third", 3), Some(3));
+    /// ```
+    pub fn insert(&self, key: K, value: V, now: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.remove(&key);
+        self.make_room(&mut entries, now);
+        entries.insert(key, (now, value));
+    }
+
+    /// Store the value unless the key holds a live one, which is returned instead.
+    /// Checked and stored under one lock, so of concurrent callers only one stores.
+    /// Basic usage:
+    ///
+    /// ```
+    /// use misato_utils::ttl_cache::TtlCache;
+    ///
+    /// let cache = TtlCache::new(60, 100);
+    ///
+    /// assert_eq!(cache.insert_if_absent("key", "first", 0), None);
+    /// assert_eq!(cache.insert_if_absent("key", "second", 1000), Some("first"));
+    /// assert_eq!(cache.insert_if_absent("key", "third", 60_000), None);
+    /// ```
+    pub fn insert_if_absent(&self, key: K, value: V, now: u64) -> Option<V> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(&key) {
+            Some((stored_at, stored)) if now < stored_at + self.ttl => return Some(stored.clone()),
+            _ => {}
+        }
+        entries.remove(&key);
+        self.make_room(&mut entries, now);
+        entries.insert(key, (now, value));
+        None
+    }
+
+    pub fn remove(&self, key: &K) {
+        self.entries.lock().unwrap().remove(key);
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub const USERNAME_MIN_LENGTH: usize = 3;
+pub const USERNAME_MAX_LENGTH: usize = 32;
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum UsernameError {
+    TooShort,
+    TooLong,
+    SurroundingWhitespace,
+    IllegalCharacter(char),
+}
+
+impl fmt::Display for UsernameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UsernameError::TooShort => write!(
+                f,
+                "Username must be at least {} characters long.",
+                USERNAME_MIN_LENGTH
+            ),
+            UsernameError::TooLong => write!(
+                f,
+                "Username must be at most {} characters long.",
+                USERNAME_MAX_LENGTH
+            ),
+            UsernameError::SurroundingWhitespace => {
+                write!(f, "Username must not start or end with whitespace.")
+            }
+            UsernameError::IllegalCharacter(c) => write!(
+                f,
+                "[{}]: Username may only contain letters, digits, '_', '-' and '.'.",
+                c.escape_default()
+            ),
+        }
+    }
+}
+
+/// ASCII letters, digits, `_`, `-` and `.` only, so usernames are safe in URLs and logs.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::validation::*;
+///
+/// assert_eq!(validate_username("ab"), Err(UsernameError::TooShort));
+/// assert_eq!(validate_username(&"a".repeat(33)), Err(UsernameError::TooLong));
+/// assert_eq!(validate_username(" misato"), Err(UsernameError::SurroundingWhitespace));
+/// assert_eq!(validate_username("mi sato"), Err(UsernameError::IllegalCharacter(' ')));
+/// assert_eq!(validate_username("misato\u{7}"), Err(UsernameError::IllegalCharacter('\u{7}')));
+/// assert_eq!(validate_username("misato🎉"), Err(UsernameError::IllegalCharacter('🎉')));
+/// assert_eq!(validate_username("Misato_Katsuragi-29.5"), Ok(()));
+/// ```
+pub fn validate_username(username: &str) -> Result<(), UsernameError> {
+    if username.trim() != username {
+        return Err(UsernameError::SurroundingWhitespace);
+    }
+    let length = username.chars().count();
+    if length < USERNAME_MIN_LENGTH {
+        return Err(UsernameError::TooShort);
+    }
+    if length > USERNAME_MAX_LENGTH {
+        return Err(UsernameError::TooLong);
+    }
+    match username
+        .chars()
+        .find(|c| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.')))
+    {
+        Some(c) => Err(UsernameError::IllegalCharacter(c)),
+        None => Ok(()),
+    }
+}
+
+/// Only the shape is checked, the verification token proves the address works.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::validation::validate_email;
+///
+/// assert!(validate_email("misato@misato.wiki"));
+/// assert!(!validate_email("@misato.wiki"));
+/// assert!(!validate_email("misato@localhost"));
+/// assert!(!validate_email("misato"));
+/// ```
+pub fn validate_email(email: &str) -> bool {
+    match email.split_once('@') {
+        Some((local, domain)) => !local.is_empty() && domain.contains('.'),
+        None => false,
+    }
+}
+
+/// Every problem of a form at once, messages by field name.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::validation::*;
+///
+/// let mut errors = ValidationErrors::default();
+/// assert!(errors.is_empty());
+///
+/// errors.add("username", "Username must be at least 3 characters long.".to_string());
+/// errors.add("password", "Password must be at least 8 characters long.".to_string());
+/// errors.add("password", "Password must contain a digit.".to_string());
+/// assert_eq!(errors.len(), 2);
+/// assert_eq!(errors.fields()["password"].len(), 2);
+/// assert_eq!(errors.to_string(), "[password, username]: Invalid fields.");
+/// ```
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct ValidationErrors {
+    fields: BTreeMap<String, Vec<String>>, // Sorted, so responses are stable
+}
+
+impl ValidationErrors {
+    pub fn add(&mut self, field: &str, message: String) {
+        self.fields
+            .entry(field.to_string())
+            .or_default()
+            .push(message);
+    }
+
+    /// Number of invalid fields.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    pub fn fields(&self) -> &BTreeMap<String, Vec<String>> {
+        &self.fields
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.fields.keys().map(|name| name.as_str()).collect();
+        write!(f, "[{}]: Invalid fields.", names.join(", "))
+    }
+}
+
+/// Type a JSON field must have.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum FieldKind {
+    String,
+    Integer,
+}
+
+/// One field of a JSON form, `aliases` are other accepted names for it.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Field {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub kind: FieldKind,
+    pub required: bool, // null is accepted for optional fields
+}
+
+impl Field {
+    pub const fn required(name: &'static str, kind: FieldKind) -> Self {
+        Self {
+            name,
+            aliases: &[],
+            kind,
+            required: true,
+        }
+    }
+
+    pub const fn optional(name: &'static str, kind: FieldKind) -> Self {
+        Self {
+            name,
+            aliases: &[],
+            kind,
+            required: false,
+        }
+    }
+
+    pub const fn alias(self, aliases: &'static [&'static str]) -> Self {
+        Self { aliases, ..self }
+    }
+
+    fn matches(&self, key: &str) -> bool {
+        self.name == key || self.aliases.contains(&key)
+    }
+}
+
+/// Fields a JSON form is made of, checked before it is deserialized.
+pub trait Schema {
+    const FIELDS: &'static [Field];
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum FieldError {
+    Missing(String),
+    Unknown(String),
+    WrongType(String, FieldKind),
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldError::Missing(field) => write!(f, "[{}]: Missing field.", field),
+            FieldError::Unknown(field) => write!(f, "[{}]: Unknown field.", field),
+            FieldError::WrongType(field, FieldKind::String) => {
+                write!(f, "[{}]: Must be a string.", field)
+            }
+            FieldError::WrongType(field, FieldKind::Integer) => {
+                write!(f, "[{}]: Must be an integer.", field)
+            }
+        }
+    }
+}
+
+/// First problem of the members of a JSON object against `fields`, unknown fields first.
+/// Basic usage:
+///
+/// ```
+/// use misato_utils::validation::*;
+/// use serde_json::json;
+///
+/// const FIELDS: &[Field] = &[
+///     Field::required("username", FieldKind::String).alias(&["login"]),
+///     Field::optional("email", FieldKind::String),
+/// ];
+/// let check = |value: serde_json::Value| validate_fields(value.as_object().unwrap(), FIELDS);
+///
+/// assert_eq!(check(json!({ "username": "misato" })), Ok(()));
+/// assert_eq!(check(json!({ "login": "misato", "email": null })), Ok(()));
+/// assert_eq!(
+///     check(json!({ "email": "misato@misato.wiki" })),
+///     Err(FieldError::Missing("username".to_string()))
+/// );
+/// assert_eq!(
+///     check(json!({ "username": "misato", "emial": "" })),
+///     Err(FieldError::Unknown("emial".to_string()))
+/// );
+/// assert_eq!(
+///     check(json!({ "username": 29 })),
+///     Err(FieldError::WrongType("username".to_string(), FieldKind::String))
+/// );
+/// assert_eq!(
+///     check(json!({ "username": 29 })).unwrap_err().to_string(),
+///     "[username]: Must be a string."
+/// );
+/// ```
+pub fn validate_fields(
+    object: &serde_json::Map<String, serde_json::Value>,
+    fields: &[Field],
+) -> Result<(), FieldError> {
+    for (key, value) in object {
+        let field = match fields.iter().find(|field| field.matches(key)) {
+            Some(field) => field,
+            None => return Err(FieldError::Unknown(key.to_string())),
+        };
+        let valid = match field.kind {
+            FieldKind::String => value.is_string(),
+            FieldKind::Integer => value.is_u64() || value.is_i64(),
+        };
+        let omitted = value.is_null() && !field.required;
+        if !valid && !omitted {
+            return Err(FieldError::WrongType(key.to_string(), field.kind));
+        }
+    }
+    for field in fields.iter().filter(|field| field.required) {
+        if !object.keys().any(|key| field.matches(key)) {
+            return Err(FieldError::Missing(field.name.to_string()));
+        }
+    }
+    Ok(())
+}
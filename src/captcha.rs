@@ -0,0 +1,118 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use rocket::request::{self, FromRequest, Outcome, Request};
+use serde::Deserialize;
+use tracing::{error, warn};
+
+use misato_utils::settings::Settings;
+
+use crate::errors::api_errors::ApiError;
+
+const TIMEOUT: Duration = Duration::from_secs(5); // A slow provider shouldn't hang a signup
+const TOKEN_MAX_LENGTH: usize = 4096;
+pub const CAPTCHA_HEADER: &str = "X-Misato-Captcha-Token";
+
+/// Answer of a `siteverify` endpoint, the error codes are only logged.
+#[derive(Deserialize)]
+struct Verification {
+    success: bool,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+/// Checks the CAPTCHA tokens of clients with the provider, which fail closed:
+/// a provider that can't be reached or answers garbage refuses the request.
+pub struct Captcha {
+    client: reqwest::Client,
+    url: Option<String>, // None when CAPTCHA is off
+    secret: String,
+}
+
+impl Captcha {
+    pub fn new(url: Option<String>, secret: String) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            url,
+            secret,
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        let security = &settings.security;
+        Self::new(
+            match security.captcha {
+                true => Some(security.captcha_verify_url.clone()),
+                false => None,
+            },
+            security.captcha_secret.clone(),
+        )
+    }
+
+    /// Ok when CAPTCHA is off, else the token must be accepted by the provider.
+    pub async fn verify(&self, token: Option<&str>, ip: Option<IpAddr>) -> Result<(), ApiError> {
+        let url = match &self.url {
+            Some(url) => url,
+            None => return Ok(()),
+        };
+        let token = match token {
+            Some(token) if !token.is_empty() => token,
+            _ => return Err(ApiError::CaptchaRequired),
+        };
+        if token.len() > TOKEN_MAX_LENGTH {
+            return Err(ApiError::InvalidCaptcha);
+        }
+        let mut form = vec![
+            ("secret", self.secret.clone()),
+            ("response", token.to_string()),
+        ];
+        if let Some(ip) = ip {
+            form.push(("remoteip", ip.to_string()));
+        }
+        let response = match self.client.post(url).form(&form).send().await {
+            Ok(response) => response.text().await,
+            Err(error) => Err(error),
+        };
+        let verification = match response {
+            Ok(body) => serde_json::from_str::<Verification>(&body).ok(),
+            Err(error) => {
+                error!(url = url.as_str(), error = ?error, "Cannot verify a CAPTCHA.");
+                return Err(ApiError::CaptchaUnavailable);
+            }
+        };
+        match verification {
+            Some(verification) if verification.success => Ok(()),
+            Some(verification) => {
+                warn!(codes = %verification.error_codes.join(", "), "CAPTCHA refused.");
+                Err(ApiError::InvalidCaptcha)
+            }
+            None => {
+                error!(
+                    url = url.as_str(),
+                    "Unexpected answer of the CAPTCHA provider."
+                );
+                Err(ApiError::CaptchaUnavailable)
+            }
+        }
+    }
+}
+
+/// The `X-Misato-Captcha-Token` header, if sent.
+pub struct CaptchaToken(pub Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CaptchaToken {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(CaptchaToken(
+            request
+                .headers()
+                .get_one(CAPTCHA_HEADER)
+                .map(|token| token.trim().to_string()),
+        ))
+    }
+}
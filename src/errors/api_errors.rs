@@ -0,0 +1,299 @@
+use rocket::http::Status;
+use serde_json::json;
+
+use misato_database::database::Unavailable;
+use misato_security::policy::PolicyViolation;
+use misato_utils::validation::{FieldError, ValidationErrors};
+
+#[derive(Debug)]
+pub enum ApiError {
+    NoPermission,
+    MissingScope(String),
+    Unauthenticated,
+    LastAdmin,
+    RegistrationClosed,
+    InvalidCredentials,
+    CorruptCredentials,
+    TotpRequired,
+    InvalidTotp,
+    InvalidRecoveryCode,
+    TotpUnavailable,
+    TotpEnabled,
+    TotpNotEnrolled,
+    InvalidToken(String),
+    TokenReused,
+    TokenNotFound(String),
+    WeakPassword(String),
+    PasswordTooLong(usize), // In bytes
+    InvalidEmail(String),
+    ValidationError(String),
+    UserExists(String),
+    EmailExists(String),
+    AccountNotFound(String),
+    ApiAccountExists(String),
+    ApiAccountNotFound(String),
+    IdempotencyKeyInUse,
+    CaptchaRequired,
+    InvalidCaptcha,
+    CaptchaUnavailable,
+    TooManyRequests(u64), // Seconds before retrying
+    AccountLocked(u64),   // Seconds before unlocking
+    RouteNotFound(String),
+    MethodNotAllowed(String, String), // The method, then the allowed ones
+    BadRequest,
+    InvalidBody,
+    InvalidField(FieldError),
+    InvalidFields(ValidationErrors),
+    PayloadTooLarge,
+    DbError,
+    DbUnavailable,
+    Maintenance(u64), // Seconds before retrying
+    Timeout(u64),     // Seconds the handler was given
+    InternalError,
+}
+
+impl ApiError {
+    /// 503 when MongoDB couldn't be reached in time, so clients know to retry, 500 otherwise.
+    pub fn from_db<E: Unavailable>(error: &E) -> Self {
+        match error.is_unavailable() {
+            true => ApiError::DbUnavailable,
+            false => ApiError::DbError,
+        }
+    }
+
+    /// The failures of a form checked as a whole, by field name, `None` when there are none.
+    /// A single failure keeps its own error, several are returned together.
+    pub fn from_failures(failures: Vec<(&str, ApiError)>) -> Option<Self> {
+        match failures.len() {
+            0 => None,
+            1 => failures.into_iter().next().map(|(_, error)| error),
+            _ => {
+                let mut errors = ValidationErrors::default();
+                for (field, error) in failures {
+                    errors.add(field, error.message());
+                }
+                Some(ApiError::InvalidFields(errors))
+            }
+        }
+    }
+
+    /// Too long passwords are told apart, they are refused before any strength rule.
+    pub fn from_policy(violation: PolicyViolation) -> Self {
+        match violation {
+            PolicyViolation::TooLong(max) => ApiError::PasswordTooLong(max),
+            violation => ApiError::WeakPassword(violation.to_string()),
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::NoPermission => "NO_PERMISSION",
+            ApiError::MissingScope(_) => "MISSING_SCOPE",
+            ApiError::Unauthenticated => "UNAUTHENTICATED",
+            ApiError::LastAdmin => "LAST_ADMIN",
+            ApiError::RegistrationClosed => "REGISTRATION_CLOSED",
+            ApiError::InvalidCredentials => "INVALID_CREDENTIALS",
+            ApiError::CorruptCredentials => "CORRUPT_CREDENTIALS",
+            ApiError::TotpRequired => "TOTP_REQUIRED",
+            ApiError::InvalidTotp => "INVALID_TOTP",
+            ApiError::InvalidRecoveryCode => "INVALID_RECOVERY_CODE",
+            ApiError::TotpUnavailable => "TOTP_UNAVAILABLE",
+            ApiError::TotpEnabled => "TOTP_ENABLED",
+            ApiError::TotpNotEnrolled => "TOTP_NOT_ENROLLED",
+            ApiError::InvalidToken(_) => "INVALID_TOKEN",
+            ApiError::TokenReused => "TOKEN_REUSED",
+            ApiError::TokenNotFound(_) => "TOKEN_NOT_FOUND",
+            ApiError::WeakPassword(_) => "WEAK_PASSWORD",
+            ApiError::PasswordTooLong(_) => "PASSWORD_TOO_LONG",
+            ApiError::InvalidEmail(_) => "INVALID_EMAIL",
+            ApiError::ValidationError(_) => "VALIDATION_ERROR",
+            ApiError::UserExists(_) => "USER_EXISTS",
+            ApiError::EmailExists(_) => "EMAIL_EXISTS",
+            ApiError::AccountNotFound(_) => "ACCOUNT_NOT_FOUND",
+            ApiError::ApiAccountExists(_) => "API_ACCOUNT_EXISTS",
+            ApiError::IdempotencyKeyInUse => "IDEMPOTENCY_KEY_IN_USE",
+            ApiError::ApiAccountNotFound(_) => "API_ACCOUNT_NOT_FOUND",
+            ApiError::CaptchaRequired => "CAPTCHA_REQUIRED",
+            ApiError::InvalidCaptcha => "INVALID_CAPTCHA",
+            ApiError::CaptchaUnavailable => "CAPTCHA_UNAVAILABLE",
+            ApiError::TooManyRequests(_) => "TOO_MANY_REQUESTS",
+            ApiError::AccountLocked(_) => "ACCOUNT_LOCKED",
+            ApiError::RouteNotFound(_) => "ROUTE_NOT_FOUND",
+            ApiError::MethodNotAllowed(..) => "METHOD_NOT_ALLOWED",
+            ApiError::BadRequest => "BAD_REQUEST",
+            ApiError::InvalidBody => "INVALID_BODY",
+            ApiError::InvalidField(_) => "INVALID_FIELD",
+            ApiError::InvalidFields(_) => "VALIDATION_FAILED",
+            ApiError::PayloadTooLarge => "PAYLOAD_TOO_LARGE",
+            ApiError::DbError => "DB_ERROR",
+            ApiError::DbUnavailable => "DB_UNAVAILABLE",
+            ApiError::Maintenance(_) => "MAINTENANCE",
+            ApiError::Timeout(_) => "TIMEOUT",
+            ApiError::InternalError => "INTERNAL_ERROR",
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::NoPermission
+            | ApiError::MissingScope(_)
+            | ApiError::RegistrationClosed
+            | ApiError::TotpUnavailable
+            | ApiError::InvalidCaptcha => Status::Forbidden,
+            ApiError::Unauthenticated
+            | ApiError::InvalidCredentials
+            | ApiError::InvalidToken(_)
+            | ApiError::TokenReused
+            | ApiError::TotpRequired
+            | ApiError::InvalidTotp
+            | ApiError::InvalidRecoveryCode => Status::Unauthorized,
+            ApiError::WeakPassword(_)
+            | ApiError::PasswordTooLong(_)
+            | ApiError::InvalidEmail(_)
+            | ApiError::ValidationError(_)
+            | ApiError::CaptchaRequired
+            | ApiError::BadRequest => Status::BadRequest,
+            ApiError::LastAdmin
+            | ApiError::TotpEnabled
+            | ApiError::TotpNotEnrolled
+            | ApiError::UserExists(_)
+            | ApiError::EmailExists(_)
+            | ApiError::ApiAccountExists(_)
+            | ApiError::IdempotencyKeyInUse => Status::Conflict,
+            ApiError::AccountNotFound(_)
+            | ApiError::ApiAccountNotFound(_)
+            | ApiError::TokenNotFound(_)
+            | ApiError::RouteNotFound(_) => Status::NotFound,
+            ApiError::MethodNotAllowed(..) => Status::MethodNotAllowed,
+            ApiError::InvalidBody | ApiError::InvalidField(_) | ApiError::InvalidFields(_) => {
+                Status::UnprocessableEntity
+            }
+            ApiError::PayloadTooLarge => Status::PayloadTooLarge,
+            ApiError::TooManyRequests(_) => Status::TooManyRequests,
+            ApiError::AccountLocked(_) => Status::Locked,
+            ApiError::CorruptCredentials | ApiError::DbError | ApiError::InternalError => {
+                Status::InternalServerError
+            }
+            ApiError::DbUnavailable | ApiError::Maintenance(_) | ApiError::CaptchaUnavailable => {
+                Status::ServiceUnavailable
+            }
+            ApiError::Timeout(_) => Status::GatewayTimeout,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            ApiError::NoPermission => "No permission.".to_string(),
+            ApiError::MissingScope(scope) => format!("[{}]: Token lacks this scope.", scope),
+            ApiError::Unauthenticated => "Missing or invalid token.".to_string(),
+            ApiError::LastAdmin => "At least one admin must remain.".to_string(),
+            ApiError::RegistrationClosed => {
+                "Registration is closed, an invite is required.".to_string()
+            }
+            ApiError::InvalidCredentials => "Invalid credentials.".to_string(),
+            ApiError::CorruptCredentials => {
+                "Stored credentials are corrupt, contact an administrator.".to_string()
+            }
+            ApiError::TotpRequired => "A code of the authenticator app is required.".to_string(),
+            ApiError::InvalidTotp => "Invalid authenticator code.".to_string(),
+            ApiError::InvalidRecoveryCode => "Invalid or already used recovery code.".to_string(),
+            ApiError::TotpUnavailable => {
+                "Two factor authentication is not configured on this server.".to_string()
+            }
+            ApiError::TotpEnabled => "Two factor authentication is already enabled.".to_string(),
+            ApiError::TotpNotEnrolled => {
+                "Two factor authentication has not been enrolled.".to_string()
+            }
+            ApiError::InvalidToken(token) => {
+                format!("[{}]: Token not related to any account.", token)
+            }
+            ApiError::TokenReused => {
+                "Refresh token already used, the session has been revoked.".to_string()
+            }
+            ApiError::TokenNotFound(token) => format!("[{}]: Token doesn't exist.", token),
+            ApiError::WeakPassword(reason) => reason.to_string(),
+            ApiError::PasswordTooLong(max) => PolicyViolation::TooLong(*max).to_string(),
+            ApiError::InvalidEmail(email) => format!("[{}]: Invalid email address.", email),
+            ApiError::ValidationError(reason) => reason.to_string(),
+            ApiError::UserExists(username) => {
+                format!("[{}]: Username already used by an account.", username)
+            }
+            ApiError::EmailExists(email) => {
+                format!("[{}]: Email address already used by an account.", email)
+            }
+            ApiError::AccountNotFound(uuid) => format!("[{}]: Account doesn't exist.", uuid),
+            ApiError::ApiAccountExists(uuid) => {
+                format!("[{}]: API Account already exists.", uuid)
+            }
+            ApiError::ApiAccountNotFound(uuid) => {
+                format!("[{}]: API Account doesn't exist.", uuid)
+            }
+            ApiError::IdempotencyKeyInUse => {
+                "A request with this Idempotency-Key is still running.".to_string()
+            }
+            ApiError::CaptchaRequired => "A CAPTCHA token is required.".to_string(),
+            ApiError::InvalidCaptcha => "Invalid or expired CAPTCHA token.".to_string(),
+            ApiError::CaptchaUnavailable => {
+                "CAPTCHA can't be verified, try again later.".to_string()
+            }
+            ApiError::TooManyRequests(seconds) => {
+                format!("Too many attempts, retry in {} seconds.", seconds)
+            }
+            ApiError::AccountLocked(seconds) => {
+                format!("Account locked, retry in {} seconds.", seconds)
+            }
+            ApiError::RouteNotFound(path) => format!("[{}]: No such route.", path),
+            ApiError::MethodNotAllowed(method, _) => {
+                format!("[{}]: Method not allowed on this route.", method)
+            }
+            ApiError::BadRequest => "Malformed request.".to_string(),
+            ApiError::InvalidBody => {
+                "Request body doesn't match the expected JSON document.".to_string()
+            }
+            ApiError::InvalidField(error) => error.to_string(),
+            ApiError::InvalidFields(errors) => errors.to_string(),
+            ApiError::PayloadTooLarge => "Request body is too large.".to_string(),
+            ApiError::DbError => "Database error.".to_string(),
+            ApiError::DbUnavailable => "Database unavailable, try again later.".to_string(),
+            ApiError::Maintenance(seconds) => {
+                format!("Down for maintenance, retry in {} seconds.", seconds)
+            }
+            ApiError::Timeout(seconds) => {
+                format!("Request not handled within {} seconds.", seconds)
+            }
+            ApiError::InternalError => "Internal error.".to_string(),
+        }
+    }
+}
+
+impl<'r> rocket::response::Responder<'r, 'static> for ApiError {
+    fn respond_to(self, _: &'r rocket::Request<'_>) -> rocket::response::Result<'static> {
+        // Convert object to json
+        let mut body = json!({
+            "data": null,
+            "error": {
+                "code": self.code(),
+                "message": self.message(),
+            }
+        });
+        if let ApiError::InvalidFields(errors) = &self {
+            body["error"]["fields"] = json!(errors.fields());
+        }
+        let body = body.to_string();
+        let mut response = rocket::Response::build();
+        response
+            .sized_body(body.len(), std::io::Cursor::new(body))
+            .header(rocket::http::ContentType::JSON)
+            .status(self.status());
+        if let ApiError::TooManyRequests(seconds)
+        | ApiError::AccountLocked(seconds)
+        | ApiError::Maintenance(seconds) = self
+        {
+            response.raw_header("Retry-After", seconds.to_string());
+        }
+        if let ApiError::MethodNotAllowed(_, allowed) = self {
+            response.raw_header("Allow", allowed);
+        }
+        response.ok()
+    }
+}
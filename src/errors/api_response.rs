@@ -0,0 +1,34 @@
+use rocket::http::{ContentType, Status};
+use rocket::response::{self, Responder, Response};
+use rocket::Request;
+use serde::Serialize;
+use tracing::error;
+
+/// Success body of a route, `{ "data": ..., "error": null }`, the same envelope as an `ApiError`.
+#[derive(Debug)]
+pub struct ApiResponse<T>(pub T);
+
+#[derive(Serialize)]
+struct Envelope<T> {
+    data: T,
+    error: Option<()>, // Always null, see `ApiError` for failures
+}
+
+impl<'r, T: Serialize> Responder<'r, 'static> for ApiResponse<T> {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let body = match serde_json::to_string(&Envelope {
+            data: self.0,
+            error: None,
+        }) {
+            Ok(body) => body,
+            Err(error) => {
+                error!(error = ?error, "Cannot serialize the response.");
+                return Err(Status::InternalServerError);
+            }
+        };
+        Response::build()
+            .sized_body(body.len(), std::io::Cursor::new(body))
+            .header(ContentType::JSON)
+            .ok()
+    }
+}
@@ -0,0 +1,112 @@
+use rocket::http::Method;
+use rocket::{catch, catchers, Catcher, Request, Route};
+
+use crate::errors::api_errors::ApiError;
+use crate::fairings::json_form::field_error;
+use crate::fairings::scope::missing_scope;
+
+/// Every catcher, so no error leaves without the `ApiError` body.
+pub fn catchers() -> Vec<Catcher> {
+    catchers![
+        bad_request,
+        unauthorized,
+        forbidden,
+        not_found,
+        payload_too_large,
+        unprocessable_entity,
+        internal_error,
+    ]
+}
+
+/// Guards failing with 400, like an `Idempotency-Key` too long.
+#[catch(400)]
+pub fn bad_request() -> ApiError {
+    ApiError::BadRequest
+}
+
+/// Guards failing with 401, the missing or invalid token isn't told apart.
+#[catch(401)]
+pub fn unauthorized() -> ApiError {
+    ApiError::Unauthenticated
+}
+
+/// Guards failing with 403, naming the scope when the token lacked one.
+#[catch(403)]
+pub fn forbidden(request: &Request) -> ApiError {
+    match missing_scope(request) {
+        Some(scope) => ApiError::MissingScope(scope.to_string()),
+        None => ApiError::NoPermission,
+    }
+}
+
+/// Whether `path` is one of the paths of `route`, its parameters matching any segment.
+fn route_matches(route: &Route, path: &str) -> bool {
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+    for pattern in route
+        .uri
+        .path()
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+    {
+        if pattern.starts_with('<') && pattern.ends_with("..>") {
+            return true;
+        }
+        match segments.next() {
+            Some(segment) if pattern.starts_with('<') || segment == pattern => {}
+            _ => return false,
+        }
+    }
+    segments.next().is_none()
+}
+
+/// Methods of the routes on the path of `request`, sorted.
+fn allowed_methods(request: &Request) -> Vec<Method> {
+    let path = request.uri().path();
+    let mut methods: Vec<Method> = request
+        .rocket()
+        .routes()
+        .filter(|route| route_matches(route, path.as_str()))
+        .map(|route| route.method)
+        .collect();
+    methods.sort_by_key(|method| method.as_str());
+    methods.dedup();
+    methods
+}
+
+/// Rocket answers 404 to a path only routed for other methods too, those get a 405.
+/// A route of the same method may have forwarded, and preflights are answered by `Cors`.
+#[catch(404)]
+pub fn not_found(request: &Request) -> ApiError {
+    let method = match request.method() {
+        Method::Head => Method::Get,
+        method => method,
+    };
+    if method != Method::Options {
+        let allowed = allowed_methods(request);
+        if !allowed.is_empty() && !allowed.contains(&method) {
+            let allowed: Vec<&str> = allowed.iter().map(|method| method.as_str()).collect();
+            return ApiError::MethodNotAllowed(request.method().to_string(), allowed.join(", "));
+        }
+    }
+    ApiError::RouteNotFound(request.uri().path().to_string())
+}
+
+/// Bodies over the limits from the settings.
+#[catch(413)]
+pub fn payload_too_large() -> ApiError {
+    ApiError::PayloadTooLarge
+}
+
+/// Rocket doesn't hand the serde error to catchers, only `JsonForm` bodies name the bad field.
+#[catch(422)]
+pub fn unprocessable_entity(request: &Request) -> ApiError {
+    match field_error(request) {
+        Some(error) => ApiError::InvalidField(error),
+        None => ApiError::InvalidBody,
+    }
+}
+
+#[catch(500)]
+pub fn internal_error() -> ApiError {
+    ApiError::InternalError
+}
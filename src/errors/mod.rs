@@ -1,2 +1,3 @@
-pub mod account_errors;
-pub mod apiaccount_errors;
+pub mod api_errors;
+pub mod api_response;
+pub mod catchers;
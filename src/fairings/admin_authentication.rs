@@ -0,0 +1,63 @@
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Outcome, Request};
+
+use misato_database::{database::*, models::*};
+
+pub struct AdminUser {
+    pub uuid: String,
+    pub role: apiuser_model::ApiUserRoleType,
+}
+
+#[derive(Debug)]
+pub enum ApiRoleError {
+    Missing,
+    Invalid,
+    InsufficientRole,
+}
+
+/// Token from `Authorization: Bearer <token>`, or from `X-Misato-API-Token` as before.
+fn admin_token<'r>(request: &'r Request<'_>) -> Option<&'r str> {
+    match request.headers().get_one("Authorization") {
+        Some(header) => header.strip_prefix("Bearer "),
+        None => request.headers().get_one("X-Misato-API-Token"),
+    }
+}
+
+/// Api user behind the request token, if its role is at least `role`.
+async fn apiuser_with_role(
+    request: &Request<'_>,
+    role: apiuser_model::ApiUserRoleType,
+) -> Result<apiuser_model::ApiUser, (Status, ApiRoleError)> {
+    let token = match admin_token(request) {
+        Some(token) => token,
+        None => return Err((Status::Unauthorized, ApiRoleError::Missing)),
+    };
+
+    let db = request.rocket().state::<Database>().unwrap();
+
+    match db.apiusermanager.get_apiuser_from_token(token).await {
+        // The lookup is by hash, checking again keeps the final comparison constant time
+        Ok(Some(apiuser)) if apiuser.token_matches(token) => {
+            if !apiuser.has_at_least(&role) {
+                return Err((Status::Forbidden, ApiRoleError::InsufficientRole));
+            }
+            Ok(apiuser)
+        }
+        _ => Err((Status::Unauthorized, ApiRoleError::Invalid)),
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminUser {
+    type Error = ApiRoleError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<AdminUser, Self::Error> {
+        match apiuser_with_role(request, apiuser_model::ApiUserRoleType::Admin).await {
+            Ok(apiuser) => Outcome::Success(AdminUser {
+                uuid: apiuser.uuid,
+                role: apiuser.access.role,
+            }),
+            Err(failure) => Outcome::Failure(failure),
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use rocket::http::Status;
 use rocket::request::{self, FromRequest, Outcome, Request};
+use tracing::error;
 
 use misato_database::{database::*, models::*};
 
 pub struct ApiUserToken {
     pub apiuser: apiuser_model::ApiUser,
+    pub token: String, // As sent, only its hash is stored
 }
 
 #[derive(Debug)]
@@ -21,23 +23,90 @@ impl<'r> FromRequest<'r> for ApiUserToken {
     async fn from_request(request: &'r Request<'_>) -> request::Outcome<ApiUserToken, Self::Error> {
         let keys: Vec<_> = request.headers().get("X-Misato-API-Token").collect();
         match keys.len() {
-            0 => return Outcome::Failure((Status::BadRequest, ApiUserTokenError::Missing)),
+            0 => return Outcome::Failure((Status::Unauthorized, ApiUserTokenError::Missing)),
             1 => {
-                let token = keys.get(0).unwrap();
+                let token = keys.first().unwrap();
 
                 let db = request.rocket().state::<Database>().unwrap();
 
-                let apiuser = db.apiusermanager.get_apiuser_from_token(&token).await;
+                let apiuser = db.apiusermanager.get_apiuser_from_token(token).await;
 
-                if apiuser.is_ok() && apiuser.as_ref().unwrap().is_some() {
-                    return Outcome::Success(ApiUserToken {
-                        apiuser: apiuser.unwrap().unwrap(),
-                    });
+                if let Ok(Some(apiuser)) = apiuser {
+                    if apiuser.token_matches(token) {
+                        return Outcome::Success(ApiUserToken {
+                            apiuser,
+                            token: token.to_string(),
+                        });
+                    }
                 }
-                return Outcome::Failure((Status::BadRequest, ApiUserTokenError::Invalid));
+                return Outcome::Failure((Status::Unauthorized, ApiUserTokenError::Invalid));
             }
             _ => {
-                return Outcome::Failure((Status::BadRequest, ApiUserTokenError::BadCount));
+                return Outcome::Failure((Status::Unauthorized, ApiUserTokenError::BadCount));
+            }
+        }
+    }
+}
+
+/// An api account authenticated by `X-Misato-API-Token`, every failure is a 401.
+/// The header is either the api token or one of the named keys of the account.
+pub struct AuthenticatedApiUser {
+    pub apiuser: apiuser_model::ApiUser,
+    pub token: String,                      // The api token or key as sent
+    pub key: Option<apiuser_model::ApiKey>, // The key used, None with the api token
+}
+
+#[derive(Debug)]
+pub enum AuthenticatedApiUserError {
+    Missing,
+    Invalid,
+    Database,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthenticatedApiUser {
+    type Error = AuthenticatedApiUserError;
+
+    async fn from_request(
+        request: &'r Request<'_>,
+    ) -> request::Outcome<AuthenticatedApiUser, Self::Error> {
+        match request.guard::<ApiUserToken>().await {
+            Outcome::Success(api) => {
+                return Outcome::Success(AuthenticatedApiUser {
+                    apiuser: api.apiuser,
+                    token: api.token,
+                    key: None,
+                })
+            }
+            Outcome::Failure((_, ApiUserTokenError::Missing)) => {
+                return Outcome::Failure((Status::Unauthorized, AuthenticatedApiUserError::Missing))
+            }
+            Outcome::Failure((_, ApiUserTokenError::Invalid)) => {}
+            Outcome::Failure(_) => {
+                return Outcome::Failure((Status::Unauthorized, AuthenticatedApiUserError::Invalid))
+            }
+            Outcome::Forward(forward) => return Outcome::Forward(forward),
+        }
+
+        let db = request.rocket().state::<Database>().unwrap();
+        let sent = request.headers().get_one("X-Misato-API-Token").unwrap();
+        match db.apiusermanager.get_apiuser_from_key(sent).await {
+            Ok(Some((apiuser, key))) => {
+                return Outcome::Success(AuthenticatedApiUser {
+                    apiuser,
+                    token: sent.to_string(),
+                    key: Some(key),
+                })
+            }
+            Ok(None) => {
+                return Outcome::Failure((Status::Unauthorized, AuthenticatedApiUserError::Invalid))
+            }
+            Err(error) => {
+                error!(error = ?error, "Cannot look the api key up.");
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    AuthenticatedApiUserError::Database,
+                ));
             }
         }
     }
@@ -0,0 +1,56 @@
+use rocket::request::{self, FromRequest, Outcome, Request};
+use tracing::error;
+
+use misato_database::{database::*, models::audit_model::*};
+
+use crate::fairings::client_info::client_ip;
+use crate::fairings::request_id::request_id;
+use crate::webhooks::Webhooks;
+
+/// Records audit events along with where the request came from, and sends them to the webhooks.
+pub struct Audit<'r> {
+    db: &'r Database,
+    ip: Option<String>,
+    request_id: String,
+    webhooks: Option<&'r Webhooks>,
+}
+
+impl<'r> Audit<'r> {
+    /// For events done by an anonymized account, which keeps no address.
+    pub fn without_ip(mut self) -> Self {
+        self.ip = None;
+        self
+    }
+
+    /// A failure to record is logged, it never fails the request.
+    pub async fn record(&self, action: AuditAction, actor: Option<&str>, target: Option<&str>) {
+        let mut event = AuditEvent::create(
+            action,
+            actor.map(|v| v.to_string()),
+            target.map(|v| v.to_string()),
+        );
+        event.ip = self.ip.clone();
+        event.request_id = Some(self.request_id.clone());
+        if let Err(error) = self.db.auditmanager.record_event(&event).await {
+            error!(error = ?error, "Cannot record the audit event.");
+        }
+        if let Some(webhooks) = self.webhooks {
+            webhooks.dispatch(&event);
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Audit<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let db = request.rocket().state::<Database>().unwrap();
+        Outcome::Success(Audit {
+            db,
+            ip: client_ip(request).map(|ip| ip.to_string()),
+            request_id: request_id(request).id.clone(),
+            webhooks: request.rocket().state::<Webhooks>(),
+        })
+    }
+}
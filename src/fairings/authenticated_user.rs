@@ -0,0 +1,131 @@
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Outcome, Request};
+use tracing::error;
+
+use misato_database::{database::*, models::*};
+use misato_security::{constant_time_eq, hash_token};
+use misato_utils::get_current_timestamp;
+
+/// A user resolved from `Authorization: Bearer <token>`, every failure is a 401.
+/// The token is either one of its sessions or a named key of its api account.
+pub struct AuthenticatedUser {
+    pub user: user_model::User,
+    pub token: String, // As sent, only its hash is stored
+    pub credential: Credential,
+}
+
+/// What the bearer token matched.
+pub enum Credential {
+    Session(user_model::UserToken),
+    Key(apiuser_model::ApiKey),
+}
+
+#[derive(Debug)]
+pub enum AuthenticatedUserError {
+    BadCount,
+    Missing,
+    Invalid,
+    Database,
+}
+
+impl AuthenticatedUser {
+    pub fn allows(&self, scope: &str) -> bool {
+        match &self.credential {
+            Credential::Session(session) => session.allows(scope),
+            Credential::Key(key) => key.allows(scope),
+        }
+    }
+}
+
+/// The unexpired session of `user` matching `token`.
+fn session(user: &user_model::User, token: &str) -> Option<user_model::UserToken> {
+    let now = get_current_timestamp();
+    let hash = hash_token(token);
+    user.tokens
+        .iter()
+        .flatten()
+        .find(|session| {
+            constant_time_eq(session.token.as_bytes(), hash.as_bytes()) && !session.is_expired(now)
+        })
+        .cloned()
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AuthenticatedUser {
+    type Error = AuthenticatedUserError;
+
+    async fn from_request(
+        request: &'r Request<'_>,
+    ) -> request::Outcome<AuthenticatedUser, Self::Error> {
+        let headers: Vec<_> = request.headers().get("Authorization").collect();
+        let token = match headers.as_slice() {
+            [] => return Outcome::Failure((Status::Unauthorized, AuthenticatedUserError::Missing)),
+            [header] => match header.strip_prefix("Bearer ") {
+                Some(token) => token,
+                None => {
+                    return Outcome::Failure((
+                        Status::Unauthorized,
+                        AuthenticatedUserError::Invalid,
+                    ))
+                }
+            },
+            _ => return Outcome::Failure((Status::Unauthorized, AuthenticatedUserError::BadCount)),
+        };
+
+        let db = request.rocket().state::<Database>().unwrap();
+        let authenticated = |user: user_model::User, credential: Credential| {
+            Outcome::Success(AuthenticatedUser {
+                user,
+                token: token.to_string(),
+                credential,
+            })
+        };
+
+        match db.usermanager.get_user_from_token(token).await {
+            Ok(Some(user)) => match session(&user, token) {
+                Some(session) => return authenticated(user, Credential::Session(session)),
+                None => {
+                    return Outcome::Failure((
+                        Status::Unauthorized,
+                        AuthenticatedUserError::Invalid,
+                    ))
+                }
+            },
+            Ok(None) => {}
+            Err(error) => {
+                error!(error = ?error, "Cannot look the user of the session up.");
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    AuthenticatedUserError::Database,
+                ));
+            }
+        }
+
+        let (uuid, key) = match db.apiusermanager.get_apiuser_from_key(token).await {
+            Ok(Some((apiuser, key))) => (apiuser.uuid, key),
+            Ok(None) => {
+                return Outcome::Failure((Status::Unauthorized, AuthenticatedUserError::Invalid))
+            }
+            Err(error) => {
+                error!(error = ?error, "Cannot look the api key up.");
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    AuthenticatedUserError::Database,
+                ));
+            }
+        };
+        match db.usermanager.get_user(None, Some(&uuid)).await {
+            Ok(Some(user)) => return authenticated(user, Credential::Key(key)),
+            Ok(None) => {
+                return Outcome::Failure((Status::Unauthorized, AuthenticatedUserError::Invalid))
+            }
+            Err(error) => {
+                error!(error = ?error, "Cannot look the user of the api key up.");
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    AuthenticatedUserError::Database,
+                ));
+            }
+        }
+    }
+}
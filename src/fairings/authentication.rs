@@ -3,6 +3,9 @@ use rocket::request::{self, FromRequest, Outcome, Request};
 
 use misato_database::{database::*, models::*};
 
+use crate::fairings::authenticated_user::{AuthenticatedUser, AuthenticatedUserError};
+
+/// A user from `X-Misato-User-Token`, as the v1 routes authenticate it.
 pub struct UserToken {
     pub user: user_model::User,
 }
@@ -21,24 +24,59 @@ impl<'r> FromRequest<'r> for UserToken {
     async fn from_request(request: &'r Request<'_>) -> request::Outcome<UserToken, Self::Error> {
         let keys: Vec<_> = request.headers().get("X-Misato-User-Token").collect();
         match keys.len() {
-            0 => return Outcome::Failure((Status::BadRequest, UserTokenError::Missing)),
+            0 => return Outcome::Failure((Status::Unauthorized, UserTokenError::Missing)),
             1 => {
-                let token = keys.get(0).unwrap();
+                let token = keys.first().unwrap();
 
                 let db = request.rocket().state::<Database>().unwrap();
 
-                let user = db.usermanager.get_user_from_token(&token).await;
+                let user = db.usermanager.get_user_from_token(token).await;
 
-                if user.is_ok() && user.as_ref().unwrap().is_some() {
-                    return Outcome::Success(UserToken {
-                        user: user.unwrap().unwrap(),
-                    });
+                if let Ok(Some(user)) = user {
+                    return Outcome::Success(UserToken { user });
                 }
-                return Outcome::Failure((Status::BadRequest, UserTokenError::Invalid));
+                return Outcome::Failure((Status::Unauthorized, UserTokenError::Invalid));
             }
             _ => {
-                return Outcome::Failure((Status::BadRequest, UserTokenError::BadCount));
+                return Outcome::Failure((Status::Unauthorized, UserTokenError::BadCount));
+            }
+        }
+    }
+}
+
+/// A user whose email has been verified, for the actions it gates.
+pub struct VerifiedUser {
+    pub user: user_model::User,
+}
+
+#[derive(Debug)]
+pub enum VerifiedUserError {
+    Missing,
+    Invalid,
+    NotVerified,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for VerifiedUser {
+    type Error = VerifiedUserError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<VerifiedUser, Self::Error> {
+        match request.guard::<AuthenticatedUser>().await {
+            Outcome::Success(authenticated) => {
+                if !authenticated.user.email_verified {
+                    return Outcome::Failure((Status::Forbidden, VerifiedUserError::NotVerified));
+                }
+                return Outcome::Success(VerifiedUser {
+                    user: authenticated.user,
+                });
+            }
+            Outcome::Failure((status, AuthenticatedUserError::Missing)) => {
+                return Outcome::Failure((status, VerifiedUserError::Missing))
+            }
+            Outcome::Failure((status, _)) => {
+                return Outcome::Failure((status, VerifiedUserError::Invalid))
             }
+            Outcome::Forward(forward) => return Outcome::Forward(forward),
         }
     }
 }
@@ -0,0 +1,82 @@
+use std::net::IpAddr;
+
+use rocket::request::{self, FromRequest, Outcome, Request};
+
+use misato_utils::{
+    proxy::{client_address, forwarded, x_forwarded_for},
+    settings::Settings,
+};
+
+const USER_AGENT_MAX_LENGTH: usize = 256;
+
+/// Where a request comes from, shown to users with their sessions.
+pub struct ClientInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Address of the client, the peer's unless it is a trusted proxy.
+/// Behind one, the chain of the trusted proxy header is followed, by default `Forwarded`
+/// then `X-Forwarded-For`. None for the local test clients, which have no peer.
+pub struct ClientIp(pub Option<IpAddr>);
+
+/// The chain the proxies sent, None when it is missing or can't be parsed.
+fn proxy_chain(request: &Request<'_>, header: Option<&str>) -> Option<Vec<IpAddr>> {
+    let headers = request.headers();
+    match header {
+        Some(header) => x_forwarded_for(headers.get_one(header)?),
+        None => match headers.get_one("Forwarded") {
+            Some(value) => forwarded(value),
+            None => x_forwarded_for(headers.get_one("X-Forwarded-For")?),
+        },
+    }
+}
+
+/// Like the `ClientIp` guard, for the fairings.
+pub fn client_ip(request: &Request<'_>) -> Option<IpAddr> {
+    request
+        .local_cache(|| {
+            let peer = request.remote().map(|remote| remote.ip());
+            let settings = request.rocket().state::<Settings>();
+            ClientIp(match (peer, settings) {
+                (Some(peer), Some(settings)) => {
+                    let http = &settings.http;
+                    let chain = match http.trusted_proxies.is_empty() {
+                        true => None,
+                        false => proxy_chain(request, http.trusted_proxy_header.as_deref()),
+                    };
+                    Some(client_address(
+                        peer,
+                        chain.as_deref(),
+                        &http.trusted_proxies,
+                    ))
+                }
+                (peer, _) => peer,
+            })
+        })
+        .0
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(ClientIp(client_ip(request)))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientInfo {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(ClientInfo {
+            ip: client_ip(request).map(|ip| ip.to_string()),
+            user_agent: request
+                .headers()
+                .get_one("User-Agent")
+                .map(|agent| agent.chars().take(USER_AGENT_MAX_LENGTH).collect()),
+        })
+    }
+}
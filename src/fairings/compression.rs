@@ -0,0 +1,112 @@
+use std::io::Cursor;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{ContentType, Header};
+use rocket::{Request, Response};
+use tracing::error;
+
+use misato_utils::{compression::Encoding, settings::Settings};
+
+const BLOCKING_SIZE: usize = 64 * 1024; // In bytes, encoded on a blocking thread from there
+
+/// Encodes sized bodies with the coding the client prefers, left out of `rocket()` so `main` decides.
+pub struct Compression {
+    min_size: usize, // In bytes
+}
+
+impl Compression {
+    pub fn new(min_size: usize) -> Self {
+        Self { min_size }
+    }
+
+    /// None when compression is turned off.
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        match settings.http.compression {
+            true => Some(Self::new(settings.http.compression_min_size)),
+            false => None,
+        }
+    }
+}
+
+/// Media that is compressed already, encoding it again only costs time.
+fn already_compressed(content_type: &ContentType) -> bool {
+    match content_type.top().as_str() {
+        "image" => content_type.sub() != "svg+xml",
+        "audio" | "video" => true,
+        "font" => matches!(content_type.sub().as_str(), "woff" | "woff2"),
+        "application" => matches!(
+            content_type.sub().as_str(),
+            "gzip" | "x-gzip" | "zip" | "zstd" | "x-bzip2" | "x-xz" | "x-7z-compressed" | "pdf"
+        ),
+        _ => false,
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Compression {
+    fn info(&self) -> Info {
+        Info {
+            name: "Compression",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if response.headers().contains("Content-Encoding") {
+            return;
+        }
+        if let Some(content_type) = response.content_type() {
+            if already_compressed(&content_type) {
+                return;
+            }
+        }
+        // Streamed bodies have no size and may never end
+        match response.body().preset_size() {
+            Some(size) if size >= self.min_size => {}
+            _ => return,
+        }
+        response.adjoin_header(Header::new("Vary", "Accept-Encoding"));
+
+        let encoding = match request.headers().get_one("Accept-Encoding") {
+            Some(accept_encoding) => Encoding::negotiate(accept_encoding),
+            None => None,
+        };
+        let encoding = match encoding {
+            Some(encoding) => encoding,
+            None => return,
+        };
+        let body = match response.body_mut().to_bytes().await {
+            Ok(body) => body,
+            Err(error) => {
+                error!(error = ?error, "Cannot read the body to compress.");
+                return;
+            }
+        };
+        // Larger bodies take long enough to encode to hold up the other requests of the worker
+        let (body, encoded) = match body.len() >= BLOCKING_SIZE {
+            true => match rocket::tokio::task::spawn_blocking(move || {
+                let encoded = encoding.encode(&body);
+                (body, encoded)
+            })
+            .await
+            {
+                Ok(encoded) => encoded,
+                Err(error) => {
+                    error!(error = ?error, "Cannot compress the body.");
+                    return;
+                }
+            },
+            false => {
+                let encoded = encoding.encode(&body);
+                (body, encoded)
+            }
+        };
+        // Data that doesn't shrink is sent as it was
+        if encoded.len() >= body.len() {
+            response.set_sized_body(body.len(), Cursor::new(body));
+            return;
+        }
+        response.set_header(Header::new("Content-Encoding", encoding.name()));
+        response.set_sized_body(encoded.len(), Cursor::new(encoded));
+    }
+}
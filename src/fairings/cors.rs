@@ -0,0 +1,67 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::{Header, Method, Status};
+use rocket::{Request, Response};
+
+use misato_utils::settings::Settings;
+
+const ALLOWED_METHODS: &str = "GET, POST, PUT, DELETE, OPTIONS";
+const ALLOWED_HEADERS: &str = concat!(
+    "Authorization, Content-Type, Idempotency-Key, ",
+    "X-Misato-API-Token, X-Misato-Captcha-Token, X-Misato-User-Token, X-Request-Id"
+);
+
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "CORS headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let origin = match request.headers().get_one("Origin") {
+            Some(origin) => origin,
+            None => return,
+        };
+        let settings = request.rocket().state::<Settings>().unwrap();
+        // A wildcard never echoes the origin, so browsers won't send credentials with it,
+        // the settings refuse to allow credentials along with it
+        let allowed_origin = if settings.http.cors_allowed_origins.iter().any(|v| v == "*") {
+            "*"
+        } else if settings
+            .http
+            .cors_allowed_origins
+            .iter()
+            .any(|v| v == origin)
+        {
+            response.set_header(Header::new("Vary", "Origin"));
+            if settings.http.cors_allow_credentials {
+                response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
+            }
+            origin
+        } else {
+            return;
+        };
+        response.set_header(Header::new(
+            "Access-Control-Allow-Origin",
+            allowed_origin.to_string(),
+        ));
+        response.set_header(Header::new("Access-Control-Allow-Methods", ALLOWED_METHODS));
+        response.set_header(Header::new("Access-Control-Allow-Headers", ALLOWED_HEADERS));
+        if !settings.http.cors_expose_headers.is_empty() {
+            response.set_header(Header::new(
+                "Access-Control-Expose-Headers",
+                settings.http.cors_expose_headers.join(", "),
+            ));
+        }
+
+        // Preflight requests never match a route
+        if request.method() == Method::Options && response.status() == Status::NotFound {
+            response.set_status(Status::NoContent);
+            response.set_sized_body(0, std::io::Cursor::new(""));
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Request, Response};
+use tracing::warn;
+
+/// Flag the requests served through the unversioned API base.
+pub struct ApiDeprecation {
+    legacy_base: String,
+    successor_base: String,
+}
+
+impl ApiDeprecation {
+    pub fn new(legacy_base: &str, successor_base: &str) -> Self {
+        Self {
+            legacy_base: legacy_base.to_string(),
+            successor_base: successor_base.to_string(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for ApiDeprecation {
+    fn info(&self) -> Info {
+        Info {
+            name: "Deprecated API paths",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let route = match request.route() {
+            Some(route) if route.uri.base() == self.legacy_base => route,
+            _ => return,
+        };
+        let successor = format!(
+            "{}{}",
+            self.successor_base,
+            route.uri.unmounted_origin.path()
+        );
+        warn!(
+            path = request.uri().path().as_str(),
+            successor = successor.as_str(),
+            "Deprecated API path."
+        );
+        response.set_header(Header::new("Deprecation", "true"));
+        response.set_header(Header::new(
+            "Link",
+            format!("<{}>; rel=\"successor-version\"", successor),
+        ));
+    }
+}
@@ -0,0 +1,84 @@
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Outcome, Request};
+
+use crate::errors::api_errors::ApiError;
+use misato::models::apiaccount_model::ApiAccountTokenInfos;
+use misato_utils::{get_current_timestamp, ttl_cache::TtlCache};
+
+pub const IDEMPOTENCY_HEADER: &str = "Idempotency-Key";
+const IDEMPOTENCY_KEY_MAX_LENGTH: usize = 255;
+
+/// A signup running under a key, then its result.
+#[derive(Clone)]
+pub enum SignupResult {
+    InProgress,
+    Done(ApiAccountTokenInfos),
+}
+
+/// Keyed by account then by `Idempotency-Key`, so a key can't replay another account's result.
+pub type SignupResults = TtlCache<(String, String), SignupResult>;
+pub const SIGNUP_RESULTS_MAX: usize = 10_000; // Results are small, a few hundred bytes
+
+/// The `Idempotency-Key` of the request, if any.
+pub struct IdempotencyKey<'r> {
+    results: &'r SignupResults,
+    key: Option<String>,
+}
+
+impl<'r> IdempotencyKey<'r> {
+    fn cache_key(&self, account: &str) -> Option<(String, String)> {
+        Some((account.to_string(), self.key.clone()?))
+    }
+
+    /// Reserve the key for the request of the account, before running it. Gives the result
+    /// of a previous request with the same key, and refuses while that one is still running.
+    pub fn reserve(&self, account: &str) -> Result<Option<ApiAccountTokenInfos>, ApiError> {
+        let key = match self.cache_key(account) {
+            Some(key) => key,
+            None => return Ok(None),
+        };
+        match self
+            .results
+            .insert_if_absent(key, SignupResult::InProgress, get_current_timestamp())
+        {
+            None => Ok(None),
+            Some(SignupResult::Done(previous)) => Ok(Some(previous)),
+            Some(SignupResult::InProgress) => Err(ApiError::IdempotencyKeyInUse),
+        }
+    }
+
+    /// Keep the result for the next requests with the same key, nothing without a key.
+    pub fn record(&self, account: &str, result: &ApiAccountTokenInfos) {
+        if let Some(key) = self.cache_key(account) {
+            self.results.insert(
+                key,
+                SignupResult::Done(result.clone()),
+                get_current_timestamp(),
+            );
+        }
+    }
+
+    /// Free the key of a failed request, so it can be retried.
+    pub fn release(&self, account: &str) {
+        if let Some(key) = self.cache_key(account) {
+            self.results.remove(&key);
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for IdempotencyKey<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let results = request.rocket().state::<SignupResults>().unwrap();
+        let key = request.headers().get_one(IDEMPOTENCY_HEADER);
+        if key.is_some_and(|key| key.len() > IDEMPOTENCY_KEY_MAX_LENGTH) {
+            return Outcome::Failure((Status::BadRequest, ()));
+        }
+        Outcome::Success(IdempotencyKey {
+            results,
+            key: key.map(|key| key.to_string()),
+        })
+    }
+}
@@ -0,0 +1,53 @@
+use rocket::data::{self, Data, FromData, Outcome};
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::serde::{json::Json, DeserializeOwned};
+use serde_json::Value;
+
+use misato_utils::validation::{validate_fields, FieldError, Schema};
+
+/// A JSON body checked against the fields of `T`, unlike `Json` a bad field is named to the client.
+pub struct JsonForm<T>(pub T);
+
+impl<T> JsonForm<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> std::ops::Deref for JsonForm<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// Problem of the body of the request, read back by the 422 catcher.
+pub fn field_error(request: &Request<'_>) -> Option<FieldError> {
+    request.local_cache(|| None::<FieldError>).clone()
+}
+
+#[rocket::async_trait]
+impl<'r, T: Schema + DeserializeOwned> FromData<'r> for JsonForm<T> {
+    type Error = Option<FieldError>;
+
+    async fn from_data(request: &'r Request<'_>, data: Data<'r>) -> data::Outcome<'r, Self> {
+        // Json keeps the size limit and the syntax errors
+        let value = match Json::<Value>::from_data(request, data).await {
+            Outcome::Success(value) => value.into_inner(),
+            Outcome::Failure((status, _)) => return Outcome::Failure((status, None)),
+            Outcome::Forward(data) => return Outcome::Forward(data),
+        };
+        if let Some(object) = value.as_object() {
+            if let Err(error) = validate_fields(object, T::FIELDS) {
+                request.local_cache(|| Some(error.clone()));
+                return Outcome::Failure((Status::UnprocessableEntity, Some(error)));
+            }
+        }
+        match serde_json::from_value(value) {
+            Ok(form) => Outcome::Success(JsonForm(form)),
+            Err(_) => Outcome::Failure((Status::UnprocessableEntity, None)),
+        }
+    }
+}
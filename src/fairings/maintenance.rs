@@ -0,0 +1,102 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::uri::Origin;
+use rocket::response::Responder;
+use rocket::{Request, Response};
+
+use crate::errors::api_errors::ApiError;
+
+const DEFAULT_RETRY_AFTER: u64 = 5 * 60; // In seconds
+const BLOCKED_PATH: &str = "/__maintenance"; // Matches no route, so nothing runs
+
+/// Whether the api is down for maintenance, toggled at runtime by an admin.
+pub struct MaintenanceMode {
+    enabled: AtomicBool,
+    retry_after: AtomicU64, // In seconds
+}
+
+impl Default for MaintenanceMode {
+    fn default() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            retry_after: AtomicU64::new(DEFAULT_RETRY_AFTER),
+        }
+    }
+}
+
+impl MaintenanceMode {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn retry_after(&self) -> u64 {
+        self.retry_after.load(Ordering::Relaxed)
+    }
+
+    /// The previous `Retry-After` is kept when `retry_after` is None.
+    pub fn set(&self, enabled: bool, retry_after: Option<u64>) {
+        if let Some(seconds) = retry_after {
+            self.retry_after.store(seconds, Ordering::Relaxed);
+        }
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Set on the requests turned away, so the response is replaced by the 503.
+struct Blocked(bool);
+
+/// Answers 503 to every request during maintenance, except under the allowed paths.
+pub struct Maintenance {
+    allowed: Vec<String>, // Path prefixes, whole segments only
+}
+
+impl Maintenance {
+    pub fn new(allowed: Vec<String>) -> Self {
+        Self { allowed }
+    }
+
+    fn is_allowed(&self, path: &str) -> bool {
+        self.allowed
+            .iter()
+            .any(|prefix| match path.strip_prefix(prefix.as_str()) {
+                Some(rest) => rest.is_empty() || rest.starts_with('/'),
+                None => false,
+            })
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for Maintenance {
+    fn info(&self) -> Info {
+        Info {
+            name: "Maintenance mode",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut rocket::Data<'_>) {
+        let enabled = match request.rocket().state::<MaintenanceMode>() {
+            Some(mode) => mode.is_enabled(),
+            None => false,
+        };
+        if !enabled || self.is_allowed(request.uri().path().as_str()) {
+            return;
+        }
+        request.local_cache(|| Blocked(true));
+        request.set_uri(Origin::parse(BLOCKED_PATH).unwrap());
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        if !request.local_cache(|| Blocked(false)).0 {
+            return;
+        }
+        let retry_after = match request.rocket().state::<MaintenanceMode>() {
+            Some(mode) => mode.retry_after(),
+            None => DEFAULT_RETRY_AFTER,
+        };
+        if let Ok(maintenance) = ApiError::Maintenance(retry_after).respond_to(request) {
+            response.merge(maintenance);
+        }
+    }
+}
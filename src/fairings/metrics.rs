@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Build, Data, Request, Response, Rocket};
+
+/// Upper bounds of the latency buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()], // Not cumulative, summed when rendered
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct MetricsData {
+    requests: BTreeMap<(String, String, u16), u64>, // By method, path and status
+    latencies: BTreeMap<(String, String), Histogram>, // By method and path
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    data: Mutex<MetricsData>,
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Metrics {
+    pub fn record(&self, method: &str, path: &str, status: u16, seconds: f64) {
+        let mut data = self.data.lock().unwrap();
+        *data
+            .requests
+            .entry((method.to_string(), path.to_string(), status))
+            .or_insert(0) += 1;
+        let histogram = data
+            .latencies
+            .entry((method.to_string(), path.to_string()))
+            .or_default();
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|le| seconds <= *le) {
+            histogram.buckets[index] += 1;
+        }
+        histogram.sum += seconds;
+        histogram.count += 1;
+    }
+
+    /// Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let data = self.data.lock().unwrap();
+        let mut out = String::new();
+        out.push_str("# HELP misato_http_requests_total Number of HTTP requests.\n");
+        out.push_str("# TYPE misato_http_requests_total counter\n");
+        for ((method, path, status), count) in data.requests.iter() {
+            let _ = writeln!(
+                out,
+                "misato_http_requests_total{{method=\"{}\",path=\"{}\",status=\"{}\"}} {}",
+                escape(method),
+                escape(path),
+                status,
+                count
+            );
+        }
+        out.push_str("# HELP misato_http_request_duration_seconds HTTP request latency.\n");
+        out.push_str("# TYPE misato_http_request_duration_seconds histogram\n");
+        for ((method, path), histogram) in data.latencies.iter() {
+            let labels = format!("method=\"{}\",path=\"{}\"", escape(method), escape(path));
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets.iter()) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "misato_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "misato_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "misato_http_request_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "misato_http_request_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+        out
+    }
+}
+
+struct RequestStart(Instant);
+
+pub struct MetricsFairing;
+
+#[rocket::async_trait]
+impl Fairing for MetricsFairing {
+    fn info(&self) -> Info {
+        Info {
+            name: "Prometheus metrics",
+            kind: Kind::Ignite | Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> rocket::fairing::Result {
+        Ok(rocket.manage(Metrics::default()))
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        request.local_cache(|| RequestStart(Instant::now()));
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let start = request.local_cache(|| RequestStart(Instant::now()));
+        // Route templates rather than raw paths, to keep the label count bounded
+        let path = match request.route() {
+            Some(route) => route.uri.as_str(),
+            None => "unmatched",
+        };
+        let metrics = request.rocket().state::<Metrics>().unwrap();
+        metrics.record(
+            request.method().as_str(),
+            path,
+            response.status().code,
+            start.0.elapsed().as_secs_f64(),
+        );
+    }
+}
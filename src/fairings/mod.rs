@@ -1,2 +1,19 @@
+pub mod admin_authentication;
 pub mod api_authentication;
+pub mod audit;
+pub mod authenticated_user;
 pub mod authentication;
+pub mod client_info;
+pub mod compression;
+pub mod cors;
+pub mod deprecation;
+pub mod idempotency;
+pub mod json_form;
+pub mod maintenance;
+pub mod metrics;
+pub mod rate_limit;
+pub mod request_id;
+pub mod scope;
+pub mod security_headers;
+pub mod timeout;
+pub mod token_purge;
@@ -0,0 +1,43 @@
+use std::net::IpAddr;
+
+use rocket::request::{self, FromRequest, Outcome, Request};
+
+use misato_security::rate_limit::RateLimiter;
+use misato_utils::get_current_timestamp;
+
+use crate::fairings::client_info::client_ip;
+
+pub type LoginRateLimiter = RateLimiter<Option<IpAddr>>;
+
+pub struct LoginRateLimit<'r> {
+    limiter: &'r LoginRateLimiter,
+    ip: Option<IpAddr>,
+}
+
+impl<'r> LoginRateLimit<'r> {
+    pub fn retry_after(&self) -> Option<u64> {
+        self.limiter.retry_after(&self.ip, get_current_timestamp())
+    }
+
+    pub fn record_failure(&self) {
+        self.limiter
+            .record_failure(self.ip, get_current_timestamp());
+    }
+
+    pub fn failures(&self) -> u32 {
+        self.limiter.failures(&self.ip, get_current_timestamp())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for LoginRateLimit<'r> {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let limiter = request.rocket().state::<LoginRateLimiter>().unwrap();
+        Outcome::Success(LoginRateLimit {
+            limiter,
+            ip: client_ip(request),
+        })
+    }
+}
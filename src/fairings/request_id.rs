@@ -0,0 +1,68 @@
+use std::time::Instant;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Data, Request, Response};
+use tracing::info;
+use uuid::Uuid;
+
+const REQUEST_ID_HEADER: &str = "X-Request-Id";
+const REQUEST_ID_MAX_LENGTH: usize = 128;
+
+pub struct RequestId {
+    pub id: String,
+    start: Instant,
+}
+
+/// Id of the request, one is generated if the fairing didn't run.
+pub fn request_id<'r>(request: &'r Request<'_>) -> &'r RequestId {
+    request.local_cache(|| RequestId {
+        id: Uuid::new_v4().to_string(),
+        start: Instant::now(),
+    })
+}
+
+/// Keep the id given by the client when it is safe to log and echo back.
+fn client_request_id(request: &Request<'_>) -> Option<String> {
+    let id = request.headers().get_one(REQUEST_ID_HEADER)?;
+    if id.is_empty()
+        || id.len() > REQUEST_ID_MAX_LENGTH
+        || !id.chars().all(|c| c.is_ascii_graphic())
+    {
+        return None;
+    }
+    Some(id.to_string())
+}
+
+pub struct RequestLogger;
+
+#[rocket::async_trait]
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request ids and logging",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, request: &mut Request<'_>, _: &mut Data<'_>) {
+        let id = client_request_id(request).unwrap_or_else(|| Uuid::new_v4().to_string());
+        request.local_cache(|| RequestId {
+            id,
+            start: Instant::now(),
+        });
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let request_id = request_id(request);
+        info!(
+            request_id = %request_id.id,
+            method = request.method().as_str(),
+            path = request.uri().path().as_str(),
+            status = response.status().code,
+            latency_ms = request_id.start.elapsed().as_secs_f64() * 1000.0,
+            "Request handled."
+        );
+        response.set_header(Header::new(REQUEST_ID_HEADER, request_id.id.clone()));
+    }
+}
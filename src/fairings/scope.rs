@@ -0,0 +1,85 @@
+use std::marker::PhantomData;
+
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Outcome, Request};
+
+use crate::fairings::api_authentication::{AuthenticatedApiUser, AuthenticatedApiUserError};
+use crate::fairings::authenticated_user::{AuthenticatedUser, AuthenticatedUserError};
+use misato_database::models::scope_model;
+
+/// A scope a route can require, guards can't take arguments so each one is a type.
+pub trait Scope {
+    const NAME: &'static str;
+}
+
+pub struct AccountDelete;
+
+impl Scope for AccountDelete {
+    const NAME: &'static str = scope_model::ACCOUNT_DELETE;
+}
+
+/// The token of the request has the scope `S`, 403 otherwise.
+/// `X-Misato-API-Token` is checked as `AuthenticatedApiUser` does, else the bearer token as
+/// `AuthenticatedUser` does.
+pub struct RequireScope<S: Scope>(PhantomData<S>);
+
+#[derive(Debug)]
+pub enum RequireScopeError {
+    Unauthenticated,
+    Missing(&'static str),
+    Database,
+}
+
+/// Set on the requests that lacked a scope, for the 403 catcher.
+struct MissingScope(Option<&'static str>);
+
+pub fn missing_scope(request: &Request) -> Option<&'static str> {
+    request.local_cache(|| MissingScope(None)).0
+}
+
+#[rocket::async_trait]
+impl<'r, S: Scope> FromRequest<'r> for RequireScope<S> {
+    type Error = RequireScopeError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let allowed = match request.guard::<AuthenticatedApiUser>().await {
+            Outcome::Success(api) => match (&api.key, &api.apiuser.token) {
+                (Some(key), _) => key.allows(S::NAME),
+                (None, Some(token)) => token.allows(S::NAME),
+                (None, None) => false,
+            },
+            Outcome::Failure((_, AuthenticatedApiUserError::Missing)) => {
+                match request.guard::<AuthenticatedUser>().await {
+                    Outcome::Success(user) => user.allows(S::NAME),
+                    Outcome::Failure((_, AuthenticatedUserError::Database)) => {
+                        return Outcome::Failure((
+                            Status::InternalServerError,
+                            RequireScopeError::Database,
+                        ))
+                    }
+                    _ => {
+                        return Outcome::Failure((
+                            Status::Unauthorized,
+                            RequireScopeError::Unauthenticated,
+                        ))
+                    }
+                }
+            }
+            Outcome::Failure((_, AuthenticatedApiUserError::Database)) => {
+                return Outcome::Failure((Status::InternalServerError, RequireScopeError::Database))
+            }
+            Outcome::Failure(_) => {
+                return Outcome::Failure((Status::Unauthorized, RequireScopeError::Unauthenticated))
+            }
+            Outcome::Forward(forward) => return Outcome::Forward(forward),
+        };
+
+        match allowed {
+            true => return Outcome::Success(RequireScope(PhantomData)),
+            false => {
+                request.local_cache(|| MissingScope(Some(S::NAME)));
+                return Outcome::Failure((Status::Forbidden, RequireScopeError::Missing(S::NAME)));
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::http::Header;
+use rocket::{Request, Response};
+
+use misato_utils::settings::Settings;
+
+const REFERRER_POLICY: &str = "no-referrer";
+
+/// Hardening headers on every response, whatever the CORS settings.
+pub struct SecurityHeaders {
+    hsts: Option<String>, // `Strict-Transport-Security` value, None to leave it out
+}
+
+impl SecurityHeaders {
+    pub fn new(hsts: Option<String>) -> Self {
+        Self { hsts }
+    }
+
+    /// HSTS only makes sense over HTTPS, so it is left out without TLS or with a max age of 0.
+    pub fn from_settings(settings: &Settings) -> Self {
+        if settings.http.tls.is_none() || settings.http.hsts_max_age == 0 {
+            return Self::new(None);
+        }
+        let mut hsts = format!("max-age={}", settings.http.hsts_max_age);
+        if settings.http.hsts_include_subdomains {
+            hsts.push_str("; includeSubDomains");
+        }
+        Self::new(Some(hsts))
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for SecurityHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "Security headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, _: &'r Request<'_>, response: &mut Response<'r>) {
+        response.set_header(Header::new("X-Content-Type-Options", "nosniff"));
+        response.set_header(Header::new("X-Frame-Options", "DENY"));
+        response.set_header(Header::new("Referrer-Policy", REFERRER_POLICY));
+        if let Some(hsts) = &self.hsts {
+            response.set_header(Header::new("Strict-Transport-Security", hsts.clone()));
+        }
+    }
+}
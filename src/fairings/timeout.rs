@@ -0,0 +1,72 @@
+use std::time::{Duration, Instant};
+
+use rocket::route::{Handler, Outcome};
+use rocket::tokio::time;
+use rocket::{Data, Request, Route};
+use tracing::warn;
+
+use crate::errors::api_errors::ApiError;
+use crate::fairings::request_id::request_id;
+
+/// Handlers past this share of the timeout are logged as slow, before they get cut off.
+const SLOW_FRACTION: f64 = 0.75;
+
+/// A route handler given at most `timeout`, after which it is dropped and the request
+/// answers 504. Fairings can't stop a handler, so every route is wrapped instead.
+#[derive(Clone)]
+struct TimeoutHandler {
+    handler: Box<dyn Handler>,
+    timeout: Duration,
+}
+
+#[rocket::async_trait]
+impl Handler for TimeoutHandler {
+    async fn handle<'r>(&self, request: &'r Request<'_>, data: Data<'r>) -> Outcome<'r> {
+        let start = Instant::now();
+        let outcome = time::timeout(self.timeout, self.handler.handle(request, data)).await;
+        let elapsed = start.elapsed();
+        match outcome {
+            Ok(outcome) => {
+                if elapsed.as_secs_f64() >= self.timeout.as_secs_f64() * SLOW_FRACTION {
+                    warn!(
+                        request_id = %request_id(request).id,
+                        method = request.method().as_str(),
+                        path = request.uri().path().as_str(),
+                        latency_ms = elapsed.as_millis() as u64,
+                        timeout_ms = self.timeout.as_millis() as u64,
+                        "Slow request, close to the timeout."
+                    );
+                }
+                outcome
+            }
+            Err(_) => {
+                warn!(
+                    request_id = %request_id(request).id,
+                    method = request.method().as_str(),
+                    path = request.uri().path().as_str(),
+                    timeout_ms = self.timeout.as_millis() as u64,
+                    "Request timed out, its handler was stopped."
+                );
+                Outcome::from(request, ApiError::Timeout(self.timeout.as_secs()))
+            }
+        }
+    }
+}
+
+/// The routes with their handlers stopped after `seconds`, as they are for 0.
+pub fn with_timeout(routes: Vec<Route>, seconds: u64) -> Vec<Route> {
+    if seconds == 0 {
+        return routes;
+    }
+    let timeout = Duration::from_secs(seconds);
+    routes
+        .into_iter()
+        .map(|mut route| {
+            route.handler = Box::new(TimeoutHandler {
+                handler: route.handler,
+                timeout,
+            });
+            route
+        })
+        .collect()
+}
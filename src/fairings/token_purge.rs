@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{tokio, Orbit, Rocket};
+use tracing::{error, info};
+
+use misato_database::{database::Database, user_manager::UserManager};
+use misato_utils::{get_current_timestamp, settings::Settings};
+
+/// Remove the tokens expired at `now`, and the accounts deleted more than
+/// `deletion_grace_period` seconds before it, none when the grace period is 0.
+pub async fn purge(usermanager: &UserManager, deletion_grace_period: u64, now: u64) {
+    match usermanager.purge_expired_tokens(now).await {
+        Ok(result) => info!(users = result.modified_count, "Purged expired tokens."),
+        Err(error) => error!(error = ?error, "Cannot purge the expired tokens."),
+    }
+    if deletion_grace_period == 0 {
+        return;
+    }
+    let before = now.saturating_sub(deletion_grace_period.saturating_mul(1000));
+    match usermanager.purge_deleted_users(before).await {
+        Ok(result) => info!(accounts = result.deleted_count, "Purged deleted accounts."),
+        Err(error) => error!(error = ?error, "Cannot purge the deleted accounts."),
+    }
+}
+
+/// Periodically run `purge`, in a background task so liftoff doesn't wait on it.
+pub struct TokenPurge;
+
+#[rocket::async_trait]
+impl Fairing for TokenPurge {
+    fn info(&self) -> Info {
+        Info {
+            name: "Expired tokens and deleted accounts purge",
+            kind: Kind::Liftoff,
+        }
+    }
+
+    async fn on_liftoff(&self, rocket: &Rocket<Orbit>) {
+        let security = &rocket.state::<Settings>().unwrap().security;
+        let (interval, deletion_grace_period) = (
+            security.token_purge_interval,
+            security.deletion_grace_period,
+        );
+        if interval == 0 {
+            return;
+        }
+        let usermanager = rocket.state::<Database>().unwrap().usermanager.clone();
+        tokio::spawn(async move {
+            let mut ticks = tokio::time::interval(Duration::from_secs(interval));
+            loop {
+                ticks.tick().await;
+                purge(&usermanager, deletion_grace_period, get_current_timestamp()).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,225 @@
+use rocket::{fairing::AdHoc, *};
+use tracing::{error, info};
+
+use misato_database::{database::*, models::apiuser_model::ApiUser};
+use misato_security::password::PasswordFormat;
+use misato_utils::settings::{join_path, Settings};
+
+pub mod captcha;
+pub mod errors;
+pub mod fairings;
+pub mod logging;
+pub mod pwned;
+pub mod routes;
+pub mod webhooks;
+
+use captcha::Captcha;
+use fairings::{
+    cors::Cors,
+    deprecation::ApiDeprecation,
+    idempotency::{SignupResults, SIGNUP_RESULTS_MAX},
+    maintenance::{Maintenance, MaintenanceMode},
+    metrics::MetricsFairing,
+    rate_limit::LoginRateLimiter,
+    request_id::RequestLogger,
+    security_headers::SecurityHeaders,
+    timeout::with_timeout,
+    token_purge::TokenPurge,
+};
+use pwned::PwnedPasswords;
+use routes::{admin, api, root, user};
+use webhooks::Webhooks;
+
+fn init(settings: Settings) -> AdHoc {
+    AdHoc::try_on_ignite("Connecting to MongoDB", |rocket| async {
+        match Database::connect(&settings).await {
+            Ok(database) => {
+                // Create admin user, unless the first user becomes one
+                if !settings.security.first_user_admin {
+                    let user = ApiUser::create_default(settings.security.admin_token.clone());
+                    let result = database
+                        .apiusermanager
+                        .ensure_apiuser(&user, settings.security.reset_admin)
+                        .await;
+                    match result {
+                        Ok(result) if result.upserted_id.is_some() => {
+                            info!("Successfully created default user.")
+                        }
+                        Ok(_) if settings.security.reset_admin => {
+                            info!("Default user already exists, its token has been reset.")
+                        }
+                        Ok(_) => {
+                            info!("Default user already exists.")
+                        }
+                        Err(err) => {
+                            error!(error = ?err, "Error whilst creating default user.");
+                        }
+                    }
+                }
+                match database.usermanager.hash_tokens().await {
+                    Ok(0) => {}
+                    Ok(hashed) => info!(users = hashed, "Hashed the stored tokens."),
+                    Err(error) => error!(error = ?error, "Error whilst hashing tokens."),
+                }
+                match database.apiusermanager.hash_tokens().await {
+                    Ok(0) => {}
+                    Ok(hashed) => info!(api_users = hashed, "Hashed the stored api tokens."),
+                    Err(error) => error!(error = ?error, "Error whilst hashing api tokens."),
+                }
+                if settings.security.password_format == PasswordFormat::Phc {
+                    match database.usermanager.encode_passwords().await {
+                        Ok(0) => {}
+                        Ok(converted) => {
+                            info!(passwords = converted, "Converted passwords to PHC strings.")
+                        }
+                        Err(error) => error!(error = ?error, "Error whilst converting passwords."),
+                    }
+                }
+                let limiter = LoginRateLimiter::new(
+                    settings.security.login_rate_window,
+                    settings.security.login_rate_max_attempts,
+                );
+                let rocket = match Webhooks::from_settings(&settings) {
+                    Some(webhooks) => rocket.manage(webhooks),
+                    None => rocket,
+                };
+                let signup_results =
+                    SignupResults::new(settings.http.idempotency_ttl, SIGNUP_RESULTS_MAX);
+                let pwned = PwnedPasswords::from_settings(&settings);
+                let captcha = Captcha::from_settings(&settings);
+                Ok(rocket
+                    .manage(database)
+                    .manage(limiter)
+                    .manage(signup_results)
+                    .manage(pwned)
+                    .manage(captcha)
+                    .manage(settings))
+            }
+            Err(error) => {
+                panic!("Cannot connect to MongoDB instance:: {:?}", error)
+            }
+        }
+    })
+}
+
+fn shutdown_log() -> AdHoc {
+    AdHoc::on_shutdown("Shutdown log", |rocket| {
+        Box::pin(async move {
+            info!(
+                grace_seconds = rocket.config().shutdown.grace,
+                "Shutting down, waiting for in-flight requests to complete."
+            );
+        })
+    })
+}
+
+/// Rocket configuration overridden by the settings.
+pub fn figment(settings: &Settings) -> figment::Figment {
+    let mut figment = Config::figment()
+        .merge(("shutdown.grace", settings.http.shutdown_grace))
+        .merge(("limits.json", settings.http.json_limit));
+    for (name, bytes) in &settings.http.body_limits {
+        figment = figment.merge((format!("limits.{}", name), bytes));
+    }
+    if let Some(tls) = &settings.http.tls {
+        figment = figment
+            .merge(("tls.certs", &tls.certs))
+            .merge(("tls.key", &tls.key));
+    }
+    figment
+}
+
+pub fn rocket(settings: Settings) -> Rocket<Build> {
+    let mut routes: Vec<Route> = Vec::new();
+
+    // Everyone
+    routes.append(&mut routes![
+        root::account::login,
+        root::account::login_recovery,
+        root::account::refresh,
+        root::account::logout,
+        root::account::validate_signup,
+        root::account::reset_request,
+        root::account::reset_confirm,
+        root::account::verify_confirm,
+        root::health::health,
+        root::health::health_db,
+        root::meta::meta,
+        root::metrics::metrics,
+        root::docs::openapi_json,
+        root::docs::docs,
+    ]);
+
+    // User
+    routes.append(&mut routes![
+        user::account::delete,
+        user::account::clear_tokens,
+        user::account::check_token,
+        user::account::token_info,
+        user::account::change_password,
+        user::account::change_username,
+        user::account::verify_request,
+        user::account::email,
+        user::account::me,
+        user::account::sessions,
+        user::account::export,
+        user::account::revoke_session,
+        user::account::refresh_families,
+        user::account::revoke_refresh_family,
+        user::account::totp_enroll,
+        user::account::totp_verify,
+        user::account::totp_disable,
+        user::account::totp_recovery_codes,
+    ]);
+
+    // Admin
+    routes.append(&mut routes![
+        admin::account::signup,
+        admin::account::create_users,
+        admin::account::refresh_token,
+        admin::account::profile,
+        admin::account::profile_from_token,
+        admin::account::user_details,
+        admin::account::export,
+        admin::account::refresh_families,
+        admin::account::revoke_refresh_family,
+        admin::account::clear_tokens,
+        admin::account::delete,
+        admin::account::restore,
+        admin::account::check_token,
+        admin::account::users,
+        admin::account::audit,
+        admin::account::rotate_token,
+        admin::account::whoami,
+        admin::account::revoke_all_tokens,
+        admin::account::create_invite,
+        admin::account::maintenance,
+    ]);
+
+    let base = |path: &str| join_path(&settings.http.base_path, path);
+    let timeout = settings.http.request_timeout;
+    let legacy_api_base = base(api::LEGACY_BASE);
+    let api_base = base(api::v1::BASE);
+    let maintenance = Maintenance::new(vec![
+        base("/health"),
+        base("/admin"),
+        join_path(&api_base, "/admin"),
+        join_path(&legacy_api_base, "/admin"),
+    ]);
+    rocket::custom(figment(&settings))
+        .manage(MaintenanceMode::default())
+        .attach(init(settings.clone()))
+        // Before the fairings adding headers, so they are kept on its 503
+        .attach(maintenance)
+        .attach(Cors)
+        .attach(SecurityHeaders::from_settings(&settings))
+        .attach(MetricsFairing)
+        .attach(RequestLogger)
+        .attach(TokenPurge)
+        .attach(shutdown_log())
+        .attach(ApiDeprecation::new(&legacy_api_base, &api_base))
+        .register("/", errors::catchers::catchers())
+        .mount(base("/"), with_timeout(routes, timeout))
+        .mount(api_base, with_timeout(api::v1::routes(), timeout))
+        .mount(legacy_api_base, with_timeout(api::v1::routes(), timeout))
+}
@@ -1,80 +1,40 @@
-use rocket::{fairing::AdHoc, *};
-
-use misato_database::{database::*, models::apiuser_model::ApiUser};
-use misato_utils::settings::Settings;
-
-mod errors;
-mod fairings;
-mod routes;
-
-use routes::{admin, api, root, user};
-
-fn init() -> AdHoc {
-    AdHoc::on_ignite("Connecting to MongoDB", |rocket| async {
-        let settings = Settings::init();
-        match Database::init(&settings).await {
-            Ok(database) => {
-                // Create admin user
-                let user = ApiUser::create_default(settings.admin_token.clone());
-                match database.apiusermanager.create_apiuser(&user).await {
-                    Ok(_) => {
-                        println!("Successfully created default user.")
-                    }
-                    Err(err) => {
-                        println!("Error whilst creating default user [{:?}]", err);
-                    }
-                }
-                rocket.manage(database).manage(settings)
-            }
-            Err(error) => {
-                panic!("Cannot connect to MongoDB instance:: {:?}", error)
-            }
+use tracing::info;
+
+use misato_api::{fairings::compression::Compression, logging};
+use misato_database::database::Database;
+use misato_utils::settings::{LogFormat, LogLevel, LogSettings, Settings};
+
+#[rocket::main]
+async fn main() -> Result<(), Box<rocket::Error>> {
+    // Logging is configured by the settings, their own warnings go to the standard error
+    let fallback = LogSettings {
+        level: LogLevel::Warn,
+        format: LogFormat::Pretty,
+    };
+    let parsed = tracing::subscriber::with_default(
+        logging::subscriber(&fallback, std::io::stderr),
+        Settings::try_init,
+    );
+    let settings = match parsed {
+        Ok(settings) => settings,
+        Err(error) => {
+            // Logging is configured by the settings, so it isn't set up yet
+            eprintln!("{}", error);
+            std::process::exit(1);
         }
-    })
-}
-
-#[launch]
-async fn rocket() -> _ {
-    let mut routes: Vec<Route> = Vec::new();
-
-    // Api Admin
-    routes.append(&mut routes![
-        api::admin::account::signup,
-        api::admin::account::refresh_token,
-        api::admin::account::clear_tokens,
-        api::admin::account::delete,
-        api::admin::account::check_token,
-    ]);
-
-    // Api root
-    routes.append(&mut routes![
-        api::root::account::signup,
-        api::root::account::refresh_token,
-        api::root::account::clear_tokens,
-        api::root::account::delete,
-        api::root::account::check_token,
-    ]);
-
-    // Everyone
-    routes.append(&mut routes![root::account::login]);
-
-    // User
-    routes.append(&mut routes![
-        user::account::delete,
-        user::account::clear_tokens,
-        user::account::check_token
-    ]);
-
-    // Admin
-    routes.append(&mut routes![
-        admin::account::signup,
-        admin::account::refresh_token,
-        admin::account::profile,
-        admin::account::profile_from_token,
-        admin::account::clear_tokens,
-        admin::account::delete,
-        admin::account::check_token,
-    ]);
-
-    rocket::build().attach(init()).mount("/", routes)
+    };
+    logging::init(&settings.log);
+    let compression = Compression::from_settings(&settings);
+    let mut rocket = misato_api::rocket(settings);
+    if let Some(compression) = compression {
+        // Last, so it encodes the body every other fairing is done with
+        rocket = rocket.attach(compression);
+    }
+    // Returns once every in-flight request completed or the grace period is over
+    let rocket = rocket.launch().await.map_err(Box::new)?;
+    if let Some(database) = rocket.state::<Database>() {
+        database.close().await;
+    }
+    info!("Shutdown complete.");
+    Ok(())
 }
@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use tracing::error;
+
+use misato_security::{
+    policy::{PasswordPolicy, PolicyViolation},
+    pwned::{breach_count, range_query},
+};
+use misato_utils::{get_current_timestamp, settings::Settings, ttl_cache::TtlCache};
+
+const TIMEOUT: Duration = Duration::from_secs(3); // A slow range API shouldn't hang a signup
+const RANGES_MAX: usize = 256; // About 30 KB each once padded, so 8 MB at most
+
+/// Looks new passwords up in a Have I Been Pwned range API, with k-anonymity:
+/// only the first 5 hex digits of their SHA-1 are sent, the match is made here.
+pub struct PwnedPasswords {
+    client: reqwest::Client,
+    url: Option<String>, // None when the check is off
+    fail_closed: bool,
+    ranges: TtlCache<String, String>, // Range responses by prefix
+}
+
+impl PwnedPasswords {
+    pub fn new(url: Option<String>, fail_closed: bool, cache_ttl: u64) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            url: url.map(|url| url.trim_end_matches('/').to_string()),
+            fail_closed,
+            ranges: TtlCache::new(cache_ttl, RANGES_MAX),
+        }
+    }
+
+    pub fn from_settings(settings: &Settings) -> Self {
+        let security = &settings.security;
+        Self::new(
+            match security.pwned_check {
+                true => Some(security.pwned_api_url.clone()),
+                false => None,
+            },
+            security.pwned_fail_closed,
+            security.pwned_cache_ttl,
+        )
+    }
+
+    async fn range(&self, url: &str, prefix: &str) -> Result<String, reqwest::Error> {
+        if let Some(range) = self
+            .ranges
+            .get(&prefix.to_string(), get_current_timestamp())
+        {
+            return Ok(range);
+        }
+        let range = self
+            .client
+            .get(format!("{}/{}", url, prefix))
+            .header("Add-Padding", "true") // Hides which prefix was asked from the response size
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        self.ranges
+            .insert(prefix.to_string(), range.clone(), get_current_timestamp());
+        Ok(range)
+    }
+
+    /// The policy first, then the breach check when it is on.
+    /// An unreachable range API lets the password through unless the check fails closed.
+    pub async fn validate(
+        &self,
+        policy: &PasswordPolicy,
+        password: &[u8],
+    ) -> Result<(), PolicyViolation> {
+        policy.validate(password)?;
+        let url = match &self.url {
+            Some(url) => url,
+            None => return Ok(()),
+        };
+        let (prefix, suffix) = range_query(password);
+        match self.range(url, &prefix).await {
+            Ok(range) => match breach_count(&range, &suffix) {
+                0 => Ok(()),
+                count => Err(PolicyViolation::Pwned(count)),
+            },
+            Err(error) => {
+                error!(url = url.as_str(), error = ?error, "Cannot check the password.");
+                match self.fail_closed {
+                    true => Err(PolicyViolation::PwnedUnchecked),
+                    false => Ok(()),
+                }
+            }
+        }
+    }
+}
@@ -1,325 +1,869 @@
+use std::collections::HashMap;
+
 use rocket::serde::json::Json;
 use rocket::*;
+use tracing::error;
 
-use misato_database::{database::*, models::*};
-use misato_security::password::*;
+use misato_database::{
+    database::*,
+    models::{audit_model::AuditAction, *},
+    user_manager::UserError,
+    user_store::UserStore,
+};
+use misato_security::{constant_time_eq, hash_token, password::*};
+use misato_utils::{
+    get_current_timestamp,
+    settings::Settings,
+    validation::{validate_email, validate_username},
+};
 
-use misato::models::account_model;
+use misato::models::{account_model, apiaccount_model};
 
-use crate::errors::account_errors;
+use crate::errors::{api_errors::ApiError, api_response::ApiResponse};
 
-const TOKEN_DURATION: u64 = 24 * 60 * 60;
+const USERS_PAGE_DEFAULT_LIMIT: u64 = 20;
+const USERS_PAGE_MAX_LIMIT: u64 = 100;
+const AUDIT_PAGE_DEFAULT_LIMIT: u64 = 50;
+const AUDIT_PAGE_MAX_LIMIT: u64 = 200;
+const BATCH_MAX_SIZE: usize = 50;
 
-use crate::fairings::api_authentication::ApiUserToken;
+use crate::fairings::{
+    admin_authentication::{AdminUser, ApiRoleError},
+    audit::Audit,
+    json_form::JsonForm,
+    maintenance::MaintenanceMode,
+};
+use crate::pwned::PwnedPasswords;
+use crate::routes::user::account::export_data;
 
+/// When the first user becomes admin, a fresh install is signed up to without a token.
 #[post("/admin/signup", data = "<input>")]
 pub async fn signup(
-    api: ApiUserToken,
+    admin: Result<AdminUser, ApiRoleError>,
+    audit: Audit<'_>,
     db: &State<Database>,
-    input: Json<account_model::AccountCredentials>,
-) -> Result<Json<account_model::AccountTokenInfos>, account_errors::Error> {
-    if api.apiuser.access.role != apiuser_model::ApiUserRoleType::Admin {
-        return Err(account_errors::Error {
-            content: account_model::AccountError::build(400, Some("No permission.".to_string())),
-        });
-    }
-    let mut user = user_model::User::create(
-        input.username.to_string(),
-        Password::hash_password(input.password.as_bytes()),
-        None,
-    );
+    settings: &State<Settings>,
+    pwned: &State<PwnedPasswords>,
+    input: JsonForm<request_model::Signup>,
+) -> Result<ApiResponse<account_model::AccountTokenInfos>, ApiError> {
+    let input = input.into_inner();
+    let admin = match admin {
+        Ok(admin) => Some(admin),
+        Err(ApiRoleError::Missing) if settings.security.first_user_admin => {
+            match db.usermanager.has_users().await {
+                Ok(false) => None,
+                Ok(true) => return Err(ApiError::Unauthenticated),
+                Err(error) => {
+                    error!(error = ?error, "Cannot check for existing users.");
+                    return Err(ApiError::from_db(&error));
+                }
+            }
+        }
+        Err(ApiRoleError::InsufficientRole) => return Err(ApiError::NoPermission),
+        Err(_) => return Err(ApiError::Unauthenticated),
+    };
+    // Every field is checked, so all their problems are told at once
+    let mut failures = Vec::new();
+    if let Err(error) = validate_username(&input.username) {
+        failures.push(("username", ApiError::ValidationError(error.to_string())));
+    }
+    if let Some(email) = &input.email {
+        if !validate_email(email) {
+            failures.push(("email", ApiError::InvalidEmail(email.to_string())));
+        }
+    }
+    let password = SecurePassword::from(input.password);
+    if let Err(violation) = pwned
+        .validate(&settings.security.password_policy, password.as_bytes())
+        .await
+    {
+        failures.push(("password", ApiError::from_policy(violation)));
+    }
+    if let Some(error) = ApiError::from_failures(failures) {
+        return Err(error);
+    }
+    let password = match Password::hash(
+        &settings.security.argon2_params,
+        settings
+            .security
+            .password_pepper
+            .as_ref()
+            .map(|v| v.as_bytes()),
+        password.as_bytes(),
+    ) {
+        Ok(password) => password,
+        Err(error) => {
+            error!(error = %error, "Cannot hash the password.");
+            return Err(ApiError::InternalError);
+        }
+    };
+    let mut user = user_model::User::create(input.username.to_string(), password, None);
 
     match db.usermanager.username_exists(&user.username).await {
         Ok(exists) => {
             if exists {
-                return Err(account_errors::Error {
-                    content: account_model::AccountError::build(
-                        400,
-                        Some(format!(
-                            "[{}]: Username already used by an account.",
-                            input.username
-                        )),
-                    ),
-                });
+                return Err(ApiError::UserExists(input.username.to_string()));
             }
         }
         Err(error) => {
-            println!("{:?}", error);
-            return Err(account_errors::Error {
-                content: account_model::AccountError::build(
-                    500,
-                    Some("Database error.".to_string()),
-                ),
-            });
+            error!(error = ?error, "Cannot check the username.");
+            return Err(ApiError::from_db(&error));
+        }
+    }
+    if let Some(email) = input.email {
+        match db.usermanager.email_exists(&email).await {
+            Ok(true) => return Err(ApiError::EmailExists(email)),
+            Ok(false) => user.set_email(email),
+            Err(error) => {
+                error!(error = ?error, "Cannot check the email.");
+                return Err(ApiError::from_db(&error));
+            }
         }
     }
 
-    match db.usermanager.create_user(&user).await {
+    // Of signups racing on a fresh install, only the one claiming it becomes admin
+    if admin.is_none() {
+        match db.claim_first_admin(&user.uuid).await {
+            Ok(true) => user.access.role = user_model::UserRoleType::Admin,
+            Ok(false) => return Err(ApiError::Unauthenticated),
+            Err(error) => {
+                error!(error = ?error, "Cannot claim the first admin.");
+                return Err(ApiError::from_db(&error));
+            }
+        }
+    }
+
+    let created = db.usermanager.create_user(&user).await;
+    if created.is_err() && admin.is_none() {
+        // So a next signup can claim it
+        if let Err(error) = db.release_first_admin(&user.uuid).await {
+            error!(error = ?error, "Cannot release the first admin claim.");
+        }
+    }
+    match created {
         Ok(_) => {
-            let token = user.new_token(TOKEN_DURATION);
-            let _ = db.usermanager.save_token(&user.uuid, &token).await;
-            return Ok(Json(account_model::AccountTokenInfos {
+            let actor = admin.as_ref().map_or(&user.uuid, |admin| &admin.uuid);
+            audit
+                .record(AuditAction::Signup, Some(actor), Some(&user.uuid))
+                .await;
+            let token = user.new_token(settings.security.token_ttl);
+            if let Err(error) = db
+                .usermanager
+                .save_token(&user.uuid, &token, settings.security.max_active_tokens)
+                .await
+            {
+                error!(uuid = user.uuid.as_str(), error = ?error, "Cannot save the token.");
+                return Err(ApiError::from_db(&error));
+            }
+            Ok(ApiResponse(account_model::AccountTokenInfos {
                 token: token.token.clone(),
                 timestamp: token.timestamp,
                 expiration_timestamp: token.expiration_timestamp,
                 uuid: user.uuid,
-            }));
+            }))
         }
+        // Lost a race on the username or the email
+        Err(UserError::AlreadyExists) => match user.email {
+            Some(email) if db.usermanager.email_exists(&email).await.unwrap_or(false) => {
+                Err(ApiError::EmailExists(email))
+            }
+            _ => Err(ApiError::UserExists(input.username.to_string())),
+        },
         Err(_error) => {
-            println!("{:?}", _error);
-            return Err(account_errors::Error {
-                content: account_model::AccountError::build(
-                    500,
-                    Some("Database error.".to_string()),
-                ),
-            });
+            error!(error = ?_error, "Cannot check the email.");
+            Err(ApiError::from_db(&_error))
         }
     }
 }
 
-#[post("/admin/profile", data = "<input>")]
-pub async fn profile(
-    api: ApiUserToken,
-    db: &State<Database>,
-    input: Json<account_model::AccountUuid>,
-) -> Result<Json<account_model::Account>, account_errors::Error> {
-    if api.apiuser.access.role != apiuser_model::ApiUserRoleType::Admin {
-        return Err(account_errors::Error {
-            content: account_model::AccountError::build(400, Some("No permission.".to_string())),
-        });
+fn batch_failure(username: &str, error: ApiError) -> response_model::BatchItem {
+    response_model::BatchItem {
+        username: username.to_string(),
+        status: error.status().code,
+        uuid: None,
+        error: Some(error.code().to_string()),
+        message: Some(error.message()),
     }
-    match db.usermanager.get_user(None, Some(&input.uuid)).await {
-        Ok(user) => match user {
-            Some(user) => {
-                return Ok(Json(account_model::Account {
-                    uuid: user.uuid.clone(),
-                    username: user.username.clone(),
-                }));
+}
+
+/// Body of `create_users`, on any user store. Invalid users are reported without
+/// being inserted, the valid ones are all inserted at once.
+pub async fn create_users_batch(
+    users: &dyn UserStore,
+    settings: &Settings,
+    pwned: &PwnedPasswords,
+    input: Vec<request_model::BatchUser>,
+) -> Result<Vec<response_model::BatchItem>, ApiError> {
+    if input.len() > BATCH_MAX_SIZE {
+        return Err(ApiError::ValidationError(format!(
+            "At most {} users per batch.",
+            BATCH_MAX_SIZE
+        )));
+    }
+    let mut results: Vec<Option<response_model::BatchItem>> = Vec::new();
+    let mut created: Vec<user_model::User> = Vec::new();
+    for item in input {
+        if let Err(error) = validate_username(&item.username) {
+            results.push(Some(batch_failure(
+                &item.username,
+                ApiError::ValidationError(error.to_string()),
+            )));
+            continue;
+        }
+        let password = SecurePassword::from(item.password);
+        if let Err(violation) = pwned
+            .validate(&settings.security.password_policy, password.as_bytes())
+            .await
+        {
+            results.push(Some(batch_failure(
+                &item.username,
+                ApiError::from_policy(violation),
+            )));
+            continue;
+        }
+        let password = match Password::hash(
+            &settings.security.argon2_params,
+            settings
+                .security
+                .password_pepper
+                .as_ref()
+                .map(|v| v.as_bytes()),
+            password.as_bytes(),
+        ) {
+            Ok(password) => password,
+            Err(error) => {
+                error!(error = %error, "Cannot hash the password.");
+                results.push(Some(batch_failure(&item.username, ApiError::InternalError)));
+                continue;
             }
-            _ => {
-                return Err(account_errors::Error {
-                    content: account_model::AccountError::build(
-                        400,
-                        Some(format!("[{}]: Account doesn't exist.", input.uuid)),
+        };
+        let mut user = user_model::User::create(item.username, password, None);
+        user.access.role = item.role;
+        created.push(user);
+        results.push(None);
+    }
+
+    let mut outcomes = users.create_many(&created).await.into_iter();
+    let mut created = created.into_iter();
+    Ok(results
+        .into_iter()
+        .map(|result| match result {
+            Some(failure) => failure,
+            None => {
+                let user = created.next().unwrap();
+                match outcomes.next().unwrap() {
+                    Ok(()) => response_model::BatchItem {
+                        username: user.username,
+                        status: http::Status::Created.code,
+                        uuid: Some(user.uuid),
+                        error: None,
+                        message: None,
+                    },
+                    Err(UserError::AlreadyExists) => batch_failure(
+                        &user.username,
+                        ApiError::UserExists(user.username.to_string()),
                     ),
-                })
+                    Err(error) => {
+                        error!(error = ?error, "Cannot create the users.");
+                        batch_failure(&user.username, ApiError::from_db(&error))
+                    }
+                }
             }
+        })
+        .collect())
+}
+
+/// Answers 200 as long as the batch itself is valid, each user has its own status.
+#[post("/admin/account/users/batch", data = "<input>")]
+pub async fn create_users(
+    admin: AdminUser,
+    audit: Audit<'_>,
+    db: &State<Database>,
+    settings: &State<Settings>,
+    pwned: &State<PwnedPasswords>,
+    input: Json<Vec<request_model::BatchUser>>,
+) -> Result<ApiResponse<Vec<response_model::BatchItem>>, ApiError> {
+    let results = create_users_batch(&db.usermanager, settings, pwned, input.into_inner()).await?;
+    for uuid in results.iter().filter_map(|result| result.uuid.as_ref()) {
+        audit
+            .record(AuditAction::Signup, Some(&admin.uuid), Some(uuid))
+            .await;
+    }
+    Ok(ApiResponse(results))
+}
+
+/// Body of `profile`, on any user store.
+pub async fn find_profile(
+    users: &dyn UserStore,
+    uuid: &str,
+) -> Result<account_model::Account, ApiError> {
+    match users.get_by_uuid(uuid).await {
+        Ok(user) => match user {
+            Some(user) => Ok(account_model::Account {
+                uuid: user.uuid.clone(),
+                username: user.username.clone(),
+            }),
+            _ => Err(ApiError::AccountNotFound(uuid.to_string())),
         },
         Err(error) => {
-            println!("{:?}", error);
-            return Err(account_errors::Error {
-                content: account_model::AccountError::build(
-                    500,
-                    Some("Database error.".to_string()),
-                ),
-            });
+            error!(error = ?error, "Cannot find the user.");
+            Err(ApiError::from_db(&error))
         }
     }
 }
 
+#[post("/admin/profile", data = "<input>")]
+pub async fn profile(
+    _admin: AdminUser,
+    db: &State<Database>,
+    input: Json<account_model::AccountUuid>,
+) -> Result<ApiResponse<account_model::Account>, ApiError> {
+    find_profile(&db.usermanager, &input.uuid)
+        .await
+        .map(ApiResponse)
+}
+
 #[post("/admin/profile-from-token", data = "<input>")]
 pub async fn profile_from_token(
-    api: ApiUserToken,
+    _admin: AdminUser,
     db: &State<Database>,
     input: Json<account_model::AccountToken>,
-) -> Result<Json<account_model::Account>, account_errors::Error> {
-    if api.apiuser.access.role != apiuser_model::ApiUserRoleType::Admin {
-        return Err(account_errors::Error {
-            content: account_model::AccountError::build(400, Some("No permission.".to_string())),
-        });
-    }
+) -> Result<ApiResponse<account_model::Account>, ApiError> {
     match db.usermanager.get_user_from_token(&input.token).await {
         Ok(user) => match user {
-            Some(user) => {
-                return Ok(Json(account_model::Account {
-                    uuid: user.uuid.clone(),
-                    username: user.username.clone(),
-                }));
-            }
-            _ => {
-                return Err(account_errors::Error {
-                    content: account_model::AccountError::build(
-                        400,
-                        Some(format!(
-                            "[{}]: Token not related to any account.",
-                            input.token
-                        )),
-                    ),
-                })
-            }
+            Some(user) => Ok(ApiResponse(account_model::Account {
+                uuid: user.uuid.clone(),
+                username: user.username.clone(),
+            })),
+            _ => Err(ApiError::InvalidToken(input.token.to_string())),
+        },
+        Err(error) => {
+            error!(error = ?error, "Cannot find the user of the token.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// An account with what support needs to look into it, never its password or tokens.
+#[get("/admin/account/users/<username>")]
+pub async fn user_details(
+    _admin: AdminUser,
+    db: &State<Database>,
+    username: &str,
+) -> Result<ApiResponse<response_model::UserDetails>, ApiError> {
+    match db.usermanager.get_user(Some(username), None).await {
+        Ok(user) => match user {
+            Some(user) => Ok(ApiResponse(response_model::UserDetails::from_user(
+                &user,
+                get_current_timestamp(),
+            ))),
+            _ => Err(ApiError::AccountNotFound(username.to_string())),
+        },
+        Err(error) => {
+            error!(error = ?error, "Cannot find the user.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// The export a user gets from `/user/account/export`, of any account.
+#[get("/admin/account/users/<username>/export")]
+pub async fn export(
+    _admin: AdminUser,
+    db: &State<Database>,
+    username: &str,
+) -> Result<ApiResponse<response_model::UserExport>, ApiError> {
+    match db.usermanager.get_user(Some(username), None).await {
+        Ok(user) => match user {
+            Some(user) => return export_data(db, &user).await,
+            _ => Err(ApiError::AccountNotFound(username.to_string())),
         },
         Err(error) => {
-            println!("{:?}", error);
-            return Err(account_errors::Error {
-                content: account_model::AccountError::build(
-                    500,
-                    Some("Database error.".to_string()),
-                ),
-            });
+            error!(error = ?error, "Cannot find the user.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// The refresh token families of any account, as its owner sees them.
+#[get("/admin/account/users/<username>/refresh-families")]
+pub async fn refresh_families(
+    _admin: AdminUser,
+    db: &State<Database>,
+    username: &str,
+) -> Result<ApiResponse<Vec<response_model::RefreshFamily>>, ApiError> {
+    match db.usermanager.get_user(Some(username), None).await {
+        Ok(user) => match user {
+            Some(user) => Ok(ApiResponse(match &user.refresh_tokens {
+                Some(tokens) => response_model::RefreshFamily::list(tokens),
+                None => Vec::new(),
+            })),
+            _ => Err(ApiError::AccountNotFound(username.to_string())),
+        },
+        Err(error) => {
+            error!(error = ?error, "Cannot find the user.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// For incidents: a family is revoked from its id alone, whichever account holds it.
+#[delete("/admin/refresh-families/<id>")]
+pub async fn revoke_refresh_family(
+    admin: AdminUser,
+    audit: Audit<'_>,
+    db: &State<Database>,
+    id: &str,
+) -> Result<http::Status, ApiError> {
+    match db
+        .usermanager
+        .revoke_refresh_token_family_everywhere(id)
+        .await
+    {
+        Ok(revoked) if revoked.is_empty() => Err(ApiError::TokenNotFound(id.to_string())),
+        Ok(revoked) => {
+            for uuid in &revoked {
+                audit
+                    .record(
+                        AuditAction::RefreshFamilyRevoked,
+                        Some(&admin.uuid),
+                        Some(uuid),
+                    )
+                    .await;
+            }
+            Ok(http::Status::NoContent)
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot revoke the refresh token family.");
+            Err(ApiError::from_db(&error))
         }
     }
 }
 
 #[post("/admin/refresh-token", data = "<input>")]
 pub async fn refresh_token(
-    api: ApiUserToken,
+    _admin: AdminUser,
     db: &State<Database>,
+    settings: &State<Settings>,
     input: Json<account_model::AccountUuid>,
-) -> Result<Json<account_model::AccountTokenInfos>, account_errors::Error> {
-    if api.apiuser.access.role != apiuser_model::ApiUserRoleType::Admin {
-        return Err(account_errors::Error {
-            content: account_model::AccountError::build(400, Some("No permission.".to_string())),
-        });
-    }
+) -> Result<ApiResponse<account_model::AccountTokenInfos>, ApiError> {
     match db.usermanager.get_user(None, Some(&input.uuid)).await {
         Ok(mut user) => match &mut user {
             Some(user) => {
-                let token = user.new_token(TOKEN_DURATION);
-                let _ = db.usermanager.save_token(&user.uuid, &token).await;
-                return Ok(Json(account_model::AccountTokenInfos {
+                let token = user.new_token(settings.security.token_ttl);
+                if let Err(error) = db
+                    .usermanager
+                    .save_token(&user.uuid, &token, settings.security.max_active_tokens)
+                    .await
+                {
+                    error!(uuid = user.uuid.as_str(), error = ?error, "Cannot save the token.");
+                    return Err(ApiError::from_db(&error));
+                }
+                Ok(ApiResponse(account_model::AccountTokenInfos {
                     token: token.token.clone(),
                     timestamp: token.timestamp,
                     expiration_timestamp: token.expiration_timestamp,
                     uuid: user.uuid.clone(),
-                }));
-            }
-            _ => {
-                return Err(account_errors::Error {
-                    content: account_model::AccountError::build(
-                        400,
-                        Some(format!("[{}]: Account doesn't exist.", input.uuid)),
-                    ),
-                })
+                }))
             }
+            _ => Err(ApiError::AccountNotFound(input.uuid.to_string())),
         },
         Err(error) => {
-            println!("{:?}", error);
-            return Err(account_errors::Error {
-                content: account_model::AccountError::build(
-                    500,
-                    Some("Database error.".to_string()),
-                ),
-            });
+            error!(error = ?error, "Cannot find the user.");
+            Err(ApiError::from_db(&error))
         }
     }
 }
 
 #[post("/admin/check-token", data = "<input>")]
 pub async fn check_token(
-    api: ApiUserToken,
+    _admin: AdminUser,
     db: &State<Database>,
     input: Json<account_model::AccountToken>,
-) -> Result<Json<account_model::AccountTokenInfos>, account_errors::Error> {
-    if api.apiuser.access.role != apiuser_model::ApiUserRoleType::Admin {
-        return Err(account_errors::Error {
-            content: account_model::AccountError::build(400, Some("No permission.".to_string())),
-        });
-    }
+) -> Result<ApiResponse<account_model::AccountTokenInfos>, ApiError> {
     match db.usermanager.get_user_from_token(&input.token).await {
         Ok(user) => match user {
             Some(user) => {
+                let hash = hash_token(&input.token);
                 let mut tokens = user.tokens.clone().unwrap();
-                tokens.retain(|filter| filter.token == input.token);
-                let token = &tokens.get(0).unwrap();
-                return Ok(Json(account_model::AccountTokenInfos {
-                    token: token.token.clone(),
+                tokens.retain(|filter| constant_time_eq(filter.token.as_bytes(), hash.as_bytes()));
+                let token = &tokens.first().unwrap();
+                Ok(ApiResponse(account_model::AccountTokenInfos {
+                    token: input.token.clone(),
                     timestamp: token.timestamp,
                     expiration_timestamp: token.expiration_timestamp,
                     uuid: user.uuid,
-                }));
-            }
-            _ => {
-                return Err(account_errors::Error {
-                    content: account_model::AccountError::build(
-                        400,
-                        Some(format!(
-                            "[{}]: Token not related to any account.",
-                            input.token
-                        )),
-                    ),
-                })
+                }))
             }
+            _ => Err(ApiError::InvalidToken(input.token.to_string())),
         },
         Err(error) => {
-            println!("{:?}", error);
-            return Err(account_errors::Error {
-                content: account_model::AccountError::build(
-                    500,
-                    Some("Database error.".to_string()),
-                ),
-            });
+            error!(error = ?error, "Cannot find the user of the token.");
+            Err(ApiError::from_db(&error))
         }
     }
 }
 
 #[post("/admin/delete", data = "<input>")]
 pub async fn delete(
-    api: ApiUserToken,
+    admin: AdminUser,
+    audit: Audit<'_>,
     db: &State<Database>,
+    settings: &State<Settings>,
     input: Json<account_model::AccountUuid>,
-) -> Result<Json<String>, account_errors::Error> {
-    if api.apiuser.access.role != apiuser_model::ApiUserRoleType::Admin {
-        return Err(account_errors::Error {
-            content: account_model::AccountError::build(400, Some("No permission.".to_string())),
-        });
-    }
-    match db.usermanager.delete_user(None, Some(&input.uuid)).await {
-        Ok(user) => match user {
-            Some(count) if count.deleted_count >= 1 => {
-                return Ok(Json("Account deleted.".to_string()));
+) -> Result<ApiResponse<String>, ApiError> {
+    let mode = settings.security.deletion_mode;
+    match db
+        .delete_account(&input.uuid, mode, &settings.security.anonymization_key)
+        .await
+    {
+        Ok(id) => match id {
+            Some(id) => {
+                audit
+                    .record(AuditAction::AccountDeleted, Some(&admin.uuid), Some(&id))
+                    .await;
+                Ok(ApiResponse("Account deleted.".to_string()))
             }
-            _ => {
-                return Err(account_errors::Error {
-                    content: account_model::AccountError::build(
-                        400,
-                        Some(format!("[{}]: Account doesn't exist.", input.uuid)),
-                    ),
-                })
+            None => Err(ApiError::AccountNotFound(input.uuid.to_string())),
+        },
+        Err(error) => {
+            error!(error = ?error, "Cannot delete the account.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+#[post("/admin/restore", data = "<input>")]
+pub async fn restore(
+    admin: AdminUser,
+    audit: Audit<'_>,
+    db: &State<Database>,
+    input: Json<account_model::AccountUuid>,
+) -> Result<ApiResponse<String>, ApiError> {
+    match db.usermanager.restore_user(&input.uuid).await {
+        Ok(result) => match result.modified_count {
+            1 => {
+                audit
+                    .record(
+                        AuditAction::AccountRestored,
+                        Some(&admin.uuid),
+                        Some(&input.uuid),
+                    )
+                    .await;
+                Ok(ApiResponse("Account restored.".to_string()))
             }
+            _ => Err(ApiError::AccountNotFound(input.uuid.to_string())),
         },
         Err(error) => {
-            println!("{:?}", error);
-            return Err(account_errors::Error {
-                content: account_model::AccountError::build(
-                    500,
-                    Some("Database error.".to_string()),
-                ),
-            });
+            error!(error = ?error, "Cannot restore the account.");
+            Err(ApiError::from_db(&error))
         }
     }
 }
 
 #[post("/admin/clear-tokens", data = "<input>")]
 pub async fn clear_tokens(
-    api: ApiUserToken,
+    admin: AdminUser,
+    audit: Audit<'_>,
     db: &State<Database>,
     input: Json<account_model::AccountUuid>,
-) -> Result<Json<String>, account_errors::Error> {
-    if api.apiuser.access.role != apiuser_model::ApiUserRoleType::Admin {
-        return Err(account_errors::Error {
-            content: account_model::AccountError::build(400, Some("No permission.".to_string())),
-        });
-    }
+) -> Result<ApiResponse<String>, ApiError> {
     match db.usermanager.clear_tokens(&input.uuid).await {
         Ok(user) => match user.modified_count {
-            1 => return Ok(Json("Tokens cleared.".to_string())),
-            _ => {
-                return Err(account_errors::Error {
-                    content: account_model::AccountError::build(
-                        400,
-                        Some(format!("[{}]: Account doesn't exist.", input.uuid)),
-                    ),
-                })
+            1 => {
+                audit
+                    .record(
+                        AuditAction::TokensCleared,
+                        Some(&admin.uuid),
+                        Some(&input.uuid),
+                    )
+                    .await;
+                Ok(ApiResponse("Tokens cleared.".to_string()))
             }
+            _ => Err(ApiError::AccountNotFound(input.uuid.to_string())),
         },
         Err(error) => {
-            println!("{:?}", error);
-            return Err(account_errors::Error {
-                content: account_model::AccountError::build(
-                    500,
-                    Some("Database error.".to_string()),
-                ),
-            });
+            error!(error = ?error, "Cannot clear the tokens.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// No side effect, for operators to check their token. A bad token is refused by the guard.
+#[get("/admin/account/whoami")]
+pub async fn whoami(admin: AdminUser) -> ApiResponse<response_model::AdminIdentity> {
+    ApiResponse(response_model::AdminIdentity {
+        uuid: admin.uuid,
+        role: admin.role,
+        token_valid: true,
+    })
+}
+
+/// The new token is only returned here, the previous one stops working right away.
+/// It is kept over restarts, the default admin is only reset from the settings with `MISATO_RESET_ADMIN`.
+#[post("/admin/account/rotate-token")]
+pub async fn rotate_token(
+    admin: AdminUser,
+    audit: Audit<'_>,
+    db: &State<Database>,
+    settings: &State<Settings>,
+) -> Result<ApiResponse<apiaccount_model::ApiAccountTokenInfos>, ApiError> {
+    let mut apiuser = match db.apiusermanager.get_apiuser(None, Some(&admin.uuid)).await {
+        Ok(Some(apiuser)) => apiuser,
+        Ok(None) => return Err(ApiError::ApiAccountNotFound(admin.uuid.to_string())),
+        Err(error) => {
+            error!(error = ?error, "Cannot find the api account.");
+            return Err(ApiError::from_db(&error));
+        }
+    };
+    let token = match apiuser.uuid.as_str() {
+        apiuser_model::DEFAULT_ADMIN_UUID => apiuser.new_permanent_token(),
+        _ => apiuser.new_token(settings.security.token_ttl),
+    };
+    match db.apiusermanager.set_token(&apiuser.uuid, &token).await {
+        Ok(_) => {
+            audit
+                .record(
+                    AuditAction::AdminTokenRotated,
+                    Some(&admin.uuid),
+                    Some(&admin.uuid),
+                )
+                .await;
+            Ok(ApiResponse(apiaccount_model::ApiAccountTokenInfos {
+                token: token.token,
+                timestamp: token.timestamp,
+                expiration_timestamp: token.expiration_timestamp,
+                uuid: apiuser.uuid,
+            }))
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot save the token.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// `cursor` switches to cursor pagination, empty for the first page, `page` is then ignored.
+#[get("/admin/account/users?<page>&<limit>&<cursor>")]
+pub async fn users(
+    _admin: AdminUser,
+    db: &State<Database>,
+    page: Option<u64>,
+    limit: Option<u64>,
+    cursor: Option<&str>,
+) -> Result<ApiResponse<response_model::Page<account_model::Account>>, ApiError> {
+    let pagination = response_model::Pagination::new(
+        page,
+        limit,
+        USERS_PAGE_DEFAULT_LIMIT,
+        USERS_PAGE_MAX_LIMIT,
+    );
+    match cursor {
+        Some(cursor) => list_users_after(&db.usermanager, cursor, pagination.limit)
+            .await
+            .map(|page| ApiResponse(response_model::Page::Cursor(page))),
+        None => list_users_page(&db.usermanager, pagination)
+            .await
+            .map(|page| ApiResponse(response_model::Page::Offset(page))),
+    }
+}
+
+/// Cursor mode of `users`, on any user store, an empty `cursor` starts from the first user.
+pub async fn list_users_after(
+    users: &dyn UserStore,
+    cursor: &str,
+    limit: u64,
+) -> Result<response_model::CursorPaginated<account_model::Account>, ApiError> {
+    let cursor = match cursor.is_empty() {
+        true => None,
+        false => Some(cursor),
+    };
+    match users.list_after(cursor, limit as i64 + 1).await {
+        Ok(users) => {
+            let users = users
+                .into_iter()
+                .map(|(cursor, user)| {
+                    (
+                        cursor,
+                        account_model::Account {
+                            uuid: user.uuid,
+                            username: user.username,
+                        },
+                    )
+                })
+                .collect();
+            Ok(response_model::CursorPaginated::new(users, limit))
+        }
+        Err(UserError::InvalidCursor) => {
+            Err(ApiError::ValidationError("invalid cursor".to_string()))
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot list the users.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// Offset mode of `users`, on any user store.
+pub async fn list_users_page(
+    users: &dyn UserStore,
+    pagination: response_model::Pagination,
+) -> Result<response_model::Paginated<account_model::Account>, ApiError> {
+    let total = match users.count().await {
+        Ok(total) => total,
+        Err(error) => {
+            error!(error = ?error, "Cannot count the users.");
+            return Err(ApiError::from_db(&error));
+        }
+    };
+    match users.list(pagination.skip(), pagination.limit as i64).await {
+        Ok(users) => {
+            let users = users
+                .into_iter()
+                .map(|user| account_model::Account {
+                    uuid: user.uuid,
+                    username: user.username,
+                })
+                .collect();
+            Ok(pagination.paginate(users, total))
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot list the users.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// The invite token is only returned here, it can be spent once at signup while registration is closed.
+#[post("/admin/invites")]
+pub async fn create_invite(
+    admin: AdminUser,
+    audit: Audit<'_>,
+    db: &State<Database>,
+    settings: &State<Settings>,
+) -> Result<ApiResponse<response_model::HashedTokenResponse>, ApiError> {
+    let (token, invite) =
+        invite_model::Invite::create(admin.uuid.clone(), settings.security.invite_ttl);
+    match db.invitemanager.create_invite(&invite).await {
+        Ok(_) => {
+            audit
+                .record(AuditAction::InviteCreated, Some(&admin.uuid), None)
+                .await;
+            Ok(ApiResponse(response_model::HashedTokenResponse {
+                token,
+                expiration_timestamp: invite.token.expiration_timestamp,
+            }))
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot create the invite.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// Health and admin routes stay up while it is enabled, every other one answers 503.
+#[post("/admin/maintenance", data = "<input>")]
+pub async fn maintenance(
+    admin: AdminUser,
+    audit: Audit<'_>,
+    mode: &State<MaintenanceMode>,
+    input: Json<request_model::MaintenanceToggle>,
+) -> Result<ApiResponse<response_model::MaintenanceStatus>, ApiError> {
+    mode.set(input.enabled, input.retry_after);
+    audit
+        .record(AuditAction::MaintenanceToggled, Some(&admin.uuid), None)
+        .await;
+    Ok(ApiResponse(response_model::MaintenanceStatus {
+        enabled: mode.is_enabled(),
+        retry_after: mode.retry_after(),
+    }))
+}
+
+/// The timestamp of the `name` criterion of the audit listing, if given.
+fn audit_timestamp(query: &HashMap<&str, &str>, name: &str) -> Result<Option<u64>, ApiError> {
+    match query.get(name) {
+        Some(value) => match value.parse() {
+            Ok(timestamp) => Ok(Some(timestamp)),
+            Err(_) => Err(ApiError::ValidationError(format!(
+                "[{}]: `{}` is a timestamp in milliseconds.",
+                value, name
+            ))),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Criteria of the audit listing are `actor`, `action`, and `from` and `to`, timestamps in
+/// milliseconds, both included.
+#[get("/admin/audit?<page>&<limit>&<query..>")]
+pub async fn audit(
+    _admin: AdminUser,
+    db: &State<Database>,
+    page: Option<u64>,
+    limit: Option<u64>,
+    query: HashMap<&str, &str>,
+) -> Result<ApiResponse<response_model::Paginated<audit_model::AuditEvent>>, ApiError> {
+    let filter = match audit_model::AuditFilter::new(
+        query.get("actor").map(|actor| actor.to_string()),
+        query.get("action").copied(),
+        audit_timestamp(&query, "from")?,
+        audit_timestamp(&query, "to")?,
+    ) {
+        Ok(filter) => filter,
+        Err(reason) => return Err(ApiError::ValidationError(reason)),
+    };
+    let pagination = response_model::Pagination::new(
+        page,
+        limit,
+        AUDIT_PAGE_DEFAULT_LIMIT,
+        AUDIT_PAGE_MAX_LIMIT,
+    );
+    let total = match db.auditmanager.count_events(&filter).await {
+        Ok(total) => total,
+        Err(error) => {
+            error!(error = ?error, "Cannot count the audit events.");
+            return Err(ApiError::from_db(&error));
+        }
+    };
+    match db
+        .auditmanager
+        .list_events(&filter, pagination.skip(), pagination.limit as i64)
+        .await
+    {
+        Ok(events) => Ok(ApiResponse(pagination.paginate(events, total))),
+        Err(error) => {
+            error!(error = ?error, "Cannot list the audit events.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// For incidents: every session ends at once, optionally only for a role or for tokens
+/// issued before a timestamp. Send `{}` to revoke everything.
+#[post("/admin/account/revoke-all-tokens", data = "<input>")]
+pub async fn revoke_all_tokens(
+    admin: AdminUser,
+    audit: Audit<'_>,
+    db: &State<Database>,
+    input: Json<request_model::RevokeAllTokens>,
+) -> Result<ApiResponse<String>, ApiError> {
+    match db
+        .usermanager
+        .revoke_all_tokens(input.role.as_ref(), input.before)
+        .await
+    {
+        Ok(result) => {
+            audit
+                .record(AuditAction::AllTokensRevoked, Some(&admin.uuid), None)
+                .await;
+            Ok(ApiResponse(format!(
+                "Tokens revoked for {} users.",
+                result.modified_count
+            )))
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot revoke the tokens.");
+            Err(ApiError::from_db(&error))
         }
     }
 }
@@ -1,2 +1,4 @@
-pub mod admin;
-pub mod root;
+pub mod v1;
+
+/// Unversioned base, kept as a deprecated alias of the v1 routes.
+pub const LEGACY_BASE: &str = "/api";
@@ -0,0 +1,261 @@
+use rocket::serde::json::Json;
+use rocket::*;
+use tracing::error;
+
+use misato_database::{
+    api_manager::{ApiUserError, RoleChange},
+    database::*,
+    models::*,
+};
+use misato_utils::settings::Settings;
+
+use misato::models::apiaccount_model;
+
+use crate::errors::{api_errors::ApiError, api_response::ApiResponse};
+use crate::fairings::admin_authentication::AdminUser;
+
+/// The error of a change refused to keep an admin, or of an unknown api user.
+fn role_change(change: RoleChange, uuid: &str) -> Result<(), ApiError> {
+    match change {
+        RoleChange::Changed => Ok(()),
+        RoleChange::NotFound => Err(ApiError::ApiAccountNotFound(uuid.to_string())),
+        RoleChange::LastAdmin => Err(ApiError::LastAdmin),
+    }
+}
+
+#[post("/admin/signup", data = "<input>")]
+pub async fn signup(
+    _admin: AdminUser,
+    db: &State<Database>,
+    settings: &State<Settings>,
+    input: Json<request_model::ApiSignup>,
+) -> Result<ApiResponse<apiaccount_model::ApiAccountTokenInfos>, ApiError> {
+    let mut user = apiuser_model::ApiUser::create(input.uuid.clone(), input.role.clone());
+
+    let result = db
+        .apiusermanager
+        .get_apiuser(None, Some(&user.uuid.to_string()))
+        .await;
+    if result.is_ok() && result.as_ref().unwrap().is_some() {
+        return Err(ApiError::ApiAccountExists(input.uuid.to_string()));
+    }
+    if let Err(error) = result {
+        error!(error = ?error, "Cannot find the api account.");
+        return Err(ApiError::from_db(&error));
+    }
+
+    match db.apiusermanager.uuid_exists(&user.uuid).await {
+        Ok(exists) => {
+            if !exists {
+                return Err(ApiError::AccountNotFound(input.uuid.to_string()));
+            }
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot check the account.");
+            return Err(ApiError::from_db(&error));
+        }
+    }
+
+    match db.apiusermanager.create_apiuser(&user).await {
+        Ok(_) => {
+            let token = user.new_token(settings.security.token_ttl);
+            match db.apiusermanager.set_token(&user.uuid, &token).await {
+                Ok(_) => Ok(ApiResponse(apiaccount_model::ApiAccountTokenInfos {
+                    token: token.token,
+                    timestamp: token.timestamp,
+                    expiration_timestamp: token.expiration_timestamp,
+                    uuid: user.uuid,
+                })),
+                Err(_error) => {
+                    error!(error = ?_error, "Cannot save the token.");
+                    Err(ApiError::from_db(&_error))
+                }
+            }
+        }
+        Err(ApiUserError::AlreadyExists) => Err(ApiError::ApiAccountExists(input.uuid.to_string())),
+        Err(_error) => {
+            error!(error = ?_error, "Cannot create the api account.");
+            Err(ApiError::from_db(&_error))
+        }
+    }
+}
+
+#[post("/admin/refresh-token", data = "<input>")]
+pub async fn refresh_token(
+    _admin: AdminUser,
+    db: &State<Database>,
+    settings: &State<Settings>,
+    input: Json<apiaccount_model::ApiAccountUuid>,
+) -> Result<ApiResponse<apiaccount_model::ApiAccountTokenInfos>, ApiError> {
+    match db
+        .apiusermanager
+        .get_apiuser(None, Some(&input.uuid.to_string()))
+        .await
+    {
+        Ok(mut user) => match &mut user {
+            Some(user) => {
+                let token = user.new_token(settings.security.token_ttl);
+                match db.apiusermanager.set_token(&user.uuid, &token).await {
+                    Ok(_) => Ok(ApiResponse(apiaccount_model::ApiAccountTokenInfos {
+                        token: token.token,
+                        timestamp: token.timestamp,
+                        expiration_timestamp: token.expiration_timestamp,
+                        uuid: user.uuid.clone(),
+                    })),
+                    Err(_error) => {
+                        error!(error = ?_error, "Cannot save the token.");
+                        Err(ApiError::from_db(&_error))
+                    }
+                }
+            }
+            _ => Err(ApiError::AccountNotFound(input.uuid.to_string())),
+        },
+        Err(error) => {
+            error!(error = ?error, "Cannot find the api account.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+#[post("/admin/check-token", data = "<input>")]
+pub async fn check_token(
+    _admin: AdminUser,
+    db: &State<Database>,
+    input: Json<apiaccount_model::ApiAccountToken>,
+) -> Result<ApiResponse<apiaccount_model::ApiAccountTokenInfos>, ApiError> {
+    match db.apiusermanager.get_apiuser_from_token(&input.token).await {
+        Ok(user) => match user {
+            Some(user) => {
+                let token = user.token.unwrap();
+                Ok(ApiResponse(apiaccount_model::ApiAccountTokenInfos {
+                    token: input.token.clone(),
+                    timestamp: token.timestamp,
+                    expiration_timestamp: token.expiration_timestamp,
+                    uuid: user.uuid,
+                }))
+            }
+            _ => Err(ApiError::InvalidToken(input.token.to_string())),
+        },
+        Err(error) => {
+            error!(error = ?error, "Cannot find the api account of the token.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+#[post("/admin/delete", data = "<input>")]
+pub async fn delete(
+    _admin: AdminUser,
+    db: &State<Database>,
+    input: Json<apiaccount_model::ApiAccountUuid>,
+) -> Result<ApiResponse<String>, ApiError> {
+    match db
+        .apiusermanager
+        .delete_apiuser_keeping_an_admin(&input.uuid)
+        .await
+    {
+        Ok(RoleChange::NotFound) => Err(ApiError::AccountNotFound(input.uuid.to_string())),
+        Ok(change) => {
+            role_change(change, &input.uuid)?;
+            Ok(ApiResponse("account deleted.".to_string()))
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot delete the api account.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+#[post("/admin/clear-tokens", data = "<input>")]
+pub async fn clear_tokens(
+    _admin: AdminUser,
+    db: &State<Database>,
+    input: Json<apiaccount_model::ApiAccountUuid>,
+) -> Result<ApiResponse<String>, ApiError> {
+    match db.apiusermanager.clear_tokens(&input.uuid).await {
+        Ok(user) => match user.modified_count {
+            1 => Ok(ApiResponse("Token removed.".to_string())),
+            _ => Err(ApiError::AccountNotFound(input.uuid.to_string())),
+        },
+        Err(error) => {
+            error!(error = ?error, "Cannot clear the tokens.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+#[post("/admin/role", data = "<input>")]
+pub async fn role(
+    _admin: AdminUser,
+    db: &State<Database>,
+    input: Json<request_model::ApiRoleChange>,
+) -> Result<ApiResponse<String>, ApiError> {
+    // The default admin is recreated from the settings on every start
+    if input.uuid == apiuser_model::DEFAULT_ADMIN_UUID {
+        return Err(ApiError::NoPermission);
+    }
+    match db
+        .apiusermanager
+        .set_role_keeping_an_admin(&input.uuid, &input.role)
+        .await
+    {
+        Ok(change) => {
+            role_change(change, &input.uuid)?;
+            Ok(ApiResponse("Role changed.".to_string()))
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot change the role.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// Give the admin role to an existing api user, its token then passes the admin routes.
+#[post("/admin/promote", data = "<input>")]
+pub async fn promote(
+    _admin: AdminUser,
+    db: &State<Database>,
+    input: Json<apiaccount_model::ApiAccountUuid>,
+) -> Result<ApiResponse<String>, ApiError> {
+    match db
+        .apiusermanager
+        .set_role(&input.uuid, &apiuser_model::ApiUserRoleType::Admin)
+        .await
+    {
+        Ok(result) => match result.matched_count {
+            1 => Ok(ApiResponse("Promoted to admin.".to_string())),
+            _ => Err(ApiError::ApiAccountNotFound(input.uuid.to_string())),
+        },
+        Err(error) => {
+            error!(error = ?error, "Cannot change the role.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// Back to the user role, refused for the last admin, the caller included.
+#[post("/admin/demote", data = "<input>")]
+pub async fn demote(
+    _admin: AdminUser,
+    db: &State<Database>,
+    input: Json<apiaccount_model::ApiAccountUuid>,
+) -> Result<ApiResponse<String>, ApiError> {
+    // The default admin is recreated from the settings on every start
+    if input.uuid == apiuser_model::DEFAULT_ADMIN_UUID {
+        return Err(ApiError::NoPermission);
+    }
+    match db
+        .apiusermanager
+        .set_role_keeping_an_admin(&input.uuid, &apiuser_model::ApiUserRoleType::User)
+        .await
+    {
+        Ok(change) => {
+            role_change(change, &input.uuid)?;
+            Ok(ApiResponse("Demoted to user.".to_string()))
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot change the role.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
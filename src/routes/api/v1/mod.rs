@@ -0,0 +1,36 @@
+use rocket::{routes, Route};
+
+pub mod admin;
+pub mod root;
+
+pub const BASE: &str = "/api/v1";
+
+pub fn routes() -> Vec<Route> {
+    let mut routes: Vec<Route> = Vec::new();
+
+    // Api Admin
+    routes.append(&mut routes![
+        admin::account::signup,
+        admin::account::refresh_token,
+        admin::account::clear_tokens,
+        admin::account::delete,
+        admin::account::check_token,
+        admin::account::role,
+        admin::account::promote,
+        admin::account::demote,
+    ]);
+
+    // Api root
+    routes.append(&mut routes![
+        root::account::signup,
+        root::account::refresh_token,
+        root::account::clear_tokens,
+        root::account::delete,
+        root::account::check_token,
+        root::account::create_key,
+        root::account::keys,
+        root::account::revoke_key,
+    ]);
+
+    routes
+}
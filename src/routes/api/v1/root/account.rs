@@ -0,0 +1,326 @@
+use rocket::outcome::try_outcome;
+use rocket::request::{self, FromRequest, Outcome, Request};
+use rocket::serde::json::Json;
+use rocket::*;
+use tracing::error;
+
+use misato_database::{api_manager::ApiUserError, database::*, models::*};
+use misato_security::hash_token;
+use misato_utils::settings::Settings;
+
+use misato::models::apiaccount_model;
+
+const API_KEY_NAME_MAX_LENGTH: usize = 64;
+const API_KEYS_MAX: usize = 20; // Per api account
+const API_KEY_MAX_LIFETIME: u64 = 10 * 365 * 24 * 60 * 60; // In seconds
+
+use crate::captcha::{Captcha, CaptchaToken};
+use crate::errors::{api_errors::ApiError, api_response::ApiResponse};
+use crate::fairings::api_authentication::{ApiUserToken, AuthenticatedApiUser};
+use crate::fairings::authentication::{UserToken, UserTokenError};
+use crate::fairings::client_info::ClientIp;
+use crate::fairings::idempotency::IdempotencyKey;
+use crate::fairings::scope::{AccountDelete, RequireScope};
+
+/// What a signup reads from the request, checked in this order.
+pub struct SignupRequest<'r> {
+    user: UserToken,
+    idempotency: IdempotencyKey<'r>,
+    captcha_token: CaptchaToken,
+    client: ClientIp,
+}
+
+#[derive(Debug)]
+pub enum SignupRequestError {
+    User(UserTokenError),
+    Header, // The idempotency key is too long
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SignupRequest<'r> {
+    type Error = SignupRequestError;
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let header = |(status, ())| (status, SignupRequestError::Header);
+        Outcome::Success(SignupRequest {
+            user: try_outcome!(request
+                .guard::<UserToken>()
+                .await
+                .map_failure(|(status, error)| (status, SignupRequestError::User(error)))),
+            idempotency: try_outcome!(request.guard::<IdempotencyKey>().await.map_failure(header)),
+            captcha_token: try_outcome!(request.guard::<CaptchaToken>().await.map_failure(header)),
+            client: try_outcome!(request.guard::<ClientIp>().await.map_failure(header)),
+        })
+    }
+}
+
+/// Open to every user unless registration is closed, an admin invite is then required.
+/// New accounts get the default role, but the first user's when it becomes admin.
+/// A retry with the same `Idempotency-Key` gets the first answer again, 409 while it still runs.
+/// With CAPTCHA on, the `X-Misato-Captcha-Token` header must be accepted by the provider.
+#[post("/signup?<invite>")]
+pub async fn signup(
+    request: SignupRequest<'_>,
+    db: &State<Database>,
+    settings: &State<Settings>,
+    captcha: &State<Captcha>,
+    invite: Option<&str>,
+) -> Result<ApiResponse<apiaccount_model::ApiAccountTokenInfos>, ApiError> {
+    let SignupRequest {
+        user,
+        idempotency,
+        captcha_token,
+        client,
+    } = request;
+    let uuid = user.user.uuid.clone();
+    if let Some(previous) = idempotency.reserve(&uuid)? {
+        return Ok(ApiResponse(previous));
+    }
+    let result =
+        create_api_account(user, db, settings, captcha, captcha_token, client, invite).await;
+    match &result {
+        Ok(result) => idempotency.record(&uuid, result),
+        Err(_) => idempotency.release(&uuid),
+    }
+    result.map(ApiResponse)
+}
+
+async fn create_api_account(
+    user: UserToken,
+    db: &State<Database>,
+    settings: &State<Settings>,
+    captcha: &State<Captcha>,
+    captcha_token: CaptchaToken,
+    client: ClientIp,
+    invite: Option<&str>,
+) -> Result<apiaccount_model::ApiAccountTokenInfos, ApiError> {
+    let user = user.user;
+    captcha.verify(captcha_token.0.as_deref(), client.0).await?;
+    // The account of the first user, in place of the seeded admin
+    let role = match settings.security.first_user_admin
+        && user.access.role == user_model::UserRoleType::Admin
+    {
+        true => apiuser_model::ApiUserRoleType::Admin,
+        false => apiuser_model::ApiUserRoleType::from(settings.security.default_role),
+    };
+    let is_admin = role == apiuser_model::ApiUserRoleType::Admin;
+    if !settings.security.registration_open && invite.is_none() && !is_admin {
+        return Err(ApiError::RegistrationClosed);
+    }
+
+    let mut apiuser = apiuser_model::ApiUser::create(user.uuid.clone(), role);
+    let token = apiuser.new_token(settings.security.token_ttl);
+    match db.apiusermanager.create_apiuser(&apiuser).await {
+        Ok(_) => {}
+        Err(ApiUserError::AlreadyExists) => {
+            return Err(ApiError::ApiAccountExists(user.uuid.to_string()))
+        }
+        Err(error) => {
+            error!(uuid = user.uuid.as_str(), error = ?error, "Cannot create the api account.");
+            return Err(ApiError::from_db(&error));
+        }
+    }
+    // Spent only once the account exists, so a failed insert leaves the invite usable
+    if let Some(invite) = invite {
+        let refused = match db
+            .invitemanager
+            .use_invite(&hash_token(invite), &user.uuid)
+            .await
+        {
+            Ok(result) if result.modified_count == 1 => None,
+            Ok(_) => Some(ApiError::InvalidToken(invite.to_string())),
+            Err(error) => {
+                error!(uuid = user.uuid.as_str(), error = ?error, "Cannot use the invite.");
+                Some(ApiError::from_db(&error))
+            }
+        };
+        if let Some(refused) = refused {
+            if let Err(error) = db
+                .apiusermanager
+                .delete_apiuser(None, Some(&user.uuid))
+                .await
+            {
+                error!(uuid = user.uuid.as_str(), error = ?error, "Cannot remove the api account of a refused invite.");
+            }
+            return Err(refused);
+        }
+    }
+    Ok(apiaccount_model::ApiAccountTokenInfos {
+        token: token.token,
+        timestamp: token.timestamp,
+        expiration_timestamp: token.expiration_timestamp,
+        uuid: user.uuid,
+    })
+}
+
+#[post("/refresh-token")]
+pub async fn refresh_token(
+    user: UserToken,
+    db: &State<Database>,
+    settings: &State<Settings>,
+) -> Result<ApiResponse<apiaccount_model::ApiAccountTokenInfos>, ApiError> {
+    let user = user.user;
+
+    let result = db
+        .apiusermanager
+        .get_apiuser(None, Some(&user.uuid.to_string()))
+        .await;
+    if result.is_ok() && result.as_ref().unwrap().is_none() {
+        return Err(ApiError::ApiAccountNotFound(user.uuid.to_string()));
+    }
+    if let Err(error) = result {
+        error!(error = ?error, "Cannot find the api account.");
+        return Err(ApiError::from_db(&error));
+    }
+    let token = result
+        .unwrap()
+        .unwrap()
+        .new_token(settings.security.token_ttl);
+    match db.apiusermanager.set_token(&user.uuid, &token).await {
+        Ok(_) => Ok(ApiResponse(apiaccount_model::ApiAccountTokenInfos {
+            token: token.token,
+            timestamp: token.timestamp,
+            expiration_timestamp: token.expiration_timestamp,
+            uuid: user.uuid,
+        })),
+        Err(_error) => {
+            error!(error = ?_error, "Cannot save the token.");
+            Err(ApiError::from_db(&_error))
+        }
+    }
+}
+
+#[post("/check-token")]
+pub async fn check_token(
+    api: ApiUserToken,
+) -> Result<ApiResponse<apiaccount_model::ApiAccountTokenInfos>, ApiError> {
+    let token = api.apiuser.token.unwrap();
+    Ok(ApiResponse(apiaccount_model::ApiAccountTokenInfos {
+        token: api.token,
+        timestamp: token.timestamp,
+        expiration_timestamp: token.expiration_timestamp,
+        uuid: api.apiuser.uuid,
+    }))
+}
+
+#[post("/delete")]
+pub async fn delete(
+    api: ApiUserToken,
+    _scope: RequireScope<AccountDelete>,
+    db: &State<Database>,
+) -> Result<ApiResponse<String>, ApiError> {
+    match db
+        .apiusermanager
+        .delete_apiuser_from_token(&api.token)
+        .await
+    {
+        Ok(_) => Ok(ApiResponse("Account deleted.".to_string())),
+        Err(error) => {
+            error!(error = ?error, "Cannot delete the api account.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+#[post("/clear-tokens")]
+pub async fn clear_tokens(
+    api: ApiUserToken,
+    db: &State<Database>,
+) -> Result<ApiResponse<String>, ApiError> {
+    match db.apiusermanager.clear_tokens_from_token(&api.token).await {
+        Ok(_) => Ok(ApiResponse("Token removed.".to_string())),
+        Err(error) => {
+            error!(error = ?error, "Cannot clear the token.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// Keys can't manage keys, only the api token can.
+fn with_api_token(api: &AuthenticatedApiUser) -> Result<(), ApiError> {
+    match api.key {
+        Some(_) => Err(ApiError::NoPermission),
+        None => Ok(()),
+    }
+}
+
+/// The key is only returned here, it is stored hashed.
+#[post("/keys", data = "<input>")]
+pub async fn create_key(
+    api: AuthenticatedApiUser,
+    db: &State<Database>,
+    input: Json<request_model::ApiKeyCreate>,
+) -> Result<ApiResponse<response_model::ApiKeyInfo>, ApiError> {
+    with_api_token(&api)?;
+    let input = input.into_inner();
+    let name = input.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > API_KEY_NAME_MAX_LENGTH {
+        return Err(ApiError::ValidationError(format!(
+            "A key name is 1 to {} characters long.",
+            API_KEY_NAME_MAX_LENGTH
+        )));
+    }
+    if let Err(error) = scope_model::validate(&input.scopes) {
+        return Err(ApiError::ValidationError(error));
+    }
+    let mut apiuser = api.apiuser;
+    if apiuser.keys.as_ref().map_or(0, Vec::len) >= API_KEYS_MAX {
+        return Err(ApiError::ValidationError(format!(
+            "At most {} keys per account, revoke one first.",
+            API_KEYS_MAX
+        )));
+    }
+    let key = match input.expires_in {
+        Some(0) => None,
+        Some(seconds) if seconds > API_KEY_MAX_LIFETIME => None,
+        seconds => apiuser.new_key(name, seconds, input.scopes),
+    };
+    let key = match key {
+        Some(key) => key,
+        None => {
+            return Err(ApiError::ValidationError(format!(
+                "A key expires in 1 to {} seconds, or never.",
+                API_KEY_MAX_LIFETIME
+            )))
+        }
+    };
+    match db.apiusermanager.add_key(&apiuser.uuid, &key).await {
+        Ok(_) => {
+            let mut info = response_model::ApiKeyInfo::from(&key);
+            info.key = Some(key.key);
+            Ok(ApiResponse(info))
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot save the key.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+#[get("/keys")]
+pub async fn keys(api: AuthenticatedApiUser) -> ApiResponse<Vec<response_model::ApiKeyInfo>> {
+    let keys = match &api.apiuser.keys {
+        Some(keys) => keys.iter().map(response_model::ApiKeyInfo::from).collect(),
+        None => Vec::new(),
+    };
+    ApiResponse(keys)
+}
+
+#[delete("/keys/<id>")]
+pub async fn revoke_key(
+    api: AuthenticatedApiUser,
+    db: &State<Database>,
+    id: &str,
+) -> Result<http::Status, ApiError> {
+    with_api_token(&api)?;
+    match db.apiusermanager.remove_key(&api.apiuser.uuid, id).await {
+        Ok(result) => match result.modified_count {
+            1 => Ok(http::Status::NoContent),
+            _ => Err(ApiError::TokenNotFound(id.to_string())),
+        },
+        Err(error) => {
+            error!(error = ?error, "Cannot remove the key.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
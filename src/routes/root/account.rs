@@ -1,65 +1,704 @@
+use std::time::Duration;
+
 use rocket::serde::json::Json;
 use rocket::*;
+use tracing::{error, warn};
 
 use misato::models::*;
 
-use misato_database::database::*;
+use misato_database::{
+    database::*,
+    models::{
+        audit_model::AuditAction, login_failure_model::FailedLogin, request_model, response_model,
+        user_model,
+    },
+};
+use misato_security::{
+    constant_time_eq, hash_token, jwt,
+    password::{Password, SecurePassword},
+    rate_limit::progressive_delay,
+};
+use misato_utils::{
+    get_current_timestamp,
+    settings::Settings,
+    validation::{validate_email, validate_username},
+};
 
-use crate::errors::account_errors;
+use crate::errors::{api_errors::ApiError, api_response::ApiResponse};
+use crate::fairings::audit::Audit;
+use crate::fairings::client_info::ClientInfo;
+use crate::fairings::json_form::JsonForm;
+use crate::fairings::rate_limit::LoginRateLimit;
+use crate::pwned::PwnedPasswords;
+use crate::routes::user::account::check_totp_code;
+use crate::webhooks::{TokenDelivery, Webhooks};
 
-const TOKEN_DURATION: u64 = 24 * 60 * 60;
+/// Slow down guessing, without blocking the worker thread.
+async fn failed_login_delay(settings: &Settings, failures: u32) {
+    let delay = progressive_delay(
+        failures,
+        settings.security.login_delay_base,
+        settings.security.login_delay_cap,
+    );
+    if delay > 0 {
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+    }
+}
 
-#[post("/login", data = "<input>")]
-pub async fn login(
+/// Count a wrong password or a wrong code against the client and the account,
+/// the seconds left when this failure or a concurrent one locked it.
+async fn record_failed_login(
+    db: &Database,
+    settings: &Settings,
+    rate_limit: &LoginRateLimit<'_>,
+    audit: &Audit<'_>,
+    user: &user_model::User,
+    now: u64,
+) -> Option<u64> {
+    rate_limit.record_failure();
+    let lockout = (
+        settings.security.lockout_threshold,
+        settings.security.lockout_duration,
+    );
+    let failure = match db
+        .usermanager
+        .record_login_failure(&user.uuid, lockout, now)
+        .await
+    {
+        Ok(failure) => failure,
+        Err(error) => {
+            error!(error = ?error, "Cannot count the failed login.");
+            FailedLogin {
+                failed_logins: user.failed_logins.saturating_add(1),
+                locked_for: None,
+            }
+        }
+    };
+    audit
+        .record(AuditAction::LoginFailed, None, Some(&user.uuid))
+        .await;
+    if failure.locked_for.is_none() {
+        failed_login_delay(settings, failure.failed_logins).await;
+    }
+    failure.locked_for
+}
+
+/// Like `record_failed_login` for an identifier matching no account, so it is delayed and
+/// locked the same way.
+async fn record_unknown_login(
+    db: &Database,
+    settings: &Settings,
+    rate_limit: &LoginRateLimit<'_>,
+    audit: &Audit<'_>,
+    identifier: &str,
+    now: u64,
+) -> Option<u64> {
+    rate_limit.record_failure();
+    let lockout = (
+        settings.security.lockout_threshold,
+        settings.security.lockout_duration,
+    );
+    let failure = match db
+        .loginfailuremanager
+        .record_failure(identifier, lockout, now)
+        .await
+    {
+        Ok(failure) => failure,
+        Err(error) => {
+            error!(error = ?error, "Cannot count the failed login.");
+            FailedLogin {
+                failed_logins: rate_limit.failures(),
+                locked_for: None,
+            }
+        }
+    };
+    audit
+        .record(AuditAction::LoginFailed, None, Some(identifier))
+        .await;
+    if failure.locked_for.is_none() {
+        failed_login_delay(settings, failure.failed_logins).await;
+    }
+    failure.locked_for
+}
+
+/// What a login brings besides the password.
+enum SecondFactor<'a> {
+    Totp(Option<&'a str>),
+    RecoveryCode(&'a str), // Used up by the login
+}
+
+struct Credentials<'a> {
+    identifier: &'a str, // A username or an email
+    password: SecurePassword,
+    second_factor: SecondFactor<'a>,
+}
+
+/// Nothing to check for users without a second factor.
+async fn check_second_factor(
+    db: &Database,
+    settings: &Settings,
+    user: &user_model::User,
+    second_factor: SecondFactor<'_>,
+) -> Result<(), ApiError> {
+    match (user.has_totp(), &second_factor) {
+        (true, _) => {}
+        (false, SecondFactor::RecoveryCode(_)) => return Err(ApiError::TotpNotEnrolled),
+        (false, _) => return Ok(()),
+    }
+    let code = match second_factor {
+        SecondFactor::RecoveryCode(code) => {
+            // A single digest lookup, and only one of two concurrent logins with the code removes it
+            return match db.usermanager.use_recovery_code(&user.uuid, code).await {
+                Ok(result) if result.modified_count == 1 => Ok(()),
+                Ok(_) => Err(ApiError::InvalidRecoveryCode),
+                Err(error) => {
+                    error!(error = ?error, "Cannot use the recovery code.");
+                    Err(ApiError::from_db(&error))
+                }
+            };
+        }
+        SecondFactor::Totp(Some(code)) => code,
+        SecondFactor::Totp(None) => return Err(ApiError::TotpRequired),
+    };
+    check_totp_code(db, settings, user, code).await
+}
+
+/// Issue a token and a refresh token, the refresh token joins `family` when given.
+async fn new_session(
     db: &State<Database>,
-    input: Json<account_model::AccountCredentials>,
-) -> Result<Json<account_model::AccountTokenInfos>, account_errors::Error> {
-    match db.usermanager.get_user(Some(&input.username), None).await {
+    settings: &State<Settings>,
+    user: &mut user_model::User,
+    family: Option<String>,
+    client: &ClientInfo,
+) -> Result<response_model::LoginResponse, ApiError> {
+    let refresh_token = user.new_refresh_token(
+        settings.security.refresh_token_ttl,
+        family,
+        client.ip.clone(),
+        client.user_agent.clone(),
+    );
+    let token = user.new_session_token(
+        settings.security.token_ttl,
+        Some(refresh_token.family.clone()),
+        client.ip.clone(),
+        client.user_agent.clone(),
+    );
+    if let Err(error) = db
+        .usermanager
+        .save_token(&user.uuid, &token, settings.security.max_active_tokens)
+        .await
+    {
+        error!(uuid = user.uuid.as_str(), error = ?error, "Cannot save the session.");
+        return Err(ApiError::from_db(&error));
+    }
+    if let Err(error) = db
+        .usermanager
+        .save_refresh_token(&user.uuid, &refresh_token)
+        .await
+    {
+        error!(error = ?error, "Cannot save the refresh token.");
+        return Err(ApiError::from_db(&error));
+    }
+    let access_token = match &settings.security.jwt_secret {
+        Some(secret) => {
+            match jwt::encode_access_token(&user.uuid, settings.security.jwt_ttl, secret.as_bytes())
+            {
+                Ok(access_token) => Some(access_token),
+                Err(error) => {
+                    error!(error = ?error, "Cannot sign the access token.");
+                    None
+                }
+            }
+        }
+        None => None,
+    };
+    Ok(response_model::LoginResponse {
+        token: token.token,
+        timestamp: token.timestamp,
+        expiration_timestamp: token.expiration_timestamp,
+        uuid: user.uuid.clone(),
+        refresh_token: refresh_token.token,
+        access_token,
+    })
+}
+
+/// Check the password then the second factor, and open a session.
+async fn authenticate(
+    db: &State<Database>,
+    settings: &State<Settings>,
+    rate_limit: &LoginRateLimit<'_>,
+    audit: &Audit<'_>,
+    client: &ClientInfo,
+    credentials: Credentials<'_>,
+) -> Result<response_model::LoginResponse, ApiError> {
+    if let Some(retry_after) = rate_limit.retry_after() {
+        return Err(ApiError::TooManyRequests(retry_after));
+    }
+    let Credentials {
+        identifier,
+        password: input_password,
+        second_factor,
+    } = credentials;
+    // Refused before hashing, and not counted as a failed login
+    if let Err(violation) = settings
+        .security
+        .password_policy
+        .check_length(input_password.as_bytes())
+    {
+        return Err(ApiError::from_policy(violation));
+    }
+    // Accounts deleted within the grace period are restored by logging in
+    let deleted_since = match settings.security.deletion_grace_period {
+        0 => None,
+        grace => Some(get_current_timestamp().saturating_sub(grace.saturating_mul(1000))),
+    };
+    match db
+        .usermanager
+        .get_login_user(identifier, deleted_since)
+        .await
+    {
         Ok(mut user) => match &mut user {
             Some(user) => {
+                let now = get_current_timestamp();
+                let pepper = settings
+                    .security
+                    .password_pepper
+                    .as_ref()
+                    .map(|v| v.as_bytes());
+                if let Some(remaining) = user.lock_remaining(now) {
+                    // Same work and same answer as a locked unknown identifier
+                    Password::verify_dummy(
+                        &settings.security.argon2_params,
+                        pepper,
+                        input_password.as_bytes(),
+                    );
+                    // Not counted, so an attempt made during the lock cannot extend it
+                    rate_limit.record_failure();
+                    audit
+                        .record(AuditAction::LoginFailed, None, Some(&user.uuid))
+                        .await;
+                    return Err(ApiError::AccountLocked(remaining));
+                }
                 let password = user.password.as_ref();
-                if password.is_some()
-                    && password
-                        .unwrap()
-                        .is_correct_password(input.password.as_bytes())
+                let integrity = &settings.security.password_integrity;
+                if let Some(Err(error)) =
+                    password.map(|password| password.check_integrity(integrity))
                 {
-                    let token = user.new_token(TOKEN_DURATION);
-                    let _ = db.usermanager.save_token(&user.uuid, &token).await;
-                    return Ok(Json(account_model::AccountTokenInfos {
-                        token: token.token,
-                        timestamp: token.timestamp,
-                        expiration_timestamp: token.expiration_timestamp,
-                        uuid: user.uuid.clone(),
-                    }));
+                    // Would fail as a wrong password forever, and lock the user out
+                    error!("Corrupt stored password of {} [{}]", user.uuid, error);
+                    return Err(ApiError::CorruptCredentials);
+                }
+                let verified = match password {
+                    Some(password) => password.try_verify(pepper, input_password.as_bytes()),
+                    None => Ok(false),
+                };
+                if let Err(error) = &verified {
+                    // Not the user's fault, so not a failed login either
+                    error!("Cannot verify the password of {} [{}]", user.uuid, error);
+                    return Err(ApiError::CorruptCredentials);
+                }
+                if verified == Ok(true) {
+                    if let Err(error) = check_second_factor(db, settings, user, second_factor).await
+                    {
+                        // A missing code is the first step of the login, only a wrong one counts
+                        if let ApiError::InvalidTotp | ApiError::InvalidRecoveryCode = error {
+                            if let Some(locked_for) =
+                                record_failed_login(db, settings, rate_limit, audit, user, now)
+                                    .await
+                            {
+                                return Err(ApiError::AccountLocked(locked_for));
+                            }
+                        }
+                        return Err(error);
+                    }
+                    // Upgrades the cost as well as legacy Argon2i hashes
+                    if password
+                        .unwrap()
+                        .needs_rehash(&settings.security.argon2_params)
+                    {
+                        match Password::hash(
+                            &settings.security.argon2_params,
+                            pepper,
+                            input_password.as_bytes(),
+                        ) {
+                            Ok(rehashed) => {
+                                let result =
+                                    db.usermanager.set_password(&user.uuid, &rehashed).await;
+                                if let Err(error) = result {
+                                    error!(error = ?error, "Cannot save the password.");
+                                }
+                            }
+                            // The stored hash still verifies, the upgrade waits for the next login
+                            Err(error) => {
+                                error!(uuid = user.uuid.as_str(), error = %error, "Cannot hash the password.");
+                            }
+                        }
+                    }
+                    if user.deleted_at.is_some() {
+                        match db.usermanager.restore_user(&user.uuid).await {
+                            // Purged in the meantime
+                            Ok(result) if result.modified_count == 0 => {
+                                return Err(ApiError::InvalidCredentials);
+                            }
+                            Ok(_) => {
+                                user.deleted_at = None;
+                                audit
+                                    .record(
+                                        AuditAction::AccountRestored,
+                                        Some(&user.uuid),
+                                        Some(&user.uuid),
+                                    )
+                                    .await;
+                            }
+                            Err(error) => {
+                                error!(error = ?error, "Cannot restore the account.");
+                                return Err(ApiError::from_db(&error));
+                            }
+                        }
+                    }
+                    if user.failed_logins > 0 || user.locked_until > 0 {
+                        user.reset_failed_logins();
+                        if let Err(error) = db.usermanager.reset_login_failures(&user.uuid).await {
+                            error!(uuid = user.uuid.as_str(), error = ?error, "Cannot reset the failed logins.");
+                        }
+                    }
+                    user.last_login_at = Some(now);
+                    user.last_login_ip = client.ip.clone();
+                    if let Err(error) = db
+                        .usermanager
+                        .save_last_login(&user.uuid, now, client.ip.as_deref())
+                        .await
+                    {
+                        error!(uuid = user.uuid.as_str(), error = ?error, "Cannot save the last login.");
+                    }
+                    audit
+                        .record(AuditAction::Login, Some(&user.uuid), Some(&user.uuid))
+                        .await;
+                    return new_session(db, settings, user, None, client).await;
                 } else {
-                    return Err(account_errors::Error {
-                        content: account_model::AccountError::build(
-                            400,
-                            Some(format!("[{}]: Account has disabled login.", input.username)),
-                        ),
-                    });
+                    // Locked by this attempt, or by a concurrent one
+                    if let Some(locked_for) =
+                        record_failed_login(db, settings, rate_limit, audit, user, now).await
+                    {
+                        return Err(ApiError::AccountLocked(locked_for));
+                    }
+                    Err(ApiError::InvalidCredentials)
                 }
             }
             _ => {
-                return Err(account_errors::Error {
-                    content: account_model::AccountError::build(
-                        400,
-                        Some(format!(
-                            "[{}]: Username not related to any account.",
-                            input.username
-                        )),
-                    ),
-                })
+                // Same work and same answer as a wrong password, or a locked account
+                let pepper = settings
+                    .security
+                    .password_pepper
+                    .as_ref()
+                    .map(|v| v.as_bytes());
+                Password::verify_dummy(
+                    &settings.security.argon2_params,
+                    pepper,
+                    input_password.as_bytes(),
+                );
+                let now = get_current_timestamp();
+                if let Some(locked_for) =
+                    record_unknown_login(db, settings, rate_limit, audit, identifier, now).await
+                {
+                    return Err(ApiError::AccountLocked(locked_for));
+                }
+                Err(ApiError::InvalidCredentials)
+            }
+        },
+        Err(error) => {
+            error!(error = ?error, "Cannot find the user logging in.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+#[post("/login", data = "<input>")]
+pub async fn login(
+    db: &State<Database>,
+    settings: &State<Settings>,
+    rate_limit: LoginRateLimit<'_>,
+    audit: Audit<'_>,
+    client: ClientInfo,
+    input: JsonForm<request_model::Login>,
+) -> Result<ApiResponse<response_model::LoginResponse>, ApiError> {
+    let input = input.into_inner();
+    authenticate(
+        db,
+        settings,
+        &rate_limit,
+        &audit,
+        &client,
+        Credentials {
+            identifier: &input.identifier,
+            password: SecurePassword::from(input.password),
+            second_factor: SecondFactor::Totp(input.totp.as_deref()),
+        },
+    )
+    .await
+    .map(ApiResponse)
+}
+
+/// Instead of a code of the authenticator app, each recovery code works once.
+#[post("/login/recovery", data = "<input>")]
+pub async fn login_recovery(
+    db: &State<Database>,
+    settings: &State<Settings>,
+    rate_limit: LoginRateLimit<'_>,
+    audit: Audit<'_>,
+    client: ClientInfo,
+    input: JsonForm<request_model::RecoveryLogin>,
+) -> Result<ApiResponse<response_model::LoginResponse>, ApiError> {
+    let input = input.into_inner();
+    authenticate(
+        db,
+        settings,
+        &rate_limit,
+        &audit,
+        &client,
+        Credentials {
+            identifier: &input.identifier,
+            password: SecurePassword::from(input.password),
+            second_factor: SecondFactor::RecoveryCode(&input.recovery_code),
+        },
+    )
+    .await
+    .map(ApiResponse)
+}
+
+#[post("/api/account/refresh", data = "<input>")]
+pub async fn refresh(
+    db: &State<Database>,
+    settings: &State<Settings>,
+    client: ClientInfo,
+    input: Json<account_model::AccountToken>,
+) -> Result<ApiResponse<response_model::LoginResponse>, ApiError> {
+    match db
+        .usermanager
+        .get_user_from_refresh_token(&input.token)
+        .await
+    {
+        Ok(mut user) => match &mut user {
+            Some(user) => {
+                let hash = hash_token(&input.token);
+                // The document may have changed since it was looked up
+                let refresh_token = user
+                    .refresh_tokens
+                    .iter()
+                    .flatten()
+                    .find(|filter| constant_time_eq(filter.token.as_bytes(), hash.as_bytes()))
+                    .cloned()
+                    .ok_or_else(|| ApiError::InvalidToken(input.token.to_string()))?;
+                if refresh_token.expiration_timestamp < get_current_timestamp() {
+                    return Err(ApiError::InvalidToken(input.token.to_string()));
+                }
+                let rotated = match db.usermanager.use_refresh_token(&input.token).await {
+                    Ok(result) => result.modified_count == 1,
+                    Err(error) => {
+                        error!(error = ?error, "Cannot use the refresh token.");
+                        return Err(ApiError::from_db(&error));
+                    }
+                };
+                if !rotated {
+                    // Reused token, it may have been stolen: revoke the whole family
+                    if let Err(error) = db
+                        .usermanager
+                        .revoke_refresh_token_family(&user.uuid, &refresh_token.family)
+                        .await
+                    {
+                        error!(uuid = user.uuid.as_str(), error = ?error, "Cannot revoke the refresh token family.");
+                    }
+                    return Err(ApiError::TokenReused);
+                }
+                return new_session(db, settings, user, Some(refresh_token.family), &client)
+                    .await
+                    .map(ApiResponse);
+            }
+            _ => Err(ApiError::InvalidToken(input.token.to_string())),
+        },
+        Err(error) => {
+            error!(error = ?error, "Cannot find the user of the refresh token.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// Ends the session of the token, its refresh token included.
+#[post("/api/account/logout", data = "<input>")]
+pub async fn logout(
+    db: &State<Database>,
+    input: Json<account_model::AccountToken>,
+) -> Result<http::Status, ApiError> {
+    match db.usermanager.remove_token(&input.token).await {
+        Ok(true) => Ok(http::Status::NoContent),
+        Ok(false) => Err(ApiError::TokenNotFound(input.token.to_string())),
+        Err(error) => {
+            error!(error = ?error, "Cannot remove the token.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// The checks of `signup` without creating the user. Whether the username or the email
+/// is taken is only told when `MISATO_SIGNUP_VALIDATION_REPORTS_TAKEN` is set.
+#[post("/api/account/signup/validate", data = "<input>")]
+pub async fn validate_signup(
+    db: &State<Database>,
+    settings: &State<Settings>,
+    pwned: &State<PwnedPasswords>,
+    input: JsonForm<request_model::Signup>,
+) -> Result<ApiResponse<response_model::SignupValidation>, ApiError> {
+    let input = input.into_inner();
+    let reports_taken = settings.security.signup_validation_reports_taken;
+    let mut username = validate_username(&input.username)
+        .err()
+        .map(|error| error.to_string());
+    if username.is_none() && reports_taken {
+        match db.usermanager.username_exists(&input.username).await {
+            Ok(true) => username = Some(ApiError::UserExists(input.username.clone()).message()),
+            Ok(false) => {}
+            Err(error) => {
+                error!(error = ?error, "Cannot check the username.");
+                return Err(ApiError::from_db(&error));
             }
+        }
+    }
+    let password = SecurePassword::from(input.password);
+    let password = pwned
+        .validate(&settings.security.password_policy, password.as_bytes())
+        .await
+        .err()
+        .map(|violation| violation.to_string());
+    let mut email = None;
+    if let Some(address) = input.email {
+        if !validate_email(&address) {
+            email = Some(ApiError::InvalidEmail(address).message());
+        } else if reports_taken {
+            match db.usermanager.email_exists(&address).await {
+                Ok(true) => email = Some(ApiError::EmailExists(address).message()),
+                Ok(false) => {}
+                Err(error) => {
+                    error!(error = ?error, "Cannot check the email.");
+                    return Err(ApiError::from_db(&error));
+                }
+            }
+        }
+    }
+    Ok(ApiResponse(response_model::SignupValidation {
+        valid: username.is_none() && password.is_none() && email.is_none(),
+        username,
+        password,
+        email,
+    }))
+}
+
+/// Answers 202 whether the account exists or not. The reset token is only sent to the webhooks,
+/// as a `password.reset_requested` event, for the website to mail it to the user.
+#[post("/api/account/reset/request", data = "<input>")]
+pub async fn reset_request(
+    db: &State<Database>,
+    settings: &State<Settings>,
+    webhooks: Option<&State<Webhooks>>,
+    input: Json<request_model::ResetRequest>,
+) -> Result<http::Status, ApiError> {
+    let mut user = match db.usermanager.get_user(Some(&input.username), None).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Ok(http::Status::Accepted),
+        Err(error) => {
+            error!(error = ?error, "Cannot look the user up.");
+            return Err(ApiError::from_db(&error));
+        }
+    };
+    let token = user.new_reset_token(settings.security.reset_token_ttl);
+    let reset_token = user.reset_token.as_ref().unwrap();
+    if let Err(error) = db
+        .usermanager
+        .save_reset_token(&user.uuid, reset_token)
+        .await
+    {
+        // Still the same answer, an error would tell the account exists
+        error!(uuid = user.uuid.as_str(), error = ?error, "Cannot save the reset token.");
+        return Ok(http::Status::Accepted);
+    }
+    match webhooks {
+        Some(webhooks) => webhooks.send(
+            "password.reset_requested",
+            &TokenDelivery {
+                uuid: &user.uuid,
+                username: &user.username,
+                email: user.email.as_deref(),
+                token: &token,
+                expiration_timestamp: reset_token.expiration_timestamp,
+            },
+        ),
+        None => warn!(
+            uuid = user.uuid.as_str(),
+            "No webhook to send the reset token to."
+        ),
+    }
+    Ok(http::Status::Accepted)
+}
+
+#[post("/api/account/reset/confirm", data = "<input>")]
+pub async fn reset_confirm(
+    db: &State<Database>,
+    audit: Audit<'_>,
+    settings: &State<Settings>,
+    pwned: &State<PwnedPasswords>,
+    input: Json<request_model::ResetConfirm>,
+) -> Result<http::Status, ApiError> {
+    let input = input.into_inner();
+    let new_password = SecurePassword::from(input.new_password);
+    if let Err(violation) = pwned
+        .validate(&settings.security.password_policy, new_password.as_bytes())
+        .await
+    {
+        return Err(ApiError::from_policy(violation));
+    }
+    let password = match Password::hash(
+        &settings.security.argon2_params,
+        settings
+            .security
+            .password_pepper
+            .as_ref()
+            .map(|v| v.as_bytes()),
+        new_password.as_bytes(),
+    ) {
+        Ok(password) => password,
+        Err(error) => {
+            error!(error = %error, "Cannot hash the password.");
+            return Err(ApiError::InternalError);
+        }
+    };
+    match db
+        .usermanager
+        .reset_password(&hash_token(&input.token), &password)
+        .await
+    {
+        Ok(Some(user)) => {
+            audit
+                .record(AuditAction::PasswordReset, None, Some(&user.uuid))
+                .await;
+            Ok(http::Status::NoContent)
+        }
+        Ok(None) => Err(ApiError::InvalidToken(input.token.to_string())),
+        Err(error) => {
+            error!(error = ?error, "Cannot reset the password.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+#[get("/api/account/verify/confirm?<token>")]
+pub async fn verify_confirm(db: &State<Database>, token: &str) -> Result<http::Status, ApiError> {
+    match db.usermanager.verify_email(&hash_token(token)).await {
+        Ok(result) => match result.modified_count {
+            1 => Ok(http::Status::NoContent),
+            _ => Err(ApiError::InvalidToken(token.to_string())),
         },
         Err(error) => {
-            println!("{:?}", error);
-            return Err(account_errors::Error {
-                content: account_model::AccountError::build(
-                    500,
-                    Some("Database error.".to_string()),
-                ),
-            });
+            error!(error = ?error, "Cannot verify the email.");
+            Err(ApiError::from_db(&error))
         }
     }
 }
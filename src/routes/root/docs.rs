@@ -0,0 +1,951 @@
+use rocket::response::content::RawHtml;
+use rocket::serde::json::{json, Json, Value};
+use rocket::*;
+
+use misato_utils::settings::Settings;
+
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="utf-8">
+    <title>Misato API</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui" });</script>
+</body>
+</html>"##;
+
+/// Method, path, summary, security scheme, request body and response schemas.
+type Operation = (
+    &'static str,
+    &'static str,
+    &'static str,
+    Option<&'static str>,
+    Option<&'static str>,
+    Option<&'static str>,
+);
+
+/// Keep it in sync with the routes mounted in `lib.rs`, `tests/docs.rs` checks it.
+const OPERATIONS: &[Operation] = &[
+    (
+        "post",
+        "/login",
+        "Log in with a username or an email and a password",
+        None,
+        Some("Login"),
+        Some("LoginResponse"),
+    ),
+    (
+        "post",
+        "/login/recovery",
+        "Log in with a recovery code instead of an authenticator code, once per code",
+        None,
+        Some("RecoveryLogin"),
+        Some("LoginResponse"),
+    ),
+    (
+        "post",
+        "/api/account/refresh",
+        "Rotate a refresh token",
+        None,
+        Some("AccountToken"),
+        Some("LoginResponse"),
+    ),
+    (
+        "post",
+        "/api/account/logout",
+        "Revoke a token and its refresh token",
+        None,
+        Some("AccountToken"),
+        None,
+    ),
+    (
+        "post",
+        "/api/account/signup/validate",
+        "Check a signup without creating the user",
+        None,
+        Some("Signup"),
+        Some("SignupValidation"),
+    ),
+    (
+        "post",
+        "/api/account/reset/request",
+        "Send a password reset token to the webhooks",
+        None,
+        Some("ResetRequest"),
+        None,
+    ),
+    (
+        "post",
+        "/api/account/reset/confirm",
+        "Set a new password with a reset token",
+        None,
+        Some("ResetConfirm"),
+        None,
+    ),
+    (
+        "get",
+        "/api/account/verify/confirm",
+        "Confirm an email address",
+        None,
+        None,
+        None,
+    ),
+    ("get", "/health", "Service status", None, None, None),
+    ("get", "/health/db", "Database status", None, None, None),
+    (
+        "get",
+        "/metrics",
+        "Request counts and latencies, in the Prometheus text format",
+        None,
+        None,
+        None,
+    ),
+    ("get", "/openapi.json", "This document", None, None, None),
+    ("get", "/docs", "Swagger UI for this document", None, None, None),
+    (
+        "get",
+        "/api/meta",
+        "Settings for clients: password and username rules, registration, second factor",
+        None,
+        None,
+        Some("ServerMeta"),
+    ),
+    (
+        "post",
+        "/user/check-token",
+        "Check a user token",
+        Some("UserBearer"),
+        None,
+        Some("AccountTokenInfos"),
+    ),
+    (
+        "get",
+        "/user/account/token/info",
+        "Issue and expiration dates of a user token",
+        Some("UserBearer"),
+        None,
+        Some("TokenInfo"),
+    ),
+    (
+        "post",
+        "/user/delete",
+        "Delete the user, the token needs the `account:delete` scope",
+        Some("UserBearer"),
+        None,
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/user/clear-tokens",
+        "Remove every token of the user",
+        Some("UserBearer"),
+        None,
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/user/account/password",
+        "Change the password",
+        Some("UserBearer"),
+        Some("PasswordChange"),
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/user/account/username",
+        "Rename the user, the new name must be free",
+        Some("UserBearer"),
+        Some("UsernameChange"),
+        Some("PublicUser"),
+    ),
+    (
+        "post",
+        "/api/account/verify/request",
+        "Set an email address, its verification token goes to the webhooks",
+        Some("UserBearer"),
+        Some("VerifyRequest"),
+        None,
+    ),
+    (
+        "get",
+        "/user/email",
+        "Verified email address",
+        Some("UserBearer"),
+        None,
+        Some("Message"),
+    ),
+    (
+        "get",
+        "/user/account/me",
+        "Current user",
+        Some("UserBearer"),
+        None,
+        Some("PublicUser"),
+    ),
+    (
+        "get",
+        "/user/account/sessions",
+        "Sessions of the current user",
+        Some("UserBearer"),
+        None,
+        Some("Sessions"),
+    ),
+    (
+        "get",
+        "/user/account/export",
+        "Everything stored about the current user, without its secrets",
+        Some("UserBearer"),
+        None,
+        Some("UserExport"),
+    ),
+    (
+        "delete",
+        "/user/account/sessions/{id}",
+        "Revoke a session",
+        Some("UserBearer"),
+        None,
+        None,
+    ),
+    (
+        "get",
+        "/user/refresh-families",
+        "Refresh token families of the current user, one per login",
+        Some("UserBearer"),
+        None,
+        Some("RefreshFamilies"),
+    ),
+    (
+        "delete",
+        "/user/refresh-families/{id}",
+        "Revoke every refresh token of a family",
+        Some("UserBearer"),
+        None,
+        None,
+    ),
+    (
+        "post",
+        "/user/totp/enroll",
+        "Generate a second factor secret, enabled once a code is verified",
+        Some("UserBearer"),
+        None,
+        Some("TotpEnrollment"),
+    ),
+    (
+        "post",
+        "/user/totp/verify",
+        "Enable the second factor with a first code",
+        Some("UserBearer"),
+        Some("TotpCode"),
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/user/totp/recovery-codes",
+        "Replace the recovery codes with a code",
+        Some("UserBearer"),
+        Some("TotpCode"),
+        Some("RecoveryCodes"),
+    ),
+    (
+        "post",
+        "/user/totp/disable",
+        "Remove the second factor with a code",
+        Some("UserBearer"),
+        Some("TotpCode"),
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/admin/signup",
+        "Create a user",
+        Some("AdminToken"),
+        Some("Signup"),
+        Some("AccountTokenInfos"),
+    ),
+    (
+        "post",
+        "/admin/account/users/batch",
+        "Create several users, each with its own result",
+        Some("AdminToken"),
+        Some("BatchUsers"),
+        Some("BatchResults"),
+    ),
+    (
+        "post",
+        "/admin/profile",
+        "User from its uuid",
+        Some("AdminToken"),
+        Some("AccountUuid"),
+        Some("Account"),
+    ),
+    (
+        "post",
+        "/admin/profile-from-token",
+        "User from one of its tokens",
+        Some("AdminToken"),
+        Some("AccountToken"),
+        Some("Account"),
+    ),
+    (
+        "get",
+        "/admin/account/users/{username}",
+        "User from its username, with its login and lock state",
+        Some("AdminToken"),
+        None,
+        Some("UserDetails"),
+    ),
+    (
+        "get",
+        "/admin/account/users/{username}/export",
+        "Everything stored about a user, as it would export it",
+        Some("AdminToken"),
+        None,
+        Some("UserExport"),
+    ),
+    (
+        "get",
+        "/admin/account/users/{username}/refresh-families",
+        "Refresh token families of a user",
+        Some("AdminToken"),
+        None,
+        Some("RefreshFamilies"),
+    ),
+    (
+        "delete",
+        "/admin/refresh-families/{id}",
+        "Revoke a refresh token family, whichever user holds it",
+        Some("AdminToken"),
+        None,
+        None,
+    ),
+    (
+        "post",
+        "/admin/refresh-token",
+        "Issue a new user token",
+        Some("AdminToken"),
+        Some("AccountUuid"),
+        Some("AccountTokenInfos"),
+    ),
+    (
+        "post",
+        "/admin/check-token",
+        "Check a user token",
+        Some("AdminToken"),
+        Some("AccountToken"),
+        Some("AccountTokenInfos"),
+    ),
+    (
+        "post",
+        "/admin/delete",
+        "Delete a user",
+        Some("AdminToken"),
+        Some("AccountUuid"),
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/admin/restore",
+        "Restore a deleted user",
+        Some("AdminToken"),
+        Some("AccountUuid"),
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/admin/clear-tokens",
+        "Remove every token of a user",
+        Some("AdminToken"),
+        Some("AccountUuid"),
+        Some("Message"),
+    ),
+    (
+        "get",
+        "/admin/account/users",
+        "List users, by page or after a cursor",
+        Some("AdminToken"),
+        None,
+        Some("AccountsPage"),
+    ),
+    (
+        "get",
+        "/admin/audit",
+        "List security events, newest first",
+        Some("AdminToken"),
+        None,
+        Some("AuditPage"),
+    ),
+    (
+        "post",
+        "/admin/account/revoke-all-tokens",
+        "Revoke the tokens of every user, or of a role, or issued before a timestamp",
+        Some("AdminToken"),
+        Some("RevokeAllTokens"),
+        Some("Message"),
+    ),
+    (
+        "get",
+        "/admin/account/whoami",
+        "Check the calling admin token",
+        Some("AdminToken"),
+        None,
+        Some("AdminIdentity"),
+    ),
+    (
+        "post",
+        "/admin/account/rotate-token",
+        "Replace the token of the calling admin",
+        Some("AdminToken"),
+        None,
+        Some("AccountTokenInfos"),
+    ),
+    (
+        "post",
+        "/admin/invites",
+        "Issue a single-use signup invite",
+        Some("AdminToken"),
+        None,
+        Some("HashedTokenResponse"),
+    ),
+    (
+        "post",
+        "/admin/maintenance",
+        "Turn maintenance on or off, every route but health and admin ones answers 503 meanwhile",
+        Some("AdminToken"),
+        Some("MaintenanceToggle"),
+        Some("MaintenanceStatus"),
+    ),
+    (
+        "post",
+        "/api/v1/signup",
+        "Create the API account of a user, with an `invite` when registration is closed",
+        Some("UserToken"),
+        None,
+        Some("AccountTokenInfos"),
+    ),
+    (
+        "post",
+        "/api/v1/refresh-token",
+        "Issue a new API token",
+        Some("UserToken"),
+        None,
+        Some("AccountTokenInfos"),
+    ),
+    (
+        "post",
+        "/api/v1/check-token",
+        "Check the API token",
+        Some("ApiToken"),
+        None,
+        Some("AccountTokenInfos"),
+    ),
+    (
+        "post",
+        "/api/v1/delete",
+        "Delete the API account, the token needs the `account:delete` scope",
+        Some("ApiToken"),
+        None,
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/api/v1/clear-tokens",
+        "Remove the API token",
+        Some("ApiToken"),
+        None,
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/api/v1/keys",
+        "Create a named API key, limited to `scopes` when given, also accepted as `X-Misato-API-Token`",
+        Some("ApiToken"),
+        Some("ApiKeyCreate"),
+        Some("ApiKeyInfo"),
+    ),
+    (
+        "get",
+        "/api/v1/keys",
+        "List the named API keys, without their values",
+        Some("ApiToken"),
+        None,
+        Some("ApiKeys"),
+    ),
+    (
+        "delete",
+        "/api/v1/keys/{id}",
+        "Revoke a named API key",
+        Some("ApiToken"),
+        None,
+        None,
+    ),
+    (
+        "post",
+        "/api/v1/admin/signup",
+        "Create an API account",
+        Some("AdminToken"),
+        Some("ApiSignup"),
+        Some("AccountTokenInfos"),
+    ),
+    (
+        "post",
+        "/api/v1/admin/refresh-token",
+        "Issue a new API token",
+        Some("AdminToken"),
+        Some("AccountUuid"),
+        Some("AccountTokenInfos"),
+    ),
+    (
+        "post",
+        "/api/v1/admin/check-token",
+        "Check an API token",
+        Some("AdminToken"),
+        Some("AccountToken"),
+        Some("AccountTokenInfos"),
+    ),
+    (
+        "post",
+        "/api/v1/admin/delete",
+        "Delete an API account",
+        Some("AdminToken"),
+        Some("AccountUuid"),
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/api/v1/admin/clear-tokens",
+        "Remove an API token",
+        Some("AdminToken"),
+        Some("AccountUuid"),
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/api/v1/admin/role",
+        "Change the role of an API account",
+        Some("AdminToken"),
+        Some("ApiRoleChange"),
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/api/v1/admin/promote",
+        "Make an API account admin",
+        Some("AdminToken"),
+        Some("AccountUuid"),
+        Some("Message"),
+    ),
+    (
+        "post",
+        "/api/v1/admin/demote",
+        "Take the admin role from an API account, never from the last admin",
+        Some("AdminToken"),
+        Some("AccountUuid"),
+        Some("Message"),
+    ),
+];
+
+fn reference(schema: &str) -> Value {
+    json!({ "$ref": format!("#/components/schemas/{}", schema) })
+}
+
+fn object(properties: &[(&str, &str)]) -> Value {
+    let mut fields = serde_json::Map::new();
+    for (name, kind) in properties {
+        let schema = match *kind {
+            "string" | "boolean" => json!({ "type": kind }),
+            "integer" => json!({ "type": "integer", "format": "int64" }),
+            "role" => json!({ "type": "string", "enum": ["Admin", "Dev", "User"] }),
+            _ => reference(kind),
+        };
+        fields.insert(name.to_string(), schema);
+    }
+    let required: Vec<&str> = properties.iter().map(|(name, _)| *name).collect();
+    json!({ "type": "object", "properties": fields, "required": required })
+}
+
+/// Schema of an `ApiResponse<T>` holding a `data` schema.
+fn envelope(data: &str) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "data": reference(data),
+            "error": { "nullable": true, "enum": [null] },
+        },
+        "required": ["data", "error"],
+    })
+}
+
+/// Schema of a `Paginated<T>` holding `item` schemas.
+fn paginated(item: &str) -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "items": { "type": "array", "items": reference(item) },
+            "page": { "type": "integer", "format": "int64" },
+            "limit": { "type": "integer", "format": "int64" },
+            "total": { "type": "integer", "format": "int64" },
+            "has_next": { "type": "boolean" },
+        },
+        "required": ["items", "page", "limit", "total", "has_next"],
+    })
+}
+
+fn schemas() -> Value {
+    let mut schemas = json!({
+        "Message": { "type": "string" },
+        "Error": {
+            "type": "object",
+            "properties": {
+                "data": { "nullable": true, "enum": [null] },
+                "error": reference("ErrorBody"),
+            },
+            "required": ["data", "error"],
+        },
+        "ErrorBody": object(&[("code", "string"), ("message", "string")]),
+        "Account": object(&[("uuid", "string"), ("username", "string")]),
+        "AccountCredentials": object(&[("username", "string"), ("password", "string")]),
+        "AccountToken": object(&[("token", "string")]),
+        "AdminIdentity": object(&[("uuid", "string"), ("role", "role"), ("token_valid", "boolean")]),
+        "Login": {
+            "type": "object",
+            "properties": {
+                "identifier": { "type": "string" },
+                "password": { "type": "string" },
+                "totp": { "type": "string" },
+            },
+            "required": ["identifier", "password"],
+        },
+        "TotpCode": object(&[("code", "string")]),
+        "TotpEnrollment": object(&[
+            ("secret", "string"),
+            ("uri", "string"),
+            ("recovery_codes", "RecoveryCodes"),
+        ]),
+        "RecoveryCodes": { "type": "array", "items": { "type": "string" } },
+        "RecoveryLogin": object(&[
+            ("identifier", "string"),
+            ("password", "string"),
+            ("recovery_code", "string"),
+        ]),
+        "Signup": object(&[("username", "string"), ("password", "string"), ("email", "string")]),
+        "SignupValidation": object(&[
+            ("valid", "boolean"),
+            ("username", "string"),
+            ("password", "string"),
+            ("email", "string"),
+        ]),
+        "AccountUuid": object(&[("uuid", "string")]),
+        "AccountTokenInfos": object(&[
+            ("token", "string"),
+            ("timestamp", "integer"),
+            ("expiration_timestamp", "integer"),
+            ("uuid", "string"),
+        ]),
+        "LoginResponse": object(&[
+            ("token", "string"),
+            ("timestamp", "integer"),
+            ("expiration_timestamp", "integer"),
+            ("uuid", "string"),
+            ("refresh_token", "string"),
+        ]),
+        "TokenInfo": object(&[
+            ("username", "string"),
+            ("issued_at", "integer"),
+            ("expires_at", "integer"),
+            ("remaining_ttl", "integer"),
+        ]),
+        "Session": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "ip": { "type": "string" },
+                "user_agent": { "type": "string" },
+                "created_at": { "type": "integer", "format": "int64" },
+                "expires_at": { "type": "integer", "format": "int64" },
+            },
+            "required": ["created_at", "expires_at"],
+        },
+        "Sessions": { "type": "array", "items": reference("Session") },
+        "RefreshFamily": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "string" },
+                "created_at": { "type": "integer", "format": "int64" },
+                "last_rotated_at": { "type": "integer", "format": "int64", "nullable": true },
+                "expires_at": { "type": "integer", "format": "int64" },
+                "rotations": { "type": "integer", "format": "int64" },
+                "ip": { "type": "string", "nullable": true },
+                "user_agent": { "type": "string", "nullable": true },
+            },
+            "required": ["id", "created_at", "last_rotated_at", "expires_at", "rotations"],
+        },
+        "RefreshFamilies": { "type": "array", "items": reference("RefreshFamily") },
+        "PublicUser": object(&[
+            ("uuid", "string"),
+            ("username", "string"),
+            ("role", "string"),
+            ("created_at", "integer"),
+            ("email_verified", "boolean"),
+            ("last_login_at", "integer"),
+            ("last_login_ip", "string"),
+        ]),
+        "AccountPage": paginated("Account"),
+        "BatchUser": object(&[("username", "string"), ("password", "string"), ("role", "string")]),
+        "BatchUsers": { "type": "array", "items": reference("BatchUser") },
+        "BatchItem": {
+            "type": "object",
+            "properties": {
+                "username": { "type": "string" },
+                "status": { "type": "integer", "format": "int64" },
+                "uuid": { "type": "string" },
+                "error": { "type": "string" },
+                "message": { "type": "string" },
+            },
+            "required": ["username", "status"],
+        },
+        "BatchResults": { "type": "array", "items": reference("BatchItem") },
+        "AuditEvent": {
+            "type": "object",
+            "properties": {
+                "timestamp": { "type": "integer", "format": "int64" },
+                "action": { "type": "string" },
+                "actor": { "type": "string" },
+                "target": { "type": "string" },
+                "ip": { "type": "string" },
+                "request_id": { "type": "string" },
+            },
+            "required": ["timestamp", "action"],
+        },
+        "AuditPage": paginated("AuditEvent"),
+        "HashedTokenResponse": object(&[("token", "string"), ("expiration_timestamp", "integer")]),
+        "PasswordChange": object(&[("old_password", "string"), ("new_password", "string")]),
+        "UsernameChange": object(&[("username", "string")]),
+        "ResetRequest": object(&[("username", "string")]),
+        "MaintenanceToggle": object(&[("enabled", "boolean"), ("retry_after", "integer")]),
+        "MaintenanceStatus": object(&[("enabled", "boolean"), ("retry_after", "integer")]),
+        "ResetConfirm": object(&[("token", "string"), ("new_password", "string")]),
+        "VerifyRequest": object(&[("email", "string")]),
+        "ApiSignup": object(&[("uuid", "string"), ("role", "role")]),
+        "ApiRoleChange": object(&[("uuid", "string"), ("role", "role")]),
+    });
+    // Out of the literal above, which is as large as `json!` can expand
+    let scopes = json!({ "type": "array", "items": { "type": "string" } });
+    schemas["ApiKeyCreate"] = json!({
+        "type": "object",
+        "properties": {
+            "name": { "type": "string" },
+            "expires_in": { "type": "integer", "format": "int64" },
+            "scopes": scopes,
+        },
+        "required": ["name"],
+    });
+    // Both optional, `{}` revokes everything
+    schemas["RevokeAllTokens"] = json!({
+        "type": "object",
+        "properties": {
+            "role": { "type": "string", "enum": ["Admin", "User"] },
+            "before": { "type": "integer", "format": "int64" },
+        },
+    });
+    schemas["ApiKeyInfo"] = json!({
+        "type": "object",
+        "properties": {
+            "id": { "type": "string" },
+            "name": { "type": "string" },
+            "key": { "type": "string" },
+            "scopes": scopes,
+            "created_at": { "type": "integer", "format": "int64" },
+            "expires_at": { "type": "integer", "format": "int64" },
+        },
+        "required": ["id", "name", "scopes", "created_at"],
+    });
+    schemas["ApiKeys"] = json!({ "type": "array", "items": reference("ApiKeyInfo") });
+    schemas["Scopes"] = scopes;
+    schemas["PasswordRequirements"] = object(&[
+        ("min_length", "integer"),
+        ("max_length", "integer"),
+        ("require_lowercase", "boolean"),
+        ("require_uppercase", "boolean"),
+        ("require_digit", "boolean"),
+        ("require_symbol", "boolean"),
+    ]);
+    schemas["UsernameRequirements"] =
+        object(&[("min_length", "integer"), ("max_length", "integer")]);
+    schemas["ServerMeta"] = object(&[
+        ("registration_open", "boolean"),
+        ("totp_available", "boolean"),
+        ("access_tokens", "boolean"),
+        ("password", "PasswordRequirements"),
+        ("username", "UsernameRequirements"),
+        ("token_ttl", "integer"),
+        ("refresh_token_ttl", "integer"),
+        ("scopes", "Scopes"),
+    ]);
+    // Only present with `VALIDATION_FAILED`, the messages of each invalid field
+    schemas["ErrorBody"]["properties"]["fields"] = json!({
+        "type": "object",
+        "additionalProperties": { "type": "array", "items": { "type": "string" } },
+    });
+    schemas["UserDetails"] = object(&[
+        ("uuid", "string"),
+        ("username", "string"),
+        ("role", "string"),
+        ("created_at", "integer"),
+        ("email_verified", "boolean"),
+        ("last_login_at", "integer"),
+        ("last_login_ip", "string"),
+        ("email", "string"),
+        ("locked", "boolean"),
+        ("locked_until", "integer"),
+        ("failed_logins", "integer"),
+        ("token_count", "integer"),
+        ("totp_enabled", "boolean"),
+    ]);
+    for nullable in ["last_login_at", "last_login_ip"] {
+        schemas["PublicUser"]["properties"][nullable]["nullable"] = json!(true);
+    }
+    for nullable in ["last_login_at", "last_login_ip", "email", "locked_until"] {
+        schemas["UserDetails"]["properties"][nullable]["nullable"] = json!(true);
+    }
+    schemas["UserExport"] = json!({
+        "type": "object",
+        "properties": {
+            "exported_at": { "type": "integer", "format": "int64" },
+            "profile": reference("UserDetails"),
+            "sessions": reference("Sessions"),
+            "audit": { "type": "array", "items": reference("AuditEvent") },
+        },
+        "required": ["exported_at", "profile", "sessions", "audit"],
+    });
+    schemas["AccountCursorPage"] = json!({
+        "type": "object",
+        "properties": {
+            "items": { "type": "array", "items": reference("Account") },
+            "limit": { "type": "integer", "format": "int64" },
+            "next_cursor": { "type": "string", "nullable": true },
+        },
+        "required": ["items", "limit", "next_cursor"],
+    });
+    schemas["AccountsPage"] = json!({
+        "oneOf": [reference("AccountPage"), reference("AccountCursorPage")],
+    });
+    schemas
+}
+
+pub fn openapi(base_path: &str) -> Value {
+    let mut paths = Value::Object(serde_json::Map::new());
+    for (method, path, summary, security, request, response) in OPERATIONS {
+        let mut operation = json!({
+            "summary": summary,
+            "responses": {
+                "default": {
+                    "description": "Error",
+                    "content": { "application/json": { "schema": reference("Error") } },
+                },
+            },
+        });
+        let success = match response {
+            Some(schema) => json!({
+                "description": "Success",
+                "content": { "application/json": { "schema": envelope(schema) } },
+            }),
+            None => json!({ "description": "Success" }),
+        };
+        operation["responses"]["200"] = success;
+        if let Some(schema) = request {
+            operation["requestBody"] = json!({
+                "required": true,
+                "content": { "application/json": { "schema": reference(schema) } },
+            });
+        }
+        if let Some(scheme) = security {
+            operation["security"] = json!([{ *scheme: [] }]);
+        }
+        paths[*path][*method] = operation;
+    }
+    let page_parameters = json!([
+        { "name": "page", "in": "query", "schema": { "type": "integer", "minimum": 1 } },
+        { "name": "limit", "in": "query", "schema": { "type": "integer", "minimum": 1 } },
+    ]);
+    let mut users_parameters = page_parameters.clone();
+    users_parameters.as_array_mut().unwrap().push(json!({
+        "name": "cursor",
+        "in": "query",
+        "description": "Cursor pagination, empty for the first page, then the last `next_cursor`",
+        "schema": { "type": "string" },
+    }));
+    paths["/admin/account/users"]["get"]["parameters"] = users_parameters;
+    let mut audit_parameters = page_parameters.clone();
+    for (name, kind) in [
+        ("actor", "string"),
+        ("action", "string"),
+        ("from", "integer"),
+        ("to", "integer"),
+    ] {
+        audit_parameters
+            .as_array_mut()
+            .unwrap()
+            .push(json!({ "name": name, "in": "query", "schema": { "type": kind } }));
+    }
+    paths["/admin/audit"]["get"]["parameters"] = audit_parameters;
+    let username_parameter = json!([
+        { "name": "username", "in": "path", "required": true, "schema": { "type": "string" } },
+    ]);
+    paths["/admin/account/users/{username}"]["get"]["parameters"] = username_parameter.clone();
+    paths["/admin/account/users/{username}/export"]["get"]["parameters"] =
+        username_parameter.clone();
+    paths["/admin/account/users/{username}/refresh-families"]["get"]["parameters"] =
+        username_parameter;
+    paths["/api/v1/signup"]["post"]["parameters"] = json!([
+        { "name": "invite", "in": "query", "schema": { "type": "string" } },
+        {
+            "name": "X-Misato-Captcha-Token",
+            "in": "header",
+            "description": "Required when the server has CAPTCHA on",
+            "schema": { "type": "string" },
+        },
+    ]);
+    let id_parameter = json!([
+        { "name": "id", "in": "path", "required": true, "schema": { "type": "string" } },
+    ]);
+    paths["/user/account/sessions/{id}"]["delete"]["parameters"] = id_parameter.clone();
+    paths["/user/refresh-families/{id}"]["delete"]["parameters"] = id_parameter.clone();
+    paths["/admin/refresh-families/{id}"]["delete"]["parameters"] = id_parameter.clone();
+    paths["/api/v1/keys/{id}"]["delete"]["parameters"] = id_parameter;
+    paths["/api/account/verify/confirm"]["get"]["parameters"] = json!([
+        { "name": "token", "in": "query", "required": true, "schema": { "type": "string" } },
+    ]);
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Misato API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "servers": [{ "url": base_path }],
+        "paths": paths,
+        "components": {
+            "schemas": schemas(),
+            "securitySchemes": {
+                "AdminToken": { "type": "http", "scheme": "bearer" },
+                // A session token or a named key of the api account
+                "UserBearer": { "type": "http", "scheme": "bearer" },
+                "ApiToken": { "type": "apiKey", "in": "header", "name": "X-Misato-API-Token" },
+                "UserToken": { "type": "apiKey", "in": "header", "name": "X-Misato-User-Token" },
+            },
+        },
+    })
+}
+
+#[get("/openapi.json")]
+pub async fn openapi_json(settings: &State<Settings>) -> Json<Value> {
+    Json(openapi(&settings.http.base_path))
+}
+
+#[get("/docs")]
+pub async fn docs() -> RawHtml<&'static str> {
+    RawHtml(SWAGGER_UI)
+}
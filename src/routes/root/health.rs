@@ -0,0 +1,36 @@
+use std::time::Duration;
+
+use rocket::serde::json::{json, Json, Value};
+use rocket::*;
+use tracing::error;
+
+use misato_database::database::*;
+
+const DB_PING_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[get("/health")]
+pub async fn health() -> Json<Value> {
+    Json(json!({
+        "status": "ok",
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+    }))
+}
+
+#[get("/health/db")]
+pub async fn health_db(db: &State<Database>) -> (http::Status, Json<Value>) {
+    match tokio::time::timeout(DB_PING_TIMEOUT, db.ping()).await {
+        Ok(Ok(_)) => (http::Status::Ok, Json(json!({ "status": "ok" }))),
+        Ok(Err(error)) => {
+            error!(error = ?error, "Cannot reach the database.");
+            (
+                http::Status::ServiceUnavailable,
+                Json(json!({ "status": "unavailable" })),
+            )
+        }
+        Err(_) => (
+            http::Status::ServiceUnavailable,
+            Json(json!({ "status": "timeout" })),
+        ),
+    }
+}
@@ -0,0 +1,12 @@
+use rocket::*;
+
+use misato_database::models::response_model::ServerMeta;
+use misato_utils::settings::Settings;
+
+use crate::errors::api_response::ApiResponse;
+
+/// What frontends would otherwise hardcode, secrets are never part of it.
+#[get("/api/meta")]
+pub async fn meta(settings: &State<Settings>) -> ApiResponse<ServerMeta> {
+    ApiResponse(ServerMeta::from_settings(settings))
+}
@@ -0,0 +1,12 @@
+use rocket::http::ContentType;
+use rocket::*;
+
+use crate::fairings::metrics::Metrics;
+
+#[get("/metrics")]
+pub async fn metrics(metrics: &State<Metrics>) -> (ContentType, String) {
+    (
+        ContentType::new("text", "plain").with_params(("version", "0.0.4")),
+        metrics.render(),
+    )
+}
@@ -1 +1,5 @@
 pub mod account;
+pub mod docs;
+pub mod health;
+pub mod meta;
+pub mod metrics;
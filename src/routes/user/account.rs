@@ -1,128 +1,556 @@
 use rocket::serde::json::Json;
 use rocket::*;
+use tracing::{error, warn};
 
 use misato::models::account_model;
 
-use misato_database::database::*;
+use misato_database::{database::*, user_manager::UserError};
 
-use misato_database::models::user_model;
+use misato_database::models::{
+    audit_model::AuditAction,
+    request_model, response_model, scope_model,
+    user_model::{self, canonical_username},
+};
+use misato_security::{
+    password::{Password, SecurePassword},
+    totp,
+};
+use misato_utils::{
+    get_current_timestamp,
+    settings::{DeletionMode, Settings},
+    validation::{validate_email, validate_username},
+};
 
-use crate::errors::account_errors;
+use crate::errors::{api_errors::ApiError, api_response::ApiResponse};
 
-use crate::fairings::api_authentication::ApiUserToken;
+use crate::fairings::audit::Audit;
+use crate::fairings::authenticated_user::{AuthenticatedUser, Credential};
+use crate::fairings::authentication::VerifiedUser;
+use crate::fairings::scope::{AccountDelete, RequireScope};
+use crate::pwned::PwnedPasswords;
+use crate::webhooks::{TokenDelivery, Webhooks};
 
-async fn get_user(
-    api: ApiUserToken,
+/// Keys never expire without an `expiration_timestamp`, it is then `u64::MAX` here.
+#[post("/user/check-token")]
+pub async fn check_token(user: AuthenticatedUser) -> ApiResponse<account_model::AccountTokenInfos> {
+    let (timestamp, expiration_timestamp) = match &user.credential {
+        Credential::Session(session) => (session.timestamp, session.expiration_timestamp),
+        Credential::Key(key) => (key.timestamp, key.expiration_timestamp.unwrap_or(u64::MAX)),
+    };
+    ApiResponse(account_model::AccountTokenInfos {
+        token: user.token,
+        timestamp,
+        expiration_timestamp,
+        uuid: user.user.uuid,
+    })
+}
+
+/// Only for sessions, a key isn't one.
+#[get("/user/account/token/info")]
+pub async fn token_info(
+    user: AuthenticatedUser,
+) -> Result<ApiResponse<response_model::TokenInfo>, ApiError> {
+    let session = match &user.credential {
+        Credential::Session(session) => session,
+        Credential::Key(_) => return Err(ApiError::InvalidToken(user.token)),
+    };
+    match response_model::TokenInfo::new(&user.user.username, session, get_current_timestamp()) {
+        Some(info) => Ok(ApiResponse(info)),
+        None => Err(ApiError::InvalidToken(user.token)),
+    }
+}
+
+/// The bearer token needs the `account:delete` scope.
+#[post("/user/delete")]
+pub async fn delete(
+    user: AuthenticatedUser,
+    _scope: RequireScope<AccountDelete>,
+    audit: Audit<'_>,
     db: &State<Database>,
-    token: &String,
-) -> Result<user_model::User, account_errors::Error> {
-    let api = api.apiuser;
-
-    let result = db.usermanager.get_user(None, Some(&api.uuid)).await;
-    if result.is_ok() && result.as_ref().unwrap().is_none() {
-        return Err(account_errors::Error {
-            content: account_model::AccountError::build(
-                400,
-                Some(format!("[{}]: User account doesn't exist.", api.uuid)),
-            ),
-        });
-    }
-    let user = result.unwrap().unwrap();
-    if user.tokens.is_none() {
-        return Err(account_errors::Error {
-            content: account_model::AccountError::build(
-                400,
-                Some(format!("[{}]: Token not related to any account.", token)),
-            ),
-        });
-    }
-    let mut tokens = user.tokens.clone().unwrap();
-    tokens.retain(|filter| &filter.token == token);
-    if tokens.is_empty() {
-        return Err(account_errors::Error {
-            content: account_model::AccountError::build(
-                400,
-                Some(format!("[{}]: Token not related to any account.", token)),
-            ),
-        });
-    }
-    Ok(user)
-}
-
-#[post("/user/check-token", data = "<input>")]
-pub async fn check_token(
-    api: ApiUserToken,
+    settings: &State<Settings>,
+) -> Result<ApiResponse<String>, ApiError> {
+    if !user.allows(scope_model::ACCOUNT_DELETE) {
+        return Err(ApiError::MissingScope(
+            scope_model::ACCOUNT_DELETE.to_string(),
+        ));
+    }
+    let user = user.user;
+    let mode = settings.security.deletion_mode;
+    match db
+        .delete_account(&user.uuid, mode, &settings.security.anonymization_key)
+        .await
+    {
+        Ok(Some(id)) => {
+            let audit = match mode {
+                DeletionMode::Anonymize => audit.without_ip(),
+                DeletionMode::Delete => audit,
+            };
+            audit
+                .record(AuditAction::AccountDeleted, Some(&id), Some(&id))
+                .await;
+            Ok(ApiResponse(format!("[{}]: Account deleted.", user.uuid)))
+        }
+        Ok(None) => Err(ApiError::AccountNotFound(user.uuid)),
+        Err(error) => {
+            error!(error = ?error, "Cannot delete the account.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+#[post("/user/clear-tokens")]
+pub async fn clear_tokens(
+    user: AuthenticatedUser,
+    audit: Audit<'_>,
     db: &State<Database>,
-    input: Json<account_model::AccountToken>,
-) -> Result<Json<account_model::AccountTokenInfos>, account_errors::Error> {
-    match get_user(api, db, &input.token).await {
-        Ok(user) => {
-            let mut tokens = user.tokens.clone().unwrap();
-            tokens.retain(|filter| filter.token == input.token);
-            let token = tokens.get(0).unwrap();
-            return Ok(Json(account_model::AccountTokenInfos {
-                token: token.token.clone(),
-                timestamp: token.timestamp,
-                expiration_timestamp: token.expiration_timestamp,
-                uuid: user.uuid,
-            }));
+) -> Result<ApiResponse<String>, ApiError> {
+    let token = user.token;
+    let user = user.user;
+    match db.usermanager.clear_tokens(&user.uuid).await {
+        Ok(_) => {
+            audit
+                .record(
+                    AuditAction::TokensCleared,
+                    Some(&user.uuid),
+                    Some(&user.uuid),
+                )
+                .await;
+            Ok(ApiResponse(format!("[{}]: Tokens removed.", token)))
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot clear the tokens.");
+            Err(ApiError::from_db(&error))
         }
-        Err(err) => return Err(err),
     }
 }
 
-#[post("/user/delete", data = "<input>")]
-pub async fn delete(
-    api: ApiUserToken,
+#[post("/user/account/password", data = "<input>")]
+pub async fn change_password(
+    user: AuthenticatedUser,
+    audit: Audit<'_>,
     db: &State<Database>,
-    input: Json<account_model::AccountToken>,
-) -> Result<Json<String>, account_errors::Error> {
-    match get_user(api, db, &input.token).await {
-        Ok(user) => {
-            match db
-                .apiusermanager
-                .delete_apiuser_from_token(&input.token)
-                .await
-            {
-                Ok(_) => {
-                    return Ok(Json(format!("[{}]: Account deleted.", user.uuid)));
-                }
-                Err(error) => {
-                    println!("{:?}", error);
-                    return Err(account_errors::Error {
-                        content: account_model::AccountError::build(
-                            500,
-                            Some("Database error.".to_string()),
-                        ),
-                    });
-                }
-            }
+    settings: &State<Settings>,
+    pwned: &State<PwnedPasswords>,
+    input: Json<request_model::PasswordChange>,
+) -> Result<ApiResponse<String>, ApiError> {
+    let input = input.into_inner();
+    let old_password = SecurePassword::from(input.old_password);
+    let new_password = SecurePassword::from(input.new_password);
+    let user = user.user;
+    let policy = &settings.security.password_policy;
+    if let Err(violation) = policy.check_length(old_password.as_bytes()) {
+        return Err(ApiError::from_policy(violation));
+    }
+    let pepper = settings
+        .security
+        .password_pepper
+        .as_ref()
+        .map(|v| v.as_bytes());
+    let integrity = &settings.security.password_integrity;
+    if let Some(Err(error)) = user
+        .password
+        .as_ref()
+        .map(|password| password.check_integrity(integrity))
+    {
+        error!("Corrupt stored password of {} [{}]", user.uuid, error);
+        return Err(ApiError::CorruptCredentials);
+    }
+    let verified = match &user.password {
+        Some(password) => password.try_verify(pepper, old_password.as_bytes()),
+        None => Ok(false),
+    };
+    match verified {
+        Ok(true) => {}
+        Ok(false) => return Err(ApiError::InvalidCredentials),
+        Err(error) => {
+            error!("Cannot verify the password of {} [{}]", user.uuid, error);
+            return Err(ApiError::CorruptCredentials);
+        }
+    }
+    if let Err(violation) = pwned.validate(policy, new_password.as_bytes()).await {
+        return Err(ApiError::from_policy(violation));
+    }
+    let password = match Password::hash(
+        &settings.security.argon2_params,
+        pepper,
+        new_password.as_bytes(),
+    ) {
+        Ok(password) => password,
+        Err(error) => {
+            error!(uuid = user.uuid.as_str(), error = %error, "Cannot hash the password.");
+            return Err(ApiError::InternalError);
+        }
+    };
+    if let Err(error) = db.usermanager.set_password(&user.uuid, &password).await {
+        error!(error = ?error, "Cannot save the password.");
+        return Err(ApiError::from_db(&error));
+    }
+    if settings.security.password_change_clears_tokens {
+        if let Err(error) = db.usermanager.clear_tokens(&user.uuid).await {
+            error!(error = ?error, "Cannot clear the tokens.");
+            return Err(ApiError::from_db(&error));
         }
-        Err(err) => return Err(err),
     }
+    audit
+        .record(
+            AuditAction::PasswordChange,
+            Some(&user.uuid),
+            Some(&user.uuid),
+        )
+        .await;
+    Ok(ApiResponse("Password changed.".to_string()))
 }
 
-#[post("/user/clear-tokens", data = "<input>")]
-pub async fn clear_tokens(
-    api: ApiUserToken,
+#[post("/user/account/username", data = "<input>")]
+pub async fn change_username(
+    user: AuthenticatedUser,
+    audit: Audit<'_>,
     db: &State<Database>,
-    input: Json<account_model::AccountToken>,
-) -> Result<Json<String>, account_errors::Error> {
-    match get_user(api, db, &input.token).await {
-        Ok(_) => match db.apiusermanager.clear_tokens(&input.token).await {
-            Ok(_) => {
-                return Ok(Json(format!("[{}]: Tokens removed.", input.token)));
-            }
+    input: Json<request_model::UsernameChange>,
+) -> Result<ApiResponse<response_model::PublicUser>, ApiError> {
+    let input = input.into_inner();
+    let mut user = user.user;
+    if let Err(error) = validate_username(&input.username) {
+        return Err(ApiError::ValidationError(error.to_string()));
+    }
+    // Changing only the case, the name is already its own
+    if canonical_username(&input.username) != canonical_username(&user.username) {
+        match db.usermanager.username_exists(&input.username).await {
+            Ok(true) => return Err(ApiError::UserExists(input.username)),
+            Ok(false) => {}
             Err(error) => {
-                println!("{:?}", error);
-                return Err(account_errors::Error {
-                    content: account_model::AccountError::build(
-                        500,
-                        Some("Database error.".to_string()),
-                    ),
-                });
+                error!(error = ?error, "Cannot check the username.");
+                return Err(ApiError::from_db(&error));
             }
+        }
+    }
+    // Taken since the check
+    match db
+        .usermanager
+        .set_username(&user.uuid, &input.username)
+        .await
+    {
+        Ok(_) => {}
+        Err(UserError::AlreadyExists) => return Err(ApiError::UserExists(input.username)),
+        Err(error) => {
+            error!(error = ?error, "Cannot change the username.");
+            return Err(ApiError::from_db(&error));
+        }
+    }
+    audit
+        .record(
+            AuditAction::UsernameChange,
+            Some(&user.uuid),
+            Some(&user.uuid),
+        )
+        .await;
+    user.username = input.username;
+    Ok(ApiResponse(response_model::PublicUser::from(&user)))
+}
+
+/// Answers 202, the verification token is only sent to the webhooks, as an
+/// `email.verification_requested` event, for the website to mail it to the new address.
+#[post("/api/account/verify/request", data = "<input>")]
+pub async fn verify_request(
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    settings: &State<Settings>,
+    webhooks: Option<&State<Webhooks>>,
+    input: Json<request_model::VerifyRequest>,
+) -> Result<http::Status, ApiError> {
+    let input = input.into_inner();
+    let mut user = user.user;
+    if !validate_email(&input.email) {
+        return Err(ApiError::InvalidEmail(input.email));
+    }
+    let token = user.new_verification_token(input.email, settings.security.verification_token_ttl);
+    let verification_token = user.verification_token.as_ref().unwrap();
+    match db
+        .usermanager
+        .save_verification_token(&user.uuid, user.email.as_ref().unwrap(), verification_token)
+        .await
+    {
+        Ok(_) => {}
+        Err(UserError::AlreadyExists) => return Err(ApiError::EmailExists(user.email.unwrap())),
+        Err(error) => {
+            error!(uuid = user.uuid.as_str(), error = ?error, "Cannot save the verification token.");
+            return Err(ApiError::from_db(&error));
+        }
+    }
+    match webhooks {
+        Some(webhooks) => webhooks.send(
+            "email.verification_requested",
+            &TokenDelivery {
+                uuid: &user.uuid,
+                username: &user.username,
+                email: user.email.as_deref(),
+                token: &token,
+                expiration_timestamp: verification_token.expiration_timestamp,
+            },
+        ),
+        None => warn!(
+            uuid = user.uuid.as_str(),
+            "No webhook to send the verification token to."
+        ),
+    }
+    Ok(http::Status::Accepted)
+}
+
+/// Only answers for verified users.
+#[get("/user/email")]
+pub async fn email(verified: VerifiedUser) -> ApiResponse<String> {
+    ApiResponse(verified.user.email.unwrap_or_default())
+}
+
+#[get("/user/account/me")]
+pub async fn me(user: AuthenticatedUser) -> ApiResponse<response_model::PublicUser> {
+    ApiResponse(response_model::PublicUser::from(&user.user))
+}
+
+#[get("/user/account/sessions")]
+pub async fn sessions(user: AuthenticatedUser) -> ApiResponse<Vec<response_model::Session>> {
+    let sessions = match &user.user.tokens {
+        Some(tokens) => tokens.iter().map(response_model::Session::from).collect(),
+        None => Vec::new(),
+    };
+    ApiResponse(sessions)
+}
+
+/// Everything stored about an account, audit events included, shared with the admin export.
+pub async fn export_data(
+    db: &Database,
+    user: &user_model::User,
+) -> Result<ApiResponse<response_model::UserExport>, ApiError> {
+    match db
+        .auditmanager
+        .list_events_about(&user.uuid, &user.username)
+        .await
+    {
+        Ok(events) => {
+            let now = get_current_timestamp();
+            Ok(ApiResponse(response_model::UserExport::new(
+                user, events, now,
+            )))
+        }
+        Err(error) => {
+            error!(error = ?error, "Cannot list the audit events.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// What a data subject request returns, never the password or the tokens.
+#[get("/user/account/export")]
+pub async fn export(
+    user: AuthenticatedUser,
+    db: &State<Database>,
+) -> Result<ApiResponse<response_model::UserExport>, ApiError> {
+    export_data(db, &user.user).await
+}
+
+#[delete("/user/account/sessions/<id>")]
+pub async fn revoke_session(
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    id: &str,
+) -> Result<http::Status, ApiError> {
+    match db.usermanager.remove_session(&user.user.uuid, id).await {
+        Ok(true) => Ok(http::Status::NoContent),
+        Ok(false) => Err(ApiError::TokenNotFound(id.to_string())),
+        Err(error) => {
+            error!(error = ?error, "Cannot remove the session.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// The refresh token families of the current user, one per login still able to refresh.
+#[get("/user/refresh-families")]
+pub async fn refresh_families(
+    user: AuthenticatedUser,
+) -> ApiResponse<Vec<response_model::RefreshFamily>> {
+    let families = match &user.user.refresh_tokens {
+        Some(tokens) => response_model::RefreshFamily::list(tokens),
+        None => Vec::new(),
+    };
+    ApiResponse(families)
+}
+
+/// Every refresh token of the family is dropped, the ones it was rotated from included.
+#[delete("/user/refresh-families/<id>")]
+pub async fn revoke_refresh_family(
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    id: &str,
+) -> Result<http::Status, ApiError> {
+    match db
+        .usermanager
+        .revoke_refresh_token_family(&user.user.uuid, id)
+        .await
+    {
+        Ok(result) => match result.modified_count {
+            1 => Ok(http::Status::NoContent),
+            _ => Err(ApiError::TokenNotFound(id.to_string())),
         },
-        Err(err) => return Err(err),
+        Err(error) => {
+            error!(error = ?error, "Cannot revoke the refresh token family.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// Check `code` against the enrolled secret of the user, verified or not. A code is accepted once.
+pub(crate) async fn check_totp_code(
+    db: &Database,
+    settings: &Settings,
+    user: &user_model::User,
+    code: &str,
+) -> Result<(), ApiError> {
+    let key = match &settings.security.totp_key {
+        Some(key) => key,
+        None => return Err(ApiError::TotpUnavailable),
+    };
+    let enrolled = match &user.totp {
+        Some(enrolled) => enrolled,
+        None => return Err(ApiError::TotpNotEnrolled),
+    };
+    let secret = match totp::decrypt_secret(key, &enrolled.secret) {
+        Some(secret) => secret,
+        None => {
+            error!("Cannot decrypt the second factor of {}", user.uuid);
+            return Err(ApiError::CorruptCredentials);
+        }
+    };
+    let now = get_current_timestamp() / 1000;
+    let step = match totp::verify(
+        &secret,
+        code,
+        now,
+        settings.security.totp_skew,
+        enrolled.last_step,
+    ) {
+        Some(step) => step,
+        None => return Err(ApiError::InvalidTotp),
+    };
+    // Only one of two concurrent requests with the same code stores its step
+    match db.usermanager.use_totp_step(&user.uuid, step).await {
+        Ok(result) if result.modified_count == 1 => Ok(()),
+        Ok(_) => Err(ApiError::InvalidTotp),
+        Err(error) => {
+            error!(error = ?error, "Cannot use the authenticator code.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// Start over with a new secret, login only asks for codes once one has been verified.
+#[post("/user/totp/enroll")]
+pub async fn totp_enroll(
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    settings: &State<Settings>,
+) -> Result<ApiResponse<response_model::TotpEnrollment>, ApiError> {
+    let user = user.user;
+    let key = match &settings.security.totp_key {
+        Some(key) => key,
+        None => return Err(ApiError::TotpUnavailable),
+    };
+    if user.has_totp() {
+        return Err(ApiError::TotpEnabled);
+    }
+    let secret = totp::generate_secret();
+    let (recovery_codes, stored_codes) =
+        user_model::UserRecoveryCode::generate(user_model::RECOVERY_CODES_COUNT);
+    let enrolled = user_model::UserTotp {
+        secret: totp::encrypt_secret(key, &secret),
+        enabled: false,
+        timestamp: get_current_timestamp(),
+        recovery_codes: stored_codes,
+        last_step: None,
+    };
+    if let Err(error) = db.usermanager.set_totp(&user.uuid, &enrolled).await {
+        error!(error = ?error, "Cannot save the authenticator.");
+        return Err(ApiError::from_db(&error));
+    }
+    Ok(ApiResponse(response_model::TotpEnrollment {
+        secret: totp::encode_secret(&secret),
+        uri: totp::provisioning_uri(&secret, &settings.security.totp_issuer, &user.username),
+        recovery_codes,
+    }))
+}
+
+/// A first code proves the app holds the secret.
+#[post("/user/totp/verify", data = "<input>")]
+pub async fn totp_verify(
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    settings: &State<Settings>,
+    input: Json<request_model::TotpCode>,
+) -> Result<ApiResponse<String>, ApiError> {
+    let user = user.user;
+    check_totp_code(db, settings, &user, &input.code).await?;
+    match db.usermanager.enable_totp(&user.uuid).await {
+        Ok(_) => Ok(ApiResponse(
+            "Two factor authentication enabled.".to_string(),
+        )),
+        Err(error) => {
+            error!(error = ?error, "Cannot enable two factor authentication.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// A stolen session alone can't remove the second factor, a code is required.
+#[post("/user/totp/disable", data = "<input>")]
+pub async fn totp_disable(
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    settings: &State<Settings>,
+    input: Json<request_model::TotpCode>,
+) -> Result<ApiResponse<String>, ApiError> {
+    let user = user.user;
+    if !user.has_totp() {
+        return Err(ApiError::TotpNotEnrolled);
+    }
+    check_totp_code(db, settings, &user, &input.code).await?;
+    match db.usermanager.remove_totp(&user.uuid).await {
+        Ok(_) => Ok(ApiResponse(
+            "Two factor authentication disabled.".to_string(),
+        )),
+        Err(error) => {
+            error!(error = ?error, "Cannot disable two factor authentication.");
+            Err(ApiError::from_db(&error))
+        }
+    }
+}
+
+/// Replace every recovery code, the former ones stop working.
+#[post("/user/totp/recovery-codes", data = "<input>")]
+pub async fn totp_recovery_codes(
+    user: AuthenticatedUser,
+    db: &State<Database>,
+    settings: &State<Settings>,
+    input: Json<request_model::TotpCode>,
+) -> Result<ApiResponse<Vec<String>>, ApiError> {
+    let user = user.user;
+    if !user.has_totp() {
+        return Err(ApiError::TotpNotEnrolled);
+    }
+    check_totp_code(db, settings, &user, &input.code).await?;
+    let (codes, stored_codes) =
+        user_model::UserRecoveryCode::generate(user_model::RECOVERY_CODES_COUNT);
+    match db
+        .usermanager
+        .set_recovery_codes(&user.uuid, &stored_codes)
+        .await
+    {
+        Ok(result) => match result.modified_count {
+            1 => Ok(ApiResponse(codes)),
+            _ => Err(ApiError::TotpNotEnrolled),
+        },
+        Err(error) => {
+            error!(error = ?error, "Cannot save the recovery codes.");
+            Err(ApiError::from_db(&error))
+        }
     }
 }
@@ -0,0 +1,114 @@
+use std::time::Duration;
+
+use rocket::tokio;
+use serde::Serialize;
+use tracing::{error, warn};
+
+use misato_database::{database::retry_with_backoff, models::audit_model::AuditEvent};
+use misato_security::sign_payload;
+use misato_utils::{get_current_timestamp, settings::Settings};
+
+const TIMEOUT: Duration = Duration::from_secs(10); // Per attempt, a hung receiver is retried
+/// Hex encoded HMAC-SHA256 of the body, keyed with the webhook secret.
+pub const SIGNATURE_HEADER: &str = "X-Signature";
+
+/// The signed body. Receivers should refuse a `timestamp` older than a few minutes,
+/// so a captured delivery can't be replayed later.
+#[derive(Serialize)]
+pub struct WebhookPayload<'a, T: Serialize> {
+    pub event: &'a str, // Like `user.created`
+    pub timestamp: u64, // In milliseconds, when it was first sent, kept by the retries
+    pub data: &'a T,    // An `AuditEvent`, or a `TokenDelivery`
+}
+
+/// A token the website sends to its user, the API never answers it to the requester.
+#[derive(Serialize)]
+pub struct TokenDelivery<'a> {
+    pub uuid: &'a str,
+    pub username: &'a str,
+    pub email: Option<&'a str>,
+    pub token: &'a str,
+    pub expiration_timestamp: u64,
+}
+
+/// Posts account events to every configured url.
+#[derive(Clone)]
+pub struct Webhooks {
+    client: reqwest::Client,
+    urls: Vec<String>,
+    secret: String,
+    max_attempts: u32,
+    retry_delay: u64, // In milliseconds, doubled after each attempt
+}
+
+impl Webhooks {
+    pub fn new(urls: Vec<String>, secret: String, max_attempts: u32, retry_delay: u64) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            urls,
+            secret,
+            max_attempts,
+            retry_delay,
+        }
+    }
+
+    /// None when there is no url to send to.
+    pub fn from_settings(settings: &Settings) -> Option<Self> {
+        if settings.webhooks.urls.is_empty() {
+            return None;
+        }
+        Some(Self::new(
+            settings.webhooks.urls.clone(),
+            settings.webhooks.secret.clone()?,
+            settings.webhooks.max_attempts,
+            500,
+        ))
+    }
+
+    /// Deliveries run in the background, a slow or failing receiver never delays the request.
+    pub fn dispatch(&self, event: &AuditEvent) {
+        if let Some(name) = event.action.webhook_event() {
+            self.send(name, event);
+        }
+    }
+
+    /// Like `dispatch`, for what isn't an audit event.
+    pub fn send<T: Serialize>(&self, name: &'static str, data: &T) {
+        let body = match serde_json::to_vec(&WebhookPayload {
+            event: name,
+            timestamp: get_current_timestamp(),
+            data,
+        }) {
+            Ok(body) => body,
+            Err(error) => {
+                error!(error = ?error, "Cannot serialize the webhook payload.");
+                return;
+            }
+        };
+        let signature = sign_payload(self.secret.as_bytes(), &body);
+        for url in &self.urls {
+            let client = self.client.clone();
+            let url = url.clone();
+            let body = body.clone();
+            let signature = signature.clone();
+            let (max_attempts, retry_delay) = (self.max_attempts, self.retry_delay);
+            tokio::spawn(async move {
+                let result = retry_with_backoff(max_attempts, retry_delay, |_| {
+                    let request = client
+                        .post(&url)
+                        .header(reqwest::header::CONTENT_TYPE, "application/json")
+                        .header(SIGNATURE_HEADER, &signature)
+                        .body(body.clone());
+                    async move { request.send().await?.error_for_status() }
+                })
+                .await;
+                if let Err(error) = result {
+                    warn!(event = name, url = url.as_str(), error = ?error, "Cannot deliver the webhook.");
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,2689 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use rocket::http::{ContentType, Header, Method, Status};
+use rocket::local::asynchronous::Client;
+use rocket::tokio::net::TcpListener;
+use serde_json::{json, Value};
+
+use misato_api::fairings::token_purge::purge;
+use misato_database::{
+    database::Database,
+    models::{
+        apiuser_model::ApiUserRoleType,
+        audit_model::{AuditAction, AuditEvent, AuditFilter},
+        user_model::{anonymous_id, anonymous_username, User, UserHashedToken},
+    },
+    user_manager::UserError,
+};
+use misato_security::{hash_token, password::Password, totp};
+use misato_utils::{get_current_timestamp, settings::Settings};
+
+use common::webhook::{header, receive};
+use common::{data, test_rocket, test_rocket_first_user_admin, test_rocket_with, TestRocket};
+
+#[rocket::async_test]
+async fn signup_then_login() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let credentials = json!({ "username": "misato", "password": "anypassword" }).to_string();
+
+    let response = client
+        .post("/admin/signup")
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .body(&credentials)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let signup: Value = data(response).await;
+
+    let response = client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(&credentials)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let login: Value = data(response).await;
+    assert_eq!(login["uuid"], signup["uuid"]);
+    // 32 random bytes, base64url encoded
+    for token in [&login["token"], &login["refresh_token"], &signup["token"]] {
+        let token = token.as_str().unwrap();
+        assert_eq!(token.len(), 43);
+        assert!(token
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_'));
+    }
+
+    let response = client
+        .get("/user/account/me")
+        .header(bearer(login["token"].as_str().unwrap().to_string()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let me: Value = data(response).await;
+    assert_eq!(me["username"], "misato");
+
+    let response = client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "misato", "password": "wrongpassword" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn rotated_admin_token_replaces_the_old_one() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let bearer = |token: &str| Header::new("Authorization", format!("Bearer {}", token));
+
+    let response = client
+        .post("/admin/account/rotate-token")
+        .header(bearer(&rocket.admin_token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let rotated: Value = data(response).await;
+    let new_token = rotated["token"].as_str().unwrap();
+
+    let response = client
+        .get("/admin/account/users")
+        .header(bearer(&rocket.admin_token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    let response = client
+        .get("/admin/account/users")
+        .header(bearer(new_token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn booting_again_keeps_the_default_admin() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let response = rocket
+        .client
+        .post("/admin/account/rotate-token")
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .dispatch()
+        .await;
+    let rotated: Value = data(response).await;
+    let rotated = rotated["token"].as_str().unwrap();
+
+    // The configured token is still the old one, it doesn't overwrite the rotated one
+    let rebooted = rocket.reboot(|_| {}).await;
+    assert_eq!(api_user_count(&rebooted).await, 1);
+    assert_eq!(admin_status(&rebooted, rotated).await, Status::Ok);
+    let old = admin_status(&rebooted, &rocket.admin_token).await;
+    assert_eq!(old, Status::Unauthorized);
+
+    let reset = rocket
+        .reboot(|settings| settings.security.reset_admin = true)
+        .await;
+    assert_eq!(api_user_count(&reset).await, 1);
+    let restored = admin_status(&reset, &rocket.admin_token).await;
+    assert_eq!(restored, Status::Ok);
+    assert_eq!(admin_status(&reset, rotated).await, Status::Unauthorized);
+}
+
+async fn api_user_count(client: &Client) -> u64 {
+    let database = client.rocket().state::<Database>().unwrap();
+    let apiusers = &database.apiusermanager.apiusers;
+    apiusers.count_documents(None, None).await.unwrap()
+}
+
+async fn admin_status(client: &Client, token: &str) -> Status {
+    client
+        .get("/admin/account/users")
+        .header(Header::new("Authorization", format!("Bearer {}", token)))
+        .dispatch()
+        .await
+        .status()
+}
+
+#[rocket::async_test]
+async fn whoami_checks_the_admin_token() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let bearer = |token: &str| Header::new("Authorization", format!("Bearer {}", token));
+
+    let response = rocket
+        .client
+        .get("/admin/account/whoami")
+        .header(bearer(&rocket.admin_token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let identity: Value = data(response).await;
+    assert_eq!(
+        identity,
+        json!({ "uuid": "admin", "role": "Admin", "token_valid": true })
+    );
+
+    let response = rocket
+        .client
+        .get("/admin/account/whoami")
+        .header(bearer("not the admin token"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+/// How the user routes take a session token or a key.
+fn bearer(token: impl std::fmt::Display) -> Header<'static> {
+    Header::new("Authorization", format!("Bearer {}", token))
+}
+
+/// Create a user through the admin, then log it in, returns its user token.
+async fn user_token(rocket: &TestRocket, username: &str) -> String {
+    let credentials = json!({ "username": username, "password": "anypassword" }).to_string();
+    let response = rocket
+        .client
+        .post("/admin/signup")
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .body(&credentials)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let signup: Value = data(response).await;
+    signup["token"].as_str().unwrap().to_string()
+}
+
+#[rocket::async_test]
+async fn closed_registration_requires_an_invite() {
+    let Some(rocket) = test_rocket_with("MISATO_REGISTRATION_OPEN = false").await else {
+        return;
+    };
+    let client = &rocket.client;
+    let token = user_token(&rocket, "misato").await;
+
+    let response = client
+        .post("/api/v1/signup")
+        .header(Header::new("X-Misato-User-Token", token.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let error: Value = response.into_json().await.unwrap();
+    assert_eq!(error["error"]["code"], "REGISTRATION_CLOSED");
+
+    let response = client
+        .post("/api/v1/signup?invite=unknown")
+        .header(Header::new("X-Misato-User-Token", token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn invite_signup_is_single_use() {
+    let Some(rocket) = test_rocket_with("MISATO_REGISTRATION_OPEN = false").await else {
+        return;
+    };
+    let client = &rocket.client;
+
+    let response = client
+        .post("/admin/invites")
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let invite: Value = data(response).await;
+    let signup_uri = format!(
+        "/api/v1/signup?invite={}",
+        invite["token"].as_str().unwrap()
+    );
+
+    let response = client
+        .post(signup_uri.clone())
+        .header(Header::new(
+            "X-Misato-User-Token",
+            user_token(&rocket, "misato").await,
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .post(signup_uri)
+        .header(Header::new(
+            "X-Misato-User-Token",
+            user_token(&rocket, "shinji").await,
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn refused_signups_keep_the_invite_and_the_existing_account() {
+    let Some(rocket) = test_rocket_with("MISATO_REGISTRATION_OPEN = false").await else {
+        return;
+    };
+    let client = &rocket.client;
+    let mut invites = Vec::new();
+    for _ in 0..2 {
+        let response = client
+            .post("/admin/invites")
+            .header(bearer(&rocket.admin_token))
+            .dispatch()
+            .await;
+        let invite: Value = data(response).await;
+        invites.push(format!(
+            "/api/v1/signup?invite={}",
+            invite["token"].as_str().unwrap()
+        ));
+    }
+    let misato = user_token(&rocket, "misato").await;
+    let signup = |uri: &String, token: &String| {
+        client
+            .post(uri.clone())
+            .header(Header::new("X-Misato-User-Token", token.clone()))
+            .dispatch()
+    };
+
+    let response = signup(&invites[0], &misato).await;
+    assert_eq!(response.status(), Status::Ok);
+    let first: Value = data(response).await;
+    let before = api_user_count(client).await;
+
+    // Not replaced, and the invite is not spent on it
+    let response = signup(&invites[1], &misato).await;
+    assert_eq!(response.status(), Status::Conflict);
+    let error: Value = response.into_json().await.unwrap();
+    assert_eq!(error["error"]["code"], "API_ACCOUNT_EXISTS");
+    assert_eq!(api_user_count(client).await, before);
+    let database = client.rocket().state::<Database>().unwrap();
+    let uuid = first["uuid"].as_str().unwrap();
+    let apiuser = database.apiusermanager.get_apiuser(None, Some(uuid)).await;
+    assert!(apiuser
+        .unwrap()
+        .unwrap()
+        .token_matches(first["token"].as_str().unwrap()));
+
+    let shinji = user_token(&rocket, "shinji").await;
+    let response = signup(&invites[1], &shinji).await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(api_user_count(client).await, before + 1);
+}
+
+#[rocket::async_test]
+async fn failed_logins_are_delayed_progressively() {
+    let Some(rocket) =
+        test_rocket_with("MISATO_LOGIN_DELAY_BASE = 100\nMISATO_LOGIN_DELAY_CAP = 1000").await
+    else {
+        return;
+    };
+    let client = &rocket.client;
+    user_token(&rocket, "misato").await;
+    let wrong = json!({ "username": "misato", "password": "wrongpassword" }).to_string();
+
+    // 100ms, then 200ms, then 400ms
+    for expected in [100, 200, 400] {
+        let start = Instant::now();
+        let response = client
+            .post("/login")
+            .header(ContentType::JSON)
+            .body(&wrong)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert!(start.elapsed() >= Duration::from_millis(expected));
+    }
+}
+
+/// Status and body of a password login.
+async fn login_answer(rocket: &TestRocket, identifier: &str, password: &str) -> (Status, Value) {
+    let response = rocket
+        .client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(json!({ "identifier": identifier, "password": password }).to_string())
+        .dispatch()
+        .await;
+    (response.status(), response.into_json().await.unwrap())
+}
+
+#[rocket::async_test]
+async fn accounts_lock_after_failed_logins() {
+    let Some(rocket) = test_rocket_with(
+        "MISATO_LOCKOUT_THRESHOLD = 2\nMISATO_LOCKOUT_DURATION = 1\nMISATO_LOGIN_DELAY_BASE = 0",
+    )
+    .await
+    else {
+        return;
+    };
+    user_token(&rocket, "misato").await;
+
+    let (status, _) = login_answer(&rocket, "misato", "wrongpassword").await;
+    assert_eq!(status, Status::Unauthorized);
+    // The attempt reaching the threshold is told about the lock
+    let (status, body) = login_answer(&rocket, "misato", "wrongpassword").await;
+    assert_eq!(status, Status::Locked);
+    assert_eq!(body["error"]["code"], "ACCOUNT_LOCKED");
+    // Even the right password, until the cooldown is over, and without counting
+    for password in ["anypassword", "wrongpassword"] {
+        let (status, _) = login_answer(&rocket, "misato", password).await;
+        assert_eq!(status, Status::Locked);
+    }
+    rocket::tokio::time::sleep(Duration::from_millis(1100)).await;
+    let (status, _) = login_answer(&rocket, "misato", "wrongpassword").await;
+    assert_eq!(status, Status::Unauthorized);
+    let (status, _) = login_answer(&rocket, "misato", "anypassword").await;
+    assert_eq!(status, Status::Ok);
+
+    // The success reset the count
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    assert_eq!(user.unwrap().unwrap().failed_logins, 0);
+    let (status, _) = login_answer(&rocket, "misato", "wrongpassword").await;
+    assert_eq!(status, Status::Unauthorized);
+    let (status, _) = login_answer(&rocket, "misato", "anypassword").await;
+    assert_eq!(status, Status::Ok);
+}
+
+#[rocket::async_test]
+async fn unknown_users_answer_like_wrong_passwords() {
+    let Some(rocket) =
+        test_rocket_with("MISATO_LOGIN_DELAY_BASE = 100\nMISATO_LOGIN_DELAY_CAP = 1000").await
+    else {
+        return;
+    };
+    user_token(&rocket, "misato").await;
+
+    let wrong_password = login_answer(&rocket, "misato", "wrongpassword").await;
+    let unknown_user = login_answer(&rocket, "nobody", "wrongpassword").await;
+    assert_eq!(wrong_password, unknown_user);
+    assert_eq!(unknown_user.0, Status::Unauthorized);
+    assert_eq!(unknown_user.1["error"]["code"], "INVALID_CREDENTIALS");
+
+    // Both second failures of their identifier, so both wait 200ms
+    for identifier in ["misato", "nobody"] {
+        let start = Instant::now();
+        let answer = login_answer(&rocket, identifier, "wrongpassword").await;
+        assert_eq!(answer, wrong_password);
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+}
+
+#[rocket::async_test]
+async fn unknown_identifiers_lock_like_accounts() {
+    let Some(rocket) = test_rocket_with(
+        "MISATO_LOCKOUT_THRESHOLD = 2\nMISATO_LOCKOUT_DURATION = 60\nMISATO_LOGIN_DELAY_BASE = 0",
+    )
+    .await
+    else {
+        return;
+    };
+    user_token(&rocket, "misato").await;
+
+    let mut answers = Vec::new();
+    for identifier in ["misato", "nobody"] {
+        let mut answer = Vec::new();
+        for _ in 0..3 {
+            answer.push(login_answer(&rocket, identifier, "wrongpassword").await);
+        }
+        answers.push(answer);
+    }
+    assert_eq!(answers[0], answers[1]);
+    assert_eq!(answers[0][0].0, Status::Unauthorized);
+    assert_eq!(answers[0][1].0, Status::Locked);
+    assert_eq!(answers[0][2].0, Status::Locked);
+    assert_eq!(answers[0][2].1["error"]["code"], "ACCOUNT_LOCKED");
+}
+
+#[rocket::async_test]
+async fn tokens_are_stored_hashed() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let token = user_token(&rocket, "misato").await;
+
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let user = database
+        .usermanager
+        .get_user(Some("misato"), None)
+        .await
+        .unwrap()
+        .unwrap();
+    let stored = &user.tokens.unwrap()[0].token;
+    assert!(stored != &token);
+    assert_eq!(stored, &hash_token(&token));
+
+    let response = rocket
+        .client
+        .get("/user/account/me")
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+}
+
+async fn signup(rocket: &TestRocket, body: Value) -> Status {
+    rocket
+        .client
+        .post("/admin/signup")
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .body(body.to_string())
+        .dispatch()
+        .await
+        .status()
+}
+
+async fn login(rocket: &TestRocket, body: Value) -> Status {
+    rocket
+        .client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(body.to_string())
+        .dispatch()
+        .await
+        .status()
+}
+
+#[rocket::async_test]
+async fn legacy_usernames_get_their_key_on_init() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let mut legacy = User::create(
+        "Foo".to_string(),
+        Password::hash_password(b"anypassword").unwrap(),
+        None,
+    );
+    legacy.username_key = None;
+    database
+        .usermanager
+        .users
+        .insert_one(&legacy, None)
+        .await
+        .unwrap();
+
+    // As on the next boot
+    let settings = rocket.client.rocket().state::<Settings>().unwrap();
+    Database::init(settings).await.unwrap();
+    let user = database.usermanager.get_user(Some("Foo"), None).await;
+    assert_eq!(user.unwrap().unwrap().username_key, Some("foo".to_string()));
+
+    let clash = json!({ "username": "foo", "password": "anypassword" });
+    assert_eq!(signup(&rocket, clash).await, Status::Conflict);
+    let login_upper = json!({ "identifier": "FOO", "password": "anypassword" });
+    assert_eq!(login(&rocket, login_upper).await, Status::Ok);
+    assert_eq!(database.usermanager.count_users().await.unwrap(), 1);
+}
+
+#[rocket::async_test]
+async fn login_with_username_or_email() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let user =
+        json!({ "username": "misato", "password": "anypassword", "email": "Misato@misato.wiki" });
+    assert_eq!(signup(&rocket, user).await, Status::Ok);
+
+    let by_username = json!({ "identifier": "misato", "password": "anypassword" });
+    assert_eq!(login(&rocket, by_username).await, Status::Ok);
+    let by_email = json!({ "identifier": "misato@MISATO.wiki", "password": "anypassword" });
+    assert_eq!(login(&rocket, by_email).await, Status::Ok);
+    let wrong = json!({ "identifier": "misato@misato.wiki", "password": "wrongpassword" });
+    assert_eq!(login(&rocket, wrong).await, Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn signup_rejects_a_used_email() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let first =
+        json!({ "username": "misato", "password": "anypassword", "email": "misato@misato.wiki" });
+    assert_eq!(signup(&rocket, first).await, Status::Ok);
+    let second =
+        json!({ "username": "shinji", "password": "anypassword", "email": "MISATO@misato.wiki" });
+    assert_eq!(signup(&rocket, second).await, Status::Conflict);
+    let invalid = json!({ "username": "shinji", "password": "anypassword", "email": "shinji" });
+    assert_eq!(signup(&rocket, invalid).await, Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn duplicate_usernames_are_refused() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let body = json!({ "username": "misato", "password": "anypassword" });
+    assert_eq!(signup(&rocket, body.clone()).await, Status::Ok);
+
+    // Inserted past the route's own checks, the unique index refuses it
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let duplicate = User::create(
+        "misato".to_string(),
+        Password::hash_password(b"anypassword").unwrap(),
+        None,
+    );
+    let created = database.usermanager.create_user(&duplicate).await;
+    assert!(matches!(created, Err(UserError::AlreadyExists)));
+    assert_eq!(database.usermanager.count_users().await.unwrap(), 1);
+
+    let response = rocket
+        .client
+        .post("/admin/signup")
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .body(body.to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], "USER_EXISTS");
+}
+
+#[rocket::async_test]
+async fn signup_reports_every_invalid_field() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let response = rocket
+        .client
+        .post("/admin/signup")
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .body(json!({ "username": "a b", "password": "123" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::UnprocessableEntity);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], "VALIDATION_FAILED");
+    let fields = &body["error"]["fields"];
+    assert_eq!(
+        fields["username"][0],
+        "[ ]: Username may only contain letters, digits, '_', '-' and '.'."
+    );
+    assert_eq!(
+        fields["password"][0],
+        "Password must be at least 8 characters long."
+    );
+    assert_eq!(fields.get("email"), None);
+}
+
+#[rocket::async_test]
+async fn admins_can_be_promoted_and_demoted() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let bearer = |token: &str| Header::new("Authorization", format!("Bearer {}", token));
+    let response = client
+        .post("/api/v1/signup")
+        .header(Header::new(
+            "X-Misato-User-Token",
+            user_token(&rocket, "ops").await,
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let api: Value = data(response).await;
+    let (ops_token, ops) = (
+        api["token"].as_str().unwrap().to_string(),
+        json!({ "uuid": api["uuid"] }).to_string(),
+    );
+    let whoami = |token: String| async move {
+        client
+            .get("/admin/account/whoami")
+            .header(bearer(&token))
+            .dispatch()
+            .await
+            .status()
+    };
+    assert_eq!(whoami(ops_token.clone()).await, Status::Forbidden);
+
+    let response = client
+        .post("/api/v1/admin/promote")
+        .header(ContentType::JSON)
+        .header(bearer(&rocket.admin_token))
+        .body(&ops)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(whoami(ops_token.clone()).await, Status::Ok);
+
+    let response = client
+        .post("/api/v1/admin/demote")
+        .header(ContentType::JSON)
+        .header(bearer(&rocket.admin_token))
+        .body(&ops)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(whoami(ops_token.clone()).await, Status::Forbidden);
+
+    // Without the default admin, the promoted one is the last
+    let response = client
+        .post("/api/v1/admin/promote")
+        .header(ContentType::JSON)
+        .header(bearer(&rocket.admin_token))
+        .body(&ops)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .post("/api/v1/admin/delete")
+        .header(ContentType::JSON)
+        .header(bearer(&ops_token))
+        .body(json!({ "uuid": "admin" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .post("/api/v1/admin/demote")
+        .header(ContentType::JSON)
+        .header(bearer(&ops_token))
+        .body(&ops)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+    let error: Value = response.into_json().await.unwrap();
+    assert_eq!(error["error"]["code"], "LAST_ADMIN");
+    assert_eq!(whoami(ops_token).await, Status::Ok);
+}
+
+#[rocket::async_test]
+async fn admins_removing_each_other_at_once_leave_one() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let bearer = |token: &str| Header::new("Authorization", format!("Bearer {}", token));
+    let response = client
+        .post("/api/v1/signup")
+        .header(Header::new(
+            "X-Misato-User-Token",
+            user_token(&rocket, "ops").await,
+        ))
+        .dispatch()
+        .await;
+    let api: Value = data(response).await;
+    let (ops_token, ops) = (
+        api["token"].as_str().unwrap().to_string(),
+        json!({ "uuid": api["uuid"] }).to_string(),
+    );
+    let response = client
+        .post("/api/v1/admin/promote")
+        .header(ContentType::JSON)
+        .header(bearer(&rocket.admin_token))
+        .body(&ops)
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let (deleted, demoted) = rocket::tokio::join!(
+        client
+            .post("/api/v1/admin/delete")
+            .header(ContentType::JSON)
+            .header(bearer(&ops_token))
+            .body(json!({ "uuid": "admin" }).to_string())
+            .dispatch(),
+        client
+            .post("/api/v1/admin/demote")
+            .header(ContentType::JSON)
+            .header(bearer(&ops_token))
+            .body(&ops)
+            .dispatch(),
+    );
+    let statuses = [deleted.status(), demoted.status()];
+    assert!(statuses.contains(&Status::Conflict));
+    let whoami = client
+        .get("/admin/account/whoami")
+        .header(bearer(&ops_token))
+        .dispatch()
+        .await;
+    let admin = client
+        .get("/admin/account/whoami")
+        .header(bearer(&rocket.admin_token))
+        .dispatch()
+        .await;
+    assert!([whoami.status(), admin.status()].contains(&Status::Ok));
+}
+
+#[rocket::async_test]
+async fn signup_retry_with_the_same_idempotency_key() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let token = user_token(&rocket, "misato").await;
+    let signup = |key: &'static str| {
+        rocket
+            .client
+            .post("/api/v1/signup")
+            .header(Header::new("X-Misato-User-Token", token.clone()))
+            .header(Header::new("Idempotency-Key", key))
+            .dispatch()
+    };
+
+    let first = signup("signup-1").await;
+    assert_eq!(first.status(), Status::Ok);
+    let first: Value = data(first).await;
+    let retry = signup("signup-1").await;
+    assert_eq!(retry.status(), Status::Ok);
+    let retry: Value = data(retry).await;
+    assert_eq!(retry, first);
+
+    // Another key is another request, the account exists by then
+    assert_eq!(signup("signup-2").await.status(), Status::Conflict);
+
+    // Created once: the stored token is still the one of the first answer
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let apiuser = database
+        .apiusermanager
+        .get_apiuser(None, first["uuid"].as_str())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(apiuser.token_matches(first["token"].as_str().unwrap()));
+}
+
+async fn validate_signup(rocket: &TestRocket, username: &str, password: &str) -> Value {
+    let response = rocket
+        .client
+        .post("/api/account/signup/validate")
+        .header(ContentType::JSON)
+        .body(json!({ "username": username, "password": password }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    data(response).await
+}
+
+#[rocket::async_test]
+async fn signup_validation_creates_nothing() {
+    let Some(rocket) = test_rocket_with("MISATO_SIGNUP_VALIDATION_REPORTS_TAKEN = true").await
+    else {
+        return;
+    };
+    let available = validate_signup(&rocket, "misato", "anypassword").await;
+    assert_eq!(available, json!({ "valid": true }));
+    // The first validation created nothing
+    let available = validate_signup(&rocket, "misato", "anypassword").await;
+    assert_eq!(available["valid"], true);
+
+    user_token(&rocket, "misato").await;
+    let taken = validate_signup(&rocket, "Misato", "anypassword").await;
+    assert_eq!(taken["valid"], false);
+    assert!(taken["username"].is_string());
+    assert_eq!(taken.get("password"), None);
+
+    let weak = validate_signup(&rocket, "shinji", "short").await;
+    assert_eq!(weak["valid"], false);
+    assert_eq!(weak.get("username"), None);
+    assert!(weak["password"].is_string());
+    let invalid = validate_signup(&rocket, "shinji ikari", "anypassword").await;
+    assert!(invalid["username"].is_string());
+}
+
+#[rocket::async_test]
+async fn signup_validation_hides_taken_usernames_by_default() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    user_token(&rocket, "misato").await;
+    let taken = validate_signup(&rocket, "misato", "anypassword").await;
+    assert_eq!(taken, json!({ "valid": true }));
+}
+
+#[rocket::async_test]
+async fn revoke_all_tokens_keeps_the_users() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let mut tokens = Vec::new();
+    for username in ["misato", "shinji", "asuka"] {
+        tokens.push(user_token(&rocket, username).await);
+    }
+    let revoke = |body: Value| {
+        rocket
+            .client
+            .post("/admin/account/revoke-all-tokens")
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", rocket.admin_token),
+            ))
+            .body(body.to_string())
+            .dispatch()
+    };
+
+    // Nothing was issued before the epoch
+    assert_eq!(revoke(json!({ "before": 0 })).await.status(), Status::Ok);
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    assert!(user.unwrap().unwrap().tokens.is_some());
+
+    assert_eq!(revoke(json!({})).await.status(), Status::Ok);
+    for token in tokens {
+        let response = rocket
+            .client
+            .get("/user/account/me")
+            .header(bearer(token))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Unauthorized);
+    }
+    for username in ["misato", "shinji", "asuka"] {
+        let user = database.usermanager.get_user(Some(username), None).await;
+        assert_eq!(user.unwrap().unwrap().tokens, None);
+    }
+    assert_eq!(database.usermanager.count_users().await.unwrap(), 3);
+}
+
+#[rocket::async_test]
+async fn account_forms_name_the_bad_field() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let cases = [
+        (
+            "/login",
+            json!({ "username": "misato" }),
+            "[password]: Missing field.",
+        ),
+        (
+            "/login",
+            json!({ "username": "misato", "password": "anypassword", "remember": true }),
+            "[remember]: Unknown field.",
+        ),
+        (
+            "/admin/signup",
+            json!({ "username": 29, "password": "anypassword" }),
+            "[username]: Must be a string.",
+        ),
+        (
+            "/admin/signup",
+            json!({ "username": "misato", "pasword": "anypassword" }),
+            "[pasword]: Unknown field.",
+        ),
+        (
+            "/admin/signup",
+            json!({ "password": "anypassword" }),
+            "[username]: Missing field.",
+        ),
+    ];
+    for (path, body, message) in cases {
+        let response = client
+            .post(path)
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", rocket.admin_token),
+            ))
+            .body(body.to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let error: Value = response.into_json().await.unwrap();
+        assert_eq!(error["error"]["code"], "INVALID_FIELD");
+        assert_eq!(error["error"]["message"], message);
+    }
+
+    let response = client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body("{ \"username\": ")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn corrupt_password_is_not_a_wrong_password() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    user_token(&rocket, "misato").await;
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    let user = user.unwrap().unwrap();
+    let corrupt = Password {
+        salt: Vec::new(),
+        ..user.password.unwrap()
+    };
+    database
+        .usermanager
+        .set_password(&user.uuid, &corrupt)
+        .await
+        .unwrap();
+
+    let response = rocket
+        .client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "misato", "password": "anypassword" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::InternalServerError);
+    let error: Value = response.into_json().await.unwrap();
+    assert_eq!(error["error"]["code"], "CORRUPT_CREDENTIALS");
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    assert_eq!(user.unwrap().unwrap().failed_logins, 0);
+}
+
+#[rocket::async_test]
+async fn unverifiable_password_is_not_a_wrong_password() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    user_token(&rocket, "misato").await;
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    let user = user.unwrap().unwrap();
+    // Peppered, but this server has no pepper
+    let peppered = Password::hash_password_peppered(b"pepper", b"anypassword").unwrap();
+    database
+        .usermanager
+        .set_password(&user.uuid, &peppered)
+        .await
+        .unwrap();
+
+    for _ in 0..2 {
+        let response = rocket
+            .client
+            .post("/login")
+            .header(ContentType::JSON)
+            .body(json!({ "username": "misato", "password": "anypassword" }).to_string())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::InternalServerError);
+        let error: Value = response.into_json().await.unwrap();
+        assert_eq!(error["error"]["code"], "CORRUPT_CREDENTIALS");
+    }
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    assert_eq!(user.unwrap().unwrap().failed_logins, 0);
+}
+
+#[rocket::async_test]
+async fn responses_share_one_envelope() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let keys = |body: &Value| {
+        let mut keys: Vec<String> = body.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        keys
+    };
+    let token = user_token(&rocket, "misato").await;
+
+    let response = rocket
+        .client
+        .get("/user/account/me")
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let success: Value = response.into_json().await.unwrap();
+    assert_eq!(keys(&success), ["data", "error"]);
+    assert_eq!(success["data"]["username"], "misato");
+    assert_eq!(success["error"], Value::Null);
+
+    let response = rocket
+        .client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "misato", "password": "wrongpassword" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let failure: Value = response.into_json().await.unwrap();
+    assert_eq!(keys(&failure), ["data", "error"]);
+    assert_eq!(failure["data"], Value::Null);
+    assert_eq!(failure["error"]["code"], "INVALID_CREDENTIALS");
+
+    // A token that matches no user is a 401 with the same envelope
+    let response = rocket
+        .client
+        .get("/user/account/me")
+        .header(bearer("not a token"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let failure: Value = response.into_json().await.unwrap();
+    assert_eq!(keys(&failure), ["data", "error"]);
+    assert_eq!(failure["error"]["code"], "UNAUTHENTICATED");
+
+    let response = rocket
+        .client
+        .post("/admin/signup")
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .body(json!({ "username": "misato", "password": "anypassword" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Conflict);
+    let failure: Value = response.into_json().await.unwrap();
+    assert_eq!(failure["error"]["code"], "USER_EXISTS");
+}
+
+#[rocket::async_test]
+async fn deleted_accounts_are_restored_by_an_admin() {
+    let Some(rocket) = test_rocket_with("MISATO_DELETION_GRACE_PERIOD = 0").await else {
+        return;
+    };
+    let token = user_token(&rocket, "misato").await;
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    let uuid = user.unwrap().unwrap().uuid;
+    let admin = |path: &'static str| {
+        rocket
+            .client
+            .post(path)
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", rocket.admin_token),
+            ))
+            .body(json!({ "uuid": uuid }).to_string())
+    };
+    let credentials = json!({ "identifier": "misato", "password": "anypassword" });
+
+    assert_eq!(admin("/admin/delete").dispatch().await.status(), Status::Ok);
+    assert_eq!(
+        login(&rocket, credentials.clone()).await,
+        Status::Unauthorized
+    );
+    let response = rocket
+        .client
+        .get("/user/account/me")
+        .header(bearer(token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    // Still taken, whatever its case
+    let taken = json!({ "username": "Misato", "password": "anypassword" });
+    assert_eq!(signup(&rocket, taken).await, Status::Conflict);
+
+    assert_eq!(
+        admin("/admin/restore").dispatch().await.status(),
+        Status::Ok
+    );
+    assert_eq!(login(&rocket, credentials).await, Status::Ok);
+    // Nothing left to restore
+    let response = admin("/admin/restore").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn deleted_accounts_are_restored_by_login_until_purged() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let login = |username: &str| {
+        rocket
+            .client
+            .post("/login")
+            .header(ContentType::JSON)
+            .body(json!({ "username": username, "password": "anypassword" }).to_string())
+            .dispatch()
+    };
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    for username in ["misato", "shinji"] {
+        user_token(&rocket, username).await;
+        let deleted = database.usermanager.delete_user(Some(username), None).await;
+        assert_eq!(deleted.unwrap().unwrap().modified_count, 1);
+    }
+
+    assert_eq!(login("misato").await.status(), Status::Ok);
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    assert_eq!(user.unwrap().unwrap().deleted_at, None);
+
+    let grace = 30 * 24 * 60 * 60;
+    purge(&database.usermanager, grace, get_current_timestamp()).await;
+    let kept = database.usermanager.get_login_user("shinji", Some(0)).await;
+    assert!(kept.unwrap().is_some());
+    let past_grace = get_current_timestamp() + (grace + 1) * 1000;
+    purge(&database.usermanager, grace, past_grace).await;
+    let purged = database.usermanager.get_login_user("shinji", Some(0)).await;
+    assert_eq!(purged.unwrap(), None);
+    assert_eq!(login("shinji").await.status(), Status::Unauthorized);
+    assert_eq!(database.usermanager.count_users().await.unwrap(), 1);
+}
+
+#[rocket::async_test]
+async fn login_asks_for_a_second_factor_once_verified() {
+    let Some(rocket) = test_rocket_with("MISATO_TOTP_KEY = \"test totp key\"").await else {
+        return;
+    };
+    let client = &rocket.client;
+    let token = user_token(&rocket, "misato").await;
+    let login = |totp: Option<String>| {
+        client
+            .post("/login")
+            .header(ContentType::JSON)
+            .body(
+                json!({ "username": "misato", "password": "anypassword", "totp": totp })
+                    .to_string(),
+            )
+            .dispatch()
+    };
+    let post_code = |path: &'static str, code: String| {
+        client
+            .post(path)
+            .header(ContentType::JSON)
+            .header(bearer(token.clone()))
+            .body(json!({ "code": code }).to_string())
+            .dispatch()
+    };
+
+    let response = client
+        .post("/user/totp/enroll")
+        .header(bearer(token.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let enrollment = data(response).await;
+    assert!(enrollment["uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/Misato:misato?secret="));
+    // Not verified yet, the password is still enough
+    assert_eq!(login(None).await.status(), Status::Ok);
+
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    let encrypted = user.unwrap().unwrap().totp.unwrap().secret;
+    let secret = totp::decrypt_secret("test totp key", &encrypted).unwrap();
+    // Each code works once, successive steps within the skew stand for the next ones
+    let now = get_current_timestamp() / 1000;
+    let code = |steps: u64| totp::code_at(&secret, now - totp::TOTP_STEP + steps * totp::TOTP_STEP);
+
+    let response = post_code("/user/totp/verify", "abcdef".to_string()).await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(
+        post_code("/user/totp/verify", code(0)).await.status(),
+        Status::Ok
+    );
+
+    let response = login(None).await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let error: Value = response.into_json().await.unwrap();
+    assert_eq!(error["error"]["code"], "TOTP_REQUIRED");
+    let response = login(Some("abcdef".to_string())).await;
+    let error: Value = response.into_json().await.unwrap();
+    assert_eq!(error["error"]["code"], "INVALID_TOTP");
+    assert_eq!(login(Some(code(1))).await.status(), Status::Ok);
+    // Replayed, or older than the last one
+    for replayed in [code(1), code(0)] {
+        let response = login(Some(replayed)).await;
+        let error: Value = response.into_json().await.unwrap();
+        assert_eq!(error["error"]["code"], "INVALID_TOTP");
+    }
+
+    assert_eq!(
+        post_code("/user/totp/disable", code(2)).await.status(),
+        Status::Ok
+    );
+    assert_eq!(login(None).await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn recovery_codes_work_once() {
+    let Some(rocket) = test_rocket_with("MISATO_TOTP_KEY = \"test totp key\"").await else {
+        return;
+    };
+    let client = &rocket.client;
+    let token = user_token(&rocket, "misato").await;
+    let response = client
+        .post("/user/totp/enroll")
+        .header(bearer(token.clone()))
+        .dispatch()
+        .await;
+    let enrollment = data(response).await;
+    let codes: Vec<String> = serde_json::from_value(enrollment["recovery_codes"].clone()).unwrap();
+    assert_eq!(codes.len(), 10);
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    let encrypted = user.unwrap().unwrap().totp.unwrap().secret;
+    let secret = totp::decrypt_secret("test totp key", &encrypted).unwrap();
+    let now = get_current_timestamp() / 1000;
+    let post_code = |path: &'static str, at: u64| {
+        client
+            .post(path)
+            .header(ContentType::JSON)
+            .header(bearer(token.clone()))
+            .body(json!({ "code": totp::code_at(&secret, at) }).to_string())
+            .dispatch()
+    };
+    assert_eq!(
+        post_code("/user/totp/verify", now).await.status(),
+        Status::Ok
+    );
+    let recover = |recovery_code: &str| {
+        client
+            .post("/login/recovery")
+            .header(ContentType::JSON)
+            .body(
+                json!({
+                    "username": "misato",
+                    "password": "anypassword",
+                    "recovery_code": recovery_code,
+                })
+                .to_string(),
+            )
+            .dispatch()
+    };
+
+    assert_eq!(recover(&codes[0]).await.status(), Status::Ok);
+    let response = recover(&codes[0]).await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let error: Value = response.into_json().await.unwrap();
+    assert_eq!(error["error"]["code"], "INVALID_RECOVERY_CODE");
+
+    // A new set replaces the codes that were left
+    let response = post_code("/user/totp/recovery-codes", now + totp::TOTP_STEP).await;
+    let renewed: Vec<String> = serde_json::from_value(data(response).await).unwrap();
+    assert_eq!(recover(&codes[1]).await.status(), Status::Unauthorized);
+    assert_eq!(recover(&renewed[0]).await.status(), Status::Ok);
+}
+
+/// The api account of the user of `token`, its `token` authenticates the v1 routes.
+async fn api_account(rocket: &TestRocket, token: &str) -> Value {
+    let response = rocket
+        .client
+        .post("/api/v1/signup")
+        .header(Header::new("X-Misato-User-Token", token.to_string()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    data(response).await
+}
+
+#[rocket::async_test]
+async fn user_routes_require_an_authenticated_user() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let token = user_token(&rocket, "misato").await;
+    let api = api_account(&rocket, &token).await;
+    let check = |authorization: Option<String>| {
+        let mut request = client.post("/user/check-token");
+        if let Some(authorization) = authorization {
+            request = request.header(Header::new("Authorization", authorization));
+        }
+        request.dispatch()
+    };
+
+    let api_token = api["token"].as_str().unwrap();
+    for authorization in [
+        None,
+        Some("Bearer not a token".to_string()),
+        Some(token.clone()),
+        // Only the v1 routes take the api token
+        Some(format!("Bearer {}", api_token)),
+    ] {
+        let response = check(authorization).await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let body: Value = response.into_json().await.unwrap();
+        assert_eq!(body["error"]["code"], "UNAUTHENTICATED");
+    }
+
+    let response = check(Some(format!("Bearer {}", token))).await;
+    assert_eq!(response.status(), Status::Ok);
+    let infos: Value = data(response).await;
+    assert_eq!(infos["uuid"], api["uuid"]);
+    assert_eq!(infos["token"], token.as_str());
+
+    // Each token yields its own user
+    let other = user_token(&rocket, "shinji").await;
+    let response = check(Some(format!("Bearer {}", other))).await;
+    assert_eq!(response.status(), Status::Ok);
+    let infos: Value = data(response).await;
+    let database = client.rocket().state::<Database>().unwrap();
+    let shinji = database.usermanager.get_user(Some("shinji"), None).await;
+    assert_eq!(infos["uuid"], shinji.unwrap().unwrap().uuid.as_str());
+}
+
+#[rocket::async_test]
+async fn over_length_passwords_are_never_hashed() {
+    let Some(rocket) = test_rocket_with("MISATO_PASSWORD_MAX_LENGTH = 64").await else {
+        return;
+    };
+    let long = "a".repeat(65);
+    let body = json!({ "username": "shinji", "password": long });
+    assert_eq!(signup(&rocket, body).await, Status::BadRequest);
+    user_token(&rocket, "misato").await;
+
+    let response = rocket
+        .client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "misato", "password": long }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], "PASSWORD_TOO_LONG");
+    assert_eq!(
+        body["error"]["message"],
+        "Password must be at most 64 bytes long."
+    );
+
+    // A verified wrong password would have counted as a failed login
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    assert_eq!(user.unwrap().unwrap().failed_logins, 0);
+    let user = database.usermanager.get_user(Some("shinji"), None).await;
+    assert!(user.unwrap().is_none());
+}
+
+#[rocket::async_test]
+async fn username_can_be_changed_to_a_free_one() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let token = user_token(&rocket, "misato").await;
+    user_token(&rocket, "shinji").await;
+    let rename = |username: &str| {
+        rocket
+            .client
+            .post("/user/account/username")
+            .header(ContentType::JSON)
+            .header(bearer(&token))
+            .body(json!({ "username": username }).to_string())
+            .dispatch()
+    };
+
+    let response = rename("katsuragi").await;
+    assert_eq!(response.status(), Status::Ok);
+    let user: Value = data(response).await;
+    assert_eq!(user["username"], "katsuragi");
+    let credentials = json!({ "username": "katsuragi", "password": "anypassword" });
+    assert_eq!(login(&rocket, credentials).await, Status::Ok);
+    let credentials = json!({ "username": "misato", "password": "anypassword" });
+    assert_eq!(login(&rocket, credentials).await, Status::Unauthorized);
+
+    // Its own name with another case is not a conflict
+    assert_eq!(rename("Katsuragi").await.status(), Status::Ok);
+
+    let response = rename("Shinji").await;
+    assert_eq!(response.status(), Status::Conflict);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], "USER_EXISTS");
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let user = database.usermanager.get_user(Some("shinji"), None).await;
+    assert_eq!(user.unwrap().unwrap().username, "shinji");
+}
+
+#[rocket::async_test]
+async fn login_is_audited_once() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let body = json!({ "username": "misato", "password": "anypassword" });
+    assert_eq!(signup(&rocket, body).await, Status::Ok);
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let everything = AuditFilter::new(None, None, None, None).unwrap();
+    let before = database.auditmanager.count_events(&everything).await;
+
+    let response = rocket
+        .client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(json!({ "identifier": "misato", "password": "anypassword" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let request_id = response
+        .headers()
+        .get_one("X-Request-Id")
+        .unwrap()
+        .to_string();
+    let uuid = data(response).await["uuid"].as_str().unwrap().to_string();
+
+    let after = database.auditmanager.count_events(&everything).await;
+    assert_eq!(after.unwrap(), before.unwrap() + 1);
+    let events = database.auditmanager.list_events(&everything, 0, 1).await;
+    let event = &events.unwrap()[0];
+    assert_eq!(event.action, AuditAction::Login);
+    assert_eq!(event.actor.as_ref(), Some(&uuid));
+    assert_eq!(event.target.as_ref(), Some(&uuid));
+    assert_eq!(event.request_id, Some(request_id));
+}
+
+#[rocket::async_test]
+async fn audit_log_is_filtered_by_action_and_time() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    for (timestamp, action, actor) in [
+        (1000, AuditAction::Login, "misato"),
+        (2000, AuditAction::LoginFailed, "shinji"),
+        (3000, AuditAction::Login, "shinji"),
+        (4000, AuditAction::PasswordChange, "misato"),
+    ] {
+        let mut event = AuditEvent::create(action, Some(actor.to_string()), None);
+        event.timestamp = timestamp;
+        database.auditmanager.record_event(&event).await.unwrap();
+    }
+    let audit = |query: &str| {
+        rocket
+            .client
+            .get(format!("/admin/audit?{}", query))
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", rocket.admin_token),
+            ))
+            .dispatch()
+    };
+    let timestamps = |page: &Value| -> Vec<u64> {
+        page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|event| event["timestamp"].as_u64().unwrap())
+            .collect()
+    };
+
+    let page: Value = data(audit("action=Login").await).await;
+    assert_eq!(timestamps(&page), vec![3000, 1000]);
+    assert_eq!(page["total"], 2);
+
+    let page: Value = data(audit("from=2000&to=3000").await).await;
+    assert_eq!(timestamps(&page), vec![3000, 2000]);
+
+    let page: Value = data(audit("actor=misato&from=2000").await).await;
+    assert_eq!(timestamps(&page), vec![4000]);
+
+    let page: Value = data(audit("from=1000&to=4000&limit=1&page=2").await).await;
+    assert_eq!(timestamps(&page), vec![3000]);
+    assert_eq!(
+        (page["total"].as_u64(), page["has_next"].as_bool()),
+        (Some(4), Some(true))
+    );
+
+    assert_eq!(
+        audit("from=3000&to=1000").await.status(),
+        Status::BadRequest
+    );
+    assert_eq!(audit("action=Lunch").await.status(), Status::BadRequest);
+    assert_eq!(audit("from=soon").await.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn first_user_becomes_admin() {
+    let Some(rocket) = test_rocket_first_user_admin().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let database = client.rocket().state::<Database>().unwrap();
+    let signup = |username: &str, bearer: Option<String>| {
+        let mut request = client
+            .post("/admin/signup")
+            .header(ContentType::JSON)
+            .body(json!({ "username": username, "password": "anypassword" }).to_string());
+        if let Some(bearer) = bearer {
+            request = request.header(Header::new("Authorization", format!("Bearer {}", bearer)));
+        }
+        request.dispatch()
+    };
+    let role = |token: String| async move {
+        let response = client
+            .get("/user/account/me")
+            .header(bearer(token))
+            .dispatch()
+            .await;
+        let me: Value = data(response).await;
+        me["role"].clone()
+    };
+
+    let response = signup("misato", None).await;
+    assert_eq!(response.status(), Status::Ok);
+    let misato: Value = data(response).await;
+    let misato_token = misato["token"].as_str().unwrap().to_string();
+    assert_eq!(role(misato_token.clone()).await, "Admin");
+    assert_eq!(signup("shinji", None).await.status(), Status::Unauthorized);
+
+    let api = api_account(&rocket, &misato_token).await;
+    let apiuser = database
+        .apiusermanager
+        .get_apiuser(None, misato["uuid"].as_str())
+        .await;
+    assert_eq!(
+        apiuser.unwrap().unwrap().access.role,
+        ApiUserRoleType::Admin
+    );
+
+    let response = signup("shinji", Some(api["token"].as_str().unwrap().to_string())).await;
+    assert_eq!(response.status(), Status::Ok);
+    let shinji: Value = data(response).await;
+    let shinji_token = shinji["token"].as_str().unwrap().to_string();
+    assert_eq!(role(shinji_token.clone()).await, "User");
+    api_account(&rocket, &shinji_token).await;
+    let apiuser = database
+        .apiusermanager
+        .get_apiuser(None, shinji["uuid"].as_str())
+        .await;
+    assert_eq!(apiuser.unwrap().unwrap().access.role, ApiUserRoleType::User);
+}
+
+#[rocket::async_test]
+async fn racing_first_signups_make_one_admin() {
+    let Some(rocket) = test_rocket_first_user_admin().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let database = client.rocket().state::<Database>().unwrap();
+    let signup = |username: &str| {
+        client
+            .post("/admin/signup")
+            .header(ContentType::JSON)
+            .body(json!({ "username": username, "password": "anypassword" }).to_string())
+            .dispatch()
+    };
+
+    let (misato, shinji) = rocket::tokio::join!(signup("misato"), signup("shinji"));
+    let mut statuses = [misato.status(), shinji.status()];
+    statuses.sort_by_key(|status| status.code);
+    assert_eq!(statuses, [Status::Ok, Status::Unauthorized]);
+    assert_eq!(database.usermanager.count_users().await.unwrap(), 1);
+}
+
+#[rocket::async_test]
+async fn maintenance_keeps_health_and_admin_routes_up() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let toggle = |enabled: bool| {
+        rocket
+            .client
+            .post("/admin/maintenance")
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", rocket.admin_token),
+            ))
+            .body(json!({ "enabled": enabled, "retry_after": 60 }).to_string())
+            .dispatch()
+    };
+    let login = || {
+        rocket
+            .client
+            .post("/login")
+            .header(ContentType::JSON)
+            .body(json!({ "username": "misato", "password": "anypassword" }).to_string())
+            .dispatch()
+    };
+
+    let status: Value = data(toggle(true).await).await;
+    assert_eq!(status, json!({ "enabled": true, "retry_after": 60 }));
+    let response = login().await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(response.headers().get_one("Retry-After"), Some("60"));
+    assert_eq!(
+        rocket.client.get("/health").dispatch().await.status(),
+        Status::Ok
+    );
+    assert_eq!(
+        rocket.client.get("/health/db").dispatch().await.status(),
+        Status::Ok
+    );
+
+    assert_eq!(toggle(false).await.status(), Status::Ok);
+    assert_eq!(login().await.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn key_lifetimes_are_bounded() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let token = user_token(&rocket, "misato").await;
+    let api = api_account(&rocket, &token).await;
+    let create = |expires_in: u64| {
+        rocket
+            .client
+            .post("/api/v1/keys")
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "X-Misato-API-Token",
+                api["token"].as_str().unwrap().to_string(),
+            ))
+            .body(json!({ "name": "ci", "expires_in": expires_in }).to_string())
+            .dispatch()
+    };
+
+    for expires_in in [0, u64::MAX, i64::MAX as u64] {
+        let response = create(expires_in).await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let error: Value = response.into_json().await.unwrap();
+        assert_eq!(error["error"]["code"], "VALIDATION_ERROR");
+    }
+    assert_eq!(create(60).await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn named_api_keys_authenticate_until_revoked() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let token = user_token(&rocket, "misato").await;
+    let api = api_account(&rocket, &token).await;
+    let api_token = api["token"].as_str().unwrap().to_string();
+    let api_header = |value: &str| Header::new("X-Misato-API-Token", value.to_string());
+    let create = |with: String| {
+        client
+            .post("/api/v1/keys")
+            .header(ContentType::JSON)
+            .header(api_header(&with))
+            .body(json!({ "name": "ci", "scopes": ["account:read"] }).to_string())
+            .dispatch()
+    };
+    let check = |with: String| {
+        client
+            .post("/user/check-token")
+            .header(bearer(with))
+            .dispatch()
+    };
+
+    let response = create(api_token.clone()).await;
+    assert_eq!(response.status(), Status::Ok);
+    let created: Value = data(response).await;
+    let key = created["key"].as_str().unwrap().to_string();
+    assert_eq!(created["name"], "ci");
+    assert_eq!(created["scopes"], json!(["account:read"]));
+    assert_eq!(created.get("expires_at"), None);
+
+    let response = check(key.clone()).await;
+    assert_eq!(response.status(), Status::Ok);
+    let infos: Value = data(response).await;
+    assert_eq!(infos["uuid"], api["uuid"]);
+    // Only the api token manages keys
+    assert_eq!(create(key.clone()).await.status(), Status::Forbidden);
+
+    let response = client
+        .get("/api/v1/keys")
+        .header(api_header(&key))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let keys: Value = data(response).await;
+    assert_eq!(keys.as_array().unwrap().len(), 1);
+    assert_eq!(keys[0]["id"], created["id"]);
+    assert_eq!(keys[0].get("key"), None);
+
+    let revoke = format!("/api/v1/keys/{}", created["id"].as_str().unwrap());
+    let response = client
+        .delete(revoke.clone())
+        .header(api_header(&api_token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(check(key).await.status(), Status::Unauthorized);
+    let response = client
+        .delete(revoke)
+        .header(api_header(&api_token))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn scoped_keys_only_do_what_they_allow() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let token = user_token(&rocket, "misato").await;
+    let api = api_account(&rocket, &token).await;
+    let api_token = api["token"].as_str().unwrap().to_string();
+    let create = |scopes: Value| {
+        client
+            .post("/api/v1/keys")
+            .header(ContentType::JSON)
+            .header(Header::new("X-Misato-API-Token", api_token.clone()))
+            .body(json!({ "name": "scoped", "scopes": scopes }).to_string())
+            .dispatch()
+    };
+    let with_key = |response: Value, path: &'static str| {
+        let key = response["key"].as_str().unwrap().to_string();
+        client.post(path).header(bearer(key)).dispatch()
+    };
+
+    let response = create(json!(["account:admin"])).await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let read_only: Value = data(create(json!(["account:read"])).await).await;
+    assert_eq!(
+        with_key(read_only.clone(), "/user/check-token")
+            .await
+            .status(),
+        Status::Ok
+    );
+    let response = with_key(read_only, "/user/delete").await;
+    assert_eq!(response.status(), Status::Forbidden);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], "MISSING_SCOPE");
+
+    let deleter: Value = data(create(json!(["account:delete"])).await).await;
+    assert_eq!(with_key(deleter, "/user/delete").await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn users_cursor_lists_everyone_once() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let mut expected = std::collections::HashSet::new();
+    for index in 0..7 {
+        let username = format!("user{}", index);
+        user_token(&rocket, &username).await;
+        expected.insert(username);
+    }
+
+    let mut seen = Vec::new();
+    let mut cursor = String::new();
+    loop {
+        let response = rocket
+            .client
+            .get(format!("/admin/account/users?limit=3&cursor={}", cursor))
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", rocket.admin_token),
+            ))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let page: Value = data(response).await;
+        assert_eq!(page.get("page"), None);
+        for account in page["items"].as_array().unwrap() {
+            seen.push(account["username"].as_str().unwrap().to_string());
+        }
+        // A user created mid listing comes after the ones already listed, never twice
+        if seen.len() == 3 {
+            user_token(&rocket, "latecomer").await;
+            expected.insert("latecomer".to_string());
+        }
+        match page["next_cursor"].as_str() {
+            Some(next) => cursor = next.to_string(),
+            None => break,
+        }
+    }
+    let unique: std::collections::HashSet<String> = seen.iter().cloned().collect();
+    assert_eq!(unique.len(), seen.len());
+    assert_eq!(unique, expected);
+
+    let response = rocket
+        .client
+        .get("/admin/account/users?cursor=not-a-cursor")
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn admin_sees_user_details_without_secrets() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let signup_token = user_token(&rocket, "misato").await;
+    let response = rocket
+        .client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "misato", "password": "anypassword" }).to_string())
+        .dispatch()
+        .await;
+    let login: Value = data(response).await;
+    let details = |token: Option<String>, username: &str| {
+        let mut request = rocket
+            .client
+            .get(format!("/admin/account/users/{}", username));
+        if let Some(token) = token {
+            request = request.header(Header::new("Authorization", format!("Bearer {}", token)));
+        }
+        request.dispatch()
+    };
+
+    assert_eq!(details(None, "misato").await.status(), Status::Unauthorized);
+    let response = details(Some(signup_token.clone()), "misato").await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let response = details(Some(rocket.admin_token.clone()), "nobody").await;
+    assert_eq!(response.status(), Status::NotFound);
+
+    let response = details(Some(rocket.admin_token.clone()), "MISATO").await;
+    assert_eq!(response.status(), Status::Ok);
+    let body = response.into_string().await.unwrap();
+    let user: Value = serde_json::from_str::<Value>(&body).unwrap()["data"].take();
+    assert_eq!(user["username"], "misato");
+    assert_eq!(user["role"], "User");
+    assert_eq!(user["locked"], false);
+    assert_eq!(user["token_count"], 2);
+    assert!(user["last_login_at"].as_u64().unwrap() >= user["created_at"].as_u64().unwrap());
+    for secret in ["password", "salt", "refresh_token"] {
+        assert!(!body.contains(secret));
+    }
+    for token in [&signup_token, login["token"].as_str().unwrap()] {
+        assert!(!body.contains(token));
+        assert!(!body.contains(&hash_token(token)));
+    }
+}
+
+#[rocket::async_test]
+async fn login_records_when_and_from_where() {
+    let Some(rocket) = test_rocket_with("MISATO_TRUSTED_PROXIES = \"127.0.0.1\"").await else {
+        return;
+    };
+    let signup_token = user_token(&rocket, "misato").await;
+    let me = |token: String| {
+        rocket
+            .client
+            .get("/user/account/me")
+            .header(bearer(token))
+            .dispatch()
+    };
+    let before: Value = data(me(signup_token).await).await;
+    assert_eq!(before["last_login_at"], Value::Null);
+
+    let started = get_current_timestamp();
+    let response = rocket
+        .client
+        .post("/login")
+        .remote("127.0.0.1:4000".parse().unwrap())
+        .header(ContentType::JSON)
+        // Only the address the proxy added is trusted, not the one the client claims
+        .header(Header::new("X-Forwarded-For", "10.0.0.1, 203.0.113.7"))
+        .body(json!({ "username": "misato", "password": "anypassword" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let login: Value = data(response).await;
+
+    let after: Value = data(me(login["token"].as_str().unwrap().to_string()).await).await;
+    assert!(after["last_login_at"].as_u64().unwrap() >= started);
+    assert_eq!(after["last_login_ip"], "203.0.113.7");
+
+    let response = rocket
+        .client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "misato", "password": "wrongpassword" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    let user = user.unwrap().unwrap();
+    assert_eq!(user.last_login_at, after["last_login_at"].as_u64());
+}
+
+#[rocket::async_test]
+async fn export_has_everything_but_credentials() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let signup_token = user_token(&rocket, "misato").await;
+    user_token(&rocket, "asuka").await;
+    let response = rocket
+        .client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "misato", "password": "anypassword" }).to_string())
+        .dispatch()
+        .await;
+    let login: Value = data(response).await;
+    let login_token = login["token"].as_str().unwrap().to_string();
+
+    let response = rocket
+        .client
+        .get("/user/account/export")
+        .header(bearer(signup_token.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let body = response.into_string().await.unwrap();
+    let export: Value = serde_json::from_str::<Value>(&body).unwrap()["data"].take();
+    assert_eq!(export["profile"]["username"], "misato");
+    assert_eq!(export["sessions"].as_array().unwrap().len(), 2);
+    let uuid = export["profile"]["uuid"].as_str().unwrap();
+    let audit = export["audit"].as_array().unwrap();
+    let actions: Vec<&str> = audit
+        .iter()
+        .map(|e| e["action"].as_str().unwrap())
+        .collect();
+    assert!(actions.contains(&"Signup"));
+    assert!(actions.contains(&"Login"));
+    assert!(audit
+        .iter()
+        .all(|e| e["actor"] == uuid || e["target"] == uuid || e["target"] == "misato"));
+    for secret in ["password", "salt", "refresh_token", "recovery"] {
+        assert!(!body.contains(secret));
+    }
+    for token in [&signup_token, &login_token] {
+        assert!(!body.contains(token.as_str()));
+        assert!(!body.contains(&hash_token(token)));
+    }
+
+    let admin_export = |token: String, username: &str| {
+        rocket
+            .client
+            .get(format!("/admin/account/users/{}/export", username))
+            .header(Header::new("Authorization", format!("Bearer {}", token)))
+            .dispatch()
+    };
+    assert_eq!(
+        admin_export(signup_token.clone(), "misato").await.status(),
+        Status::Unauthorized
+    );
+    let response = admin_export(rocket.admin_token.clone(), "nobody").await;
+    assert_eq!(response.status(), Status::NotFound);
+    let response = admin_export(rocket.admin_token.clone(), "misato").await;
+    assert_eq!(response.status(), Status::Ok);
+    let export: Value = data(response).await;
+    assert_eq!(export["profile"]["uuid"], uuid);
+    assert_eq!(export["audit"].as_array().unwrap().len(), audit.len());
+}
+
+#[rocket::async_test]
+async fn anonymized_accounts_keep_only_their_audit_trail() {
+    let Some(rocket) = test_rocket_with(
+        "MISATO_DELETION_MODE = \"anonymize\"\nMISATO_ANONYMIZATION_KEY = \"test key\"",
+    )
+    .await
+    else {
+        return;
+    };
+    let token = user_token(&rocket, "misato").await;
+    api_account(&rocket, &token).await;
+    let login = |username: &str| {
+        rocket
+            .client
+            .post("/login")
+            .header(ContentType::JSON)
+            .body(json!({ "username": username, "password": "anypassword" }).to_string())
+            .dispatch()
+    };
+    assert_eq!(login("misato").await.status(), Status::Ok);
+    let database = rocket.client.rocket().state::<Database>().unwrap();
+    let user = database.usermanager.get_user(Some("misato"), None).await;
+    let uuid = user.unwrap().unwrap().uuid;
+
+    let response = rocket
+        .client
+        .post("/admin/delete")
+        .header(ContentType::JSON)
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .body(json!({ "uuid": uuid }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    // Not even its api account still holds the uuid
+    for name in database.mongo.list_collection_names(None).await.unwrap() {
+        let mut cursor = database
+            .mongo
+            .collection::<Value>(&name)
+            .find(None, None)
+            .await
+            .unwrap();
+        while cursor.advance().await.unwrap() {
+            let document = cursor.deserialize_current().unwrap();
+            assert_eq!(
+                (name.as_str(), document.to_string().contains(&uuid)),
+                (name.as_str(), false)
+            );
+        }
+    }
+
+    // The only account, whatever it has become
+    let stored = database.usermanager.users.find_one(None, None).await;
+    let stored = stored.unwrap().unwrap();
+    let id = anonymous_id("test key", &uuid);
+    assert_eq!(stored.uuid, id);
+    assert_eq!(stored.username, anonymous_username(&id));
+    assert!(stored.anonymized);
+    assert_eq!(stored.password, None);
+    assert_eq!(
+        (stored.email, stored.tokens, stored.last_login_ip),
+        (None, None, None)
+    );
+    assert_eq!(login("misato").await.status(), Status::Unauthorized);
+    assert_eq!(login(&stored.username).await.status(), Status::Unauthorized);
+
+    let about = |uuid: String, username: String| async move {
+        database
+            .auditmanager
+            .list_events_about(&uuid, &username)
+            .await
+            .unwrap()
+    };
+    assert_eq!(about(uuid.clone(), "misato".to_string()).await, Vec::new());
+    let events = about(id.clone(), stored.username.clone()).await;
+    let actions: Vec<AuditAction> = events.iter().map(|event| event.action.clone()).collect();
+    for action in [
+        AuditAction::Signup,
+        AuditAction::Login,
+        AuditAction::AccountDeleted,
+    ] {
+        assert!(actions.contains(&action));
+    }
+    let login_event = events
+        .iter()
+        .find(|event| event.action == AuditAction::Login)
+        .unwrap();
+    assert_eq!(login_event.actor, Some(id.clone()));
+    assert_eq!(login_event.ip, None);
+
+    // Purging deleted accounts would break the trail
+    purge(&database.usermanager, 1, get_current_timestamp() + 10_000).await;
+    let stored = database.usermanager.users.find_one(None, None).await;
+    assert_eq!(stored.unwrap().map(|user| user.uuid), Some(id));
+}
+
+#[rocket::async_test]
+async fn revoking_a_refresh_family_spares_the_others() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let token = user_token(&rocket, "misato").await;
+    user_token(&rocket, "asuka").await;
+    let login = |username: &str, user_agent: &str| {
+        client
+            .post("/login")
+            .header(ContentType::JSON)
+            .header(Header::new("User-Agent", user_agent.to_string()))
+            .body(json!({ "username": username, "password": "anypassword" }).to_string())
+            .dispatch()
+    };
+    let refresh = |token: &str| {
+        client
+            .post("/api/account/refresh")
+            .header(ContentType::JSON)
+            .body(json!({ "token": token }).to_string())
+            .dispatch()
+    };
+    let refresh_token = |login: Value| login["refresh_token"].as_str().unwrap().to_string();
+    let laptop = refresh_token(data(login("misato", "Firefox").await).await);
+    let phone = refresh_token(data(login("misato", "Safari").await).await);
+    let other = refresh_token(data(login("asuka", "Firefox").await).await);
+    let response = refresh(&laptop).await;
+    assert_eq!(response.status(), Status::Ok);
+    let rotated = refresh_token(data(response).await);
+
+    let response = client
+        .get("/user/refresh-families")
+        .header(bearer(token.clone()))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let body = response.into_string().await.unwrap();
+    for secret in [&laptop, &rotated, &phone] {
+        assert!(!body.contains(secret.as_str()));
+        assert!(!body.contains(&hash_token(secret)));
+    }
+    let families: Value = serde_json::from_str::<Value>(&body).unwrap()["data"].take();
+    let families = families.as_array().unwrap();
+    assert_eq!(families.len(), 2);
+    assert_eq!(families[0]["rotations"], 1);
+    assert_eq!(families[0]["user_agent"], "Firefox");
+    assert!(families[0]["last_rotated_at"].is_u64());
+    assert_eq!(families[1]["user_agent"], "Safari");
+    assert_eq!(families[1]["last_rotated_at"], Value::Null);
+    let laptop_family = families[0]["id"].as_str().unwrap();
+
+    let revoke = |token: &str, id: &str| {
+        client
+            .delete(format!("/user/refresh-families/{}", id))
+            .header(bearer(token.to_string()))
+            .dispatch()
+    };
+    assert_eq!(
+        revoke(&token, laptop_family).await.status(),
+        Status::NoContent
+    );
+    assert_eq!(
+        revoke(&token, laptop_family).await.status(),
+        Status::NotFound
+    );
+    // The whole lineage is gone, the others still refresh
+    assert_eq!(refresh(&rotated).await.status(), Status::Unauthorized);
+    assert_eq!(refresh(&laptop).await.status(), Status::Unauthorized);
+    let response = refresh(&phone).await;
+    assert_eq!(response.status(), Status::Ok);
+    let phone = refresh_token(data(response).await);
+
+    // An admin revokes a family from its id, without knowing whose it is
+    let response = client
+        .get("/admin/account/users/asuka/refresh-families")
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let families: Value = data(response).await;
+    let other_family = families[0]["id"].as_str().unwrap().to_string();
+    assert_eq!(
+        revoke(&token, &other_family).await.status(),
+        Status::NotFound
+    );
+    let admin_revoke = |id: &str| {
+        client
+            .delete(format!("/admin/refresh-families/{}", id))
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", rocket.admin_token),
+            ))
+            .dispatch()
+    };
+    assert_eq!(
+        admin_revoke(&other_family).await.status(),
+        Status::NoContent
+    );
+    assert_eq!(admin_revoke(&other_family).await.status(), Status::NotFound);
+    assert_eq!(refresh(&other).await.status(), Status::Unauthorized);
+    assert_eq!(refresh(&phone).await.status(), Status::Ok);
+
+    let response = client
+        .get("/admin/audit?action=RefreshFamilyRevoked")
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .dispatch()
+        .await;
+    let page: Value = data(response).await;
+    assert_eq!(page["total"], 1);
+}
+
+#[rocket::async_test]
+async fn reused_refresh_token_revokes_its_family() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    user_token(&rocket, "misato").await;
+    let refresh = |token: String| {
+        client
+            .post("/api/account/refresh")
+            .header(ContentType::JSON)
+            .body(json!({ "token": token }).to_string())
+            .dispatch()
+    };
+    let response = client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(json!({ "username": "misato", "password": "anypassword" }).to_string())
+        .dispatch()
+        .await;
+    let login: Value = data(response).await;
+    let first = login["refresh_token"].as_str().unwrap().to_string();
+
+    let response = refresh(first.clone()).await;
+    assert_eq!(response.status(), Status::Ok);
+    let rotated: Value = data(response).await;
+    let second = rotated["refresh_token"].as_str().unwrap().to_string();
+    assert!(second != first);
+    assert!(rotated["token"].is_string());
+
+    // The rotated token again, as a thief would: the whole family goes
+    let response = refresh(first).await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], "TOKEN_REUSED");
+    let response = refresh(second).await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], "INVALID_TOKEN");
+}
+
+#[rocket::async_test]
+async fn logout_only_ends_its_own_session() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let kept = user_token(&rocket, "misato").await;
+    let log_in = || async {
+        let response = client
+            .post("/login")
+            .header(ContentType::JSON)
+            .body(json!({ "username": "misato", "password": "anypassword" }).to_string())
+            .dispatch()
+            .await;
+        data(response).await
+    };
+    let login: Value = log_in().await;
+    let other_login: Value = log_in().await;
+    let ended = login["token"].as_str().unwrap().to_string();
+    let logout = |token: &str| {
+        client
+            .post("/api/account/logout")
+            .header(ContentType::JSON)
+            .body(json!({ "token": token }).to_string())
+            .dispatch()
+    };
+    let check = |token: &str| {
+        client
+            .post("/admin/check-token")
+            .header(ContentType::JSON)
+            .header(Header::new(
+                "Authorization",
+                format!("Bearer {}", rocket.admin_token),
+            ))
+            .body(json!({ "token": token }).to_string())
+            .dispatch()
+    };
+
+    assert_eq!(logout(&ended).await.status(), Status::NoContent);
+    assert_eq!(logout(&ended).await.status(), Status::NotFound);
+    assert_eq!(check(&ended).await.status(), Status::Unauthorized);
+    let response = check(&kept).await;
+    assert_eq!(response.status(), Status::Ok);
+    let infos: Value = data(response).await;
+    assert_eq!(infos["uuid"], login["uuid"]);
+
+    // Its refresh token went with it, not the one of the other login
+    let refresh = |login: &Value| {
+        client
+            .post("/api/account/refresh")
+            .header(ContentType::JSON)
+            .body(json!({ "token": login["refresh_token"] }).to_string())
+            .dispatch()
+    };
+    assert_eq!(refresh(&login).await.status(), Status::Unauthorized);
+    assert_eq!(refresh(&other_login).await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn sessions_are_listed_and_revoked_one_at_a_time() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    user_token(&rocket, "misato").await;
+    let login = |user_agent: &str| {
+        client
+            .post("/login")
+            .header(ContentType::JSON)
+            .header(Header::new("User-Agent", user_agent.to_string()))
+            .body(json!({ "username": "misato", "password": "anypassword" }).to_string())
+            .dispatch()
+    };
+    let laptop = data(login("Firefox").await).await["token"].take();
+    let phone = data(login("Safari").await).await["token"].take();
+    let (laptop, phone) = (laptop.as_str().unwrap(), phone.as_str().unwrap());
+    let sessions = || async {
+        let response = client
+            .get("/user/account/sessions")
+            .header(bearer(phone.to_string()))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let body = response.into_string().await.unwrap();
+        assert!(!body.contains(laptop) && !body.contains(phone));
+        serde_json::from_str::<Value>(&body).unwrap()["data"].take()
+    };
+    let user_agents = |sessions: &Value| -> Vec<Value> {
+        let sessions = sessions.as_array().unwrap();
+        sessions
+            .iter()
+            .map(|session| session["user_agent"].clone())
+            .collect()
+    };
+
+    // The signup token is a session too, opened without a user agent
+    let listed = sessions().await;
+    assert_eq!(
+        user_agents(&listed),
+        [Value::Null, json!("Firefox"), json!("Safari")]
+    );
+    let revoke = |id: &Value| {
+        client
+            .delete(format!("/user/account/sessions/{}", id.as_str().unwrap()))
+            .header(bearer(phone.to_string()))
+            .dispatch()
+    };
+    assert_eq!(revoke(&listed[1]["id"]).await.status(), Status::NoContent);
+    assert_eq!(revoke(&listed[1]["id"]).await.status(), Status::NotFound);
+    assert_eq!(
+        user_agents(&sessions().await),
+        [Value::Null, json!("Safari")]
+    );
+    let me = |token: &str| {
+        client
+            .get("/user/account/me")
+            .header(bearer(token.to_string()))
+            .dispatch()
+    };
+    assert_eq!(me(laptop).await.status(), Status::Unauthorized);
+    assert_eq!(me(phone).await.status(), Status::Ok);
+}
+
+#[rocket::async_test]
+async fn users_list_is_admin_only_and_clamped() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let token = user_token(&rocket, "misato").await;
+    user_token(&rocket, "shinji").await;
+    let api = api_account(&rocket, &token).await;
+    let users = |query: &str, bearer: Option<String>| {
+        let mut request = client.get(format!("/admin/account/users?{}", query));
+        if let Some(bearer) = bearer {
+            request = request.header(Header::new("Authorization", format!("Bearer {}", bearer)));
+        }
+        request.dispatch()
+    };
+
+    for bearer in [None, Some("not a token".to_string()), Some(token.clone())] {
+        let response = users("page=1", bearer).await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let body: Value = response.into_json().await.unwrap();
+        assert_eq!(body["error"]["code"], "UNAUTHENTICATED");
+    }
+    // A valid api token without the admin role
+    let response = users("page=1", Some(api["token"].as_str().unwrap().to_string())).await;
+    assert_eq!(response.status(), Status::Forbidden);
+
+    let admin = Some(rocket.admin_token.clone());
+    let page: Value = data(users("page=2&limit=1", admin.clone()).await).await;
+    assert_eq!(page["items"][0]["username"], "shinji");
+    assert_eq!(
+        (page["total"].as_u64(), page["has_next"].as_bool()),
+        (Some(2), Some(false))
+    );
+    let page: Value = data(users("page=3&limit=1", admin.clone()).await).await;
+    assert_eq!(page["items"].as_array().unwrap().len(), 0);
+    let page: Value = data(users("page=0&limit=1000", admin).await).await;
+    assert_eq!(
+        (page["page"].as_u64(), page["limit"].as_u64()),
+        (Some(1), Some(100))
+    );
+    assert_eq!(page["items"].as_array().unwrap().len(), 2);
+}
+
+#[rocket::async_test]
+async fn admin_routes_refuse_other_tokens() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let token = user_token(&rocket, "misato").await;
+    let api = api_account(&rocket, &token).await;
+    let api_token = api["token"].as_str().unwrap().to_string();
+    let routes: Vec<(Method, String)> = client
+        .rocket()
+        .routes()
+        .filter(|route| route.uri.path().contains("/admin"))
+        .map(|route| {
+            let segments: Vec<&str> = route
+                .uri
+                .path()
+                .split('/')
+                .map(|segment| match segment.starts_with('<') {
+                    true => "x",
+                    false => segment,
+                })
+                .collect();
+            (route.method, segments.join("/"))
+        })
+        .collect();
+    let body = json!({ "username": "shinji", "password": "anypassword" }).to_string();
+
+    for (method, path) in &routes {
+        for (bearer, status) in [
+            ("not a token", Status::Unauthorized),
+            (token.as_str(), Status::Unauthorized),
+            (api_token.as_str(), Status::Forbidden),
+        ] {
+            let response = client
+                .req(*method, path.as_str())
+                .header(ContentType::JSON)
+                .header(Header::new("Authorization", format!("Bearer {}", bearer)))
+                .body(&body)
+                .dispatch()
+                .await;
+            assert_eq!((method, path, response.status()), (method, path, status));
+        }
+    }
+    // Nothing was done by any of them
+    let response = client
+        .get("/admin/account/users/shinji")
+        .header(Header::new(
+            "Authorization",
+            format!("Bearer {}", rocket.admin_token),
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn password_change_needs_the_old_password() {
+    let Some(rocket) = test_rocket().await else {
+        return;
+    };
+    let client = &rocket.client;
+    let token = user_token(&rocket, "misato").await;
+    let change = |old: &str, new: &str| {
+        client
+            .post("/user/account/password")
+            .header(ContentType::JSON)
+            .header(bearer(&token))
+            .body(json!({ "old_password": old, "new_password": new }).to_string())
+            .dispatch()
+    };
+    let login = |password: &str| {
+        client
+            .post("/login")
+            .header(ContentType::JSON)
+            .body(json!({ "username": "misato", "password": password }).to_string())
+            .dispatch()
+    };
+
+    let response = change("wrongpassword", "a new passphrase").await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], "INVALID_CREDENTIALS");
+    let response = login("anypassword").await;
+    assert_eq!(response.status(), Status::Ok);
+    let before: Value = data(response).await;
+    assert_eq!(
+        login("a new passphrase").await.status(),
+        Status::Unauthorized
+    );
+
+    let response = change("anypassword", "a new passphrase").await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(login("a new passphrase").await.status(), Status::Ok);
+    assert_eq!(login("anypassword").await.status(), Status::Unauthorized);
+
+    // A stolen refresh token can't open a session once the password changed
+    let response = client
+        .post("/api/account/refresh")
+        .header(ContentType::JSON)
+        .body(json!({ "token": before["refresh_token"] }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+}
+
+/// The data of the next `event` delivery, other events are skipped.
+async fn delivery(listener: &TcpListener, event: &str) -> Value {
+    loop {
+        let (headers, body) = receive(listener, "200 OK").await;
+        assert!(header(&headers, "x-signature").is_some());
+        let mut payload: Value = serde_json::from_slice(&body).unwrap();
+        if payload["event"] == event {
+            return payload["data"].take();
+        }
+    }
+}
+
+/// A client sending its webhooks to `listener`.
+async fn test_rocket_with_webhooks(listener: &TcpListener) -> Option<TestRocket> {
+    test_rocket_with(&format!(
+        "MISATO_WEBHOOK_URLS = \"http://{}/hook\"\nMISATO_WEBHOOK_SECRET = \"secret\"",
+        listener.local_addr().unwrap()
+    ))
+    .await
+}
+
+#[rocket::async_test]
+async fn reset_tokens_only_reach_the_webhooks() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let Some(rocket) = test_rocket_with_webhooks(&listener).await else {
+        return;
+    };
+    let client = &rocket.client;
+    user_token(&rocket, "misato").await;
+    let request = |username: &str| {
+        client
+            .post("/api/account/reset/request")
+            .header(ContentType::JSON)
+            .body(json!({ "username": username }).to_string())
+            .dispatch()
+    };
+    let confirm = |token: &str| {
+        client
+            .post("/api/account/reset/confirm")
+            .header(ContentType::JSON)
+            .body(json!({ "token": token, "new_password": "a new passphrase" }).to_string())
+            .dispatch()
+    };
+
+    // Same answer either way, nothing is sent for the unknown one
+    let unknown = request("nobody").await;
+    assert_eq!(unknown.status(), Status::Accepted);
+    let unknown = unknown.into_string().await;
+    let known = request("misato").await;
+    assert_eq!(known.status(), Status::Accepted);
+    assert_eq!(known.into_string().await, unknown);
+    let delivery = delivery(&listener, "password.reset_requested").await;
+    assert_eq!(delivery["username"], "misato");
+    let token = delivery["token"].as_str().unwrap().to_string();
+
+    assert_eq!(confirm(&token).await.status(), Status::NoContent);
+    assert_eq!(
+        login(
+            &rocket,
+            json!({ "username": "misato", "password": "a new passphrase" })
+        )
+        .await,
+        Status::Ok
+    );
+    assert_eq!(confirm(&token).await.status(), Status::Unauthorized);
+
+    // Expired before being used
+    let database = client.rocket().state::<Database>().unwrap();
+    let user = database
+        .usermanager
+        .get_user(Some("misato"), None)
+        .await
+        .unwrap()
+        .unwrap();
+    database
+        .usermanager
+        .save_reset_token(
+            &user.uuid,
+            &UserHashedToken {
+                hash: hash_token("expired"),
+                timestamp: 0,
+                expiration_timestamp: 1,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(confirm("expired").await.status(), Status::Unauthorized);
+}
+
+#[rocket::async_test]
+async fn verified_email_is_required_by_the_guard() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let Some(rocket) = test_rocket_with_webhooks(&listener).await else {
+        return;
+    };
+    let client = &rocket.client;
+    let token = user_token(&rocket, "misato").await;
+    let email = || {
+        client
+            .get("/user/email")
+            .header(bearer(token.clone()))
+            .dispatch()
+    };
+    let confirm = |token: &str| {
+        client
+            .get(format!("/api/account/verify/confirm?token={}", token))
+            .dispatch()
+    };
+
+    let response = client
+        .post("/api/account/verify/request")
+        .header(ContentType::JSON)
+        .header(bearer(&token))
+        .body(json!({ "email": "misato@misato.wiki" }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Accepted);
+    assert_eq!(response.into_string().await.unwrap_or_default(), "");
+    assert_eq!(email().await.status(), Status::Forbidden);
+
+    let delivery = delivery(&listener, "email.verification_requested").await;
+    assert_eq!(delivery["email"], "misato@misato.wiki");
+    let verification = delivery["token"].as_str().unwrap().to_string();
+    assert_eq!(confirm("wrong").await.status(), Status::Unauthorized);
+    assert_eq!(email().await.status(), Status::Forbidden);
+    assert_eq!(confirm(&verification).await.status(), Status::NoContent);
+    let response = email().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(data(response).await, "misato@misato.wiki");
+    assert_eq!(confirm(&verification).await.status(), Status::Unauthorized);
+}
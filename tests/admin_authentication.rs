@@ -0,0 +1,89 @@
+use rocket::http::{ContentType, Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket::Route;
+use serde_json::{json, Value};
+
+use misato_api::{
+    errors::catchers::catchers, fairings::maintenance::MaintenanceMode, pwned::PwnedPasswords,
+};
+use misato_database::database::Database;
+use misato_utils::{config::Config, settings::Settings};
+
+fn settings() -> Settings {
+    let config = Config::from_toml(
+        "MONGODB_URI = \"mongodb://127.0.0.1:1\"\nMONGODB_NAME = \"misato\"\n\
+         MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"",
+    )
+    .unwrap();
+    Settings::from_config(&config).unwrap()
+}
+
+/// Every admin route of the API, under all its bases, with its path parameters filled in.
+fn admin_routes() -> Vec<(Route, String)> {
+    misato_api::rocket(settings())
+        .routes()
+        .filter(|route| route.uri.path().contains("/admin"))
+        .map(|route| {
+            let path = route
+                .uri
+                .path()
+                .split('/')
+                .map(|segment| match segment.starts_with('<') {
+                    true => "x",
+                    false => segment,
+                })
+                .collect::<Vec<&str>>()
+                .join("/");
+            (route.clone(), path)
+        })
+        .collect()
+}
+
+#[rocket::async_test]
+async fn admin_routes_need_admin_credentials() {
+    let routes = admin_routes();
+    // The ones of `/admin`, `/api/v1/admin` and `/api/admin`
+    assert!(routes.len() > 30);
+    let settings = settings();
+    let rocket = rocket::build()
+        .manage(Database::open(&settings).await.unwrap())
+        .manage(PwnedPasswords::from_settings(&settings))
+        .manage(MaintenanceMode::default())
+        .manage(settings)
+        .register("/", catchers())
+        .mount(
+            "/",
+            routes
+                .iter()
+                .map(|(route, _)| route.clone())
+                .collect::<Vec<Route>>(),
+        );
+    let client = Client::untracked(rocket).await.unwrap();
+    // Valid for the admin signup, so it gets past its body too
+    let body = json!({ "username": "misato", "password": "anypassword" }).to_string();
+
+    for (route, path) in &routes {
+        for header in [
+            None,
+            Some(Header::new(
+                "Authorization",
+                "Basic Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe",
+            )),
+        ] {
+            let mut request = client
+                .req(route.method, path.as_str())
+                .header(ContentType::JSON)
+                .body(&body);
+            if let Some(header) = header {
+                request = request.header(header);
+            }
+            let response = request.dispatch().await;
+            assert_eq!(
+                (route.method, path.as_str(), response.status()),
+                (route.method, path.as_str(), Status::Unauthorized)
+            );
+            let body: Value = response.into_json().await.unwrap();
+            assert_eq!(body["error"]["code"], "UNAUTHENTICATED");
+        }
+    }
+}
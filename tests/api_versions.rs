@@ -0,0 +1,82 @@
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use serde_json::Value;
+
+use misato_api::{
+    captcha::Captcha,
+    errors::catchers::catchers,
+    fairings::{deprecation::ApiDeprecation, idempotency::SignupResults},
+    pwned::PwnedPasswords,
+    routes::api::{self, v1},
+};
+use misato_database::database::Database;
+use misato_utils::{config::Config, settings::Settings};
+
+fn settings() -> Settings {
+    let config = Config::from_toml(
+        "MONGODB_URI = \"mongodb://127.0.0.1:1\"\nMONGODB_NAME = \"misato\"\n\
+         MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"",
+    )
+    .unwrap();
+    Settings::from_config(&config).unwrap()
+}
+
+#[test]
+fn legacy_base_mounts_the_same_handlers() {
+    let rocket = misato_api::rocket(settings());
+    let mounted = |base: &str| {
+        let mut routes: Vec<(String, String, String)> = rocket
+            .routes()
+            .filter(|route| route.uri.base() == base)
+            .map(|route| {
+                (
+                    route.method.to_string(),
+                    route.uri.unmounted_origin.path().to_string(),
+                    route.name.as_deref().unwrap_or_default().to_string(),
+                )
+            })
+            .collect();
+        routes.sort();
+        routes
+    };
+    assert_eq!(mounted(v1::BASE).len(), v1::routes().len());
+    assert_eq!(mounted(api::LEGACY_BASE), mounted(v1::BASE));
+}
+
+#[rocket::async_test]
+async fn legacy_paths_answer_alike_and_are_flagged() {
+    let settings = settings();
+    let rocket = rocket::build()
+        .manage(Database::open(&settings).await.unwrap())
+        .manage(Captcha::from_settings(&settings))
+        .manage(PwnedPasswords::from_settings(&settings))
+        .manage(SignupResults::new(60, 100))
+        .manage(settings)
+        .attach(ApiDeprecation::new(api::LEGACY_BASE, v1::BASE))
+        .register("/", catchers())
+        .mount(v1::BASE, v1::routes())
+        .mount(api::LEGACY_BASE, v1::routes());
+    let client = Client::tracked(rocket).await.unwrap();
+
+    // Refused by the api token guard, before it reaches MongoDB
+    let mut answers = Vec::new();
+    for path in ["/api/v1/keys", "/api/keys"] {
+        let response = client.get(path).dispatch().await;
+        let headers = (
+            response.headers().get_one("Deprecation").map(String::from),
+            response.headers().get_one("Link").map(String::from),
+        );
+        let answer: (Status, Value) = (response.status(), response.into_json().await.unwrap());
+        answers.push((answer, headers));
+    }
+    assert_eq!(answers[0].0, answers[1].0);
+    assert_eq!(answers[0].0 .0, Status::Unauthorized);
+    assert_eq!(answers[0].1, (None, None));
+    assert_eq!(
+        answers[1].1,
+        (
+            Some("true".to_string()),
+            Some("</api/v1/keys>; rel=\"successor-version\"".to_string())
+        )
+    );
+}
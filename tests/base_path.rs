@@ -0,0 +1,57 @@
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use rocket::Route;
+
+use misato_api::{errors::catchers::catchers, fairings::metrics::MetricsFairing};
+use misato_utils::{config::Config, settings::Settings};
+
+fn settings() -> Settings {
+    let config = Config::from_toml(
+        "MONGODB_URI = \"mongodb://127.0.0.1:1\"\nMONGODB_NAME = \"misato\"\n\
+         MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"\n\
+         MISATO_BASE_PATH = \"/misato-api/\"",
+    )
+    .unwrap();
+    Settings::from_config(&config).unwrap()
+}
+
+#[test]
+fn every_route_is_under_the_base_path() {
+    let rocket = misato_api::rocket(settings());
+    let paths: Vec<&str> = rocket.routes().map(|route| route.uri.path()).collect();
+    assert!(!paths.is_empty());
+    for path in &paths {
+        assert!(path.starts_with("/misato-api/"), "{}", path);
+    }
+    for path in [
+        "/misato-api/health",
+        "/misato-api/metrics",
+        "/misato-api/api/v1/signup",
+    ] {
+        assert!(paths.contains(&path), "{}", path);
+    }
+}
+
+#[rocket::async_test]
+async fn routes_resolve_under_the_base_path_only() {
+    // The routes needing no state, as mounted by the API
+    let routes: Vec<Route> = misato_api::rocket(settings())
+        .routes()
+        .filter(|route| {
+            route.uri.path().ends_with("/health") || route.uri.path().ends_with("/metrics")
+        })
+        .cloned()
+        .collect();
+    let rocket = rocket::build()
+        .attach(MetricsFairing)
+        .register("/", catchers())
+        .mount("/", routes);
+    let client = Client::tracked(rocket).await.unwrap();
+
+    for path in ["/misato-api/health", "/misato-api/metrics"] {
+        assert_eq!(client.get(path).dispatch().await.status(), Status::Ok);
+    }
+    for path in ["/health", "/metrics", "/misato-api"] {
+        assert_eq!(client.get(path).dispatch().await.status(), Status::NotFound);
+    }
+}
@@ -0,0 +1,97 @@
+use std::sync::{Arc, Mutex};
+
+use rocket::tokio::{
+    self,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+use misato_api::captcha::Captcha;
+use misato_api::errors::api_errors::ApiError;
+
+/// A `siteverify` endpoint accepting only `good-token`, returns its url and the bodies posted.
+async fn mock_provider() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/siteverify", listener.local_addr().unwrap());
+    let bodies = Arc::new(Mutex::new(Vec::new()));
+    let posted = bodies.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 1024];
+            let body = loop {
+                let text = String::from_utf8_lossy(&request).to_string();
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length = head
+                        .lines()
+                        .find_map(|line| {
+                            let (name, value) = line.split_once(':')?;
+                            match name.eq_ignore_ascii_case("content-length") {
+                                true => value.trim().parse::<usize>().ok(),
+                                false => None,
+                            }
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break body.to_string();
+                    }
+                }
+                let read = stream.read(&mut buffer).await.unwrap();
+                if read == 0 {
+                    break String::new();
+                }
+                request.extend_from_slice(&buffer[..read]);
+            };
+            let answer = match body.contains("response=good-token") {
+                true => r#"{"success": true, "error-codes": []}"#,
+                false => r#"{"success": false, "error-codes": ["invalid-input-response"]}"#,
+            };
+            posted.lock().unwrap().push(body);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                answer.len(),
+                answer
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    (url, bodies)
+}
+
+#[rocket::async_test]
+async fn captcha_tokens_are_checked_with_the_provider() {
+    let (url, bodies) = mock_provider().await;
+    let captcha = Captcha::new(Some(url), "shh".to_string());
+    let ip = "203.0.113.7".parse().ok();
+
+    assert!(captcha.verify(Some("good-token"), ip).await.is_ok());
+    let result = captcha.verify(Some("bad-token"), None).await;
+    assert!(matches!(result, Err(ApiError::InvalidCaptcha)));
+    // Nothing is asked without a token
+    let result = captcha.verify(None, ip).await;
+    assert!(matches!(result, Err(ApiError::CaptchaRequired)));
+    let result = captcha.verify(Some(""), ip).await;
+    assert!(matches!(result, Err(ApiError::CaptchaRequired)));
+
+    let bodies = bodies.lock().unwrap().clone();
+    assert_eq!(bodies.len(), 2);
+    assert!(bodies[0].contains("secret=shh"));
+    assert!(bodies[0].contains("remoteip=203.0.113.7"));
+    assert!(!bodies[1].contains("remoteip"));
+}
+
+#[rocket::async_test]
+async fn unreachable_provider_fails_closed() {
+    // Bound then dropped, so nothing listens there anymore
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/siteverify", listener.local_addr().unwrap());
+    drop(listener);
+
+    let captcha = Captcha::new(Some(url), "shh".to_string());
+    let result = captcha.verify(Some("good-token"), None).await;
+    assert!(matches!(result, Err(ApiError::CaptchaUnavailable)));
+
+    let off = Captcha::new(None, String::new());
+    assert!(off.verify(None, None).await.is_ok());
+}
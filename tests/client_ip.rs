@@ -0,0 +1,115 @@
+use std::net::SocketAddr;
+
+use rocket::local::asynchronous::Client;
+use rocket::{get, routes};
+
+use misato_api::fairings::client_info::ClientIp;
+use misato_utils::{config::Config, settings::Settings};
+
+#[get("/ip")]
+fn ip(client: ClientIp) -> String {
+    match client.0 {
+        Some(ip) => ip.to_string(),
+        None => "none".to_string(),
+    }
+}
+
+async fn client(extra: &str) -> Client {
+    let config = Config::from_toml(&format!(
+        "MONGODB_URI = \"mongodb://localhost\"\nMONGODB_NAME = \"misato\"\n\
+         MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"\n{}",
+        extra
+    ))
+    .unwrap();
+    let rocket = rocket::build()
+        .manage(Settings::from_config(&config).unwrap())
+        .mount("/", routes![ip]);
+    Client::tracked(rocket).await.unwrap()
+}
+
+async fn ip_of(client: &Client, peer: &str, headers: &[(&'static str, &'static str)]) -> String {
+    let mut request = client
+        .get("/ip")
+        .remote(peer.parse::<SocketAddr>().unwrap());
+    for (name, value) in headers {
+        request = request.header(rocket::http::Header::new(*name, *value));
+    }
+    request.dispatch().await.into_string().await.unwrap()
+}
+
+#[rocket::async_test]
+async fn direct_connections_use_the_peer() {
+    let client = client("").await;
+    assert_eq!(ip_of(&client, "203.0.113.7:4000", &[]).await, "203.0.113.7");
+    // Nothing is trusted, so the headers are whatever the client wants
+    let spoofed = [
+        ("X-Forwarded-For", "198.51.100.1"),
+        ("X-Real-IP", "198.51.100.1"),
+        ("Forwarded", "for=198.51.100.1"),
+    ];
+    assert_eq!(
+        ip_of(&client, "203.0.113.7:4000", &spoofed).await,
+        "203.0.113.7"
+    );
+    assert_eq!(
+        client
+            .get("/ip")
+            .dispatch()
+            .await
+            .into_string()
+            .await
+            .unwrap(),
+        "none"
+    );
+}
+
+#[rocket::async_test]
+async fn forwarded_headers_are_only_trusted_from_proxies() {
+    let client = client("MISATO_TRUSTED_PROXIES = \"10.0.0.0/8, 2001:db8::1\"").await;
+    let headers = [("X-Forwarded-For", "198.51.100.1, 203.0.113.7, 10.0.0.2")];
+    // The client claims 198.51.100.1, the first proxy saw 203.0.113.7
+    assert_eq!(
+        ip_of(&client, "10.0.0.1:4000", &headers).await,
+        "203.0.113.7"
+    );
+    assert_eq!(
+        ip_of(&client, "[2001:db8::1]:4000", &headers).await,
+        "203.0.113.7"
+    );
+    // Not from a proxy, the header is ignored
+    assert_eq!(
+        ip_of(&client, "192.0.2.5:4000", &headers).await,
+        "192.0.2.5"
+    );
+
+    let headers = [
+        ("Forwarded", "for=\"[2001:db8:cafe::17]:4711\";proto=https"),
+        ("X-Forwarded-For", "198.51.100.1"),
+    ];
+    assert_eq!(
+        ip_of(&client, "10.0.0.1:4000", &headers).await,
+        "2001:db8:cafe::17"
+    );
+    // Unusable chains fall back to the proxy itself
+    let headers = [("Forwarded", "for=_hidden")];
+    assert_eq!(ip_of(&client, "10.0.0.1:4000", &headers).await, "10.0.0.1");
+    assert_eq!(ip_of(&client, "10.0.0.1:4000", &[]).await, "10.0.0.1");
+}
+
+#[rocket::async_test]
+async fn a_configured_header_replaces_the_standard_ones() {
+    let client = client(
+        "MISATO_TRUSTED_PROXIES = \"10.0.0.1\"\nMISATO_TRUSTED_PROXY_HEADER = \"X-Real-IP\"",
+    )
+    .await;
+    let headers = [
+        ("X-Real-IP", "203.0.113.7"),
+        ("X-Forwarded-For", "198.51.100.1"),
+    ];
+    assert_eq!(
+        ip_of(&client, "10.0.0.1:4000", &headers).await,
+        "203.0.113.7"
+    );
+    let headers = [("X-Forwarded-For", "198.51.100.1")];
+    assert_eq!(ip_of(&client, "10.0.0.1:4000", &headers).await, "10.0.0.1");
+}
@@ -0,0 +1,92 @@
+pub mod webhook;
+
+use rocket::local::asynchronous::{Client, LocalResponse};
+use rocket::tokio::{runtime::Handle, task};
+use serde_json::Value;
+
+use misato_database::database::Database;
+use misato_security::generate_token;
+use misato_utils::{config::Config, settings::Settings};
+
+/// A client for the whole API, backed by a database of its own on the MongoDB server
+/// given by `MISATO_TEST_MONGODB_URI`, dropped with it even when the test fails.
+/// None when it is unset, tests using it then pass without running, as the doctests do:
+///
+/// ```text
+/// MISATO_TEST_MONGODB_URI=mongodb://localhost:27017 cargo test
+/// ```
+pub struct TestRocket {
+    pub client: Client,
+    pub admin_token: String,
+}
+
+pub async fn test_rocket() -> Option<TestRocket> {
+    test_rocket_with("").await
+}
+
+/// Like `test_rocket`, with `extra` TOML lines appended to the settings.
+pub async fn test_rocket_with(extra: &str) -> Option<TestRocket> {
+    let admin_token = generate_token(64);
+    let client = client(&format!(
+        "MISATO_ADMIN_TOKEN = {:?}\n{}",
+        admin_token, extra
+    ))
+    .await?;
+    Some(TestRocket {
+        client,
+        admin_token,
+    })
+}
+
+/// Without a seeded admin, the first user becomes one, `admin_token` is empty.
+pub async fn test_rocket_first_user_admin() -> Option<TestRocket> {
+    let client = client("MISATO_FIRST_USER_ADMIN = true").await?;
+    Some(TestRocket {
+        client,
+        admin_token: String::new(),
+    })
+}
+
+async fn client(settings: &str) -> Option<Client> {
+    let uri = std::env::var("MISATO_TEST_MONGODB_URI").ok()?;
+    let config = Config::from_toml(&format!(
+        "MONGODB_URI = {:?}\nMONGODB_NAME = \"misato_test_{}\"\n{}",
+        uri,
+        uuid::Uuid::new_v4().simple(),
+        settings
+    ))
+    .unwrap();
+    let settings = Settings::from_config(&config).unwrap();
+    Some(Client::tracked(misato_api::rocket(settings)).await.unwrap())
+}
+
+impl TestRocket {
+    /// Another client for the same database, as after a restart with `change` made to the settings.
+    /// Only this one drops the database.
+    pub async fn reboot(&self, change: impl FnOnce(&mut Settings)) -> Client {
+        let mut settings = self.client.rocket().state::<Settings>().unwrap().clone();
+        change(&mut settings);
+        Client::tracked(misato_api::rocket(settings)).await.unwrap()
+    }
+}
+
+impl Drop for TestRocket {
+    /// Drop the database of this test, a failure is only reported to not hide the test's.
+    fn drop(&mut self) {
+        let database = match self.client.rocket().state::<Database>() {
+            Some(database) => database,
+            None => return,
+        };
+        let result = task::block_in_place(|| Handle::current().block_on(database.mongo.drop(None)));
+        if let Err(error) = result {
+            eprintln!("Cannot drop the test database [{:?}]", error);
+        }
+    }
+}
+
+/// `data` of a success envelope, checking there is no error next to it.
+pub async fn data(response: LocalResponse<'_>) -> Value {
+    let mut body: Value = response.into_json().await.unwrap();
+    assert_eq!(body.get("error"), Some(&Value::Null));
+    body["data"].take()
+}
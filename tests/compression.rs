@@ -0,0 +1,112 @@
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket::{get, routes};
+
+use misato_api::{
+    fairings::compression::Compression,
+    routes::root::{docs, health},
+};
+use misato_utils::{config::Config, settings::Settings};
+
+/// Large enough to be encoded on a blocking thread.
+#[get("/large")]
+fn large() -> String {
+    "{\"username\":\"misato\"}".repeat(10_000)
+}
+
+async fn client(extra: &str) -> Client {
+    let config = Config::from_toml(&format!(
+        "MONGODB_URI = \"mongodb://localhost\"\nMONGODB_NAME = \"misato\"\n\
+         MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"\n{}",
+        extra
+    ))
+    .unwrap();
+    let settings = Settings::from_config(&config).unwrap();
+    let compression = Compression::from_settings(&settings).unwrap();
+    let rocket = rocket::build()
+        .manage(settings)
+        .attach(compression)
+        .mount("/", routes![docs::openapi_json, health::health, large]);
+    Client::tracked(rocket).await.unwrap()
+}
+
+#[rocket::async_test]
+async fn large_json_is_compressed_when_asked() {
+    let client = client("").await;
+
+    let plain = client.get("/openapi.json").dispatch().await;
+    assert_eq!(plain.status(), Status::Ok);
+    assert_eq!(plain.headers().get_one("Content-Encoding"), None);
+    assert_eq!(plain.headers().get_one("Vary"), Some("Accept-Encoding"));
+    let plain = plain.into_bytes().await.unwrap();
+    assert!(plain.len() > 1024);
+    assert!(serde_json::from_slice::<serde_json::Value>(&plain).is_ok());
+
+    let br = client
+        .get("/openapi.json")
+        .header(Header::new("Accept-Encoding", "gzip, deflate, br"))
+        .dispatch()
+        .await;
+    assert_eq!(br.headers().get_one("Content-Encoding"), Some("br"));
+    let br = br.into_bytes().await.unwrap();
+    assert!(br.len() < plain.len() / 2);
+
+    let gzip = client
+        .get("/openapi.json")
+        .header(Header::new("Accept-Encoding", "br;q=0.5, gzip;q=0.8"))
+        .dispatch()
+        .await;
+    assert_eq!(gzip.status(), Status::Ok);
+    assert_eq!(gzip.headers().get_one("Content-Encoding"), Some("gzip"));
+    let gzip = gzip.into_bytes().await.unwrap();
+    assert_eq!(gzip[..2], [0x1f, 0x8b]);
+    assert_eq!(gzip[gzip.len() - 4..], (plain.len() as u32).to_le_bytes());
+    assert!(gzip.len() < plain.len() / 2);
+
+    let deflate = client
+        .get("/openapi.json")
+        .header(Header::new("Accept-Encoding", "deflate"))
+        .dispatch()
+        .await;
+    assert_eq!(
+        deflate.headers().get_one("Content-Encoding"),
+        Some("deflate")
+    );
+}
+
+#[rocket::async_test]
+async fn small_bodies_are_sent_as_is() {
+    let defaults = client("").await;
+    let response = defaults
+        .get("/health")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.headers().get_one("Content-Encoding"), None);
+
+    let unlimited = client("MISATO_COMPRESSION_MIN_SIZE = 0").await;
+    let response = unlimited
+        .get("/health")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch()
+        .await;
+    // Too short to shrink, even with no threshold
+    assert_eq!(response.headers().get_one("Content-Encoding"), None);
+}
+
+#[rocket::async_test]
+async fn large_bodies_are_compressed_too() {
+    let client = client("").await;
+    let response = client
+        .get("/large")
+        .header(Header::new("Accept-Encoding", "gzip"))
+        .dispatch()
+        .await;
+
+    assert_eq!(response.headers().get_one("Content-Encoding"), Some("gzip"));
+    let gzip = response.into_bytes().await.unwrap();
+    assert_eq!(gzip[..2], [0x1f, 0x8b]);
+    assert_eq!(gzip[gzip.len() - 4..], (large().len() as u32).to_le_bytes());
+}
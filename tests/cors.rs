@@ -0,0 +1,131 @@
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+
+use misato_api::{errors::catchers::catchers, fairings::cors::Cors, routes::root::health};
+use misato_utils::{
+    config::{Config, ConfigError},
+    settings::Settings,
+};
+
+const ORIGIN: &str = "https://misato.wiki";
+
+fn settings(extra: &str) -> Result<Settings, ConfigError> {
+    let config = Config::from_toml(&format!(
+        "MONGODB_URI = \"mongodb://localhost\"\nMONGODB_NAME = \"misato\"\n\
+         MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"\n{}",
+        extra
+    ))
+    .unwrap();
+    Settings::from_config(&config)
+}
+
+async fn client(settings: Settings) -> Client {
+    let rocket = rocket::build()
+        .manage(settings)
+        .attach(Cors)
+        .register("/", catchers())
+        .mount("/", routes![health::health]);
+    Client::tracked(rocket).await.unwrap()
+}
+
+#[rocket::async_test]
+async fn credentials_echo_the_allowed_origin() {
+    let settings = settings(&format!(
+        "MISATO_CORS_ORIGINS = {:?}\nMISATO_CORS_ALLOW_CREDENTIALS = true\n\
+         MISATO_CORS_EXPOSE_HEADERS = \"X-Request-Id, Retry-After\"",
+        ORIGIN
+    ))
+    .unwrap();
+    let client = client(settings).await;
+    let response = client
+        .get("/health")
+        .header(Header::new("Origin", ORIGIN))
+        .dispatch()
+        .await;
+    let headers = response.headers();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(headers.get_one("Access-Control-Allow-Origin"), Some(ORIGIN));
+    assert_eq!(
+        headers.get_one("Access-Control-Allow-Credentials"),
+        Some("true")
+    );
+    assert_eq!(
+        headers.get_one("Access-Control-Expose-Headers"),
+        Some("X-Request-Id, Retry-After")
+    );
+
+    let response = client
+        .get("/health")
+        .header(Header::new("Origin", "https://evil.example"))
+        .dispatch()
+        .await;
+    let headers = response.headers();
+    assert_eq!(headers.get_one("Access-Control-Allow-Origin"), None);
+    assert_eq!(headers.get_one("Access-Control-Allow-Credentials"), None);
+}
+
+#[rocket::async_test]
+async fn credentials_are_off_by_default() {
+    let settings = settings(&format!("MISATO_CORS_ORIGINS = {:?}", ORIGIN)).unwrap();
+    let client = client(settings).await;
+    let response = client
+        .get("/health")
+        .header(Header::new("Origin", ORIGIN))
+        .dispatch()
+        .await;
+    let headers = response.headers();
+
+    assert_eq!(headers.get_one("Access-Control-Allow-Origin"), Some(ORIGIN));
+    assert_eq!(headers.get_one("Access-Control-Allow-Credentials"), None);
+    assert_eq!(headers.get_one("Access-Control-Expose-Headers"), None);
+}
+
+#[rocket::async_test]
+async fn preflight_requests_are_answered() {
+    let settings = settings(&format!("MISATO_CORS_ORIGINS = {:?}", ORIGIN)).unwrap();
+    let client = client(settings).await;
+    let preflight = |origin: &'static str| {
+        client
+            .options("/health")
+            .header(Header::new("Origin", origin))
+            .header(Header::new("Access-Control-Request-Method", "POST"))
+            .header(Header::new(
+                "Access-Control-Request-Headers",
+                "content-type, x-misato-user-token",
+            ))
+            .dispatch()
+    };
+
+    let response = preflight(ORIGIN).await;
+    assert_eq!(response.status(), Status::NoContent);
+    let headers = response.headers();
+    assert_eq!(headers.get_one("Access-Control-Allow-Origin"), Some(ORIGIN));
+    let methods = headers.get_one("Access-Control-Allow-Methods").unwrap();
+    assert!(methods.split(", ").any(|method| method == "POST"));
+    let allowed = headers.get_one("Access-Control-Allow-Headers").unwrap();
+    for header in ["Content-Type", "X-Misato-User-Token"] {
+        assert!(allowed.split(", ").any(|allowed| allowed == header));
+    }
+    assert_eq!(response.into_string().await.unwrap_or_default(), "");
+
+    // Other origins get nothing to go on
+    let response = preflight("https://evil.example").await;
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Origin"),
+        None
+    );
+    assert_eq!(
+        response.headers().get_one("Access-Control-Allow-Methods"),
+        None
+    );
+}
+
+#[test]
+fn wildcard_with_credentials_is_refused() {
+    let error = settings("MISATO_CORS_ORIGINS = \"*\"\nMISATO_CORS_ALLOW_CREDENTIALS = true");
+    assert!(matches!(error, Err(ConfigError::CredentialedWildcard)));
+    assert!(settings("MISATO_CORS_ORIGINS = \"*\"").is_ok());
+}
@@ -0,0 +1,70 @@
+use std::collections::BTreeSet;
+
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+use serde_json::Value;
+
+use misato_api::routes::{api, root::docs};
+use misato_utils::{config::Config, settings::Settings};
+
+fn settings() -> Settings {
+    let config = Config::from_toml(
+        "MONGODB_URI = \"mongodb://127.0.0.1:1\"\nMONGODB_NAME = \"misato\"\n\
+         MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"",
+    )
+    .unwrap();
+    Settings::from_config(&config).unwrap()
+}
+
+#[rocket::async_test]
+async fn openapi_json_lists_the_known_paths() {
+    let rocket = rocket::build()
+        .manage(settings())
+        .mount("/", routes![docs::openapi_json, docs::docs]);
+    let client = Client::tracked(rocket).await.unwrap();
+
+    let response = client.get("/openapi.json").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let spec: Value = response.into_json().await.unwrap();
+    assert_eq!(spec["openapi"], "3.0.3");
+    assert_eq!(spec["info"]["version"], env!("CARGO_PKG_VERSION"));
+    for (path, method) in [
+        ("/login", "post"),
+        ("/user/account/me", "get"),
+        ("/admin/signup", "post"),
+        ("/api/v1/signup", "post"),
+    ] {
+        assert!(spec["paths"][path][method].is_object(), "{}", path);
+    }
+
+    let response = client.get("/docs").dispatch().await;
+    assert_eq!(response.content_type(), Some(ContentType::HTML));
+}
+
+#[test]
+fn openapi_documents_every_mounted_route() {
+    let documented: BTreeSet<(String, String)> = docs::openapi("")["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(path, operations)| {
+            operations
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(move |method| (method.to_uppercase(), path.clone()))
+        })
+        .collect();
+    // The legacy base only repeats `/api/v1`
+    let mounted: BTreeSet<(String, String)> = misato_api::rocket(settings())
+        .routes()
+        .filter(|route| route.uri.base() != api::LEGACY_BASE)
+        .map(|route| {
+            let path = route.uri.path().replace('<', "{").replace('>', "}");
+            (route.method.as_str().to_string(), path)
+        })
+        .collect();
+    assert_eq!(documented, mounted);
+}
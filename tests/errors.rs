@@ -0,0 +1,180 @@
+use std::time::Duration;
+
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::{Client, LocalResponse};
+use rocket::{get, post, routes};
+use serde_json::Value;
+
+use misato_api::errors::{api_errors::ApiError, catchers::catchers};
+use misato_api::fairings::idempotency::{IdempotencyKey, SignupResults};
+use misato_api::routes::user::account;
+
+use misato::models::apiaccount_model::ApiAccountTokenInfos;
+
+#[get("/broken")]
+fn broken() -> Result<&'static str, ApiError> {
+    Err(ApiError::DbError)
+}
+
+#[post("/keyed")]
+fn keyed(_key: IdempotencyKey<'_>) -> &'static str {
+    "keyed"
+}
+
+/// Runs as long as a slow signup, under the `Idempotency-Key` of a single account.
+#[post("/slow-keyed")]
+async fn slow_keyed(key: IdempotencyKey<'_>) -> Result<String, ApiError> {
+    if let Some(previous) = key.reserve("misato")? {
+        return Ok(previous.token);
+    }
+    rocket::tokio::time::sleep(Duration::from_millis(200)).await;
+    let result = ApiAccountTokenInfos {
+        token: "first".to_string(),
+        ..Default::default()
+    };
+    key.record("misato", &result);
+    Ok(result.token)
+}
+
+#[get("/items/<id>")]
+fn item(id: u64) -> String {
+    id.to_string()
+}
+
+/// Routes failing before they need MongoDB, with every catcher.
+async fn client() -> Client {
+    let rocket = rocket::build()
+        .manage(SignupResults::new(60, 100))
+        .register("/", catchers())
+        .mount(
+            "/",
+            routes![
+                broken,
+                keyed,
+                slow_keyed,
+                item,
+                account::me,
+                account::sessions
+            ],
+        );
+    Client::tracked(rocket).await.unwrap()
+}
+
+/// The error of a failure envelope, checking there is no data next to it.
+async fn error(response: LocalResponse<'_>) -> Value {
+    let mut body: Value = response.into_json().await.unwrap();
+    let mut keys: Vec<&String> = body.as_object().unwrap().keys().collect();
+    keys.sort();
+    assert_eq!(keys, ["data", "error"]);
+    assert_eq!(body["data"], Value::Null);
+    assert!(body["error"]["message"].is_string());
+    body["error"].take()
+}
+
+#[rocket::async_test]
+async fn errors_share_one_json_shape() {
+    let client = client().await;
+
+    let response = client.get("/broken").dispatch().await;
+    assert_eq!(response.status(), Status::InternalServerError);
+    assert_eq!(error(response).await["code"], "DB_ERROR");
+
+    // Guards failing with 400 go through the catcher too
+    let response = client
+        .post("/keyed")
+        .header(Header::new("Idempotency-Key", "k".repeat(256)))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+    assert_eq!(error(response).await["code"], "BAD_REQUEST");
+
+    let codes = [
+        (
+            ApiError::InvalidCredentials,
+            Status::Unauthorized,
+            "INVALID_CREDENTIALS",
+        ),
+        (
+            ApiError::UserExists("misato".to_string()),
+            Status::Conflict,
+            "USER_EXISTS",
+        ),
+        (ApiError::DbError, Status::InternalServerError, "DB_ERROR"),
+    ];
+    for (error, status, code) in codes {
+        assert_eq!((error.status(), error.code()), (status, code));
+    }
+}
+
+#[rocket::async_test]
+async fn missing_user_tokens_are_unauthenticated() {
+    let client = client().await;
+    for path in ["/user/account/me", "/user/account/sessions"] {
+        let response = client.get(path).dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(error(response).await["code"], "UNAUTHENTICATED");
+    }
+
+    let response = client
+        .get("/user/account/me")
+        .header(Header::new("Authorization", "Bearer one"))
+        .header(Header::new("Authorization", "Bearer two"))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Unauthorized);
+    assert_eq!(error(response).await["code"], "UNAUTHENTICATED");
+}
+
+#[rocket::async_test]
+async fn unknown_paths_and_wrong_methods_are_told_apart() {
+    let client = client().await;
+
+    let response = client.get("/nowhere").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    assert_eq!(error(response).await["code"], "ROUTE_NOT_FOUND");
+
+    let response = client.post("/user/account/me").dispatch().await;
+    assert_eq!(response.status(), Status::MethodNotAllowed);
+    assert_eq!(response.headers().get_one("Allow"), Some("GET"));
+    assert_eq!(error(response).await["code"], "METHOD_NOT_ALLOWED");
+    let response = client.delete("/keyed").dispatch().await;
+    assert_eq!(response.status(), Status::MethodNotAllowed);
+    assert_eq!(response.headers().get_one("Allow"), Some("POST"));
+
+    // Parameters match any segment, not more
+    let response = client.post("/items/1").dispatch().await;
+    assert_eq!(response.headers().get_one("Allow"), Some("GET"));
+    let response = client.post("/items/1/more").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    // Forwarded by its own route, rather than routed for another method
+    let response = client.get("/items/one").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+    // Left to the CORS fairing
+    let response = client.options("/user/account/me").dispatch().await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn idempotency_keys_run_one_request_at_a_time() {
+    let client = client().await;
+    let request = || {
+        client
+            .post("/slow-keyed")
+            .header(Header::new("Idempotency-Key", "signup-1"))
+            .dispatch()
+    };
+
+    let (first, second) = rocket::tokio::join!(request(), request());
+    let mut statuses = [first.status(), second.status()];
+    statuses.sort_by_key(|status| status.code);
+    assert_eq!(statuses, [Status::Ok, Status::Conflict]);
+    let running = match first.status() == Status::Conflict {
+        true => first,
+        false => second,
+    };
+    assert_eq!(error(running).await["code"], "IDEMPOTENCY_KEY_IN_USE");
+
+    let retry = request().await;
+    assert_eq!(retry.status(), Status::Ok);
+    assert_eq!(retry.into_string().await.unwrap(), "first");
+}
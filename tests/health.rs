@@ -0,0 +1,45 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+use serde_json::Value;
+
+use misato_api::routes::root::health;
+use misato_database::database::Database;
+use misato_utils::{config::Config, settings::Settings};
+
+#[rocket::async_test]
+async fn health_tells_the_version() {
+    let rocket = rocket::build().mount("/", routes![health::health]);
+    let client = Client::tracked(rocket).await.unwrap();
+
+    let response = client.get("/health").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["name"], env!("CARGO_PKG_NAME"));
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+}
+
+#[rocket::async_test]
+async fn health_db_fails_without_mongodb() {
+    // Nothing listens on port 1
+    let config = Config::from_toml(
+        "MONGODB_URI = \"mongodb://127.0.0.1:1\"\nMONGODB_NAME = \"misato\"\n\
+         MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"\n\
+         MONGODB_SERVER_SELECTION_TIMEOUT = 300",
+    )
+    .unwrap();
+    let settings = Settings::from_config(&config).unwrap();
+    let rocket = rocket::build()
+        .manage(Database::open(&settings).await.unwrap())
+        .mount("/", routes![health::health, health::health_db]);
+    let client = Client::tracked(rocket).await.unwrap();
+
+    let response = client.get("/health/db").dispatch().await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["status"], "unavailable");
+    // The liveness probe doesn't depend on it
+    assert_eq!(client.get("/health").dispatch().await.status(), Status::Ok);
+}
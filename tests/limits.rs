@@ -0,0 +1,80 @@
+use rocket::data::{Data, Limits, ToByteUnit};
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use rocket::{post, routes};
+use serde_json::{json, Value};
+
+use misato_api::{errors::catchers::catchers, fairings::json_form::JsonForm};
+use misato_database::models::request_model::Login;
+use misato_utils::{config::Config, settings::Settings};
+
+#[post("/login", data = "<input>")]
+fn login(input: JsonForm<Login>) -> String {
+    input.into_inner().identifier
+}
+
+/// A route needing more than the JSON limit, through a named one.
+#[post("/avatar", data = "<data>")]
+async fn avatar(limits: &Limits, data: Data<'_>) -> Result<String, Status> {
+    let limit = limits.get("avatar").unwrap_or_else(|| 1.kibibytes());
+    let bytes = data.open(limit).into_bytes().await.unwrap();
+    match bytes.is_complete() {
+        true => Ok(bytes.len().to_string()),
+        false => Err(Status::PayloadTooLarge),
+    }
+}
+
+async fn client() -> Client {
+    let config = Config::from_toml(
+        "MONGODB_URI = \"mongodb://127.0.0.1:1\"\nMONGODB_NAME = \"misato\"\n\
+         MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"\n\
+         MISATO_JSON_LIMIT = 1024\nMISATO_BODY_LIMITS = \"avatar=4096\"",
+    )
+    .unwrap();
+    let settings = Settings::from_config(&config).unwrap();
+    let rocket = rocket::custom(misato_api::figment(&settings))
+        .register("/", catchers())
+        .mount("/", routes![login, avatar]);
+    Client::tracked(rocket).await.unwrap()
+}
+
+#[rocket::async_test]
+async fn oversized_bodies_are_refused_with_413() {
+    let client = client().await;
+    let body = |password: usize| {
+        json!({ "identifier": "misato", "password": "p".repeat(password) }).to_string()
+    };
+
+    let response = client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(body(512))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let response = client
+        .post("/login")
+        .header(ContentType::JSON)
+        .body(body(2048))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let error: Value = response.into_json().await.unwrap();
+    assert_eq!(error["error"]["code"], "PAYLOAD_TOO_LARGE");
+}
+
+#[rocket::async_test]
+async fn named_limits_override_the_json_one() {
+    let client = client().await;
+
+    let response = client.post("/avatar").body(vec![0; 3000]).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().await.unwrap(), "3000");
+
+    let response = client.post("/avatar").body(vec![0; 5000]).dispatch().await;
+    assert_eq!(response.status(), Status::PayloadTooLarge);
+    let error: Value = response.into_json().await.unwrap();
+    assert_eq!(error["error"]["code"], "PAYLOAD_TOO_LARGE");
+}
@@ -0,0 +1,66 @@
+use std::{
+    io,
+    sync::{Arc, Mutex},
+};
+
+use tracing::{debug, info};
+
+use misato_api::logging;
+use misato_utils::settings::{LogFormat, LogLevel, LogSettings};
+
+/// Keeps everything written, shared with the test.
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Buffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(bytes);
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Buffer {
+    fn lines(&self) -> Vec<String> {
+        let bytes = self.0.lock().unwrap();
+        String::from_utf8_lossy(&bytes)
+            .lines()
+            .map(|line| line.to_string())
+            .collect()
+    }
+}
+
+fn log(level: LogLevel, format: LogFormat) -> Vec<String> {
+    let buffer = Buffer::default();
+    let writer = buffer.clone();
+    let subscriber = logging::subscriber(&LogSettings { level, format }, move || writer.clone());
+    tracing::subscriber::with_default(subscriber, || {
+        debug!("debug event");
+        info!(users = 3, "info event");
+    });
+    buffer.lines()
+}
+
+#[test]
+fn level_filters_debug_events() {
+    let lines = log(LogLevel::Info, LogFormat::Pretty);
+    assert_eq!(lines.len(), 1);
+    assert!(lines[0].contains("info event"));
+
+    let lines = log(LogLevel::Debug, LogFormat::Pretty);
+    assert_eq!(lines.len(), 2);
+    assert!(lines[0].contains("debug event"));
+}
+
+#[test]
+fn json_format_has_one_object_per_event() {
+    let lines = log(LogLevel::Info, LogFormat::Json);
+    assert_eq!(lines.len(), 1);
+    let event: serde_json::Value = serde_json::from_str(&lines[0]).unwrap();
+    assert_eq!(event["level"], "INFO");
+    assert_eq!(event["fields"]["message"], "info event");
+    assert_eq!(event["fields"]["users"], 3);
+}
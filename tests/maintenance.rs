@@ -0,0 +1,54 @@
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+use serde_json::Value;
+
+use misato_api::{
+    fairings::maintenance::{Maintenance, MaintenanceMode},
+    routes::root::{docs, health},
+};
+use misato_utils::{config::Config, settings::Settings};
+
+async fn client() -> Client {
+    let config = Config::from_toml(
+        "MONGODB_URI = \"mongodb://localhost\"\nMONGODB_NAME = \"misato\"\n\
+         MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"",
+    )
+    .unwrap();
+    let rocket = rocket::build()
+        .manage(Settings::from_config(&config).unwrap())
+        .manage(MaintenanceMode::default())
+        .attach(Maintenance::new(vec!["/health".to_string()]))
+        .mount("/", routes![docs::openapi_json, health::health]);
+    Client::tracked(rocket).await.unwrap()
+}
+
+#[rocket::async_test]
+async fn maintenance_blocks_everything_but_health() {
+    let client = client().await;
+    assert_eq!(
+        client.get("/openapi.json").dispatch().await.status(),
+        Status::Ok
+    );
+
+    let mode = client.rocket().state::<MaintenanceMode>().unwrap();
+    mode.set(true, Some(120));
+    let response = client.get("/openapi.json").dispatch().await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    assert_eq!(response.headers().get_one("Retry-After"), Some("120"));
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], "MAINTENANCE");
+
+    // Routes that don't exist don't tell anything either
+    let response = client.post("/healthy").dispatch().await;
+    assert_eq!(response.status(), Status::ServiceUnavailable);
+    assert_eq!(client.get("/health").dispatch().await.status(), Status::Ok);
+
+    mode.set(false, None);
+    assert_eq!(
+        client.get("/openapi.json").dispatch().await.status(),
+        Status::Ok
+    );
+    assert_eq!(mode.retry_after(), 120);
+}
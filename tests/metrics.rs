@@ -0,0 +1,51 @@
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+
+use misato_api::{
+    fairings::metrics::MetricsFairing,
+    routes::root::{health, metrics},
+};
+
+const HEALTH_REQUESTS: &str =
+    "misato_http_requests_total{method=\"GET\",path=\"/health\",status=\"200\"}";
+
+/// The value of the `name{labels}` sample of a scrape, None when it isn't there.
+fn sample(scrape: &str, series: &str) -> Option<u64> {
+    scrape.lines().find_map(|line| {
+        let (name, value) = line.rsplit_once(' ')?;
+        match name == series {
+            true => value.parse().ok(),
+            false => None,
+        }
+    })
+}
+
+#[rocket::async_test]
+async fn requests_are_counted_by_route() {
+    let rocket = rocket::build()
+        .attach(MetricsFairing)
+        .mount("/", routes![health::health, metrics::metrics]);
+    let client = Client::tracked(rocket).await.unwrap();
+    let scrape = || async {
+        let response = client.get("/metrics").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        response.into_string().await.unwrap()
+    };
+
+    assert_eq!(sample(&scrape().await, HEALTH_REQUESTS), None);
+    for _ in 0..2 {
+        client.get("/health").dispatch().await;
+    }
+    let first = scrape().await;
+    assert_eq!(sample(&first, HEALTH_REQUESTS), Some(2));
+    let latencies = "misato_http_request_duration_seconds_count{method=\"GET\",path=\"/health\"}";
+    assert_eq!(sample(&first, latencies), Some(2));
+
+    client.get("/health").dispatch().await;
+    client.get("/nowhere").dispatch().await;
+    let second = scrape().await;
+    assert_eq!(sample(&second, HEALTH_REQUESTS), Some(3));
+    let unmatched = "misato_http_requests_total{method=\"GET\",path=\"unmatched\",status=\"404\"}";
+    assert_eq!(sample(&second, unmatched), Some(1));
+}
@@ -0,0 +1,87 @@
+use std::sync::{Arc, Mutex};
+
+use rocket::tokio::{
+    self,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+use misato_api::pwned::PwnedPasswords;
+use misato_security::policy::{PasswordPolicy, PolicyViolation};
+
+/// The range of `password`, with its suffix and a padding line.
+const RANGE: &str = "003D68EB55068C33ACE09247EE4C639306B:3\r\n\
+                     1E4C9B93F3F0682250B6CF8331B7EE68FD8:9545824\r\n\
+                     7A3FC3F58D1C2D1B1C9E7B0A7B4F6E2D4C1:0";
+
+/// A range API answering `RANGE` to every prefix, returns its url and the paths asked.
+async fn mock_range_api() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/range/", listener.local_addr().unwrap());
+    let paths = Arc::new(Mutex::new(Vec::new()));
+    let asked = paths.clone();
+    tokio::spawn(async move {
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buffer = [0u8; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                if read == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buffer[..read]);
+            }
+            let request = String::from_utf8_lossy(&request).to_string();
+            let path = request.split(' ').nth(1).unwrap_or("").to_string();
+            asked.lock().unwrap().push(path);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                RANGE.len(),
+                RANGE
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    });
+    (url, paths)
+}
+
+#[rocket::async_test]
+async fn pwned_passwords_are_rejected() {
+    let (url, paths) = mock_range_api().await;
+    let pwned = PwnedPasswords::new(Some(url), false, 60);
+    let policy = PasswordPolicy::default();
+
+    let result = pwned.validate(&policy, b"password").await;
+    assert_eq!(result, Err(PolicyViolation::Pwned(9545824)));
+    assert_eq!(pwned.validate(&policy, b"a clean passphrase").await, Ok(()));
+    // The policy still runs first, nothing is asked for a too short password
+    let result = pwned.validate(&policy, b"short").await;
+    assert_eq!(result, Err(PolicyViolation::TooShort(8)));
+
+    // Only 5 hex digits are ever sent, and a range is fetched once while cached
+    assert!(pwned.validate(&policy, b"password").await.is_err());
+    let paths = paths.lock().unwrap().clone();
+    assert_eq!(paths.len(), 2);
+    assert_eq!(paths[0], "/range/5BAA6");
+    assert!(paths.iter().all(|path| path.len() == "/range/".len() + 5));
+}
+
+#[rocket::async_test]
+async fn unreachable_range_api_fails_as_configured() {
+    // Bound then dropped, so nothing listens there anymore
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/range/", listener.local_addr().unwrap());
+    drop(listener);
+    let policy = PasswordPolicy::default();
+
+    let open = PwnedPasswords::new(Some(url.clone()), false, 60);
+    assert_eq!(open.validate(&policy, b"password").await, Ok(()));
+
+    let closed = PwnedPasswords::new(Some(url), true, 60);
+    let result = closed.validate(&policy, b"password").await;
+    assert_eq!(result, Err(PolicyViolation::PwnedUnchecked));
+
+    let off = PwnedPasswords::new(None, true, 60);
+    assert_eq!(off.validate(&policy, b"password").await, Ok(()));
+}
@@ -0,0 +1,50 @@
+use rocket::http::{Header, Status};
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+
+use misato_api::{fairings::request_id::RequestLogger, routes::root::health};
+
+async fn client() -> Client {
+    let rocket = rocket::build()
+        .attach(RequestLogger)
+        .mount("/", routes![health::health]);
+    Client::tracked(rocket).await.unwrap()
+}
+
+#[rocket::async_test]
+async fn responses_carry_a_request_id() {
+    let client = client().await;
+
+    let first = client.get("/health").dispatch().await;
+    assert_eq!(first.status(), Status::Ok);
+    let first = first.headers().get_one("X-Request-Id").unwrap().to_string();
+    let second = client.get("/nowhere").dispatch().await;
+    assert_eq!(second.status(), Status::NotFound);
+    let second = second.headers().get_one("X-Request-Id").unwrap();
+    assert!(!first.is_empty());
+    assert_ne!(first, second);
+}
+
+#[rocket::async_test]
+async fn supplied_request_ids_are_echoed() {
+    let client = client().await;
+
+    let response = client
+        .get("/health")
+        .header(Header::new("X-Request-Id", "trace-42"))
+        .dispatch()
+        .await;
+    assert_eq!(response.headers().get_one("X-Request-Id"), Some("trace-42"));
+
+    // Not safe to log, a new one replaces it
+    for id in ["has space", &"k".repeat(129)] {
+        let response = client
+            .get("/health")
+            .header(Header::new("X-Request-Id", id.to_string()))
+            .dispatch()
+            .await;
+        let echoed = response.headers().get_one("X-Request-Id").unwrap();
+        assert_ne!(echoed, id);
+        assert!(!echoed.is_empty());
+    }
+}
@@ -0,0 +1,37 @@
+use rocket::http::Status;
+use rocket::local::asynchronous::Client;
+use rocket::routes;
+
+use misato_api::{fairings::security_headers::SecurityHeaders, routes::root::health};
+
+async fn client(headers: SecurityHeaders) -> Client {
+    let rocket = rocket::build()
+        .attach(headers)
+        .mount("/", routes![health::health]);
+    Client::tracked(rocket).await.unwrap()
+}
+
+#[rocket::async_test]
+async fn headers_are_set_on_a_normal_response() {
+    let client = client(SecurityHeaders::new(None)).await;
+    let response = client.get("/health").dispatch().await;
+    let headers = response.headers();
+
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(headers.get_one("X-Content-Type-Options"), Some("nosniff"));
+    assert_eq!(headers.get_one("X-Frame-Options"), Some("DENY"));
+    assert_eq!(headers.get_one("Referrer-Policy"), Some("no-referrer"));
+    assert_eq!(headers.get_one("Strict-Transport-Security"), None);
+}
+
+#[rocket::async_test]
+async fn hsts_is_set_when_configured() {
+    let hsts = "max-age=60; includeSubDomains".to_string();
+    let client = client(SecurityHeaders::new(Some(hsts.clone()))).await;
+    let response = client.get("/health").dispatch().await;
+
+    assert_eq!(
+        response.headers().get_one("Strict-Transport-Security"),
+        Some(hsts.as_str())
+    );
+}
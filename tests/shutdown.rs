@@ -0,0 +1,52 @@
+use std::net::TcpListener;
+use std::time::Duration;
+
+use rocket::tokio::{net::TcpStream, task, time::sleep};
+use rocket::{get, routes, Config};
+
+#[get("/slow")]
+async fn slow() -> &'static str {
+    sleep(Duration::from_millis(500)).await;
+    "done"
+}
+
+/// A port nothing listens on, freed for the server to take.
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+#[rocket::async_test]
+async fn in_flight_requests_complete_after_shutdown() {
+    let port = free_port();
+    let figment = Config::figment()
+        .merge(("address", "127.0.0.1"))
+        .merge(("port", port))
+        .merge(("shutdown.grace", 5))
+        .merge(("shutdown.ctrlc", false))
+        .merge(("log_level", "off"));
+    let rocket = rocket::custom(figment)
+        .mount("/", routes![slow])
+        .ignite()
+        .await
+        .unwrap();
+    let shutdown = rocket.shutdown();
+    let server = task::spawn(rocket.launch());
+    while TcpStream::connect(("127.0.0.1", port)).await.is_err() {
+        sleep(Duration::from_millis(10)).await;
+    }
+
+    let url = format!("http://127.0.0.1:{}/slow", port);
+    let request = task::spawn(async move { reqwest::get(url).await?.text().await });
+    sleep(Duration::from_millis(100)).await;
+    shutdown.notify();
+
+    assert_eq!(request.await.unwrap().unwrap(), "done");
+    // Launch only returns once it drained
+    let stopped = server.await.unwrap().unwrap();
+    assert_eq!(stopped.config().shutdown.grace, 5);
+    assert!(TcpStream::connect(("127.0.0.1", port)).await.is_err());
+}
@@ -0,0 +1,55 @@
+use std::time::{Duration, Instant};
+
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use rocket::tokio::time::sleep;
+use rocket::{get, routes};
+use serde_json::Value;
+
+use misato_api::fairings::timeout::with_timeout;
+use misato_utils::{config::Config, settings::Settings};
+
+#[get("/slow/<millis>")]
+async fn slow(millis: u64) -> &'static str {
+    sleep(Duration::from_millis(millis)).await;
+    "done"
+}
+
+async fn client(timeout: &str) -> Client {
+    let config = Config::from_toml(&format!(
+        "MONGODB_URI = \"mongodb://localhost\"\nMONGODB_NAME = \"misato\"\n\
+         MISATO_ADMIN_TOKEN = \"Zq9vR2xLk7Pw4mTn8sJd3bHc6fGy1aQe\"\n\
+         MISATO_REQUEST_TIMEOUT = {}",
+        timeout
+    ))
+    .unwrap();
+    let settings = Settings::from_config(&config).unwrap();
+    let rocket = rocket::build().mount(
+        "/",
+        with_timeout(routes![slow], settings.http.request_timeout),
+    );
+    Client::tracked(rocket).await.unwrap()
+}
+
+#[rocket::async_test]
+async fn slow_handlers_answer_504() {
+    let client = client("1").await;
+    let response = client.get("/slow/10").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(response.into_string().await.unwrap(), "done");
+
+    let start = Instant::now();
+    let response = client.get("/slow/5000").dispatch().await;
+    assert!(start.elapsed() < Duration::from_secs(3));
+    assert_eq!(response.status(), Status::GatewayTimeout);
+    assert_eq!(response.content_type(), Some(ContentType::JSON));
+    let body: Value = response.into_json().await.unwrap();
+    assert_eq!(body["error"]["code"], "TIMEOUT");
+}
+
+#[rocket::async_test]
+async fn zero_disables_the_timeout() {
+    let client = client("0").await;
+    let response = client.get("/slow/1200").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+}
@@ -0,0 +1,163 @@
+use misato_api::errors::api_errors::ApiError;
+use misato_api::pwned::PwnedPasswords;
+use misato_api::routes::admin::account::{
+    create_users_batch, find_profile, list_users_after, list_users_page,
+};
+use misato_database::{
+    models::{request_model::BatchUser, response_model::Pagination, user_model::User},
+    user_store::{MemoryUserStore, UserStore},
+};
+use misato_security::password::Password;
+use misato_utils::{config::Config, settings::Settings};
+
+async fn store_with(usernames: &[&str]) -> MemoryUserStore {
+    let store = MemoryUserStore::default();
+    for username in usernames {
+        let user = User::create(
+            username.to_string(),
+            Password::hash_password(b"password").unwrap(),
+            None,
+        );
+        store.create(&user).await.unwrap();
+    }
+    store
+}
+
+#[rocket::async_test]
+async fn users_page_from_memory() {
+    let store = store_with(&["asuka", "misato", "shinji"]).await;
+
+    let first = list_users_page(&store, Pagination::new(Some(1), Some(2), 20, 100))
+        .await
+        .unwrap();
+    let names: Vec<&str> = first.items.iter().map(|a| a.username.as_str()).collect();
+    assert_eq!(names, vec!["asuka", "misato"]);
+    assert_eq!((first.total, first.has_next), (3, true));
+
+    let last = list_users_page(&store, Pagination::new(Some(2), Some(2), 20, 100))
+        .await
+        .unwrap();
+    assert_eq!(last.items.len(), 1);
+    assert!(!last.has_next);
+}
+
+#[rocket::async_test]
+async fn users_page_boundaries() {
+    let store = store_with(&["asuka", "misato", "rei", "shinji"]).await;
+    let page = |page: u64, limit: u64| {
+        list_users_page(&store, Pagination::new(Some(page), Some(limit), 20, 3))
+    };
+
+    // The last page ends exactly on the last user
+    let last = page(2, 2).await.unwrap();
+    assert_eq!(last.items.len(), 2);
+    assert_eq!((last.total, last.has_next), (4, false));
+
+    let past = page(3, 2).await.unwrap();
+    assert_eq!(past.items.len(), 0);
+    assert_eq!((past.page, past.total, past.has_next), (3, 4, false));
+
+    // Out of range values are clamped, not refused
+    let first = page(0, 0).await.unwrap();
+    assert_eq!((first.page, first.limit, first.items.len()), (1, 1, 1));
+    assert_eq!(first.items[0].username, "asuka");
+    let capped = page(1, 1000).await.unwrap();
+    assert_eq!(
+        (capped.limit, capped.items.len(), capped.has_next),
+        (3, 3, true)
+    );
+
+    let empty = list_users_page(
+        &MemoryUserStore::default(),
+        Pagination::new(None, None, 20, 100),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        (empty.items.len(), empty.total, empty.has_next),
+        (0, 0, false)
+    );
+}
+
+#[rocket::async_test]
+async fn users_cursor_from_memory() {
+    let store = store_with(&["asuka", "misato", "rei", "shinji", "toji"]).await;
+    let rei = store.get_by_username("rei").await.unwrap().unwrap().uuid;
+    store.delete(&rei).await.unwrap();
+
+    let mut names = Vec::new();
+    let mut cursor = String::new();
+    loop {
+        let page = list_users_after(&store, &cursor, 2).await.unwrap();
+        names.extend(page.items.into_iter().map(|account| account.username));
+        match page.next_cursor {
+            Some(next) => cursor = next,
+            None => break,
+        }
+        // Created between two pages, so listed last
+        if names.len() == 2 {
+            let user = User::create(
+                "kaworu".to_string(),
+                Password::hash_password(b"password").unwrap(),
+                None,
+            );
+            store.create(&user).await.unwrap();
+        }
+    }
+    assert_eq!(names, vec!["asuka", "misato", "shinji", "toji", "kaworu"]);
+
+    let result = list_users_after(&store, "not a cursor", 2).await;
+    assert!(matches!(result, Err(ApiError::ValidationError(_))));
+}
+
+#[rocket::async_test]
+async fn profile_from_memory() {
+    let store = store_with(&["misato"]).await;
+    let uuid = store.get_by_username("misato").await.unwrap().unwrap().uuid;
+
+    let account = find_profile(&store, &uuid).await.unwrap();
+    assert_eq!(account.username, "misato");
+
+    store.delete(&uuid).await.unwrap();
+    let result = find_profile(&store, &uuid).await;
+    assert!(matches!(result, Err(ApiError::AccountNotFound(_))));
+}
+
+#[rocket::async_test]
+async fn batch_from_memory() {
+    let store = store_with(&["misato"]).await;
+    let config = Config::from_toml(
+        "MONGODB_URI = \"mongodb://localhost\"\nMONGODB_NAME = \"misato\"\nMISATO_ADMIN_TOKEN = \"a-long-enough-admin-token-for-tests\"\nMISATO_ARGON2_MEMORY_COST = 1024\nMISATO_ARGON2_TIME_COST = 1",
+    )
+    .unwrap();
+    let settings = Settings::from_config(&config).unwrap();
+    let batch = |username: &str, password: &str| BatchUser {
+        username: username.to_string(),
+        password: password.to_string(),
+        ..BatchUser::default()
+    };
+
+    let results = create_users_batch(
+        &store,
+        &settings,
+        &PwnedPasswords::from_settings(&settings),
+        vec![
+            batch("asuka", "anypassword"),
+            batch("misato", "anypassword"),
+            batch("sh", "anypassword"),
+            batch("shinji", "anypassword"),
+            batch("Asuka", "anypassword"),
+        ],
+    )
+    .await
+    .unwrap();
+    let statuses: Vec<u16> = results.iter().map(|result| result.status).collect();
+    assert_eq!(statuses, vec![201, 409, 400, 201, 409]);
+    assert_eq!(results[1].error.as_deref(), Some("USER_EXISTS"));
+    assert_eq!(results[2].error.as_deref(), Some("VALIDATION_ERROR"));
+    assert_eq!(
+        store.get_by_username("shinji").await.unwrap().unwrap().uuid,
+        results[3].uuid.clone().unwrap()
+    );
+    assert_eq!(store.count().await.unwrap(), 3);
+}
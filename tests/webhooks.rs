@@ -0,0 +1,56 @@
+#[path = "common/webhook.rs"]
+mod webhook;
+
+use rocket::tokio::net::TcpListener;
+
+use misato_api::webhooks::Webhooks;
+use misato_database::models::audit_model::{AuditAction, AuditEvent};
+use misato_security::sign_payload;
+use misato_utils::get_current_timestamp;
+
+use webhook::{header, receive};
+
+const SECRET: &str = "webhook secret";
+
+async fn webhooks() -> (TcpListener, Webhooks) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    (listener, Webhooks::new(vec![url], SECRET.to_string(), 3, 1))
+}
+
+#[rocket::async_test]
+async fn signup_is_signed() {
+    let (listener, webhooks) = webhooks().await;
+    let event = AuditEvent::create(
+        AuditAction::Signup,
+        Some("admin".to_string()),
+        Some("uuid".to_string()),
+    );
+    let sent_at = get_current_timestamp();
+    webhooks.dispatch(&event);
+
+    let (headers, body) = receive(&listener, "200 OK").await;
+    let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(payload["event"], "user.created");
+    // Signed along with the rest, against replays
+    let timestamp = payload["timestamp"].as_u64().unwrap();
+    assert!(timestamp >= sent_at && timestamp <= get_current_timestamp());
+    assert_eq!(payload["data"]["actor"], "admin");
+    assert_eq!(payload["data"]["target"], "uuid");
+    assert_eq!(
+        header(&headers, "x-signature"),
+        Some(sign_payload(SECRET.as_bytes(), &body).as_str())
+    );
+    assert_eq!(header(&headers, "content-type"), Some("application/json"));
+}
+
+#[rocket::async_test]
+async fn failed_delivery_is_retried() {
+    let (listener, webhooks) = webhooks().await;
+    let event = AuditEvent::create(AuditAction::AccountDeleted, None, Some("uuid".to_string()));
+    webhooks.dispatch(&event);
+
+    let (_, first) = receive(&listener, "500 Internal Server Error").await;
+    let (_, second) = receive(&listener, "200 OK").await;
+    assert_eq!(first, second);
+}